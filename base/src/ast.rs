@@ -229,6 +229,8 @@ pub enum Pattern<Id> {
 #[derive(Clone, PartialEq, Debug)]
 pub struct Alternative<Id> {
     pub pattern: SpannedPattern<Id>,
+    /// Optional guard, eg: `| Some x if x > 0 -> ...`
+    pub guard: Option<SpannedExpr<Id>>,
     pub expr: SpannedExpr<Id>,
 }
 
@@ -308,6 +310,8 @@ pub enum Expr<Id> {
         types: Vec<ExprField<Id, ArcType<Id>>>,
         exprs: Vec<ExprField<Id, SpannedExpr<Id>>>,
         base: Option<Box<SpannedExpr<Id>>>,
+        /// Fields of `base` which are left out of the record, eg. `{ .. base, -field }`
+        removed: Vec<Spanned<Id, BytePos>>,
     },
     /// Tuple construction
     Tuple {
@@ -504,6 +508,9 @@ pub fn walk_mut_expr<'a, V: ?Sized + MutVisitor<'a>>(v: &mut V, e: &'a mut Spann
             v.visit_expr(expr);
             for alt in alts {
                 v.visit_pattern(&mut alt.pattern);
+                if let Some(ref mut guard) = alt.guard {
+                    v.visit_expr(guard);
+                }
                 v.visit_expr(&mut alt.expr);
             }
         }
@@ -518,7 +525,7 @@ pub fn walk_mut_expr<'a, V: ?Sized + MutVisitor<'a>>(v: &mut V, e: &'a mut Spann
             ref mut types,
             ref mut exprs,
             ref mut base,
-            ..
+            ref mut removed,
         } => {
             v.visit_typ(typ);
             for typ in types {
@@ -533,6 +540,9 @@ pub fn walk_mut_expr<'a, V: ?Sized + MutVisitor<'a>>(v: &mut V, e: &'a mut Spann
             if let Some(ref mut base) = *base {
                 v.visit_expr(base);
             }
+            for name in removed {
+                v.visit_spanned_ident(name);
+            }
         }
         Expr::Tuple {
             ref mut typ,
@@ -730,6 +740,9 @@ pub fn walk_expr<'a, V: ?Sized + Visitor<'a>>(v: &mut V, e: &'a SpannedExpr<V::I
             v.visit_expr(expr);
             for alt in alts {
                 v.visit_pattern(&alt.pattern);
+                if let Some(ref guard) = alt.guard {
+                    v.visit_expr(guard);
+                }
                 v.visit_expr(&alt.expr);
             }
         }
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
+use std::fmt;
 
 use ast::Argument;
+use pos::Line;
 use symbol::{Symbol, SymbolRef};
 
 pub trait MetadataEnv {
@@ -40,18 +42,36 @@ pub struct Attribute {
     pub arguments: Option<String>,
 }
 
+/// The module and line where a binding or type is defined
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde_derive", derive(Deserialize, Serialize))]
+pub struct Definition {
+    pub module: String,
+    pub line: Line,
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.module, self.line.number())
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 #[cfg_attr(feature = "serde_derive", derive(Deserialize, Serialize))]
 pub struct Metadata {
     pub comment: Option<Comment>,
     pub attributes: Vec<Attribute>,
     pub args: Vec<Argument<Symbol>>,
+    pub definition: Option<Definition>,
     pub module: BTreeMap<String, Metadata>,
 }
 
 impl Metadata {
     pub fn has_data(&self) -> bool {
-        self.comment.is_some() || !self.module.is_empty() || !self.attributes.is_empty()
+        self.comment.is_some()
+            || !self.module.is_empty()
+            || !self.attributes.is_empty()
+            || self.definition.is_some()
     }
 
     pub fn merge(mut self, other: Metadata) -> Metadata {
@@ -63,6 +83,9 @@ impl Metadata {
         if self.comment.is_none() {
             self.comment = other.comment;
         }
+        if self.definition.is_none() {
+            self.definition = other.definition;
+        }
         for (key, value) in other.module {
             use std::collections::btree_map::Entry;
             match self.module.entry(key) {
@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::fmt;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
@@ -10,7 +10,7 @@ use pretty::{Arena, DocAllocator, DocBuilder};
 use smallvec::SmallVec;
 
 use ast::{Commented, EmptyEnv, IdentEnv};
-use fnv::FnvMap;
+use fnv::{FnvHasher, FnvMap};
 use kind::{ArcKind, Kind, KindEnv};
 use merge::merge;
 use metadata::Comment;
@@ -1096,6 +1096,17 @@ pub fn type_field_iter<T>(typ: &T) -> TypeFieldIterator<T> {
     TypeFieldIterator { typ, current: 0 }
 }
 
+/// Returns the tag which identifies the constructor `name` of an open variant at runtime.
+///
+/// An open variant can be extended with more constructors which moves its own constructors to
+/// other positions so its constructors are tagged by a hash of their name instead of by position.
+pub fn open_variant_tag(name: &str) -> u32 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(name.as_bytes());
+    // The highest bit of a tag is reserved for marking records
+    hasher.finish() as u32 & (u32::max_value() >> 1)
+}
+
 pub fn remove_forall<'a, Id, T>(typ: &'a T) -> &'a T
 where
     T: Deref<Target = Type<Id, T>>,
@@ -1782,29 +1793,34 @@ where
             Type::Variant(ref row) => {
                 let mut first = true;
 
-                let doc = match **row {
-                    Type::EmptyRow => arena.nil(),
-                    Type::ExtendRow { ref fields, .. } => {
-                        arena.concat(fields.iter().map(|field| {
+                let mut iter = row_iter(row);
+                let doc = arena.concat(iter.by_ref().map(|field| {
+                    chain![arena;
+                        if first {
+                            first = false;
+                            arena.nil()
+                        } else {
+                            arena.newline()
+                        },
+                        "| ",
+                        field.name.as_ref(),
+                        arena.concat(arg_iter(&field.typ).map(|arg| {
                             chain![arena;
-                                if first {
-                                    first = false;
-                                    arena.nil()
-                                } else {
-                                    arena.newline()
-                                },
-                                "| ",
-                                field.name.as_ref(),
-                                arena.concat(arg_iter(&field.typ).map(|arg| {
-                                    chain![arena;
-                                        " ",
-                                        dt(Prec::Constructor, arg).pretty(printer)
-                                    ]
-                                }))
-                            ].group()
+                                " ",
+                                dt(Prec::Constructor, arg).pretty(printer)
+                            ]
                         }))
-                    }
-                    _ => ice!("Unexpected type in variant"),
+                    ].group()
+                }));
+                // Open variants end with the row (or variant) that they may be extended with
+                let doc = match **iter.current_type() {
+                    Type::EmptyRow => doc,
+                    _ => chain![arena;
+                        doc,
+                        if first { arena.nil() } else { arena.newline() },
+                        "| ..",
+                        dt(Prec::Top, iter.current_type()).pretty(printer)
+                    ],
                 };
 
                 p.enclose(Prec::Constructor, arena, doc).group()
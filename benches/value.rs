@@ -0,0 +1,88 @@
+#[macro_use]
+extern crate bencher;
+
+extern crate gluon;
+
+use bencher::{black_box, Bencher};
+
+use gluon::vm::api::FunctionRef;
+use gluon::{new_vm, Compiler};
+
+// Benchmarks workloads whose cost is dominated by the size of `Value`. Arrays and the value stack
+// store one `Value` per element so these are the numbers to compare against when evaluating a
+// smaller representation.
+
+fn array_build_and_sum(b: &mut Bencher) {
+    let vm = new_vm();
+    let text = r#"
+    let array = import! std.array
+    let prim = import! std.array.prim
+
+    let fill builder n =
+        if n #Int== 0 then
+            array.builder.build builder
+        else
+            array.builder.push builder n
+            fill builder (n #Int- 1)
+
+    let sum xs =
+        let len = prim.len xs
+        let loop i acc =
+            if i #Int== len then acc
+            else loop (i #Int+ 1) (acc #Int+ prim.index xs i)
+        loop 0 0
+
+    \n -> sum (fill (array.builder.new ()) n)
+    "#;
+    Compiler::new()
+        .load_script(&vm, "array_build_and_sum", text)
+        .unwrap();
+    let mut f: FunctionRef<fn(i32) -> i32> = vm.get_global("array_build_and_sum").unwrap();
+    b.iter(|| {
+        let result = f.call(10_000).unwrap();
+        black_box(result)
+    })
+}
+
+fn array_append(b: &mut Bencher) {
+    let vm = new_vm();
+    let text = r#"
+    let prim = import! std.array.prim
+
+    let repeat xs n =
+        if n #Int== 0 then xs
+        else repeat (prim.append xs xs) (n #Int- 1)
+
+    \n -> prim.len (repeat [1, 2, 3, 4, 5, 6, 7, 8] n)
+    "#;
+    Compiler::new()
+        .load_script(&vm, "array_append", text)
+        .unwrap();
+    let mut f: FunctionRef<fn(i32) -> i32> = vm.get_global("array_append").unwrap();
+    b.iter(|| {
+        let result = f.call(12).unwrap();
+        black_box(result)
+    })
+}
+
+// A recursion which is not a tail call keeps every frame and its locals on the value stack
+fn deep_stack(b: &mut Bencher) {
+    let vm = new_vm();
+    let text = r#"
+    let sum n a b c =
+        if n #Int== 0 then a #Int+ b #Int+ c
+        else
+            let x = sum (n #Int- 1) (a #Int+ 1) (b #Int+ 2) (c #Int+ 3)
+            x #Int+ 1
+    \n -> sum n 0 0 0
+    "#;
+    Compiler::new().load_script(&vm, "deep_stack", text).unwrap();
+    let mut f: FunctionRef<fn(i32) -> i32> = vm.get_global("deep_stack").unwrap();
+    b.iter(|| {
+        let result = f.call(10_000).unwrap();
+        black_box(result)
+    })
+}
+
+benchmark_group!(value, array_build_and_sum, array_append, deep_stack);
+benchmark_main!(value);
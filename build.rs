@@ -160,9 +160,23 @@ fn example_24_up_to_date() {
     readme_file.write_all(readme.as_bytes()).unwrap();
 }
 
+/// Exposes the version of the compiler building gluon so that native modules built with another
+/// compiler can be rejected
+fn rustc_version() {
+    use std::env;
+    use std::process::Command;
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc).arg("-V").output().unwrap();
+    let version = String::from_utf8(output.stdout).unwrap();
+    println!("cargo:rustc-env=GLUON_RUSTC_VERSION={}", version.trim());
+}
+
 fn main() {
     gen_skeptic::generate();
 
+    rustc_version();
+
     check_test_declarations_in_cargo_file();
 
     example_24_up_to_date();
@@ -8,7 +8,10 @@ extern crate libc;
 use std::slice;
 use std::str;
 
-use gluon::vm::api::{CPrimitive, Getable, Hole, OpaqueValue, Pushable};
+use gluon::base::metadata::Metadata;
+use gluon::base::symbol::Symbol;
+use gluon::base::types::{self, ArcType};
+use gluon::vm::api::{CPrimitive, Getable, Hole, OpaqueValue, Pushable, VmType};
 use gluon::vm::thread::{RootedThread, Status, Thread, ThreadInternal};
 use gluon::vm::types::{VmIndex, VmInt};
 
@@ -25,6 +28,31 @@ pub enum Error {
     Unknown,
 }
 
+/// The types of the arguments and return value of a function defined with `glu_define_function`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    Unit,
+    Bool,
+    Byte,
+    Int,
+    Float,
+    String,
+}
+
+impl Type {
+    fn make_type(self, vm: &Thread) -> ArcType {
+        match self {
+            Type::Unit => <()>::make_type(vm),
+            Type::Bool => bool::make_type(vm),
+            Type::Byte => u8::make_type(vm),
+            Type::Int => VmInt::make_type(vm),
+            Type::Float => f64::make_type(vm),
+            Type::String => String::make_type(vm),
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn glu_new_vm() -> *const Thread {
     let vm = RootedThread::new();
@@ -36,26 +64,58 @@ pub unsafe extern "C" fn glu_free_vm(vm: &Thread) {
     RootedThread::from_raw(vm);
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn glu_run_expr(
-    vm: &Thread,
+unsafe fn run_expr<'vm>(
+    vm: &'vm Thread,
     module: &u8,
     module_len: usize,
     expr: &u8,
     expr_len: usize,
-) -> Error {
+) -> Result<OpaqueValue<&'vm Thread, Hole>, Error> {
     let module = match str::from_utf8(slice::from_raw_parts(module, module_len)) {
         Ok(s) => s,
-        Err(_) => return Error::Unknown,
+        Err(_) => return Err(Error::Unknown),
     };
     let expr = match str::from_utf8(slice::from_raw_parts(expr, expr_len)) {
         Ok(s) => s,
-        Err(_) => return Error::Unknown,
+        Err(_) => return Err(Error::Unknown),
     };
-    let result = Compiler::new().run_expr::<OpaqueValue<&Thread, Hole>>(&vm, module, expr);
-    match result {
+    match Compiler::new().run_expr::<OpaqueValue<&Thread, Hole>>(vm, module, expr) {
+        Ok((value, _)) => Ok(value),
+        Err(_) => Err(Error::Unknown),
+    }
+}
+
+/// Compiles and runs `expr`, discarding the resulting value
+#[no_mangle]
+pub unsafe extern "C" fn glu_run_expr(
+    vm: &Thread,
+    module: &u8,
+    module_len: usize,
+    expr: &u8,
+    expr_len: usize,
+) -> Error {
+    match run_expr(vm, module, module_len, expr, expr_len) {
         Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
+        Err(err) => err,
+    }
+}
+
+/// Compiles and runs `expr`, pushing the resulting value to the stack on success so it can be
+/// read with the `glu_get_*` functions
+#[no_mangle]
+pub unsafe extern "C" fn glu_run_expr_push(
+    vm: &Thread,
+    module: &u8,
+    module_len: usize,
+    expr: &u8,
+    expr_len: usize,
+) -> Error {
+    match run_expr(vm, module, module_len, expr, expr_len) {
+        Ok(value) => match value.push(vm, &mut vm.context()) {
+            Ok(()) => Error::Ok,
+            Err(_) => Error::Unknown,
+        },
+        Err(err) => err,
     }
 }
 
@@ -144,6 +204,53 @@ pub unsafe extern "C" fn glu_push_function(
     }
 }
 
+/// Defines `function` as a global called `name` which can then be imported from gluon code with
+/// `import! name`. `arg_types` must point to `args` types which, together with `return_type`,
+/// describe the type of the function.
+#[no_mangle]
+pub unsafe extern "C" fn glu_define_function(
+    vm: &Thread,
+    name: &u8,
+    len: usize,
+    function: Function,
+    arg_types: *const Type,
+    args: VmIndex,
+    return_type: Type,
+) -> Error {
+    let name = match str::from_utf8(slice::from_raw_parts(name, len)) {
+        Ok(s) => s,
+        Err(_) => return Error::Unknown,
+    };
+    let arg_types: &[Type] = if args == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(arg_types, args as usize)
+    };
+    let typ = types::Type::function(
+        arg_types.iter().map(|typ| typ.make_type(vm)).collect(),
+        return_type.make_type(vm),
+    );
+    let value = {
+        let mut context = vm.context();
+        if CPrimitive::new(function, args, name)
+            .push(vm, &mut context)
+            .is_err()
+        {
+            return Error::Unknown;
+        }
+        context.stack.pop()
+    };
+    match vm.set_global(
+        Symbol::from(format!("@{}", name)),
+        typ,
+        Metadata::default(),
+        value,
+    ) {
+        Ok(()) => Error::Ok,
+        Err(_) => Error::Unknown,
+    }
+}
+
 /// Push a string to the stack. The string must be valid utf-8 or an error will be returned
 #[no_mangle]
 pub unsafe extern "C" fn glu_push_string(vm: &Thread, s: &u8, len: usize) -> Error {
@@ -353,4 +460,65 @@ mod tests {
             glu_free_vm(vm);
         }
     }
+
+    #[test]
+    fn run_expr_with_defined_function() {
+        extern "C" fn mult(vm: &Thread) -> Status {
+            let mut l = 0;
+            assert_eq!(glu_get_int(vm, 0, &mut l), Error::Ok);
+            let mut r = 0;
+            assert_eq!(glu_get_int(vm, 1, &mut r), Error::Ok);
+            glu_push_int(vm, l * r);
+            Status::Ok
+        }
+
+        unsafe {
+            let vm = &*glu_new_vm();
+            let name = "mult";
+            let arg_types = [Type::Int, Type::Int];
+            assert_eq!(
+                glu_define_function(
+                    vm,
+                    &name.as_bytes()[0],
+                    name.len(),
+                    mult,
+                    arg_types.as_ptr(),
+                    2,
+                    Type::Int,
+                ),
+                Error::Ok
+            );
+
+            let module = "test";
+            let expr = "let mult = import! mult in mult 12 3";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len(),
+                ),
+                Error::Ok
+            );
+            assert_eq!(glu_len(vm), 0);
+
+            assert_eq!(
+                glu_run_expr_push(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len(),
+                ),
+                Error::Ok
+            );
+            assert_eq!(glu_len(vm), 1);
+            let mut result = 0;
+            assert_eq!(glu_get_int(vm, 0, &mut result), Error::Ok);
+            assert_eq!(result, 36);
+
+            glu_free_vm(vm);
+        }
+    }
 }
@@ -0,0 +1,474 @@
+//! Checks that `match` expressions handle every value of the matched type and that each of their
+//! alternatives can be reached.
+//!
+//! Both checks are based on the usefulness of a pattern, a pattern being useful if it matches some
+//! value which none of the patterns before it matches. An alternative whose pattern is not useful
+//! is unreachable and a match is exhaustive if a wildcard after its last alternative would not be
+//! useful.
+//!
+//! Warnings for pattern matching
+//! Luc Maranget
+//! Journal of Functional Programming, 2007
+//! http://moscova.inria.fr/~maranget/papers/warn/warn.pdf
+use std::fmt;
+use std::iter::{once, repeat};
+use std::rc::Rc;
+
+use codespan_reporting::Diagnostic;
+
+use base::ast::{walk_expr, Alternative, Expr, Literal, Pattern, SpannedExpr, SpannedPattern,
+                Visitor};
+use base::error::AsDiagnostic;
+use base::pos::{self, BytePos, Spanned};
+use base::resolve::remove_aliases_cow;
+use base::symbol::Symbol;
+use base::types::{arg_iter, ArcType, Type, TypeEnv};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// The match does not handle values matching any of the contained patterns
+    NonExhaustiveMatch(Vec<String>),
+    /// Every value which reaches the alternative is matched by an earlier alternative
+    UnreachableAlternative,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::NonExhaustiveMatch(ref missing) => {
+                write!(f, "Non-exhaustive match, the following patterns are not handled:")?;
+                for pattern in missing {
+                    write!(f, "\n    {}", pattern)?;
+                }
+                Ok(())
+            }
+            Warning::UnreachableAlternative => write!(
+                f,
+                "Unreachable alternative, all values are matched by earlier alternatives"
+            ),
+        }
+    }
+}
+
+impl AsDiagnostic for Warning {
+    fn as_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new_warning(self.to_string())
+    }
+}
+
+pub type SpannedWarning = Spanned<Warning, BytePos>;
+
+/// Returns warnings for each `match` expression in `expr` which does not handle every value or
+/// which has unreachable alternatives. `expr` must have been typechecked.
+pub fn check_matches(env: &TypeEnv, expr: &SpannedExpr<Symbol>) -> Vec<SpannedWarning> {
+    let mut checker = Checker {
+        env,
+        warnings: Vec::new(),
+    };
+    checker.visit_expr(expr);
+    checker.warnings
+}
+
+#[derive(Clone, Debug)]
+enum Ctor {
+    /// A variant constructor together with all constructors of the variant
+    Variant(Symbol, Rc<Variants>),
+    /// A record, storing the name of each field
+    Record(Vec<Symbol>),
+    Tuple(usize),
+    Literal(Literal),
+}
+
+impl Ctor {
+    fn is_same(&self, other: &Ctor) -> bool {
+        match (self, other) {
+            (&Ctor::Variant(ref l, _), &Ctor::Variant(ref r, _)) => {
+                l.declared_name() == r.declared_name()
+            }
+            (&Ctor::Record(_), &Ctor::Record(_)) | (&Ctor::Tuple(_), &Ctor::Tuple(_)) => true,
+            (&Ctor::Literal(ref l), &Ctor::Literal(ref r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+/// The constructors (and their arity) of a variant type
+#[derive(Debug)]
+struct Variants {
+    ctors: Vec<(Symbol, usize)>,
+    /// `true` if the variant is open (`| A | ..r`) so that its values may also be constructed by
+    /// constructors which are not known
+    open: bool,
+}
+
+/// A pattern with the bindings and types removed
+#[derive(Clone, Debug)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl Pat {
+    fn is_nested_variant(&self) -> bool {
+        match *self {
+            Pat::Ctor(Ctor::Variant(..), ref args) => !args.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Pat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (ctor, args) = match *self {
+            Pat::Wild => return write!(f, "_"),
+            Pat::Ctor(ref ctor, ref args) => (ctor, args),
+        };
+        match *ctor {
+            Ctor::Variant(ref name, _) => {
+                write!(f, "{}", name.declared_name())?;
+                for arg in args {
+                    if arg.is_nested_variant() {
+                        write!(f, " ({})", arg)?;
+                    } else {
+                        write!(f, " {}", arg)?;
+                    }
+                }
+                Ok(())
+            }
+            Ctor::Record(ref fields) => {
+                // Fields which may contain any value are left out of the pattern
+                let mut fields = fields.iter().zip(args).filter(|&(_, arg)| match *arg {
+                    Pat::Wild => false,
+                    _ => true,
+                });
+                match fields.next() {
+                    Some((name, arg)) => {
+                        write!(f, "{{ {} = {}", name.declared_name(), arg)?;
+                        for (name, arg) in fields {
+                            write!(f, ", {} = {}", name.declared_name(), arg)?;
+                        }
+                        write!(f, " }}")
+                    }
+                    None => write!(f, "{{ }}"),
+                }
+            }
+            Ctor::Tuple(_) => {
+                write!(f, "(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Ctor::Literal(ref literal) => match *literal {
+                Literal::Byte(b) => write!(f, "{}b", b),
+                Literal::Int(i) => write!(f, "{}", i),
+                Literal::Float(x) => write!(f, "{}", x),
+                Literal::String(ref s) => write!(f, "{:?}", s),
+                Literal::Char(c) => write!(f, "{:?}", c),
+            },
+        }
+    }
+}
+
+type Row = Vec<Pat>;
+
+/// Returns the row which remains if the first pattern of `row` matches a value constructed with
+/// `ctor` or `None` if `ctor` is not matched
+fn specialize_row(row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Row> {
+    match row[0] {
+        Pat::Wild => Some(
+            repeat(Pat::Wild)
+                .take(arity)
+                .chain(row[1..].iter().cloned())
+                .collect(),
+        ),
+        Pat::Ctor(ref row_ctor, ref args) => if row_ctor.is_same(ctor) {
+            Some(args.iter().chain(&row[1..]).cloned().collect())
+        } else {
+            None
+        },
+    }
+}
+
+fn specialize(matrix: &[Row], ctor: &Ctor, arity: usize) -> Vec<Row> {
+    matrix
+        .iter()
+        .filter_map(|row| specialize_row(row, ctor, arity))
+        .collect()
+}
+
+/// Returns the rows which match any value not constructed by the constructors in the first column
+fn default_matrix(matrix: &[Row]) -> Vec<Row> {
+    matrix
+        .iter()
+        .filter_map(|row| match row[0] {
+            Pat::Wild => Some(row[1..].to_owned()),
+            Pat::Ctor(..) => None,
+        })
+        .collect()
+}
+
+fn head_ctors(matrix: &[Row]) -> Vec<&Ctor> {
+    matrix
+        .iter()
+        .filter_map(|row| match row[0] {
+            Pat::Wild => None,
+            Pat::Ctor(ref ctor, _) => Some(ctor),
+        })
+        .collect()
+}
+
+/// Returns the constructors of the variant matched by the first column if it contains any
+/// variant constructors
+fn column_variants(matrix: &[Row]) -> Option<Rc<Variants>> {
+    head_ctors(matrix)
+        .into_iter()
+        .filter_map(|ctor| match *ctor {
+            Ctor::Variant(_, ref variants) if !variants.ctors.is_empty() => Some(variants.clone()),
+            _ => None,
+        })
+        .next()
+}
+
+/// Returns `true` if the first column contains variant constructors whose type could not be
+/// resolved, in which case it is unknown which values are left unmatched
+fn has_unknown_variants(matrix: &[Row]) -> bool {
+    head_ctors(matrix).into_iter().any(|ctor| match *ctor {
+        Ctor::Variant(_, ref variants) => variants.ctors.is_empty(),
+        _ => false,
+    })
+}
+
+fn contains_variant(ctors: &[&Ctor], name: &Symbol) -> bool {
+    ctors.iter().any(|ctor| match **ctor {
+        Ctor::Variant(ref seen, _) => seen.declared_name() == name.declared_name(),
+        _ => false,
+    })
+}
+
+/// Returns every constructor (and its arity) of the type matched by the first column if all of
+/// them appear in the column
+fn complete_signature(matrix: &[Row]) -> Option<Vec<(Ctor, usize)>> {
+    let ctors = head_ctors(matrix);
+    match **ctors.first()? {
+        Ctor::Record(ref fields) => Some(vec![(ctors[0].clone(), fields.len())]),
+        Ctor::Tuple(arity) => Some(vec![(ctors[0].clone(), arity)]),
+        // Literals can't cover every value of their type
+        Ctor::Literal(_) => None,
+        Ctor::Variant(_, ref variants) => {
+            let is_complete = !variants.open
+                && !variants.ctors.is_empty()
+                && variants
+                    .ctors
+                    .iter()
+                    .all(|&(ref name, _)| contains_variant(&ctors, name));
+            if is_complete {
+                Some(variant_ctors(variants))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn variant_ctors(variants: &Rc<Variants>) -> Vec<(Ctor, usize)> {
+    variants
+        .ctors
+        .iter()
+        .map(|&(ref name, arity)| (Ctor::Variant(name.clone(), variants.clone()), arity))
+        .collect()
+}
+
+/// Returns a pattern for a value not constructed by any of the constructors in the first column
+fn missing_ctor(matrix: &[Row]) -> Pat {
+    let ctors = head_ctors(matrix);
+    column_variants(matrix)
+        .and_then(|variants| {
+            variants
+                .ctors
+                .iter()
+                .find(|&&(ref name, _)| !contains_variant(&ctors, name))
+                .map(|&(ref name, arity)| {
+                    Pat::Ctor(
+                        Ctor::Variant(name.clone(), variants.clone()),
+                        vec![Pat::Wild; arity],
+                    )
+                })
+        })
+        .unwrap_or(Pat::Wild)
+}
+
+/// Returns `true` if `row` matches a value which no row in `matrix` matches
+fn is_useful(matrix: &[Row], row: &[Pat]) -> bool {
+    if row.is_empty() {
+        return matrix.is_empty();
+    }
+    match row[0] {
+        Pat::Ctor(ref ctor, ref args) => is_useful(
+            &specialize(matrix, ctor, args.len()),
+            &specialize_row(row, ctor, args.len()).expect("Matching constructor"),
+        ),
+        Pat::Wild => match complete_signature(matrix) {
+            Some(ctors) => ctors.iter().any(|&(ref ctor, arity)| {
+                is_useful(
+                    &specialize(matrix, ctor, arity),
+                    &specialize_row(row, ctor, arity).expect("Wildcard"),
+                )
+            }),
+            None => is_useful(&default_matrix(matrix), &row[1..]),
+        },
+    }
+}
+
+/// Returns patterns for `columns` values which no row in `matrix` matches, if there are any
+fn find_unmatched(matrix: &[Row], columns: usize) -> Option<Row> {
+    if columns == 0 {
+        return if matrix.is_empty() { Some(Vec::new()) } else { None };
+    }
+    match complete_signature(matrix) {
+        Some(ctors) => ctors
+            .into_iter()
+            .filter_map(|(ctor, arity)| {
+                let specialized = specialize(matrix, &ctor, arity);
+                find_unmatched(&specialized, arity + columns - 1).map(|mut args| {
+                    let rest = args.split_off(arity);
+                    once(Pat::Ctor(ctor, args)).chain(rest).collect()
+                })
+            })
+            .next(),
+        None if has_unknown_variants(matrix) => None,
+        None => find_unmatched(&default_matrix(matrix), columns - 1).map(|rest| {
+            once(missing_ctor(matrix)).chain(rest).collect()
+        }),
+    }
+}
+
+/// Returns a pattern for each value matched by none of the rows in the single column `matrix`.
+/// Unlike `find_unmatched` every variant constructor with unmatched values is listed.
+fn unmatched_patterns(matrix: &[Row]) -> Vec<Pat> {
+    // A wildcard matches every value
+    if !default_matrix(matrix).is_empty() || has_unknown_variants(matrix) {
+        return Vec::new();
+    }
+    let (ctors, open) = match column_variants(matrix) {
+        Some(variants) => (variant_ctors(&variants), variants.open),
+        None => match complete_signature(matrix) {
+            Some(ctors) => (ctors, false),
+            None => return vec![Pat::Wild],
+        },
+    };
+    let mut patterns: Vec<_> = ctors
+        .into_iter()
+        .filter_map(|(ctor, arity)| {
+            find_unmatched(&specialize(matrix, &ctor, arity), arity)
+                .map(|args| Pat::Ctor(ctor, args))
+        })
+        .collect();
+    // Values of an open variant may be constructed by constructors that are not known
+    if open {
+        patterns.push(Pat::Wild);
+    }
+    patterns
+}
+
+struct Checker<'e> {
+    env: &'e TypeEnv,
+    warnings: Vec<SpannedWarning>,
+}
+
+impl<'e> Checker<'e> {
+    fn variants(&self, constructor_type: &ArcType) -> Rc<Variants> {
+        let mut args = arg_iter(constructor_type.remove_forall());
+        for _ in args.by_ref() {}
+        let variant = remove_aliases_cow(self.env, args.typ);
+        let mut iter = variant.row_iter();
+        let ctors = iter
+            .by_ref()
+            .map(|field| {
+                (
+                    field.name.clone(),
+                    arg_iter(field.typ.remove_forall()).count(),
+                )
+            })
+            .collect();
+        Rc::new(Variants {
+            ctors,
+            open: **iter.current_type() != Type::EmptyRow,
+        })
+    }
+
+    fn pattern(&self, pattern: &SpannedPattern<Symbol>) -> Pat {
+        match pattern.value {
+            Pattern::As(_, ref pattern) => self.pattern(pattern),
+            Pattern::Ident(_) | Pattern::Error => Pat::Wild,
+            Pattern::Literal(ref literal) => Pat::Ctor(Ctor::Literal(literal.clone()), Vec::new()),
+            Pattern::Constructor(ref id, ref args) => Pat::Ctor(
+                Ctor::Variant(id.name.clone(), self.variants(&id.typ)),
+                args.iter().map(|arg| self.pattern(arg)).collect(),
+            ),
+            Pattern::Tuple { ref elems, .. } => Pat::Ctor(
+                Ctor::Tuple(elems.len()),
+                elems.iter().map(|elem| self.pattern(elem)).collect(),
+            ),
+            Pattern::Record {
+                ref typ,
+                ref fields,
+                ..
+            } => {
+                let typ = remove_aliases_cow(self.env, typ);
+                let names: Vec<_> = typ.row_iter().map(|field| field.name.clone()).collect();
+                let args = names
+                    .iter()
+                    .map(|name| {
+                        fields
+                            .iter()
+                            .find(|field| field.name.value.name_eq(name))
+                            .and_then(|field| field.value.as_ref())
+                            .map_or(Pat::Wild, |pattern| self.pattern(pattern))
+                    })
+                    .collect();
+                Pat::Ctor(Ctor::Record(names), args)
+            }
+        }
+    }
+
+    fn check_match(&mut self, scrutinee: &SpannedExpr<Symbol>, alts: &[Alternative<Symbol>]) {
+        let mut matrix = Vec::new();
+        for alt in alts {
+            let row = vec![self.pattern(&alt.pattern)];
+            if !is_useful(&matrix, &row) {
+                self.warnings.push(pos::spanned(
+                    alt.pattern.span,
+                    Warning::UnreachableAlternative,
+                ));
+            }
+            // A guard may reject the values matched by the pattern so later alternatives are
+            // still reachable
+            if alt.guard.is_none() {
+                matrix.push(row);
+            }
+        }
+
+        let unmatched = unmatched_patterns(&matrix);
+        if !unmatched.is_empty() {
+            self.warnings.push(pos::spanned(
+                scrutinee.span,
+                Warning::NonExhaustiveMatch(unmatched.iter().map(|pat| pat.to_string()).collect()),
+            ));
+        }
+    }
+}
+
+impl<'a, 'e> Visitor<'a> for Checker<'e> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &'a SpannedExpr<Symbol>) {
+        if let Expr::Match(ref scrutinee, ref alts) = expr.value {
+            self.check_match(scrutinee, alts);
+        }
+        walk_expr(self, expr);
+    }
+}
@@ -155,6 +155,14 @@ impl<'a> KindCheck<'a> {
         self.kindcheck_expected(typ, &type_kind)
     }
 
+    /// Kindchecks `typ` without requiring it to have kind `Type`, returning its kind (`Option` has
+    /// kind `Type -> Type`). Kinds which can't be inferred default to `Type`.
+    pub fn infer_kind(&mut self, typ: &mut AstType<Symbol>) -> Result<ArcKind> {
+        let kind = self.kindcheck(typ)?;
+        self.finalize_type(typ);
+        Ok(update_kind(&self.subs, kind, Some(&self.kind_cache.typ)))
+    }
+
     pub fn kindcheck_expected(
         &mut self,
         typ: &mut AstType<Symbol>,
@@ -241,6 +249,21 @@ impl<'a> KindCheck<'a> {
                     self.unify(field.typ.span(), &type_kind, kind)?;
                 }
 
+                // An open variant ends with either a row or another variant type which it extends
+                if let Type::ExtendRow { ref mut rest, .. } = **row {
+                    match **rest {
+                        Type::EmptyRow => (),
+                        _ => {
+                            let kind = self.kindcheck(rest)?;
+                            let kind = update_kind(&self.subs, kind, None);
+                            if *kind != Kind::Type {
+                                let row_kind = self.row_kind();
+                                self.unify(rest.span(), &row_kind, kind)?;
+                            }
+                        }
+                    }
+                }
+
                 Ok(self.type_kind())
             }
             Type::Record(ref mut row) => {
@@ -23,10 +23,12 @@ extern crate union_find;
 #[macro_use]
 extern crate gluon_base as base;
 
+pub mod exhaustiveness;
 pub mod kindcheck;
 pub mod metadata;
 pub mod rename;
 pub mod substitution;
+pub mod type_diff;
 pub mod typecheck;
 pub mod unify;
 pub mod unify_type;
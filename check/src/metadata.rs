@@ -5,7 +5,9 @@ use base::ast::{
     self, Argument, AstType, Commented, Expr, Pattern, SpannedExpr, SpannedPattern, ValueBinding,
 };
 use base::fnv::FnvMap;
-use base::metadata::{Metadata, MetadataEnv};
+use base::metadata::{Definition, Metadata, MetadataEnv};
+use base::pos::{BytePos, Span};
+use base::source::Source;
 use base::symbol::{Name, Symbol};
 use base::types::row_iter;
 
@@ -18,12 +20,42 @@ struct Environment<'b> {
 pub fn metadata(
     env: &MetadataEnv,
     expr: &SpannedExpr<Symbol>,
+) -> (Metadata, FnvMap<Symbol, Metadata>) {
+    metadata_(env, expr, None)
+}
+
+/// Queries `expr` for the metadata which it contains, recording the line in `source` where each
+/// binding of `module` is defined.
+pub fn metadata_with_definitions(
+    env: &MetadataEnv,
+    expr: &SpannedExpr<Symbol>,
+    module: &str,
+    source: &Source,
+) -> (Metadata, FnvMap<Symbol, Metadata>) {
+    metadata_(env, expr, Some((module, source)))
+}
+
+fn metadata_(
+    env: &MetadataEnv,
+    expr: &SpannedExpr<Symbol>,
+    source: Option<(&str, &Source)>,
 ) -> (Metadata, FnvMap<Symbol, Metadata>) {
     struct MetadataVisitor<'b> {
         env: Environment<'b>,
+        source: Option<(&'b str, &'b Source)>,
     }
 
     impl<'b> MetadataVisitor<'b> {
+        fn definition(&self, span: Span<BytePos>) -> Option<Definition> {
+            let (module, source) = self.source?;
+            source
+                .line_number_at_byte(span.start())
+                .map(|line| Definition {
+                    module: module.to_string(),
+                    line,
+                })
+        }
+
         fn new_binding(&mut self, metadata: Metadata, bind: &ValueBinding<Symbol>) {
             match bind.name.value {
                 Pattern::As(ref id, _) => {
@@ -52,6 +84,9 @@ pub fn metadata(
                     {
                         metadata.merge_with(type_metadata.clone());
                     }
+                    if let Some(definition) = self.definition(bind.name.span) {
+                        metadata.definition = Some(definition);
+                    }
 
                     self.stack_var(id.name.clone(), metadata);
                 }
@@ -228,10 +263,13 @@ pub fn metadata(
                     for bind in bindings {
                         let mut type_metadata =
                             Self::metadata_of_type(bind.alias.value.aliased_type());
-                        let metadata = type_metadata.map_or_else(
+                        let mut metadata = type_metadata.map_or_else(
                             || bind.metadata.clone(),
                             |m| m.merge(bind.metadata.clone()),
                         );
+                        if let Some(definition) = self.definition(bind.name.span) {
+                            metadata.definition = Some(definition);
+                        }
 
                         if metadata.has_data() {
                             // FIXME Shouldn't need to insert this metadata twice
@@ -300,6 +338,7 @@ pub fn metadata(
             env: env,
             stack: FnvMap::default(),
         },
+        source,
     };
     let metadata = visitor.metadata_expr(expr);
     (metadata, visitor.env.stack)
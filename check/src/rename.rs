@@ -154,6 +154,9 @@ pub fn rename(symbols: &mut SymbolModule, expr: &mut SpannedExpr<Symbol>) {
                     for alt in alts {
                         self.env.stack.enter_scope();
                         self.new_pattern(&mut alt.pattern);
+                        if let Some(ref mut guard) = alt.guard {
+                            self.visit_expr(guard);
+                        }
                         self.visit_expr(&mut alt.expr);
                         self.env.stack.exit_scope();
                     }
@@ -0,0 +1,140 @@
+//! Structural comparison of types, used to point out only the parts of two types which differ
+//! when they fail to unify.
+use std::fmt;
+
+use base::types::{ArcType, Type};
+
+/// A step taken from a type into one of its parts
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathElem<I> {
+    /// A field of a record or a constructor of a variant
+    Field(I),
+    /// The argument at the index of a type application or of a function type
+    Argument(usize),
+    /// The return type of a function type
+    Return,
+}
+
+impl<I: fmt::Display> fmt::Display for PathElem<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathElem::Field(ref name) => write!(f, ".{}", name),
+            PathElem::Argument(index) => write!(f, "[{}]", index),
+            PathElem::Return => write!(f, "[return]"),
+        }
+    }
+}
+
+/// A single place where the expected and the actual type differ. `path` leads from the root of
+/// both types to the differing parts, eg: `[Field(x), Argument(0)]` for
+/// `{ x : Option Int }` and `{ x : Option String }`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference<I> {
+    pub path: Vec<PathElem<I>>,
+    pub expected: ArcType<I>,
+    pub actual: ArcType<I>,
+}
+
+impl<I> fmt::Display for Difference<I>
+where
+    I: fmt::Display + AsRef<str>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for elem in &self.path {
+            write!(f, "{}", elem)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, ": ")?;
+        }
+        write!(
+            f,
+            "Expected `{}` but found `{}`",
+            self.expected, self.actual
+        )
+    }
+}
+
+/// Returns the smallest parts of `expected` and `actual` which differ from each other. Parts which
+/// can't be compared structurally (such as records with different sets of fields) are reported as
+/// a whole.
+pub fn diff<I>(expected: &ArcType<I>, actual: &ArcType<I>) -> Vec<Difference<I>>
+where
+    I: Clone + PartialEq,
+{
+    let mut differences = Vec::new();
+    diff_(&mut Vec::new(), expected, actual, &mut differences);
+    differences
+}
+
+fn diff_<I>(
+    path: &mut Vec<PathElem<I>>,
+    expected: &ArcType<I>,
+    actual: &ArcType<I>,
+    differences: &mut Vec<Difference<I>>,
+) where
+    I: Clone + PartialEq,
+{
+    if expected == actual {
+        return;
+    }
+    match (&**expected, &**actual) {
+        (&Type::App(ref l_f, ref l_args), &Type::App(ref r_f, ref r_args))
+            if l_f == r_f && l_args.len() == r_args.len() =>
+        {
+            for (i, (l, r)) in l_args.iter().zip(r_args).enumerate() {
+                diff_at(path, PathElem::Argument(i), l, r, differences);
+            }
+        }
+        (
+            &Type::Function(l_arg_type, ref l_arg, ref l_ret),
+            &Type::Function(r_arg_type, ref r_arg, ref r_ret),
+        ) if l_arg_type == r_arg_type =>
+        {
+            diff_at(path, PathElem::Argument(0), l_arg, r_arg, differences);
+            diff_at(path, PathElem::Return, l_ret, r_ret, differences);
+        }
+        (&Type::Record(_), &Type::Record(_)) | (&Type::Variant(_), &Type::Variant(_))
+            if same_fields(expected, actual) =>
+        {
+            for (l, r) in expected.row_iter().zip(actual.row_iter()) {
+                diff_at(
+                    path,
+                    PathElem::Field(l.name.clone()),
+                    &l.typ,
+                    &r.typ,
+                    differences,
+                );
+            }
+        }
+        _ => differences.push(Difference {
+            path: path.clone(),
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+    }
+}
+
+fn diff_at<I>(
+    path: &mut Vec<PathElem<I>>,
+    elem: PathElem<I>,
+    expected: &ArcType<I>,
+    actual: &ArcType<I>,
+    differences: &mut Vec<Difference<I>>,
+) where
+    I: Clone + PartialEq,
+{
+    path.push(elem);
+    diff_(path, expected, actual, differences);
+    path.pop();
+}
+
+fn same_fields<I>(expected: &ArcType<I>, actual: &ArcType<I>) -> bool
+where
+    I: PartialEq,
+{
+    expected.row_iter().len() == actual.row_iter().len()
+        && expected
+            .row_iter()
+            .zip(actual.row_iter())
+            .all(|(l, r)| l.name == r.name)
+}
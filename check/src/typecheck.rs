@@ -30,6 +30,7 @@ use implicits;
 use kindcheck::{self, Error as KindCheckError, KindCheck, KindError};
 use substitution::{self, Substitution};
 use unify::{self, Error as UnifyError};
+use type_diff::{self, Difference, PathElem};
 use unify_type::{self, new_skolem_scope, Error as UnifyTypeError};
 
 /// Type representing a single error when checking a type
@@ -61,6 +62,8 @@ pub enum TypeError<I> {
     },
     /// Found a case expression without any alternatives
     EmptyCase,
+    /// Two constructors of an open variant are tagged by the same hash of their names
+    ConstructorTagCollision(I, I),
     Message(String),
     UnableToResolveImplicit(implicits::Error<I>),
 }
@@ -79,7 +82,22 @@ impl<I> From<implicits::Error<I>> for TypeError<I> {
     }
 }
 
-impl<I: fmt::Display + AsRef<str> + Clone> fmt::Display for TypeError<I> {
+impl<I> TypeError<I>
+where
+    I: Clone + PartialEq,
+{
+    /// Returns the parts of the expected and actual types which differ if this is a unification
+    /// error. Lets tools such as the language server point out only the relevant parts of the
+    /// types.
+    pub fn type_differences(&self) -> Vec<Difference<I>> {
+        match *self {
+            TypeError::Unification(ref expected, ref actual, _) => type_diff::diff(expected, actual),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<I: fmt::Display + AsRef<str> + Clone + PartialEq> fmt::Display for TypeError<I> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::TypeError::*;
         use pretty::{Arena, DocAllocator};
@@ -111,9 +129,29 @@ impl<I: fmt::Display + AsRef<str> + Clone> fmt::Display for TypeError<I> {
                         _ => None,
                     })
                     .collect::<Vec<_>>();
+                // Without any other errors to focus on, only the fields which lead to the
+                // differing parts of the types are shown
+                let differences = self.type_differences();
+                let differing_fields = differences
+                    .iter()
+                    .flat_map(|difference| {
+                        difference.path.iter().filter_map(|elem| match *elem {
+                            PathElem::Field(ref name) => Some(name.clone()),
+                            _ => None,
+                        })
+                    })
+                    .collect::<Vec<_>>();
                 let filter = move |field: &I| {
                     if filters.is_empty() {
-                        Filter::Retain
+                        if differing_fields.is_empty()
+                            || differing_fields
+                                .iter()
+                                .any(|name| name.as_ref() == field.as_ref())
+                        {
+                            Filter::Retain
+                        } else {
+                            Filter::Drop
+                        }
                     } else {
                         filters
                             .iter()
@@ -142,11 +180,30 @@ impl<I: fmt::Display + AsRef<str> + Clone> fmt::Display for TypeError<I> {
                         TypeFormatter::new(actual).filter(&filter).pretty(&arena)
                     ].nest(4).group()
                 ].group();
+                let nested_differences = differences
+                    .iter()
+                    .filter(|difference| !difference.path.is_empty())
+                    .collect::<Vec<_>>();
+                let differences_doc = if nested_differences.is_empty() {
+                    arena.nil()
+                } else {
+                    chain![&arena;
+                        "The types differ at:",
+                        arena.concat(nested_differences.iter().map(|difference| {
+                            chain![&arena;
+                                arena.newline(),
+                                difference.to_string()
+                            ]
+                        })).nest(4),
+                        arena.newline()
+                    ]
+                };
                 let doc = chain![&arena;
                     "Expected the following types to be equal",
                     arena.newline(),
                     types,
                     arena.newline(),
+                    differences_doc,
                     arena.as_string(errors.len()),
                     " errors were found during unification:"
                 ];
@@ -191,13 +248,19 @@ impl<I: fmt::Display + AsRef<str> + Clone> fmt::Display for TypeError<I> {
                 Ok(())
             }
             EmptyCase => write!(f, "`case` expression with no alternatives"),
+            ConstructorTagCollision(ref first, ref second) => write!(
+                f,
+                "The constructors `{}` and `{}` of an open variant hash to the same tag, \
+                 rename one of them",
+                first, second
+            ),
             Message(ref msg) => write!(f, "{}", msg),
             UnableToResolveImplicit(ref err) => write!(f, "{}", err),
         }
     }
 }
 
-impl<I: fmt::Display + AsRef<str> + Clone> AsDiagnostic for TypeError<I> {
+impl<I: fmt::Display + AsRef<str> + Clone + PartialEq> AsDiagnostic for TypeError<I> {
     fn as_diagnostic(&self) -> Diagnostic {
         use self::TypeError::*;
         match *self {
@@ -549,6 +612,7 @@ impl<'a> Typecheck<'a> {
                 | DuplicateField(_)
                 | UndefinedRecord { .. }
                 | EmptyCase
+                | ConstructorTagCollision(..)
                 | KindError(_)
                 | Message(_) => (),
                 NotAFunction(ref mut typ)
@@ -832,6 +896,11 @@ impl<'a> Typecheck<'a> {
                 for alt in alts.iter_mut() {
                     self.enter_scope();
                     self.typecheck_pattern(&mut alt.pattern, typ.clone());
+                    if let Some(ref mut guard) = alt.guard {
+                        let bool_type = self.bool();
+                        let guard_type = self.typecheck(guard, &bool_type);
+                        self.unify_span(expr_check_span(guard), &bool_type, guard_type);
+                    }
                     let mut alt_type = self.typecheck_opt(&mut alt.expr, expected_type);
                     alt_type = self.instantiate_generics(&alt_type);
                     self.exit_scope();
@@ -927,6 +996,7 @@ impl<'a> Typecheck<'a> {
                 ref mut types,
                 exprs: ref mut fields,
                 ref mut base,
+                ref removed,
             } => {
                 let expected_type = expected_type.map(|expected_type| {
                     let typ = resolve::remove_aliases_cow(&self.environment, expected_type);
@@ -1026,6 +1096,14 @@ impl<'a> Typecheck<'a> {
                     }
                 }
 
+                // Removed fields are marked as defined so that they are not copied from `base`
+                let removed: Vec<_> = removed
+                    .iter()
+                    .filter(|name| {
+                        self.error_on_duplicated_field(&mut duplicated_fields, (*name).clone())
+                    })
+                    .collect();
+
                 if let Some(ref mut base) = *base {
                     let base_type = self.infer_expr(base);
                     let base_type = self.remove_aliases(base_type);
@@ -1033,6 +1111,22 @@ impl<'a> Typecheck<'a> {
                     let record_type = Type::poly_record(vec![], vec![], self.subs.new_var());
                     let base_type = self.unify_span(base.span, &record_type, base_type);
 
+                    for name in removed {
+                        let is_removed =
+                            |field: &Symbol| field.declared_name() == name.value.declared_name();
+                        let exists = base_type.row_iter().any(|f| is_removed(&f.name))
+                            || base_type.type_field_iter().any(|f| is_removed(&f.name));
+                        if !exists {
+                            self.errors.push(Spanned {
+                                span: name.span,
+                                value: TypeError::UndefinedField(
+                                    base_type.clone(),
+                                    name.value.clone(),
+                                ).into(),
+                            });
+                        }
+                    }
+
                     new_types.extend(
                         base_type
                             .type_field_iter()
@@ -1860,6 +1954,7 @@ impl<'a> Typecheck<'a> {
             if let Some(typ) = replacement {
                 *alias.unresolved_type_mut() = Type::forall(alias.params().to_owned(), typ);
             }
+            self.check_open_variant_tags(bind.alias.span, alias.unresolved_type());
             resolved_aliases.push(alias);
         }
 
@@ -1885,6 +1980,33 @@ impl<'a> Typecheck<'a> {
         }
     }
 
+    /// Reports an error if two constructors of the open variant `typ` are tagged by the same hash
+    /// as they could not be told apart at runtime
+    fn check_open_variant_tags(&mut self, span: Span<BytePos>, typ: &ArcType) {
+        let row = match **typ.remove_forall() {
+            Type::Variant(ref row) => row,
+            _ => return,
+        };
+        let mut iter = row.row_iter();
+        let constructors = iter.by_ref().collect::<Vec<_>>();
+        if **iter.current_type() == Type::EmptyRow {
+            return;
+        }
+
+        let mut tags = FnvMap::default();
+        for field in constructors {
+            let name = field.name.declared_name();
+            if let Some(other) = tags.insert(types::open_variant_tag(name), &field.name) {
+                if other.declared_name() != name {
+                    self.error(
+                        span,
+                        TypeError::ConstructorTagCollision(other.clone(), field.name.clone()),
+                    );
+                }
+            }
+        }
+    }
+
     fn kindcheck(&mut self, typ: &mut AstType<Symbol>) {
         let result = {
             let mut check =
@@ -2126,24 +2248,40 @@ impl<'a> Typecheck<'a> {
                     }),
                 );
                 let row = replacement.as_ref().unwrap_or(row);
+
+                // An open variant may extend another variant (`| B | ..Base r`) in which case the
+                // constructors of the extended variant are spliced into this variant
+                let mut rest = {
+                    let mut iter = row.row_iter();
+                    for _ in iter.by_ref() {}
+                    iter.current_type().clone()
+                };
+                let extended = match **resolve::remove_aliases_cow(&self.environment, &rest) {
+                    Type::Variant(ref extended) => Some(extended.clone()),
+                    _ => None,
+                };
+                let is_extended = extended.is_some();
+                if let Some(extended) = extended {
+                    rest = extended;
+                }
+
                 let iter = || {
                     row.row_iter()
                         .map(|var| self.original_symbols.get(&var.name))
                 };
-                if iter().any(|opt| opt.is_some()) {
+                if is_extended || iter().any(|opt| opt.is_some()) {
                     // If any of the variants requires a symbol replacement
                     // we create a new type
-                    Some(
-                        self.type_cache.variant(
-                            iter()
-                                .zip(row.row_iter())
-                                .map(|(new, old)| match new {
-                                    Some(new) => Field::new(new.clone(), old.typ.clone()),
-                                    None => old.clone(),
-                                })
-                                .collect(),
-                        ),
-                    )
+                    Some(Type::poly_variant(
+                        iter()
+                            .zip(row.row_iter())
+                            .map(|(new, old)| match new {
+                                Some(new) => Field::new(new.clone(), old.typ.clone()),
+                                None => old.clone(),
+                            })
+                            .collect(),
+                        rest,
+                    ))
                 } else {
                     replacement
                         .clone()
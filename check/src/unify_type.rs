@@ -363,6 +363,15 @@ where
             ).map_err(|_| UnifyError::TypeMismatch(expected.clone(), actual.clone()))
         }
         (&Type::App(ref l, ref l_args), &Type::App(ref r, ref r_args)) => {
+            // Aliases of two different open variants (such as `Base r` and an `Ext r` which
+            // extends `Base r`) can only be unified through the variants they alias
+            if l != r {
+                let l_variant = open_variant_alias(unifier, expected);
+                let r_variant = open_variant_alias(unifier, actual);
+                if let (Some(l_variant), Some(r_variant)) = (l_variant, r_variant) {
+                    return do_zip_match(unifier, &l_variant, &r_variant).map(|_| None);
+                }
+            }
             unify_app(unifier, l, l_args, r, r_args)
                 .map_err(|_| UnifyError::TypeMismatch(expected.clone(), actual.clone()))
         }
@@ -378,11 +387,11 @@ where
                     rest: ref r_rest,
                     ..
                 },
-            ) => if l_row.len() == r_row.len()
+            ) if l_row.len() == r_row.len()
                 && l_row
                     .iter()
                     .zip(r_row)
-                    .all(|(l, r)| l.name.name_eq(&r.name)) && l_rest == r_rest
+                    .all(|(l, r)| l.name.name_eq(&r.name)) && l_rest == r_rest =>
             {
                 let iter = l_row.iter().zip(r_row);
                 let new_fields = merge::merge_tuple_iter(iter, |l, r| {
@@ -391,10 +400,11 @@ where
                         .map(|typ| Field::new(l.name.clone(), typ))
                 });
                 Ok(new_fields.map(|fields| Type::poly_variant(fields, l_rest.clone())))
-            } else {
-                Err(UnifyError::TypeMismatch(expected.clone(), actual.clone()))
+            }
+            _ => match unify_variant_rows(unifier, l_row, r_row) {
+                Ok(()) => Ok(None),
+                Err(()) => Err(UnifyError::TypeMismatch(expected.clone(), actual.clone())),
             },
-            _ => Err(UnifyError::TypeMismatch(expected.clone(), actual.clone())),
         },
         (&Type::Record(ref l_row), &Type::Record(ref r_row)) => {
             // Store the current records so that they can be used when displaying field errors
@@ -826,6 +836,92 @@ where
     Ok(Some(Type::extend_row(types, fields, rest)))
 }
 
+/// Returns the open variant which `typ` is an alias of
+fn open_variant_alias<'a, U>(unifier: &UnifierState<'a, U>, typ: &ArcType) -> Option<ArcType> {
+    typ.alias_ident()?;
+    // Types defined in Rust are aliases of themselves so self recursive aliases must be detected
+    let typ = resolve::AliasRemover::new()
+        .remove_aliases(unifier.state.env, typ.clone())
+        .ok()?;
+    let is_open = match *typ {
+        Type::Variant(ref row) => {
+            let mut iter = row.row_iter();
+            for _ in iter.by_ref() {}
+            **iter.current_type() != Type::EmptyRow
+        }
+        _ => false,
+    };
+    if is_open {
+        Some(typ)
+    } else {
+        None
+    }
+}
+
+/// Collects the constructors of the variant `row`, following any row variables which have
+/// already been unified. Returns the constructors along with the end of the row.
+fn variant_fields<'t>(
+    subs: &'t Substitution<ArcType>,
+    mut row: &'t ArcType,
+) -> (Vec<&'t Field<Symbol, ArcType>>, &'t ArcType) {
+    let mut fields = Vec::new();
+    loop {
+        let mut iter = row.row_iter();
+        fields.extend(iter.by_ref());
+        let rest = subs.real(iter.current_type());
+        match **rest {
+            Type::ExtendRow { .. } => row = rest,
+            _ => return (fields, rest),
+        }
+    }
+}
+
+/// Unifies the rows of two variants which do not have the same constructors.
+///
+/// Closed variants (which tag their constructors by position) must have exactly the same
+/// constructors so only open variants (`| A Int | ..r`) are unified here, by unifying the
+/// constructors missing from one variant with the row variable of the other.
+fn unify_variant_rows<'a, U>(
+    unifier: &mut UnifierState<'a, U>,
+    l: &ArcType,
+    r: &ArcType,
+) -> Result<(), ()>
+where
+    UnifierState<'a, U>: Unifier<State<'a>, ArcType>,
+{
+    let subs = unifier.state.subs;
+    let (l_fields, l_rest) = variant_fields(subs, l);
+    let (r_fields, r_rest) = variant_fields(subs, r);
+
+    if **l_rest == Type::EmptyRow || **r_rest == Type::EmptyRow {
+        return Err(());
+    }
+
+    let (missing_from_left, both, missing_from_right) =
+        gather_fields(l_fields.iter().cloned(), r_fields.iter().cloned());
+
+    for (l, r) in both {
+        // The constructors return the variant type they were declared in, which may differ
+        // between two variants sharing a constructor, so only the arguments are unified
+        let l_args: Vec<_> = types::arg_iter(&l.typ).collect();
+        let r_args: Vec<_> = types::arg_iter(&r.typ).collect();
+        if l_args.len() != r_args.len() {
+            return Err(());
+        }
+        for (l_arg, r_arg) in l_args.into_iter().zip(r_args) {
+            unifier.try_match(l_arg, r_arg);
+        }
+    }
+
+    let rest = subs.new_var();
+    unifier.try_match(
+        l_rest,
+        &Type::extend_row(vec![], missing_from_left, rest.clone()),
+    );
+    unifier.try_match(&Type::extend_row(vec![], missing_from_right, rest), r_rest);
+    Ok(())
+}
+
 /// Attempt to unify two alias types.
 /// To find a possible successful unification we walk through the alias expansions of `l` in an
 /// attempt to find that `l` expands to the alias `r_id`
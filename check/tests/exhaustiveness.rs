@@ -0,0 +1,141 @@
+#[macro_use]
+extern crate collect_mac;
+extern crate env_logger;
+#[macro_use]
+extern crate pretty_assertions;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_parser as parser;
+
+use check::exhaustiveness::{check_matches, Warning};
+
+use support::MockEnv;
+
+#[macro_use]
+mod support;
+
+fn warnings(text: &str) -> Vec<Warning> {
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    check_matches(&MockEnv::new(), &expr)
+        .into_iter()
+        .map(|warning| warning.value)
+        .collect()
+}
+
+fn non_exhaustive(missing: &[&str]) -> Warning {
+    Warning::NonExhaustiveMatch(missing.iter().map(|s| s.to_string()).collect())
+}
+
+#[test]
+fn exhaustive_match() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some 1 with
+| Some x -> x
+| None -> 0
+"#;
+    assert_eq!(warnings(text), vec![]);
+}
+
+#[test]
+fn missing_constructor() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some 1 with
+| Some x -> x
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["None"])]);
+}
+
+#[test]
+fn missing_nested_constructor() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some (Some 1) with
+| Some (Some x) -> x
+| None -> 0
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["Some None"])]);
+}
+
+#[test]
+fn missing_record_field() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match { x = Some 1, y = 2 } with
+| { x = Some a } -> a
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["{ x = None }"])]);
+}
+
+#[test]
+fn literals_need_a_wildcard() {
+    let _ = env_logger::try_init();
+    let text = r#"
+match 1 with
+| 1 -> 2
+| 2 -> 3
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["_"])]);
+}
+
+#[test]
+fn open_variants_need_a_wildcard() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Base r = | A Int | ..r
+let f : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+let g : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+    | _ -> 0
+f
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["_"])]);
+}
+
+#[test]
+fn guarded_alternative_is_not_exhaustive() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some 1 with
+| Some x if True -> x
+| None -> 0
+"#;
+    assert_eq!(warnings(text), vec![non_exhaustive(&["Some _"])]);
+}
+
+#[test]
+fn unreachable_alternative() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some 1 with
+| Some x -> x
+| None -> 0
+| _ -> 1
+"#;
+    assert_eq!(warnings(text), vec![Warning::UnreachableAlternative]);
+}
+
+#[test]
+fn unreachable_nested_alternative() {
+    let _ = env_logger::try_init();
+    let text = r#"
+type Option a = | None | Some a
+match Some (Some 1) with
+| Some _ -> 1
+| Some (Some x) -> x
+| None -> 0
+"#;
+    assert_eq!(warnings(text), vec![Warning::UnreachableAlternative]);
+}
@@ -11,6 +11,7 @@ extern crate gluon_parser as parser;
 use base::symbol::Symbol;
 use base::types::{ArcType, Type};
 
+use check::type_diff::PathElem;
 use check::typecheck::TypeError;
 
 #[macro_use]
@@ -165,6 +166,19 @@ else
     assert_unify_err!(result, TypeMismatch(..));
 }
 
+#[test]
+fn match_guard_must_be_bool() {
+    let _ = env_logger::try_init();
+    let text = r#"
+match 1 with
+| x if x -> x
+| _ -> 0
+"#;
+    let result = support::typecheck(text);
+
+    assert_unify_err!(result, TypeMismatch(..));
+}
+
 #[test]
 fn arguments_need_to_be_instantiated_before_any_access() {
     let _ = env_logger::try_init();
@@ -287,6 +301,28 @@ let { Test, Test, x = y, x } = { Test, x = 1 }
     assert_err!(result, DuplicateField(..), DuplicateField(..));
 }
 
+#[test]
+fn remove_undefined_field_from_record() {
+    let _ = ::env_logger::try_init();
+    let text = r#"
+let record = { x = 1 }
+{ .. record, -y }
+"#;
+    let result = support::typecheck(text);
+    assert_err!(result, UndefinedField(..));
+}
+
+#[test]
+fn define_and_remove_same_field() {
+    let _ = ::env_logger::try_init();
+    let text = r#"
+let record = { x = 1 }
+{ x = "", .. record, -x }
+"#;
+    let result = support::typecheck(text);
+    assert_err!(result, DuplicateField(..));
+}
+
 #[test]
 fn type_alias_with_explicit_type_kind() {
     let _ = ::env_logger::try_init();
@@ -482,6 +518,36 @@ Found:
     );
 }
 
+#[test]
+fn unification_error_shows_only_differing_fields() {
+    let _ = ::env_logger::try_init();
+    let text = r#"
+let f x y : a -> a -> a = x
+f { a = 1, b = "", c = 1.0 } { a = 1, b = 2, c = 1.0 }
+"#;
+    let err = support::typecheck(text).unwrap_err();
+
+    let message = err.to_string();
+    assert!(
+        message.contains("The types differ at:\n    .b: Expected `String` but found `Int`"),
+        "{}",
+        message
+    );
+    assert!(!message.contains("c : Float"), "{}", message);
+
+    let errors = err.errors();
+    let error = (&errors).into_iter().next().expect("Type error");
+    let differences = error.value.error.type_differences();
+    assert_eq!(differences.len(), 1);
+    assert_eq!(differences[0].path.len(), 1);
+    match differences[0].path[0] {
+        PathElem::Field(ref name) => assert_eq!(name.declared_name(), "b"),
+        ref elem => panic!("Unexpected path element {:?}", elem),
+    }
+    assert_eq!(differences[0].expected, Type::string());
+    assert_eq!(differences[0].actual, Type::int());
+}
+
 #[test]
 fn undefined_field_after_overload() {
     let _ = ::env_logger::try_init();
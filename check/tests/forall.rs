@@ -416,6 +416,28 @@ let record = { x = 1 }
     );
 }
 
+#[test]
+fn record_expr_base_remove_field() {
+    let _ = ::env_logger::try_init();
+    let text = r#"
+let vec3 = { x = 1, y = 2, z = 3 }
+{ w = "", .. vec3, -y }
+"#;
+    let result = support::typecheck(text);
+
+    assert_eq!(
+        result,
+        Ok(Type::record(
+            vec![],
+            vec![
+                Field::new(intern("w"), typ("String")),
+                Field::new(intern("x"), typ("Int")),
+                Field::new(intern("z"), typ("Int")),
+            ]
+        ))
+    );
+}
+
 #[test]
 fn undefined_type_variable_in_record() {
     let _ = ::env_logger::try_init();
@@ -715,6 +737,32 @@ let test x : (forall a . a -> a) -> () = ()
     assert!(result.is_ok(), "{}", result.unwrap_err());
 }
 
+#[test]
+fn universally_quantified_argument_used_at_different_types() {
+    let _ = ::env_logger::try_init();
+
+    let text = r#"
+let pair id : (forall a . a -> a) -> (Int, String) = (id 1, id "")
+pair (\x -> x)
+"#;
+    let result = support::typecheck(text);
+
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+}
+
+#[test]
+fn monomorphic_function_is_not_a_universally_quantified_argument() {
+    let _ = ::env_logger::try_init();
+
+    let text = r#"
+let pair id : (forall a . a -> a) -> (Int, String) = (id 1, id "")
+pair (\x -> x #Int+ 1)
+"#;
+    let result = support::typecheck(text);
+
+    assert!(result.is_err());
+}
+
 #[test]
 fn alternative_dont_unify_skolem() {
     let _ = ::env_logger::try_init();
@@ -260,3 +260,72 @@ fn row_kinds_error() {
     let result = kindcheck.kindcheck_expected(&mut typ, &Kind::row());
     assert!(result.is_err());
 }
+
+#[test]
+fn function_accepting_open_variant() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Base r = | A Int | ..r
+type Ext r = | B String | ..Base r
+let get_a : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+    | _ -> 0
+get_a (B "")
+"#;
+    let result = support::typecheck(text);
+    assert_eq!(result, Ok(typ("Int")));
+}
+
+#[test]
+fn function_extending_open_variant() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Base r = | A Int | ..r
+type Ext r = | B String | ..Base r
+let get_a : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+    | _ -> 0
+let get_ext : Ext r -> Int = \x ->
+    match x with
+    | B _ -> 1
+    | _ -> get_a x
+get_ext (A 2)
+"#;
+    let result = support::typecheck(text);
+    assert_eq!(result, Ok(typ("Int")));
+}
+
+#[test]
+fn open_variant_is_not_closed_variant() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Base r = | A Int | ..r
+let get_a : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+    | _ -> 0
+type Closed = | A Int
+get_a (A 2)
+"#;
+    let result = support::typecheck(text);
+    assert!(result.is_err());
+}
+
+#[test]
+fn open_variant_constructors_with_colliding_tags() {
+    let _ = env_logger::try_init();
+
+    // `C314991` and `C522880` hash to the same tag
+    let text = r#"
+type Base r = | C314991 Int | ..r
+type Ext r = | C522880 | ..Base r
+1
+"#;
+    let result = support::typecheck(text);
+    assert_err!(result, ConstructorTagCollision(..));
+}
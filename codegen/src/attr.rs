@@ -0,0 +1,212 @@
+use syn::{self, Attribute, Lit, Meta, MetaList, MetaNameValue, NestedMeta};
+
+/// Options specified with `#[gluon(...)]` on a struct or an enum.
+#[derive(Default)]
+pub struct Container {
+    /// The fully qualified name of the gluon type the type is mapped to
+    pub vm_type: Option<String>,
+    /// Renames all fields of a struct according to a naming convention
+    pub rename_all: Option<RenameRule>,
+    /// Userdata is deep cloned by calling `Clone::clone`
+    pub clone: bool,
+}
+
+impl Container {
+    pub fn from_ast(attrs: &[Attribute]) -> Container {
+        let mut container = Container::default();
+
+        for meta in gluon_meta_items(attrs) {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ref ident, ref lit, ..
+                })) if ident == "vm_type" =>
+                {
+                    container.vm_type = Some(get_lit_str(lit, "The gluon type name"));
+                }
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ref ident, ref lit, ..
+                })) if ident == "rename_all" =>
+                {
+                    let rule = get_lit_str(lit, "The naming convention");
+                    container.rename_all = Some(RenameRule::from_str(&rule));
+                }
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "clone" => {
+                    container.clone = true;
+                }
+                // ignore other values as they may be used by other macros
+                _ => (),
+            }
+        }
+
+        container
+    }
+
+    /// Returns the name `field` has in gluon
+    pub fn field_name(&self, field: &syn::Field) -> String {
+        let ident = field
+            .ident
+            .as_ref()
+            .expect("Struct fields always have names");
+
+        match (Field::from_ast(field).rename, &self.rename_all) {
+            (Some(name), _) => name,
+            (None, Some(rule)) => rule.apply(&ident.to_string()),
+            (None, None) => ident.to_string(),
+        }
+    }
+}
+
+/// Naming conventions which can be used with `#[gluon(rename_all = "...")]`. Rust fields are
+/// assumed to be in `snake_case`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenameRule {
+    LowerCase,
+    UpperCase,
+    PascalCase,
+    CamelCase,
+    SnakeCase,
+    ScreamingSnakeCase,
+}
+
+impl RenameRule {
+    fn from_str(rule: &str) -> RenameRule {
+        match rule {
+            "lowercase" => RenameRule::LowerCase,
+            "UPPERCASE" => RenameRule::UpperCase,
+            "PascalCase" => RenameRule::PascalCase,
+            "camelCase" => RenameRule::CamelCase,
+            "snake_case" => RenameRule::SnakeCase,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnakeCase,
+            _ => panic!(
+                "Unknown naming convention `{}`. Expected one of `lowercase`, `UPPERCASE`, \
+                 `PascalCase`, `camelCase`, `snake_case` or `SCREAMING_SNAKE_CASE`",
+                rule
+            ),
+        }
+    }
+
+    pub fn apply(self, field: &str) -> String {
+        match self {
+            RenameRule::LowerCase | RenameRule::SnakeCase => field.to_owned(),
+            RenameRule::UpperCase | RenameRule::ScreamingSnakeCase => field.to_ascii_uppercase(),
+            RenameRule::PascalCase => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(c);
+                    }
+                }
+                pascal
+            }
+            RenameRule::CamelCase => {
+                let pascal = RenameRule::PascalCase.apply(field);
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                    None => pascal,
+                }
+            }
+        }
+    }
+}
+
+/// Options specified with `#[gluon(...)]` on a field.
+#[derive(Default)]
+pub struct Field {
+    /// The field contains values that are managed by the garbage collector and needs to be
+    /// traversed
+    pub traverse: bool,
+    /// The name of the field in gluon, if it differs from the name in rust
+    pub rename: Option<String>,
+    /// The field does not exist in gluon and is always initialized with `Default::default()`
+    pub skip: bool,
+    /// The field is initialized with `Default::default()` if it is missing in gluon
+    pub default: bool,
+}
+
+impl Field {
+    pub fn from_ast(field: &syn::Field) -> Field {
+        let mut attr = Field::default();
+
+        for meta in gluon_meta_items(&field.attrs) {
+            match meta {
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "traverse" => {
+                    attr.traverse = true;
+                }
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "skip" => {
+                    attr.skip = true;
+                }
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "default" => {
+                    attr.default = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ref ident, ref lit, ..
+                })) if ident == "rename" =>
+                {
+                    attr.rename = Some(get_lit_str(lit, "The field name"));
+                }
+                _ => (),
+            }
+        }
+
+        attr
+    }
+}
+
+/// Options specified with `#[gluon(...)]` on an enum variant.
+#[derive(Default)]
+pub struct Variant {
+    /// The name of the constructor in gluon, if it differs from the name in rust
+    pub rename: Option<String>,
+}
+
+impl Variant {
+    pub fn from_ast(variant: &syn::Variant) -> Variant {
+        let mut attr = Variant::default();
+
+        for meta in gluon_meta_items(&variant.attrs) {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    ref ident, ref lit, ..
+                })) if ident == "rename" =>
+                {
+                    attr.rename = Some(get_lit_str(lit, "The constructor name"));
+                }
+                _ => (),
+            }
+        }
+
+        attr
+    }
+}
+
+/// Returns all the items that are namespaced under the `gluon` attribute.
+fn gluon_meta_items(attrs: &[Attribute]) -> Vec<NestedMeta> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr.interpret_meta() {
+            Some(Meta::List(MetaList { ident, nested, .. })) => {
+                if ident == "gluon" {
+                    Some(nested.into_iter().collect::<Vec<_>>())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .flat_map(|nested| nested)
+        .collect()
+}
+
+fn get_lit_str(lit: &Lit, what: &str) -> String {
+    match lit {
+        Lit::Str(s) => s.value(),
+        _ => panic!("{} must be a string literal", what),
+    }
+}
@@ -1,3 +1,4 @@
+use attr::{self, Container};
 use proc_macro2::TokenStream;
 use shared::{map_lifetimes, map_type_params, split_for_impl};
 use syn::{
@@ -8,23 +9,31 @@ use syn::{
 pub fn derive(input: TokenStream) -> TokenStream {
     let DeriveInput {
         ident,
+        attrs,
         data,
         generics,
         ..
     } = syn::parse2(input).expect("Input is checked by rustc");
 
+    let container = Container::from_ast(&attrs);
+
     let tokens = match data {
-        Data::Struct(ast) => derive_struct(ast, ident, generics),
-        Data::Enum(ast) => derive_enum(ast, ident, generics),
+        Data::Struct(ast) => derive_struct(&container, ast, ident, generics),
+        Data::Enum(ast) => derive_enum(&container, ast, ident, generics),
         Data::Union(_) => panic!("Unions are not supported"),
     };
 
     tokens.into()
 }
 
-fn derive_struct(ast: DataStruct, ident: Ident, generics: Generics) -> TokenStream {
+fn derive_struct(
+    container: &Container,
+    ast: DataStruct,
+    ident: Ident,
+    generics: Generics,
+) -> TokenStream {
     let cons = match ast.fields {
-        Fields::Named(FieldsNamed { named, .. }) => gen_struct_cons(&ident, named),
+        Fields::Named(FieldsNamed { named, .. }) => gen_struct_cons(container, &ident, named),
         Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => gen_tuple_struct_cons(&ident, unnamed),
         Fields::Unit => quote! { #ident },
     };
@@ -32,7 +41,16 @@ fn derive_struct(ast: DataStruct, ident: Ident, generics: Generics) -> TokenStre
     gen_impl(ident, generics, cons)
 }
 
-fn gen_struct_cons<I>(ident: &Ident, fields: I) -> TokenStream
+/// Generates the expression that is used when a field cannot be found in the gluon value.
+fn gen_missing_field(field_attr: &attr::Field, error: TokenStream) -> TokenStream {
+    if field_attr.default {
+        quote! { ::std::default::Default::default() }
+    } else {
+        quote! { return Err(#error) }
+    }
+}
+
+fn gen_struct_cons<I>(container: &Container, ident: &Ident, fields: I) -> TokenStream
 where
     I: IntoIterator<Item = Field>,
 {
@@ -44,13 +62,26 @@ where
             .ident
             .as_ref()
             .expect("Struct fields always have names");
-        let quoted_ident = format!("{}", quote! { #ident });
+        let field_attr = attr::Field::from_ast(&field);
+
+        if field_attr.skip {
+            return quote! {
+                #ident: ::std::default::Default::default()
+            };
+        }
+
+        let quoted_ident = container.field_name(&field);
+        let missing = gen_missing_field(
+            &field_attr,
+            quote! {
+                ::gluon::vm::api::MarshalError::MissingField(#quoted_ident.to_string())
+            },
+        );
 
         quote! {
-            #ident: if let Some(val) = data.lookup_field(vm, #quoted_ident) {
-                <#field_ty as ::gluon::vm::api::Getable<'__vm>>::from_value(vm, val)
-            } else {
-                panic!("Cannot find the field '{}'. Do the type definitions match?", #quoted_ident);
+            #ident: match data.lookup_field(vm, #quoted_ident) {
+                Some(val) => <#field_ty as ::gluon::vm::api::Getable<'__vm>>::try_from_value(vm, val)?,
+                None => #missing,
             }
         }
     });
@@ -67,16 +98,30 @@ where
     I: IntoIterator<Item = Field>,
 {
     // do the lookup using the tag, because tuple structs don't have field names
-    let field_initializers = fields.into_iter().enumerate().map(|(tag, field)| {
+    // skipped fields do not exist in gluon so they do not take up a tag
+    let mut tag = 0usize;
+    let field_initializers = fields.into_iter().map(|field| {
         let field_ty = &field.ty;
+        let field_attr = attr::Field::from_ast(&field);
 
-        quote! {
-            if let Some(val) = data.get_variant(#tag) {
-                <#field_ty as ::gluon::vm::api::Getable<'__vm>>::from_value(vm, val)
-            } else {
-                panic!("Cannot find the field with tag '{}'. Do the type definitions match?", #tag);
-            }
+        if field_attr.skip {
+            return quote! { ::std::default::Default::default() };
         }
+
+        let missing = gen_missing_field(
+            &field_attr,
+            quote! {
+                ::gluon::vm::api::MarshalError::MissingIndex(#tag)
+            },
+        );
+        let cons = quote! {
+            match data.get_variant(#tag) {
+                Some(val) => <#field_ty as ::gluon::vm::api::Getable<'__vm>>::try_from_value(vm, val)?,
+                None => #missing,
+            }
+        };
+        tag += 1;
+        cons
     });
 
     quote! {
@@ -86,25 +131,53 @@ where
     }
 }
 
-fn derive_enum(ast: DataEnum, ident: Ident, generics: Generics) -> TokenStream {
+fn derive_enum(
+    container: &Container,
+    ast: DataEnum,
+    ident: Ident,
+    generics: Generics,
+) -> TokenStream {
     let cons;
-    {
-        let variants = ast.variants
-            .iter()
-            .enumerate()
-            .map(|(tag, variant)| gen_variant_match(&ident, tag, variant));
-
-        // data contains the the data for each field of a variant; the variant of the passed value
-        // is defined by the tag(), which is defined by order of the variants (the first variant is 0)
-        cons = quote! {
-            match data.tag() as usize {
-                #(#variants,)*
-                tag => panic!("Unexpected tag: '{}'. Do the type definitions match?", tag)
-            }
-        };
+    match container.vm_type {
+        // if we know the gluon type we can look up the name of the constructor and match on that
+        // which keeps working even if the variants are declared in a different order
+        Some(ref vm_type) => {
+            let variants = ast.variants.iter().map(|variant| {
+                let name = attr::Variant::from_ast(variant)
+                    .rename
+                    .unwrap_or_else(|| variant.ident.to_string());
+                gen_variant_match(&ident, quote! { #name }, variant)
+            });
+
+            cons = quote! {
+                let constructor = match data.constructor_name(vm, #vm_type) {
+                    Some(constructor) => constructor,
+                    None => return Err(::gluon::vm::api::MarshalError::UnexpectedTag(data.tag())),
+                };
+                match &constructor[..] {
+                    #(#variants,)*
+                    _ => return Err(::gluon::vm::api::MarshalError::UnexpectedConstructor(constructor.clone())),
+                }
+            };
+        }
+        None => {
+            let variants = ast.variants
+                .iter()
+                .enumerate()
+                .map(|(tag, variant)| gen_variant_match(&ident, quote! { #tag }, variant));
+
+            // data contains the the data for each field of a variant; the variant of the passed value
+            // is defined by the tag(), which is defined by order of the variants (the first variant is 0)
+            cons = quote! {
+                match data.tag() as usize {
+                    #(#variants,)*
+                    tag => return Err(::gluon::vm::api::MarshalError::UnexpectedTag(tag as ::gluon::vm::types::VmTag)),
+                }
+            };
+        }
     }
 
-    gen_impl(ident, generics, cons)
+    gen_impl(ident, generics, quote! { { #cons } })
 }
 
 fn gen_impl(ident: Ident, generics: Generics, cons_expr: TokenStream) -> TokenStream {
@@ -125,25 +198,35 @@ fn gen_impl(ident: Ident, generics: Generics, cons_expr: TokenStream) -> TokenSt
         #where_clause #(#getable_bounds,)* #(#lifetime_bounds),*
         {
             fn from_value(vm: &'__vm ::gluon::vm::thread::Thread, variants: ::gluon::vm::Variants) -> Self {
+                match <Self as ::gluon::vm::api::Getable<'__vm>>::try_from_value(vm, variants) {
+                    Ok(value) => value,
+                    Err(err) => panic!("{}", err),
+                }
+            }
+
+            fn try_from_value(
+                vm: &'__vm ::gluon::vm::thread::Thread,
+                variants: ::gluon::vm::Variants,
+            ) -> ::std::result::Result<Self, ::gluon::vm::api::MarshalError> {
                 let data = match variants.as_ref() {
                     ::gluon::vm::api::ValueRef::Data(data) => data,
-                    val => panic!("Unexpected value: '{:?}'. Do the type definitions match?", val),
+                    val => return Err(::gluon::vm::api::MarshalError::unexpected_value("a record or variant", val)),
                 };
 
-                #cons_expr
+                Ok(#cons_expr)
             }
         }
     }
 }
 
-fn gen_variant_match(ident: &Ident, tag: usize, variant: &Variant) -> TokenStream {
+fn gen_variant_match(ident: &Ident, pattern: TokenStream, variant: &Variant) -> TokenStream {
     let variant_ident = &variant.ident;
 
     // depending on the type of the variant we need to generate different constructors
     // for the enum
     match &variant.fields {
         Fields::Unit => quote! {
-            #tag => #ident::#variant_ident
+            #pattern => #ident::#variant_ident
         },
         // both constructors that need to marshall values extract them by using the index
         // of the field to get the content from Data::get_variant;
@@ -152,14 +235,14 @@ fn gen_variant_match(ident: &Ident, tag: usize, variant: &Variant) -> TokenStrea
             let cons = gen_tuple_variant_cons(unnamed);
 
             quote! {
-                #tag => #ident::#variant_ident#cons
+                #pattern => #ident::#variant_ident#cons
             }
         }
         Fields::Named(FieldsNamed { named, .. }) => {
             let cons = gen_struct_variant_cons(named);
 
             quote! {
-                #tag => #ident::#variant_ident#cons
+                #pattern => #ident::#variant_ident#cons
             }
         }
     }
@@ -169,16 +252,30 @@ fn gen_tuple_variant_cons<'a, I>(fields: I) -> TokenStream
 where
     I: IntoIterator<Item = &'a Field>,
 {
-    let fields = fields.into_iter().enumerate().map(|(idx, field)| {
+    // skipped fields do not exist in gluon so they do not take up an index
+    let mut idx = 0usize;
+    let fields = fields.into_iter().map(|field| {
         let field_ty = &field.ty;
+        let field_attr = attr::Field::from_ast(field);
 
-        quote! {
-            if let Some(val) = data.get_variant(#idx) {
-                <#field_ty as ::gluon::vm::api::Getable<'__vm>>::from_value(vm, val)
-            } else {
-                panic!("Enum does not contain data at index '{}'. Do the type definitions match?", #idx)
-            }
+        if field_attr.skip {
+            return quote! { ::std::default::Default::default() };
         }
+
+        let missing = gen_missing_field(
+            &field_attr,
+            quote! {
+                ::gluon::vm::api::MarshalError::MissingIndex(#idx)
+            },
+        );
+        let cons = quote! {
+            match data.get_variant(#idx) {
+                Some(val) => <#field_ty as ::gluon::vm::api::Getable<'__vm>>::try_from_value(vm, val)?,
+                None => #missing,
+            }
+        };
+        idx += 1;
+        cons
     });
 
     quote!{
@@ -190,20 +287,36 @@ fn gen_struct_variant_cons<'a, I>(fields: I) -> TokenStream
 where
     I: IntoIterator<Item = &'a Field>,
 {
-    let fields = fields.into_iter().enumerate().map(|(idx, field)| {
+    // skipped fields do not exist in gluon so they do not take up an index
+    let mut idx = 0usize;
+    let fields = fields.into_iter().map(|field| {
         let field_ty = &field.ty;
         let field_ident = field
             .ident
             .as_ref()
             .expect("Struct fields always have names");
+        let field_attr = attr::Field::from_ast(field);
 
-        quote! {
-            #field_ident: if let Some(val) = data.get_variant(#idx) {
-                <#field_ty as ::gluon::vm::api::Getable<'__vm>>::from_value(vm, val)
-            } else {
-                panic!("Enum does not contain data at index '{}'. Do the type definitions match?", #idx)
-            }
+        if field_attr.skip {
+            return quote! {
+                #field_ident: ::std::default::Default::default()
+            };
         }
+
+        let missing = gen_missing_field(
+            &field_attr,
+            quote! {
+                ::gluon::vm::api::MarshalError::MissingIndex(#idx)
+            },
+        );
+        let cons = quote! {
+            #field_ident: match data.get_variant(#idx) {
+                Some(val) => <#field_ty as ::gluon::vm::api::Getable<'__vm>>::try_from_value(vm, val)?,
+                None => #missing,
+            }
+        };
+        idx += 1;
+        cons
     });
 
     quote!{
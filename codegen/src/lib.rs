@@ -10,6 +10,9 @@
 //! `Getable` (generic type parameters included). If the type is generic over a
 //! lifetime, the lifetime will be constrained to that of the `'vm` lifetime in the
 //! trait definition.
+//!
+//! The derived `try_from_value` returns a `MarshalError` if the gluon value does not have
+//! the shape of the rust type, while `from_value` panics with the same error.
 //! 
 //! #### Examples
 //! 
@@ -29,6 +32,7 @@
 //! extern crate gluon_codegen;
 //! extern crate gluon;
 //! 
+//! #[derive(Getable)]
 //! enum Comment {
 //!     Normal(String),
 //!     Multiline(String),
@@ -37,10 +41,52 @@
 //! # fn main() {}
 //! ```
 //!
+//! #### Field names
+//!
+//! Fields of structs are looked up by the name they have in rust. To use different names,
+//! rename single fields with `#[gluon(rename = "<name>")]` or all fields of a struct with
+//! `#[gluon(rename_all = "<convention>")]`, where the convention is one of `lowercase`,
+//! `UPPERCASE`, `PascalCase`, `camelCase`, `snake_case` or `SCREAMING_SNAKE_CASE`.
+//! The same attributes are used by the `Pushable` and `VmType` derives.
+//!
+//! #### Enum variants
+//!
+//! If the enum also specifies its gluon type with `#[gluon(vm_type = "<gluon_type>")]`,
+//! variants are matched by the name of their constructor, so the order of the variants in
+//! rust and gluon does not have to agree. A variant can be mapped to a constructor with a
+//! different name with `#[gluon(rename = "<name>")]`. Without the attribute variants are
+//! matched by their position.
+//!
+//! #### Rust only fields
+//!
+//! Fields marked with `#[gluon(skip)]` do not exist in gluon. They are ignored when
+//! pushing and are always initialized with `Default::default()`. Fields marked with
+//! `#[gluon(default)]` are initialized with `Default::default()` only if they are missing
+//! from the gluon value.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//!
+//! #[derive(Getable)]
+//! #[gluon(rename_all = "camelCase")]
+//! struct User {
+//!     first_name: String,
+//!     #[gluon(rename = "years")]
+//!     age: i32,
+//! }
+//! # fn main() {}
+//! ```
+//!
 //! ### Pushable
 //!
 //! Derives `Pushable` for any enum or struct as long as all fields also implement
 //! `Pushable` (generic type parameters included).
+//!
+//! Fields of type `Box<T>` are pushed as `T`, which allows recursive types such as
+//! `enum Expr { Lit(i64), Add(Box<Expr>, Box<Expr>) }` to be derived. Recursive types
+//! have to be mapped to a named gluon type with `#[gluon(vm_type = "<gluon_type>")]`.
 //! 
 //! #### Examples
 //! 
@@ -67,11 +113,18 @@
 //!
 //! ### VmType
 //!
-//! Derives `VmType` for a rust type, mapping it to a gluon type. You must specify
-//! the corresponding gluon type with the `#[gluon(vm_type = "<gluon_type>")]` attribute,
+//! Derives `VmType` for a rust type, mapping it to a gluon type. The corresponding gluon
+//! type can be specified with the `#[gluon(vm_type = "<gluon_type>")]` attribute,
 //! where the gluon type is the fully qualified type name. The gluon type must be
 //! registered before a binding using the mapped rust type is first loaded.
 //!
+//! Structs without the attribute are mapped to a structurally equivalent gluon type
+//! instead (a record, a tuple or `()`), in which case every field has to implement
+//! `VmType`. Enums without the attribute are mapped to a variant type named after the
+//! enum, with one constructor per variant taking the fields of the variant as arguments,
+//! which is registered the first time it is needed. Generic and recursive enums need the
+//! attribute.
+//!
 //! If the rust type has type parameters, they have to implement `VmType` as well.
 //! All lifetimes have to be `'static`.
 //! 
@@ -99,11 +152,36 @@
 //! # fn main() {}
 //! ```
 //!
+//! Mapping a struct to the anonymous record type `{ x: Float, y: Float }`:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//!
+//! #[derive(VmType)]
+//! struct Vec2 {
+//!     x: f64,
+//!     y: f64,
+//! }
+//! # fn main() {}
+//! ```
+//!
 //! ### Userdata
 //!
 //! Derives `Userdata` and the required `Traverseable` and `VmType` for a rust type.
 //! Note that you will still have to use `Thread::register_type` to register the
 //! rust type with the vm before it is used.
+//!
+//! The derived `Traverseable` implementation does nothing unless some fields are marked
+//! with `#[gluon(traverse)]`. Any field holding values managed by the garbage collector
+//! (such as `Generic<T>` or `OpaqueValue`) must be marked, otherwise the values may be
+//! collected while they are still in use.
+//!
+//! Userdata can't be sent to other threads (through a channel for instance) unless it can be
+//! deep cloned. Adding `#[gluon(clone)]` to a type which implements `Clone` lets the type be
+//! deep cloned by calling `Clone::clone`. As the cloned fields would still refer to the old
+//! thread's values it can't be combined with `#[gluon(traverse)]` fields.
 //! 
 //! #### Examples
 //! 
@@ -134,6 +212,7 @@ extern crate proc_macro2;
 extern crate quote;
 extern crate syn;
 
+mod attr;
 mod getable;
 mod pushable;
 mod shared;
@@ -141,19 +220,19 @@ mod userdata;
 mod vm_type;
 
 #[doc(hidden)]
-#[proc_macro_derive(Getable)]
+#[proc_macro_derive(Getable, attributes(gluon))]
 pub fn getable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     getable::derive(input.into()).into()
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(Pushable)]
+#[proc_macro_derive(Pushable, attributes(gluon))]
 pub fn pushable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     pushable::derive(input.into()).into()
 }
 
 #[doc(hidden)]
-#[proc_macro_derive(Userdata)]
+#[proc_macro_derive(Userdata, attributes(gluon))]
 pub fn userdata(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     userdata::derive(input.into()).into()
 }
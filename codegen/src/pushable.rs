@@ -1,3 +1,4 @@
+use attr::{self, Container};
 use proc_macro2::{Span, TokenStream};
 use shared::{map_type_params, split_for_impl};
 use std::borrow::Cow;
@@ -10,21 +11,29 @@ use syn::{
 pub fn derive(input: TokenStream) -> TokenStream {
     let DeriveInput {
         ident,
+        attrs,
         data,
         generics,
         ..
     } = syn::parse2(input).expect("Input is checked by rustc");
 
+    let container = Container::from_ast(&attrs);
+
     let tokens = match data {
-        Data::Struct(ast) => derive_struct(ast, ident, generics),
-        Data::Enum(ast) => derive_enum(ast, ident, generics),
+        Data::Struct(ast) => derive_struct(&container, ast, ident, generics),
+        Data::Enum(ast) => derive_enum(&container, ast, ident, generics),
         Data::Union(_) => panic!("Unions are not supported"),
     };
 
     tokens.into()
 }
 
-fn derive_struct(ast: DataStruct, ident: Ident, generics: Generics) -> TokenStream {
+fn derive_struct(
+    container: &Container,
+    ast: DataStruct,
+    ident: Ident,
+    generics: Generics,
+) -> TokenStream {
     let (field_idents, field_types) = get_info_from_fields(&ast.fields);
     let field_idents2 = &field_idents;
 
@@ -35,15 +44,41 @@ fn derive_struct(ast: DataStruct, ident: Ident, generics: Generics) -> TokenStre
         Fields::Unit => quote!{},
     };
 
-    let push_impl = gen_push_impl(0, &field_idents, &field_types);
+    // skipped fields only exist in rust so they are not pushed
+    let (pushed_idents, pushed_types): (Vec<_>, Vec<_>) = ast
+        .fields
+        .iter()
+        .zip(field_idents.iter().cloned().zip(field_types.iter().cloned()))
+        .filter(|(field, _)| !attr::Field::from_ast(field).skip)
+        .map(|(_, info)| info)
+        .unzip();
+
+    // named structs are marshalled as records so that the fields can be looked up by name
+    let field_names = match &ast.fields {
+        Fields::Named(FieldsNamed { named, .. }) => Some(
+            named
+                .iter()
+                .filter(|field| !attr::Field::from_ast(field).skip)
+                .map(|field| container.field_name(field))
+                .collect::<Vec<_>>(),
+        ),
+        _ => None,
+    };
+
+    let push_impl = gen_push_impl(quote! { 0 }, &pushed_idents, &pushed_types, field_names);
 
     gen_impl(&ident, generics, quote! { #destructured #push_impl })
 }
 
-fn derive_enum(ast: DataEnum, ident: Ident, generics: Generics) -> TokenStream {
+fn derive_enum(
+    container: &Container,
+    ast: DataEnum,
+    ident: Ident,
+    generics: Generics,
+) -> TokenStream {
     // generate a correct implementation for each variant, destructuring the enum
     // to get access to the values
-    let match_arms = ast.variants.iter().enumerate().map(|(tag, variant)| {
+    let match_arms = ast.variants.iter().enumerate().map(|(index, variant)| {
         let (field_idents, field_types) = get_info_from_fields(&variant.fields);
         let field_idents2 = &field_idents;
         let variant_ident = &variant.ident;
@@ -54,7 +89,34 @@ fn derive_enum(ast: DataEnum, ident: Ident, generics: Generics) -> TokenStream {
             Fields::Unit => quote! { #ident::#variant_ident },
         };
 
-        let push_impl = gen_push_impl(tag, &field_idents, &field_types);
+        // skipped fields only exist in rust so they are not pushed
+        let (pushed_idents, pushed_types): (Vec<_>, Vec<_>) = variant
+            .fields
+            .iter()
+            .zip(field_idents.iter().cloned().zip(field_types.iter().cloned()))
+            .filter(|(field, _)| !attr::Field::from_ast(field).skip)
+            .map(|(_, info)| info)
+            .unzip();
+
+        let tag = match container.vm_type {
+            // if we know the gluon type the tag is looked up from the name of the constructor, the
+            // same way `Getable` finds the variant, so the variants may be declared in any order
+            Some(ref vm_type) => {
+                let name = attr::Variant::from_ast(variant)
+                    .rename
+                    .unwrap_or_else(|| variant.ident.to_string());
+                quote! {
+                    match ::gluon::vm::api::constructor_tag(vm, #vm_type, #name) {
+                        Some(tag) => tag,
+                        None => return Err(::gluon::vm::api::MarshalError::UnexpectedConstructor(
+                            #name.to_string()
+                        ).into()),
+                    }
+                }
+            }
+            None => quote! { #index as ::gluon::vm::types::VmTag },
+        };
+        let push_impl = gen_push_impl(tag, &pushed_idents, &pushed_types, None);
 
         quote! {
             #pattern => {
@@ -91,7 +153,12 @@ fn gen_impl(ident: &Ident, generics: Generics, push_impl: TokenStream) -> TokenS
     }
 }
 
-fn gen_push_impl(tag: usize, field_idents: &[Cow<Ident>], field_types: &[&Type]) -> TokenStream {
+fn gen_push_impl(
+    tag: TokenStream,
+    field_idents: &[Cow<Ident>],
+    field_types: &[&Type],
+    record_fields: Option<Vec<String>>,
+) -> TokenStream {
     debug_assert!(field_idents.len() == field_types.len());
 
     // push each field onto the stack
@@ -101,8 +168,14 @@ fn gen_push_impl(tag: usize, field_idents: &[Cow<Ident>], field_types: &[&Type])
         .iter()
         .zip(field_types)
         .map(|(ident, ty)| {
+            // `Box<T>` can't implement `Pushable` (it would overlap with the `Userdata` impl) so
+            // the field is pushed with method syntax, which derefs boxes to their contents
             quote! {
-                <#ty as ::gluon::vm::api::Pushable<'__vm>>::push(#ident, vm, ctx)?;
+                {
+                    use ::gluon::vm::api::Pushable;
+                    let field: #ty = #ident;
+                    field.push(vm, ctx)?;
+                }
             }
         })
         .rev();
@@ -111,10 +184,19 @@ fn gen_push_impl(tag: usize, field_idents: &[Cow<Ident>], field_types: &[&Type])
     // by popping the stack for each field
     let array_init = iter::repeat(quote! { ctx.stack.pop() }).take(field_idents.len());
 
+    let new_value = match record_fields {
+        Some(field_names) => quote! {
+            ctx.new_record(vm, &fields, &[ #(#field_names),* ])?
+        },
+        None => quote! {
+            ctx.new_data(vm, #tag, &fields)?
+        },
+    };
+
     quote! {
         #(#stack_pushes)*
         let fields = [ #(#array_init),* ];
-        let val = ctx.new_data(vm, #tag as ::gluon::vm::types::VmTag, &fields)?;
+        let val = #new_value;
         ctx.stack.push(val);
     }
 }
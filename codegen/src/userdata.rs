@@ -1,24 +1,79 @@
-use proc_macro2::{Ident, TokenStream};
+use attr;
+use proc_macro2::{Ident, Span, TokenStream};
 use shared::{map_lifetimes, map_type_params, split_for_impl};
-use syn::{self, Data, DeriveInput, Generics};
+use syn::{self, Data, DeriveInput, Fields, Generics};
 
 pub fn derive(input: TokenStream) -> TokenStream {
     let DeriveInput {
         ident,
         data,
         generics,
+        attrs,
         ..
     } = syn::parse2(input).expect("Input is checked by rustc");
 
+    let container = attr::Container::from_ast(&attrs);
+
     let tokens = match data {
-        Data::Struct(_) | Data::Enum(_) => gen_impl(ident, generics),
+        Data::Struct(ast) => {
+            let traverse_impl = gen_traverse_fields(&ast.fields, |idx, field| match &field.ident {
+                Some(ident) => quote! { self.#ident },
+                None => {
+                    let index = syn::Index::from(idx);
+                    quote! { self.#index }
+                }
+            });
+            let deep_clone_impl = gen_deep_clone(&container, ast.fields.iter());
+            gen_impl(ident, generics, traverse_impl, deep_clone_impl)
+        }
+        Data::Enum(ast) => {
+            let traverse_impl = {
+                let match_arms = ast.variants.iter().map(|variant| {
+                    let variant_ident = &variant.ident;
+                    let field_idents = &field_idents(&variant.fields);
+
+                    let pattern = match &variant.fields {
+                        Fields::Named(_) => quote! { #ident::#variant_ident{ #(ref #field_idents),* } },
+                        Fields::Unnamed(_) => quote! { #ident::#variant_ident( #(ref #field_idents),* ) },
+                        Fields::Unit => quote! { #ident::#variant_ident },
+                    };
+
+                    let traverse_fields = gen_traverse_fields(&variant.fields, |idx, _| {
+                        let field_ident = &field_idents[idx];
+                        quote! { *#field_ident }
+                    });
+
+                    quote! {
+                        #pattern => {
+                            #traverse_fields
+                        }
+                    }
+                });
+
+                quote! {
+                    match *self {
+                        #(#match_arms),*
+                    }
+                }
+            };
+            let deep_clone_impl = gen_deep_clone(
+                &container,
+                ast.variants.iter().flat_map(|variant| variant.fields.iter()),
+            );
+            gen_impl(ident, generics, traverse_impl, deep_clone_impl)
+        }
         Data::Union(_) => panic!("Unions are not supported"),
     };
 
     tokens.into()
 }
 
-fn gen_impl(ident: Ident, generics: Generics) -> TokenStream {
+fn gen_impl(
+    ident: Ident,
+    generics: Generics,
+    traverse_impl: TokenStream,
+    deep_clone_impl: TokenStream,
+) -> TokenStream {
     let trait_bounds = &map_type_params(&generics, |ty| {
         quote! { #ty: 'static + ::std::fmt::Debug + Sync + Send }
     });
@@ -33,12 +88,17 @@ fn gen_impl(ident: Ident, generics: Generics) -> TokenStream {
         impl #impl_generics ::gluon::vm::api::Userdata for #ident #ty_generics
         #where_clause #(#trait_bounds,)* #(#lifetime_bounds),*
         {
+            #deep_clone_impl
         }
 
         #[automatically_derived]
         #[allow(unused_attributes, unused_variables)]
-        impl #impl_generics ::gluon::vm::gc::Traverseable for #ident #ty_generics {}
-        
+        impl #impl_generics ::gluon::vm::gc::Traverseable for #ident #ty_generics {
+            fn traverse(&self, gc: &mut ::gluon::vm::gc::Gc) {
+                #traverse_impl
+            }
+        }
+
         #[automatically_derived]
         #[allow(unused_attributes, unused_variables)]
         impl #impl_generics ::gluon::vm::api::VmType for #ident #ty_generics
@@ -48,3 +108,66 @@ fn gen_impl(ident: Ident, generics: Generics) -> TokenStream {
         }
     }
 }
+
+/// Generates calls to `Traverseable::traverse` for every field marked with
+/// `#[gluon(traverse)]`, all other fields are assumed to not contain any gc values.
+/// `access` gets passed the index of the field and should return an expression that accesses
+/// the field.
+fn gen_traverse_fields<F>(fields: &Fields, mut access: F) -> TokenStream
+where
+    F: FnMut(usize, &syn::Field) -> TokenStream,
+{
+    let traversals = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| attr::Field::from_ast(field).traverse)
+        .map(|(idx, field)| {
+            let field_access = access(idx, field);
+            quote! {
+                ::gluon::vm::gc::Traverseable::traverse(&#field_access, gc);
+            }
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        #(#traversals)*
+    }
+}
+
+/// Generates a `deep_clone` method which clones the value with `Clone::clone` if the type is
+/// marked with `#[gluon(clone)]`, otherwise the default method which errors is used.
+fn gen_deep_clone<'a, I>(container: &attr::Container, mut fields: I) -> TokenStream
+where
+    I: Iterator<Item = &'a syn::Field>,
+{
+    if !container.clone {
+        return quote!{};
+    }
+    if fields.any(|field| attr::Field::from_ast(field).traverse) {
+        panic!(
+            "`#[gluon(clone)]` can't be used together with `#[gluon(traverse)]` fields as the \
+             values managed by the garbage collector would not be cloned"
+        );
+    }
+
+    quote! {
+        fn deep_clone(
+            &self,
+            deep_cloner: &mut ::gluon::vm::api::Cloner,
+        ) -> ::gluon::vm::Result<::gluon::vm::gc::GcPtr<Box<::gluon::vm::api::Userdata>>> {
+            let data: Box<::gluon::vm::api::Userdata> = Box::new(::std::clone::Clone::clone(self));
+            deep_cloner.gc().alloc(::gluon::vm::gc::Move(data))
+        }
+    }
+}
+
+fn field_idents(fields: &Fields) -> Vec<Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => Ident::new(&format!("_{}", idx), Span::call_site()),
+        })
+        .collect()
+}
@@ -1,7 +1,8 @@
+use attr::{self, Container};
 use proc_macro2::{Ident, TokenStream};
 use shared::{map_lifetimes, map_type_params, split_for_impl};
 use syn::{
-    self, Attribute, Data, DeriveInput, Generics, Lit, Meta, MetaList, MetaNameValue, NestedMeta,
+    self, Data, DataEnum, DataStruct, DeriveInput, Fields, FieldsNamed, FieldsUnnamed, Generics,
 };
 
 pub fn derive(input: TokenStream) -> TokenStream {
@@ -13,68 +14,27 @@ pub fn derive(input: TokenStream) -> TokenStream {
         ..
     } = syn::parse2(input).expect("Input is checked by rustc");
 
-    let tokens = match data {
-        Data::Struct(_) | Data::Enum(_) => gen_impl(ident, generics, &parse_attrs(&attrs)),
-        Data::Union(_) => panic!("Unions are not supported"),
-    };
+    let container = Container::from_ast(&attrs);
 
-    tokens.into()
-}
+    let make_type = match (&container.vm_type, data) {
+        (_, Data::Union(_)) => panic!("Unions are not supported"),
+        (Some(gluon_type), _) => gen_named_type(&generics, gluon_type),
+        (None, Data::Struct(ast)) => gen_struct_type(&container, ast),
+        (None, Data::Enum(ast)) => gen_enum_type(&ident, &generics, ast),
+    };
 
-fn parse_attrs(attrs: &[Attribute]) -> String {
-    let ty = attrs
-        .iter()
-        .filter_map(|attr| {
-            attr.interpret_meta().and_then(|meta| {
-                // all attrs are namespaced under the gluon attr
-                let nested = match meta {
-                    Meta::List(MetaList {
-                        ref ident,
-                        ref nested,
-                        ..
-                    }) if ident == "gluon" =>
-                    {
-                        Some(nested)
-                    }
-                    _ => None,
-                }?;
-
-                // find a literal for the vm_type key, ignore other values as they may be required
-                // by other macros
-                let lit = nested
-                    .iter()
-                    .filter_map(|meta| match meta {
-                        NestedMeta::Meta(Meta::NameValue(MetaNameValue { ident, lit, .. }))
-                            if ident == "vm_type" =>
-                        {
-                            Some(lit)
-                        }
-                        _ => None,
-                    })
-                    .next()?;
-
-                match lit {
-                    Lit::Str(ty) => Some(ty.value()),
-                    _ => panic!("The gluon type name must be a string literal"),
-                }
-            })
-        })
-        .next();
+    let tokens = gen_impl(ident, generics, make_type);
 
-    match ty {
-        Some(ty) => ty,
-        None => panic!("Did not find the gluon type this type will be mapped to. Specify it with #[gluon(vm_type = \"<gluon_type>\")]"),
-    }
+    tokens.into()
 }
 
-fn gen_impl(ident: Ident, generics: Generics, gluon_type: &str) -> TokenStream {
+fn gen_impl(ident: Ident, generics: Generics, make_type: TokenStream) -> TokenStream {
     let trait_bounds = &map_type_params(&generics, |ty| {
         quote! { #ty: 'static + ::gluon::vm::api::VmType }
     });
 
     let lifetime_bounds = &map_lifetimes(&generics, |lifetime| quote! { #lifetime: 'static });
 
-    let type_application = gen_type_application(&generics);
     let (impl_generics, ty_generics, where_clause) = split_for_impl(&generics, &[]);
 
     quote! {
@@ -86,14 +46,124 @@ fn gen_impl(ident: Ident, generics: Generics, gluon_type: &str) -> TokenStream {
             type Type = Self;
 
             fn make_type(vm: &::gluon::vm::thread::Thread) -> ::gluon::base::types::ArcType {
-                let ty = match vm.find_type_info(#gluon_type) {
-                    Ok(info) => info.into_type(),
-                    Err(_) => panic!("Could not find type '{}'. Is the module defining the type loaded?", #gluon_type),
-                };
+                #make_type
+            }
+        }
+    }
+}
+
+fn gen_named_type(generics: &Generics, gluon_type: &str) -> TokenStream {
+    let type_application = gen_type_application(generics);
+
+    quote! {
+        let ty = match vm.find_type_info(#gluon_type) {
+            Ok(info) => info.into_type(),
+            Err(_) => panic!("Could not find type '{}'. Is the module defining the type loaded?", #gluon_type),
+        };
+
+        #type_application
+    }
+}
+
+fn gen_struct_type(container: &Container, ast: DataStruct) -> TokenStream {
+    // without an explicit gluon type the type is built from the shape of the struct,
+    // mirroring how the `Getable` and `Pushable` derives marshal the values
+    match ast.fields {
+        Fields::Named(FieldsNamed { named, .. }) => {
+            let fields = named
+                .iter()
+                .filter(|field| !attr::Field::from_ast(field).skip)
+                .map(|field| {
+                    let field_ty = &field.ty;
+                    let quoted_ident = container.field_name(field);
+
+                    quote! {
+                        ::gluon::base::types::Field::new(
+                            ::gluon::base::symbol::Symbol::from(#quoted_ident),
+                            <#field_ty as ::gluon::vm::api::VmType>::make_type(vm),
+                        )
+                    }
+                });
+
+            quote! {
+                ::gluon::base::types::Type::record(vec![], vec![ #(#fields),* ])
+            }
+        }
+        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => {
+            let fields = unnamed
+                .iter()
+                .filter(|field| !attr::Field::from_ast(field).skip)
+                .map(|field| {
+                    let field_ty = &field.ty;
+                    quote! {
+                        <#field_ty as ::gluon::vm::api::VmType>::make_type(vm)
+                    }
+                });
 
-                #type_application
+            quote! {
+                ::gluon::base::types::Type::tuple(
+                    &mut ::gluon::base::symbol::Symbols::new(),
+                    vec![ #(#fields),* ],
+                )
             }
         }
+        Fields::Unit => quote! {
+            ::gluon::base::types::Type::unit()
+        },
+    }
+}
+
+fn gen_enum_type(ident: &Ident, generics: &Generics, ast: DataEnum) -> TokenStream {
+    // the type of an enum is a named variant type so its constructors can return it, each
+    // instantiation of a generic enum would need to register its own type under the same name
+    if generics.type_params().next().is_some() {
+        panic!("Generic enums must specify the gluon type they are mapped to with #[gluon(vm_type = \"<gluon_type>\")]");
+    }
+
+    let name = ident.to_string();
+    // the constructors are in the same order as the variants, matching the tags which the
+    // `Getable` and `Pushable` derives use when the gluon type is not specified
+    let constructors = ast.variants.iter().map(|variant| {
+        let constructor_name = attr::Variant::from_ast(variant)
+            .rename
+            .unwrap_or_else(|| variant.ident.to_string());
+        let args = variant
+            .fields
+            .iter()
+            .filter(|field| !attr::Field::from_ast(field).skip)
+            .map(|field| {
+                let field_ty = &field.ty;
+                quote! {
+                    <#field_ty as ::gluon::vm::api::VmType>::make_type(vm)
+                }
+            });
+
+        quote! {
+            ::gluon::base::types::Field::new(
+                ::gluon::base::symbol::Symbol::from(#constructor_name),
+                ::gluon::base::types::Type::function(
+                    vec![ #(#args),* ],
+                    ::gluon::base::types::Type::ident(name.clone()),
+                ),
+            )
+        }
+    });
+
+    quote! {
+        if let Some(typ) = vm.get_type::<Self>() {
+            return typ;
+        }
+
+        let name = ::gluon::base::symbol::Symbol::from(#name);
+        let typ = ::gluon::base::types::Type::variant(vec![ #(#constructors),* ]);
+        match vm.register_type_as(
+            name.clone(),
+            ::gluon::base::types::Alias::new(name, typ),
+            ::std::any::TypeId::of::<Self>(),
+        ) {
+            Ok(typ) => typ,
+            Err(err) => panic!("Could not register the type of `{}`: {}", #name, err),
+        }
     }
 }
 
@@ -9,7 +9,7 @@ extern crate gluon_vm;
 
 mod init;
 
-use gluon::vm::api::{self, generic, Generic};
+use gluon::vm::api::{self, generic, Generic, Getable, Hole, MarshalError, OpaqueValue};
 use gluon::vm::{self, ExternModule};
 use gluon::{import, Compiler, Thread};
 use init::new_vm;
@@ -256,3 +256,175 @@ fn tuple_struct_derive() {
         panic!("{}", why);
     }
 }
+
+#[derive(Debug, VmType, Getable)]
+#[gluon(vm_type = "types.Renamed", rename_all = "camelCase")]
+struct Renamed {
+    first_name: String,
+    #[gluon(rename = "years")]
+    age: i32,
+}
+
+fn load_renamed_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        renamed_to_str => primitive!(1 renamed_to_str),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn renamed_to_str(val: Renamed) -> String {
+    format!("{:?}", val)
+}
+
+#[test]
+fn renamed_fields() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Renamed = { firstName: String, years: Int }
+        { Renamed }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_renamed_mod);
+
+    let script = r#"
+        let { Renamed } = import! types
+        let { renamed_to_str } = import! functions
+        let { assert } = import! std.test
+
+        assert (renamed_to_str { firstName = "Jane", years = 30 } == "Renamed { first_name: \"Jane\", age: 30 }")
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Cache(Vec<i32>);
+
+#[derive(Debug, VmType, Getable)]
+#[gluon(vm_type = "types.Skipped")]
+struct Skipped {
+    name: String,
+    #[gluon(default)]
+    count: i32,
+    #[gluon(skip)]
+    cache: Cache,
+}
+
+fn load_skipped_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        skipped_to_str => primitive!(1 skipped_to_str),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn skipped_to_str(val: Skipped) -> String {
+    format!("{:?}", val)
+}
+
+#[test]
+fn skipped_fields() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Skipped = { name: String }
+        { Skipped }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_skipped_mod);
+
+    let script = r#"
+        let { Skipped } = import! types
+        let { skipped_to_str } = import! functions
+        let { assert } = import! std.test
+
+        assert (skipped_to_str { name = "test" } == "Skipped { name: \"test\", count: 0, cache: Cache([]) }")
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[test]
+fn try_from_value_mismatch() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let (value, _) = compiler
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "test", r#" { string = "test", int = 1 } "#)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = <Struct as Getable>::try_from_value(&vm, value.get_variant());
+    assert_eq!(
+        result.unwrap_err(),
+        MarshalError::MissingField("tuple".to_string())
+    );
+
+    let (value, _) = compiler
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "test", r#" "not a struct" "#)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = <Struct as Getable>::try_from_value(&vm, value.get_variant());
+    match result {
+        Err(MarshalError::UnexpectedValue(..)) => (),
+        _ => panic!("Expected an error"),
+    }
+}
+
+#[derive(Getable, VmType, Debug)]
+#[gluon(vm_type = "types.Reordered")]
+enum Reordered {
+    Second(i32),
+    #[gluon(rename = "Third")]
+    Last,
+    First(String),
+}
+
+fn load_reordered_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        reordered_to_str => primitive!(1 reordered_to_str),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn reordered_to_str(val: Reordered) -> String {
+    format!("{:?}", val)
+}
+
+#[test]
+fn enum_variants_by_name() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Reordered = | First String | Second Int | Third
+        { Reordered }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_reordered_mod);
+
+    let script = r#"
+        let { Reordered } = import! types
+        let { reordered_to_str } = import! functions
+        let { assert } = import! std.test
+
+        assert (reordered_to_str (First "a") == "First(\"a\")")
+        assert (reordered_to_str (Second 2) == "Second(2)")
+        assert (reordered_to_str Third == "Last")
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
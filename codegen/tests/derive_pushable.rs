@@ -246,3 +246,231 @@ fn normal_enum() {
         panic!("{}", why);
     }
 }
+
+#[derive(Pushable, Getable, VmType, Debug, PartialEq)]
+#[gluon(vm_type = "types.RoundTrip")]
+struct RoundTrip {
+    name: String,
+    count: i32,
+}
+
+fn load_round_trip_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        new_round_trip => primitive!(1 new_round_trip),
+        check_round_trip => primitive!(1 check_round_trip),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn new_round_trip(_: ()) -> RoundTrip {
+    RoundTrip {
+        name: "there".to_owned(),
+        count: 2,
+    }
+}
+
+fn check_round_trip(val: RoundTrip) -> bool {
+    val == new_round_trip(())
+}
+
+#[test]
+fn round_trip_struct() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type RoundTrip = { name: String, count: Int }
+        { RoundTrip }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_round_trip_mod);
+
+    let script = r#"
+        let { RoundTrip } = import! types
+        let { new_round_trip, check_round_trip } = import! functions
+        let { assert } = import! std.test
+
+        assert (check_round_trip (new_round_trip ()))
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[derive(Pushable, Getable, VmType, Debug, PartialEq)]
+#[gluon(vm_type = "types.Expr")]
+enum Expr {
+    Lit(i64),
+    Add(Box<Expr>, Box<Expr>),
+}
+
+fn load_expr_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        eval => primitive!(1 eval),
+        add_one => primitive!(1 add_one),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn eval(expr: Expr) -> i64 {
+    match expr {
+        Expr::Lit(i) => i,
+        Expr::Add(l, r) => eval(*l) + eval(*r),
+    }
+}
+
+fn add_one(expr: Expr) -> Expr {
+    Expr::Add(Box::new(expr), Box::new(Expr::Lit(1)))
+}
+
+#[test]
+fn recursive_enum() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Expr = | Lit Int | Add Expr Expr
+        { Expr }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_expr_mod);
+
+    let script = r#"
+        let { Expr } = import! types
+        let { eval, add_one } = import! functions
+        let { assert } = import! std.test
+
+        assert (eval (Add (Lit 1) (Add (Lit 2) (Lit 3))) == 6)
+        assert (eval (add_one (Lit 41)) == 42)
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[derive(Pushable, Getable, VmType, Debug, PartialEq)]
+#[gluon(vm_type = "types.Shape")]
+enum Shape {
+    Circle(#[gluon(skip)] u32, f64),
+    Rect {
+        width: f64,
+        #[gluon(default)]
+        height: f64,
+    },
+}
+
+fn load_shape_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        new_circle => primitive!(1 new_circle),
+        describe => primitive!(1 describe),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn new_circle(radius: f64) -> Shape {
+    Shape::Circle(7, radius)
+}
+
+fn describe(shape: Shape) -> String {
+    format!("{:?}", shape)
+}
+
+#[test]
+fn enum_with_skipped_and_default_fields() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Shape = | Circle Float | Rect Float
+        { Shape }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_shape_mod);
+
+    let script = r#"
+        let { Shape } = import! types
+        let { new_circle, describe } = import! functions
+        let { assert } = import! std.test
+
+        assert (describe (new_circle 2.0) == "Circle(0, 2.0)")
+        assert (describe (Rect 3.0) == "Rect { width: 3.0, height: 0.0 }")
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[derive(Pushable, Getable, VmType, Debug, PartialEq)]
+#[gluon(vm_type = "types.Reordered")]
+enum Reordered {
+    Second(i32),
+    #[gluon(rename = "Third")]
+    Last,
+    First(String),
+}
+
+fn load_reordered_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        new_reordered => primitive!(1 new_reordered),
+        round_trip_reordered => primitive!(1 round_trip_reordered),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn new_reordered(tag: u32) -> Reordered {
+    match tag {
+        0 => Reordered::First("first".to_owned()),
+        1 => Reordered::Second(2),
+        _ => Reordered::Last,
+    }
+}
+
+fn round_trip_reordered(val: Reordered) -> Reordered {
+    val
+}
+
+#[test]
+fn round_trip_reordered_enum() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    let src = r#"
+        type Reordered = | First String | Second Int | Third
+        { Reordered }
+    "#;
+
+    compiler.load_script(&vm, "types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", load_reordered_mod);
+
+    let script = r#"
+        let { Reordered } = import! types
+        let { new_reordered, round_trip_reordered } = import! functions
+        let { assert } = import! std.test
+
+        let constructor x =
+            match x with
+            | First s -> if s == "first" then 1 else 0
+            | Second i -> i
+            | Third -> 3
+
+        assert (constructor (new_reordered 0) == 1)
+        assert (constructor (new_reordered 1) == 2)
+        assert (constructor (new_reordered 2) == 3)
+        assert (constructor (round_trip_reordered (Second 2)) == 2)
+        assert (constructor (round_trip_reordered Third) == 3)
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
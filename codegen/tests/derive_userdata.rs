@@ -6,9 +6,11 @@ extern crate gluon_vm;
 
 mod init;
 
+use gluon::vm::gc::{Gc, Generation, Traverseable};
 use gluon::vm::{self, ExternModule};
 use gluon::{import, Compiler, Thread};
 use init::new_vm;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 #[derive(Userdata, Debug)]
@@ -65,3 +67,104 @@ fn userdata() {
         panic!("{}", why);
     }
 }
+
+#[derive(Userdata, Debug, Clone)]
+#[gluon(clone)]
+struct Shared {
+    name: Arc<str>,
+}
+
+fn load_shared(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Shared>("Shared", &[])?;
+
+    let module = record! {
+        new_shared => primitive!(1 new_shared),
+        name => primitive!(1 name),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn new_shared(name: String) -> Shared {
+    Shared {
+        name: Arc::from(name),
+    }
+}
+
+fn name(shared: &Shared) -> String {
+    String::from(&*shared.name)
+}
+
+#[test]
+fn send_cloned_userdata_between_threads() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    import::add_extern_module(&vm, "shared", load_shared);
+
+    let script = r#"
+        let { assert } = import! std.test
+        let { Result } = import! std.result
+        let { channel, send, recv } = import! std.channel
+        let { resume, spawn } = import! std.thread
+        let { new_shared, name } = import! shared
+
+        let { sender, receiver } = channel (new_shared "")
+
+        let thread = spawn (\_ ->
+                send sender (new_shared "from thread")
+                ())
+
+        resume thread
+        match recv receiver with
+        | Ok x -> assert (name x == "from thread")
+        | Err e -> error "Receive error"
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tracked(AtomicUsize);
+
+impl Traverseable for Tracked {
+    fn traverse(&self, _: &mut Gc) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Userdata, Debug)]
+struct Traversed {
+    #[gluon(traverse)]
+    tracked: Tracked,
+    ignored: Tracked,
+}
+
+#[derive(Userdata, Debug)]
+enum TraversedEnum {
+    Tuple(Tracked, #[gluon(traverse)] Tracked),
+}
+
+#[test]
+fn traverse_marked_fields() {
+    let mut gc = Gc::new(Generation::default(), usize::max_value());
+
+    let data = Traversed {
+        tracked: Tracked::default(),
+        ignored: Tracked::default(),
+    };
+    data.traverse(&mut gc);
+    assert_eq!(data.tracked.0.load(Ordering::SeqCst), 1);
+    assert_eq!(data.ignored.0.load(Ordering::SeqCst), 0);
+
+    let data = TraversedEnum::Tuple(Tracked::default(), Tracked::default());
+    data.traverse(&mut gc);
+    match data {
+        TraversedEnum::Tuple(ignored, tracked) => {
+            assert_eq!(ignored.0.load(Ordering::SeqCst), 0);
+            assert_eq!(tracked.0.load(Ordering::SeqCst), 1);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+#[macro_use]
+extern crate gluon_codegen;
+extern crate gluon;
+#[macro_use]
+extern crate gluon_vm;
+
+mod init;
+
+use gluon::vm::{self, ExternModule};
+use gluon::{import, Compiler, Thread};
+use init::new_vm;
+
+#[derive(Getable, VmType)]
+struct Vec2 {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Getable, VmType)]
+struct Pair(i32, String);
+
+#[derive(Getable, Pushable, VmType)]
+enum Shape {
+    Circle(f64),
+    Rect {
+        width: f64,
+        height: f64,
+    },
+    #[gluon(rename = "Nothing")]
+    Empty,
+}
+
+fn load_mod(vm: &Thread) -> vm::Result<ExternModule> {
+    let module = record! {
+        vec_sum => primitive!(1 vec_sum),
+        pair_len => primitive!(1 pair_len),
+        type Shape => Shape,
+        area => primitive!(1 area),
+        double => primitive!(1 double),
+    };
+
+    ExternModule::new(vm, module)
+}
+
+fn vec_sum(vec: Vec2) -> f64 {
+    vec.x + vec.y
+}
+
+fn pair_len(pair: Pair) -> i32 {
+    pair.0 + pair.1.len() as i32
+}
+
+fn area(shape: Shape) -> f64 {
+    match shape {
+        Shape::Circle(r) => 3.0 * r * r,
+        Shape::Rect { width, height } => width * height,
+        Shape::Empty => 0.0,
+    }
+}
+
+fn double(shape: Shape) -> Shape {
+    match shape {
+        Shape::Circle(r) => Shape::Circle(2.0 * r),
+        Shape::Rect { width, height } => Shape::Rect {
+            width: 2.0 * width,
+            height: 2.0 * height,
+        },
+        Shape::Empty => Shape::Empty,
+    }
+}
+
+#[test]
+fn structural_types() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    import::add_extern_module(&vm, "functions", load_mod);
+
+    let script = r#"
+        let { vec_sum, pair_len } = import! functions
+        let { assert } = import! std.test
+
+        assert (vec_sum { x = 1.0, y = 2.0 } == 3.0)
+        assert (pair_len (1, "abc") == 4)
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
+
+#[test]
+fn variant_types() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    import::add_extern_module(&vm, "shapes", load_mod);
+
+    let script = r#"
+        let { Shape, area, double } = import! shapes
+        let { assert } = import! std.test
+
+        assert (area (Circle 1.0) == 3.0)
+        assert (area (double (Rect 1.0 2.0)) == 8.0)
+        assert (area Nothing == 0.0)
+        match double (Circle 1.0) with
+        | Circle r -> assert (r == 2.0)
+        | _ -> assert False
+    "#;
+
+    if let Err(why) = compiler.run_expr::<()>(&vm, "test", script) {
+        panic!("{}", why);
+    }
+}
@@ -10,8 +10,10 @@ extern crate gluon_base as base;
 
 use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::fmt;
 use std::iter::once;
 use std::path::PathBuf;
+use std::ptr;
 
 use codespan::ByteOffset;
 
@@ -20,18 +22,20 @@ use either::Either;
 use itertools::Itertools;
 
 use base::ast::{
-    walk_expr, walk_pattern, AstType, Expr, Pattern, PatternField, SpannedExpr, SpannedIdent,
-    SpannedPattern, Typed, TypedIdent, Visitor,
+    is_operator_char, walk_expr, walk_pattern, Argument, AstType, Do, Expr, Pattern, PatternField, SpannedExpr,
+    SpannedIdent, SpannedPattern, Typed, TypedIdent, ValueBinding, Visitor,
 };
 use base::filename_to_module;
 use base::fnv::{FnvMap, FnvSet};
 use base::kind::{ArcKind, Kind};
-use base::metadata::Metadata;
+use base::metadata::{Comment, Metadata};
 use base::pos::{self, BytePos, HasSpan, Span, Spanned};
 use base::resolve;
 use base::scoped_map::ScopedMap;
 use base::symbol::{Name, Symbol, SymbolRef};
-use base::types::{walk_type_, AliasData, ArcType, ControlVisitation, Generic, Type, TypeEnv};
+use base::types::{
+    self, walk_type_, AliasData, ArcType, ArgType, ControlVisitation, Generic, Type, TypeEnv,
+};
 
 #[derive(Clone, Debug)]
 pub struct Found<'a> {
@@ -530,8 +534,10 @@ where
                     }
                     Err(alt) => {
                         self.on_found.on_pattern(&alt.pattern);
-                        let iter = [Ok(&alt.pattern), Err(&alt.expr)];
-                        let (_, sel) = self.select_spanned(iter.iter().cloned(), |x| match *x {
+                        let iter = once(Ok(&alt.pattern))
+                            .chain(alt.guard.as_ref().map(Err))
+                            .chain(once(Err(&alt.expr)));
+                        let (_, sel) = self.select_spanned(iter, |x| match *x {
                             Ok(p) => p.span,
                             Err(e) => e.span,
                         });
@@ -958,6 +964,949 @@ pub fn find_all_symbols(
     })
 }
 
+/// The location where a symbol is defined
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    /// The name of the module the definition is in or `None` if the definition is in the
+    /// expression which was searched
+    pub module: Option<String>,
+    pub span: Span<BytePos>,
+}
+
+/// Finds where the symbol at `pos` is defined. Symbols which are imported from other modules are
+/// followed into those modules by calling `modules` with the name of the module (`std.prelude`),
+/// which should return the typechecked expression of that module.
+pub fn find_definition<'a, F>(
+    modules: F,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Option<Definition>
+where
+    F: FnMut(&str) -> Option<&'a SpannedExpr<Symbol>>,
+{
+    let found = complete_at((), source_span, expr, pos).ok()?;
+    let (symbol, path) = match found.match_ {
+        Some(Match::Expr(expr)) => expr_path(expr)?,
+        // Patterns are the definitions themselves
+        Some(Match::Pattern(pattern)) => {
+            return Some(Definition {
+                module: None,
+                span: pattern.span,
+            })
+        }
+        Some(Match::Ident(_, id, _)) => match *found.enclosing_match() {
+            Match::Expr(&Spanned {
+                value: Expr::Projection(ref expr, _, _),
+                ..
+            }) => {
+                let (symbol, mut path) = expr_path(expr)?;
+                path.push(id.declared_name().to_string());
+                (symbol, path)
+            }
+            _ => (id.clone(), Vec::new()),
+        },
+        _ => return None,
+    };
+    DefinitionFinder::new(modules).symbol(&None, expr, &symbol, path)
+}
+
+/// A reference to a symbol. `module` is the name of the module the reference is in or `None` if
+/// it is in the expression which was searched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub module: Option<String>,
+    pub span: Span<BytePos>,
+}
+
+/// Replaces the text at `span` in `module` with `new_text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub module: Option<String>,
+    pub span: Span<BytePos>,
+    pub new_text: String,
+}
+
+/// Finds every reference to the symbol at `pos`, including its definition. `modules` should
+/// contain every loaded module by name, which are searched for references in addition to `expr`.
+pub fn find_all_references<'a>(
+    modules: &FnvMap<String, &'a SpannedExpr<Symbol>>,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Vec<Reference> {
+    find_references(modules, source_span, expr, pos)
+        .map_or(Vec::new(), |(_, references)| references)
+}
+
+/// Finds every reference to the symbol at `pos` along with the name the symbol is referred to by
+fn find_references<'a>(
+    modules: &FnvMap<String, &'a SpannedExpr<Symbol>>,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Option<(Option<String>, Vec<Reference>)> {
+    let lookup = |name: &str| modules.get(name).cloned();
+    let definition = find_definition(&lookup, source_span, expr, pos)?;
+
+    let mut seen = FnvSet::default();
+    seen.insert((
+        definition.module.clone(),
+        definition.span.start(),
+        definition.span.end(),
+    ));
+    let mut name = None;
+    let mut references = vec![Reference {
+        module: definition.module.clone(),
+        span: definition.span,
+    }];
+    let roots = once((None, expr)).chain(
+        modules
+            .iter()
+            .map(|(name, &module_expr)| (Some(name.clone()), module_expr)),
+    );
+    let mut finder = DefinitionFinder::new(&lookup);
+    for (module, root) in roots {
+        let mut visitor = CollectReferences {
+            candidates: Vec::new(),
+        };
+        visitor.visit_expr(root);
+
+        for (span, candidate) in visitor.candidates {
+            if module.is_none() && !source_span.contains(span) {
+                continue;
+            }
+            let (found, candidate_name) = match candidate {
+                Candidate::Symbol(symbol, path) => {
+                    let candidate_name = path
+                        .last()
+                        .cloned()
+                        .unwrap_or_else(|| symbol.declared_name().to_string());
+                    (finder.symbol(&module, root, &symbol, path), candidate_name)
+                }
+                Candidate::Value(value, path) => {
+                    let candidate_name = path.last().cloned().unwrap_or_default();
+                    (finder.value(&module, root, value, path), candidate_name)
+                }
+            };
+            if found.as_ref() != Some(&definition) {
+                continue;
+            }
+            if name.is_none() {
+                name = Some(candidate_name);
+            }
+            if seen.insert((module.clone(), span.start(), span.end())) {
+                references.push(Reference {
+                    module: module.clone(),
+                    span,
+                });
+            }
+        }
+    }
+    Some((name, references))
+}
+
+/// The reasons a symbol can not be renamed
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenameError {
+    /// The new name is not an identifier or an operator
+    InvalidName(String),
+    /// The new name is a different kind of identifier than the old name (`Some` and `some`,
+    /// `+` and `plus`)
+    IdentifierKindMismatch { old_name: String, new_name: String },
+    /// The renamed symbol would capture a reference to another binding with the new name or
+    /// another binding with the new name would capture a reference to the renamed symbol
+    Capture {
+        module: Option<String>,
+        span: Span<BytePos>,
+        new_name: String,
+    },
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenameError::InvalidName(ref name) => {
+                write!(f, "`{}` is not a valid identifier", name)
+            }
+            RenameError::IdentifierKindMismatch {
+                ref old_name,
+                ref new_name,
+            } => write!(
+                f,
+                "`{}` can not be renamed to `{}` as they are different kinds of identifiers",
+                old_name, new_name
+            ),
+            RenameError::Capture {
+                ref module,
+                ref new_name,
+                ..
+            } => write!(
+                f,
+                "Renaming to `{}` changes which binding a reference{} refers to",
+                new_name,
+                module
+                    .as_ref()
+                    .map_or(String::new(), |module| format!(" in `{}`", module))
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IdentifierKind {
+    Operator,
+    Constructor,
+    Value,
+}
+
+fn identifier_kind(name: &str) -> Option<IdentifierKind> {
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    if name.chars().all(is_operator_char) {
+        return Some(IdentifierKind::Operator);
+    }
+    let is_ident_start = |c: char| c == '_' || c.is_ascii_alphabetic();
+    let is_keyword = match name {
+        "and" | "else" | "forall" | "if" | "in" | "let" | "do" | "match" | "then" | "type"
+        | "with" => true,
+        _ => false,
+    };
+    if !is_ident_start(first)
+        || !chars.all(|c| is_ident_start(c) || c.is_ascii_digit() || c == '\'')
+        || is_keyword
+    {
+        return None;
+    }
+    Some(if first.is_uppercase() {
+        IdentifierKind::Constructor
+    } else {
+        IdentifierKind::Value
+    })
+}
+
+/// Returns the edits which rename every reference to the symbol at `pos` to `new_name`. See
+/// `find_all_references`.
+///
+/// Fails if `new_name` is not the same kind of identifier as the renamed symbol or if the rename
+/// would change which binding any identifier refers to.
+pub fn rename<'a>(
+    modules: &FnvMap<String, &'a SpannedExpr<Symbol>>,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<Symbol>,
+    pos: BytePos,
+    new_name: &str,
+) -> Result<Vec<TextEdit>, RenameError> {
+    let new_kind =
+        identifier_kind(new_name).ok_or_else(|| RenameError::InvalidName(new_name.to_string()))?;
+    let (old_name, references) = match find_references(modules, source_span, expr, pos) {
+        Some(found) => found,
+        None => return Ok(Vec::new()),
+    };
+    if let Some(old_name) = old_name {
+        if identifier_kind(&old_name) != Some(new_kind) {
+            return Err(RenameError::IdentifierKindMismatch {
+                old_name,
+                new_name: new_name.to_string(),
+            });
+        }
+    }
+
+    let roots = once((None, expr)).chain(
+        modules
+            .iter()
+            .map(|(name, &module_expr)| (Some(name.clone()), module_expr)),
+    );
+    for (module, root) in roots {
+        let renamed: FnvSet<_> = references
+            .iter()
+            .filter(|reference| reference.module == module)
+            .map(|reference| (reference.span.start(), reference.span.end()))
+            .collect();
+        if renamed.is_empty() {
+            continue;
+        }
+        let mut visitor = CheckCapture {
+            renamed: &renamed,
+            new_name,
+            scope: ScopedMap::new(),
+            capture: None,
+        };
+        visitor.visit_expr(root);
+        if let Some(span) = visitor.capture {
+            return Err(RenameError::Capture {
+                module,
+                span,
+                new_name: new_name.to_string(),
+            });
+        }
+    }
+
+    Ok(references
+        .into_iter()
+        .map(|reference| TextEdit {
+            module: reference.module,
+            span: reference.span,
+            new_text: new_name.to_string(),
+        })
+        .collect())
+}
+
+/// Checks that every identifier still refers to the same binding if the bindings and identifiers
+/// at the `renamed` spans are renamed to `new_name`
+struct CheckCapture<'b> {
+    renamed: &'b FnvSet<(BytePos, BytePos)>,
+    new_name: &'b str,
+    /// The bindings in scope by the name they have after renaming and whether they are renamed
+    scope: ScopedMap<String, (Symbol, bool)>,
+    /// The span of the first identifier which would refer to a different binding
+    capture: Option<Span<BytePos>>,
+}
+
+impl<'b> CheckCapture<'b> {
+    fn is_renamed(&self, span: Span<BytePos>) -> bool {
+        self.renamed.contains(&(span.start(), span.end()))
+    }
+
+    fn name_after_rename(&self, symbol: &Symbol, span: Span<BytePos>) -> String {
+        if self.is_renamed(span) {
+            self.new_name.to_string()
+        } else {
+            symbol.declared_name().to_string()
+        }
+    }
+
+    fn bind(&mut self, symbol: &Symbol, span: Span<BytePos>) {
+        let name = self.name_after_rename(symbol, span);
+        let is_renamed = self.is_renamed(span);
+        self.scope.insert(name, (symbol.clone(), is_renamed));
+    }
+
+    fn bind_pattern(&mut self, pattern: &SpannedPattern<Symbol>) {
+        bound_in_pattern(pattern, None, &mut |symbol, span, _| {
+            self.bind(symbol, span)
+        });
+    }
+
+    fn reference(&mut self, symbol: &Symbol, span: Span<BytePos>) {
+        if self.capture.is_some() || is_implicit_prelude(span) {
+            return;
+        }
+        let is_renamed = self.is_renamed(span);
+        let name = self.name_after_rename(symbol, span);
+        if let Some(&(ref bound, bound_is_renamed)) = self.scope.get(&name) {
+            if bound != symbol && (is_renamed || bound_is_renamed) {
+                self.capture = Some(span);
+            }
+        }
+    }
+}
+
+impl<'a, 'b> Visitor<'a> for CheckCapture<'b> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+        match e.value {
+            Expr::MacroExpansion { .. } => (),
+            // Implicit arguments are inserted by the typechecker and do not appear in the source
+            Expr::App {
+                ref func, ref args, ..
+            } => {
+                self.visit_expr(func);
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Ident(ref id) => self.reference(&id.name, e.span),
+            Expr::Infix {
+                ref lhs,
+                ref op,
+                ref rhs,
+                ..
+            } => {
+                self.visit_expr(lhs);
+                self.reference(&op.value.name, op.span);
+                self.visit_expr(rhs);
+            }
+            Expr::Record {
+                ref exprs,
+                ref base,
+                ..
+            } => {
+                for field in exprs {
+                    match field.value {
+                        Some(ref expr) => self.visit_expr(expr),
+                        None => self.reference(&field.name.value, field.name.span),
+                    }
+                }
+                if let Some(ref base) = *base {
+                    self.visit_expr(base);
+                }
+            }
+            Expr::LetBindings(ref binds, ref body) => {
+                self.scope.enter_scope();
+                let is_recursive = binds.iter().all(|bind| !bind.args.is_empty());
+                for bind in binds {
+                    if !is_recursive {
+                        self.visit_expr(&bind.expr);
+                    }
+                    self.bind_pattern(&bind.name);
+                }
+                if is_recursive {
+                    for bind in binds {
+                        self.scope.enter_scope();
+                        for arg in &bind.args {
+                            self.bind(&arg.name.value.name, arg.name.span);
+                        }
+                        self.visit_expr(&bind.expr);
+                        self.scope.exit_scope();
+                    }
+                }
+                self.visit_expr(body);
+                self.scope.exit_scope();
+            }
+            Expr::TypeBindings(ref binds, ref body) => {
+                self.scope.enter_scope();
+                for bind in binds {
+                    let typ = types::remove_forall(bind.alias.value.unresolved_type());
+                    if let Type::Variant(ref row) = **typ {
+                        for field in types::row_iter(row) {
+                            self.bind(&field.name, bind.name.span);
+                        }
+                    }
+                }
+                self.visit_expr(body);
+                self.scope.exit_scope();
+            }
+            Expr::Lambda(ref lambda) => {
+                self.scope.enter_scope();
+                for arg in &lambda.args {
+                    self.bind(&arg.name.value.name, arg.name.span);
+                }
+                self.visit_expr(&lambda.body);
+                self.scope.exit_scope();
+            }
+            Expr::Match(ref expr, ref alts) => {
+                self.visit_expr(expr);
+                for alt in alts {
+                    self.scope.enter_scope();
+                    self.bind_pattern(&alt.pattern);
+                    if let Some(ref guard) = alt.guard {
+                        self.visit_expr(guard);
+                    }
+                    self.visit_expr(&alt.expr);
+                    self.scope.exit_scope();
+                }
+            }
+            Expr::Do(Do {
+                ref id,
+                ref bound,
+                ref body,
+                ..
+            }) => {
+                self.visit_expr(bound);
+                self.scope.enter_scope();
+                self.bind(&id.value.name, id.span);
+                self.visit_expr(body);
+                self.scope.exit_scope();
+            }
+            _ => walk_expr(self, e),
+        }
+    }
+}
+
+/// Returns the edit which annotates the let binding at `pos` with its inferred type. Returns
+/// `None` if there is no binding at `pos` or if it already has a type annotation.
+pub fn add_type_annotation(
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Option<TextEdit> {
+    struct FindLet<'a> {
+        source_span: Span<BytePos>,
+        pos: BytePos,
+        binding: Option<&'a ValueBinding<Symbol>>,
+    }
+    impl<'a> Visitor<'a> for FindLet<'a> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+            if self.binding.is_some() {
+                return;
+            }
+            if let Expr::LetBindings(ref binds, _) = e.value {
+                let (source_span, pos) = (self.source_span, self.pos);
+                self.binding = binds.iter().find(|bind| {
+                    source_span.contains(bind.name.span)
+                        && bind.name.span.containment(pos) == Ordering::Equal
+                });
+            }
+            walk_expr(self, e)
+        }
+    }
+
+    let mut visitor = FindLet {
+        source_span,
+        pos,
+        binding: None,
+    };
+    visitor.visit_expr(expr);
+    let bind = visitor.binding?;
+    if bind.typ.is_some() {
+        return None;
+    }
+    let end = bind
+        .args
+        .last()
+        .map_or(bind.name.span.end(), |arg| arg.name.span.end());
+    Some(TextEdit {
+        module: None,
+        span: Span::new(end, end),
+        new_text: format!(" : {}", bind.resolved_type),
+    })
+}
+
+/// Returns an edit for each module in `modules` (name and type of a module) which exports the
+/// unresolved identifier at `pos`. Each edit inserts an `import!` of the identifier from that
+/// module at the start of `source_span`.
+pub fn add_import<'m, I>(
+    env: &TypeEnv,
+    modules: I,
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Vec<TextEdit>
+where
+    I: IntoIterator<Item = (&'m str, &'m ArcType)>,
+{
+    let symbol = match completion(IdentAt, source_span, expr, pos) {
+        Ok(symbol) => symbol,
+        Err(()) => return Vec::new(),
+    };
+    let name = symbol.declared_name();
+
+    modules
+        .into_iter()
+        .filter(|&(_, typ)| {
+            let typ = resolve::remove_aliases(env, typ.clone());
+            typ.row_iter().any(|field| field.name.declared_name() == name)
+                || typ
+                    .type_field_iter()
+                    .any(|field| field.name.declared_name() == name)
+        })
+        .map(|(module, _)| {
+            // Operators must be enclosed in parentheses to be bound by a pattern
+            let new_text = if name.starts_with(is_operator_char) {
+                format!("let {{ ({}) }} = import! {}\n", name, module)
+            } else {
+                format!("let {{ {} }} = import! {}\n", name, module)
+            };
+            TextEdit {
+                module: None,
+                span: Span::new(source_span.start(), source_span.start()),
+                new_text,
+            }
+        })
+        .collect()
+}
+
+enum Candidate<'a> {
+    /// A symbol followed by the fields accessed on it
+    Symbol(Symbol, Vec<String>),
+    /// The field at the path of the value, from a record pattern
+    Value(&'a SpannedExpr<Symbol>, Vec<String>),
+}
+
+/// Collects every place in an expression which may refer to a definition
+struct CollectReferences<'a> {
+    candidates: Vec<(Span<BytePos>, Candidate<'a>)>,
+}
+
+impl<'a> CollectReferences<'a> {
+    fn pattern_fields(
+        &mut self,
+        pattern: &'a SpannedPattern<Symbol>,
+        value: &'a SpannedExpr<Symbol>,
+        path: &mut Vec<String>,
+    ) {
+        if let Pattern::Record {
+            ref types,
+            ref fields,
+            ..
+        } = pattern.value
+        {
+            for field in types {
+                path.push(field.name.value.declared_name().to_string());
+                self.candidates
+                    .push((field.name.span, Candidate::Value(value, path.clone())));
+                path.pop();
+            }
+            for field in fields {
+                path.push(field.name.value.declared_name().to_string());
+                self.candidates
+                    .push((field.name.span, Candidate::Value(value, path.clone())));
+                if let Some(ref pattern) = field.value {
+                    self.pattern_fields(pattern, value, path);
+                }
+                path.pop();
+            }
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for CollectReferences<'a> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+        if is_implicit_prelude(e.span) {
+            // Only the body of the implicit prelude appears in the source
+            if let Expr::LetBindings(_, ref body) = e.value {
+                self.visit_expr(body);
+            }
+            return;
+        }
+        match e.value {
+            // The replacement is generated by the macro and does not appear in the source
+            Expr::MacroExpansion { .. } => return,
+            // Implicit arguments are inserted by the typechecker and do not appear in the source
+            Expr::App {
+                ref func, ref args, ..
+            } => {
+                self.visit_expr(func);
+                for arg in args {
+                    self.visit_expr(arg);
+                }
+                return;
+            }
+            Expr::Ident(ref id) => self
+                .candidates
+                .push((e.span, Candidate::Symbol(id.name.clone(), Vec::new()))),
+            Expr::Projection(_, ref field, _) => if let Some((symbol, path)) = expr_path(e) {
+                let field_len = field.declared_name().len() as i64;
+                let span = Span::new(e.span.end() + ByteOffset::from(-field_len), e.span.end());
+                self.candidates
+                    .push((span, Candidate::Symbol(symbol, path)));
+            },
+            Expr::LetBindings(ref binds, _) => for bind in binds {
+                self.pattern_fields(&bind.name, &bind.expr, &mut Vec::new());
+            },
+            Expr::Record { ref exprs, .. } => for field in exprs {
+                if field.value.is_none() {
+                    self.candidates.push((
+                        field.name.span,
+                        Candidate::Symbol(field.name.value.clone(), Vec::new()),
+                    ));
+                }
+            },
+            _ => (),
+        }
+        walk_expr(self, e)
+    }
+
+    fn visit_pattern(&mut self, p: &'a SpannedPattern<Self::Ident>) {
+        if is_implicit_prelude(p.span) {
+            return;
+        }
+        if let Pattern::Ident(ref id) = p.value {
+            self.candidates
+                .push((p.span, Candidate::Symbol(id.name.clone(), Vec::new())));
+        }
+        walk_pattern(self, &p.value)
+    }
+}
+
+/// The spans of the implicit prelude are all set to `u32::max_value()` when it is added to an
+/// expression
+fn is_implicit_prelude(span: Span<BytePos>) -> bool {
+    span.start() == BytePos::from(u32::max_value())
+}
+
+/// Splits `a.b.c` into `a` and the fields `[b, c]`
+fn expr_path(expr: &SpannedExpr<Symbol>) -> Option<(Symbol, Vec<String>)> {
+    match expr.value {
+        Expr::Ident(ref id) => Some((id.name.clone(), Vec::new())),
+        Expr::Projection(ref expr, ref field, _) => {
+            let (symbol, mut path) = expr_path(expr)?;
+            path.push(field.declared_name().to_string());
+            Some((symbol, path))
+        }
+        Expr::MacroExpansion {
+            ref replacement, ..
+        } => expr_path(replacement),
+        _ => None,
+    }
+}
+
+struct DefinitionFinder<'a, F> {
+    modules: F,
+    /// The bindings of each expression searched so far. Collected in a single pass the first
+    /// time a symbol of the expression is looked up.
+    bindings: Vec<(&'a SpannedExpr<Symbol>, FnvMap<Symbol, Binding<'a>>)>,
+}
+
+impl<'a, F> DefinitionFinder<'a, F>
+where
+    F: FnMut(&str) -> Option<&'a SpannedExpr<Symbol>>,
+{
+    fn new(modules: F) -> Self {
+        DefinitionFinder {
+            modules,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Returns where `symbol` is bound in `root`
+    fn binding(&mut self, root: &'a SpannedExpr<Symbol>, symbol: &Symbol) -> Option<Binding<'a>> {
+        let index = self
+            .bindings
+            .iter()
+            .position(|&(expr, _)| ptr::eq(expr, root));
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let mut visitor = CollectBindings {
+                    bindings: FnvMap::default(),
+                };
+                visitor.visit_expr(root);
+                self.bindings.push((root, visitor.bindings));
+                self.bindings.len() - 1
+            }
+        };
+        self.bindings[index].1.get(symbol).cloned()
+    }
+
+    /// Finds the definition of the field at `path` in the value of `symbol`, or of `symbol`
+    /// itself if `path` is empty. `root` is the expression of the module `symbol` appears in.
+    fn symbol(
+        &mut self,
+        module: &Option<String>,
+        root: &'a SpannedExpr<Symbol>,
+        symbol: &Symbol,
+        path: Vec<String>,
+    ) -> Option<Definition> {
+        if symbol.is_global() {
+            let name = symbol.definition_name().to_string();
+            let module_expr = (self.modules)(&name)?;
+            return self.value(&Some(name), module_expr, module_expr, path);
+        }
+
+        let binding = self.binding(root, symbol)?;
+        let span = binding.span;
+        let path_is_empty = path.is_empty();
+        let followed = match binding.value {
+            Some((value, mut value_path)) => {
+                value_path.extend(path);
+                if value_path.is_empty() {
+                    None
+                } else {
+                    self.value(module, root, value, value_path)
+                }
+            }
+            None => None,
+        };
+        followed.or_else(|| {
+            if path_is_empty {
+                Some(Definition {
+                    module: module.clone(),
+                    span,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Finds the definition of the field at `path` in `value`
+    fn value(
+        &mut self,
+        module: &Option<String>,
+        root: &'a SpannedExpr<Symbol>,
+        value: &'a SpannedExpr<Symbol>,
+        mut path: Vec<String>,
+    ) -> Option<Definition> {
+        match value.value {
+            Expr::Ident(..) | Expr::Projection(..) => {
+                let (symbol, mut value_path) = expr_path(value)?;
+                value_path.extend(path);
+                self.symbol(module, root, &symbol, value_path)
+            }
+            Expr::MacroExpansion {
+                ref replacement, ..
+            } => self.value(module, root, replacement, path),
+            Expr::LetBindings(_, ref body) | Expr::TypeBindings(_, ref body) => {
+                self.value(module, root, body, path)
+            }
+            Expr::Record {
+                ref types,
+                ref exprs,
+                ..
+            } if !path.is_empty() =>
+            {
+                let rest = path.split_off(1);
+                let name = &path[0];
+                let field_definition = |span| {
+                    if rest.is_empty() {
+                        Some(Definition {
+                            module: module.clone(),
+                            span,
+                        })
+                    } else {
+                        None
+                    }
+                };
+
+                if let Some(field) = types
+                    .iter()
+                    .find(|field| field.name.value.declared_name() == name)
+                {
+                    return field_definition(field.name.span);
+                }
+
+                let field = exprs
+                    .iter()
+                    .find(|field| field.name.value.declared_name() == name)?;
+                let followed = match field.value {
+                    Some(ref expr) => self.value(module, root, expr, rest.clone()),
+                    None => self.symbol(module, root, &field.name.value, rest.clone()),
+                };
+                followed.or_else(|| field_definition(field.name.span))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Binding<'a> {
+    span: Span<BytePos>,
+    /// The expression bound to the pattern along with the fields leading from that expression
+    /// to the symbol. `None` if the symbol is not bound to an expression which can be followed
+    /// (function arguments, match alternatives, etc).
+    value: Option<(&'a SpannedExpr<Symbol>, Vec<String>)>,
+}
+
+/// Collects where each symbol of an expression is bound
+struct CollectBindings<'a> {
+    bindings: FnvMap<Symbol, Binding<'a>>,
+}
+
+impl<'a> CollectBindings<'a> {
+    fn bind(&mut self, symbol: &Symbol, binding: Binding<'a>) {
+        // Keep the binding which is found first when walking the expression
+        self.bindings.entry(symbol.clone()).or_insert(binding);
+    }
+}
+
+impl<'a> Visitor<'a> for CollectBindings<'a> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+        match e.value {
+            Expr::LetBindings(ref binds, _) => for bind in binds {
+                let value = &bind.expr;
+                bound_in_pattern(&bind.name, Some(Vec::new()), &mut |symbol, span, path| {
+                    self.bind(
+                        symbol,
+                        Binding {
+                            span,
+                            value: path.map(|path| (value, path)),
+                        },
+                    )
+                });
+                for arg in &bind.args {
+                    self.bind(
+                        &arg.name.value.name,
+                        Binding {
+                            span: arg.name.span,
+                            value: None,
+                        },
+                    );
+                }
+            },
+            Expr::TypeBindings(ref binds, _) => for bind in binds {
+                self.bind(
+                    &bind.name.value,
+                    Binding {
+                        span: bind.name.span,
+                        value: None,
+                    },
+                );
+            },
+            Expr::Lambda(ref lambda) => for arg in &lambda.args {
+                self.bind(
+                    &arg.name.value.name,
+                    Binding {
+                        span: arg.name.span,
+                        value: None,
+                    },
+                );
+            },
+            _ => (),
+        }
+        walk_expr(self, e)
+    }
+
+    fn visit_pattern(&mut self, p: &'a SpannedPattern<Self::Ident>) {
+        bound_in_pattern(p, None, &mut |symbol, span, _| {
+            self.bind(symbol, Binding { span, value: None })
+        });
+    }
+}
+
+/// Calls `f` with every symbol bound in `pattern`, the span where it is bound and the record
+/// fields leading to it. `path` is the fields leading to `pattern` itself, `None` if it is inside
+/// a tuple or constructor pattern.
+fn bound_in_pattern(
+    pattern: &SpannedPattern<Symbol>,
+    path: Option<Vec<String>>,
+    f: &mut FnMut(&Symbol, Span<BytePos>, Option<Vec<String>>),
+) {
+    let field_path = |name: &Symbol| {
+        path.clone().map(|mut path| {
+            path.push(name.declared_name().to_string());
+            path
+        })
+    };
+    match pattern.value {
+        Pattern::Ident(ref id) => f(&id.name, pattern.span, path.clone()),
+        Pattern::As(ref id, ref sub_pattern) => {
+            f(id, pattern.span, path.clone());
+            bound_in_pattern(sub_pattern, path.clone(), f);
+        }
+        Pattern::Record {
+            ref types,
+            ref fields,
+            ..
+        } => {
+            for field in types {
+                f(
+                    field.value.as_ref().unwrap_or(&field.name.value),
+                    field.name.span,
+                    field_path(&field.name.value),
+                );
+            }
+            for field in fields {
+                match field.value {
+                    Some(ref pattern) => {
+                        bound_in_pattern(pattern, field_path(&field.name.value), f)
+                    }
+                    None => f(
+                        &field.name.value,
+                        field.name.span,
+                        field_path(&field.name.value),
+                    ),
+                }
+            }
+        }
+        Pattern::Tuple { ref elems, .. } | Pattern::Constructor(_, ref elems) => {
+            for elem in elems {
+                bound_in_pattern(elem, None, f);
+            }
+        }
+        Pattern::Literal(_) | Pattern::Error => (),
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum CompletionSymbol<'a> {
     Value {
@@ -1023,6 +1972,136 @@ pub fn all_symbols(
     visitor.result
 }
 
+/// A symbol in the outline of a module together with the symbols defined inside it
+#[derive(Debug, PartialEq)]
+pub struct Outline<'a> {
+    pub symbol: Spanned<CompletionSymbol<'a>, BytePos>,
+    pub children: Vec<Outline<'a>>,
+}
+
+/// Returns the hierarchical structure of the bindings in `expr`. Let bindings defined in the
+/// expression of another let binding are the children of that binding.
+pub fn outline(source_span: Span<BytePos>, expr: &SpannedExpr<Symbol>) -> Vec<Outline> {
+    struct OutlineVisitor<'a> {
+        source_span: Span<BytePos>,
+        result: Vec<Outline<'a>>,
+    }
+    impl<'a> OutlineVisitor<'a> {
+        fn children(&self, expr: &'a SpannedExpr<Symbol>) -> Vec<Outline<'a>> {
+            let mut visitor = OutlineVisitor {
+                source_span: self.source_span,
+                result: Vec::new(),
+            };
+            visitor.visit_expr(expr);
+            visitor.result
+        }
+    }
+    impl<'a> Visitor<'a> for OutlineVisitor<'a> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+            if !self.source_span.contains(e.span) {
+                return walk_expr(self, e);
+            }
+            match e.value {
+                Expr::TypeBindings(ref binds, ref body) => {
+                    self.result.extend(binds.iter().map(|bind| Outline {
+                        symbol: pos::spanned(
+                            bind.name.span,
+                            CompletionSymbol::Type {
+                                name: &bind.name.value,
+                                alias: &bind.alias.value,
+                            },
+                        ),
+                        children: Vec::new(),
+                    }));
+                    self.visit_expr(body);
+                }
+                Expr::LetBindings(ref binds, ref body) => {
+                    for bind in binds {
+                        let children = self.children(&bind.expr);
+                        match bind.name.value {
+                            Pattern::Ident(ref id) => self.result.push(Outline {
+                                symbol: pos::spanned(
+                                    bind.name.span,
+                                    CompletionSymbol::Value {
+                                        name: &id.name,
+                                        typ: &id.typ,
+                                        expr: &bind.expr,
+                                    },
+                                ),
+                                children,
+                            }),
+                            _ => self.result.extend(children),
+                        }
+                    }
+                    self.visit_expr(body);
+                }
+                _ => walk_expr(self, e),
+            }
+        }
+    }
+
+    let mut visitor = OutlineVisitor {
+        source_span,
+        result: Vec::new(),
+    };
+    visitor.visit_expr(expr);
+    visitor.result
+}
+
+/// Returns the inferred types of the let bindings without a type annotation and of the function
+/// and lambda parameters in `expr`, each at the span of the name it belongs to. Intended to be
+/// rendered next to the names as inline hints.
+pub fn inlay_hints(
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<Symbol>,
+) -> Vec<Spanned<String, BytePos>> {
+    struct InlayHints {
+        source_span: Span<BytePos>,
+        result: Vec<Spanned<String, BytePos>>,
+    }
+    impl InlayHints {
+        fn hint(&mut self, span: Span<BytePos>, typ: &ArcType) {
+            if self.source_span.contains(span) {
+                self.result.push(pos::spanned(span, typ.to_string()));
+            }
+        }
+
+        fn args(&mut self, args: &[Argument<SpannedIdent<Symbol>>]) {
+            for arg in args {
+                self.hint(arg.name.span, &arg.name.value.typ);
+            }
+        }
+    }
+    impl<'a> Visitor<'a> for InlayHints {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<Self::Ident>) {
+            match e.value {
+                Expr::LetBindings(ref binds, _) => for bind in binds {
+                    if bind.typ.is_none() {
+                        if let Pattern::Ident(_) = bind.name.value {
+                            self.hint(bind.name.span, &bind.resolved_type);
+                        }
+                    }
+                    self.args(&bind.args);
+                },
+                Expr::Lambda(ref lambda) => self.args(&lambda.args),
+                _ => (),
+            }
+            walk_expr(self, e)
+        }
+    }
+
+    let mut visitor = InlayHints {
+        source_span,
+        result: Vec::new(),
+    };
+    visitor.visit_expr(expr);
+    visitor.result
+}
+
 pub fn suggest<T>(
     env: &T,
     source_span: Span<BytePos>,
@@ -1489,6 +2568,8 @@ impl SuggestionQuery {
 pub struct SignatureHelp {
     pub name: String,
     pub typ: ArcType,
+    /// The names of the explicit parameters of the function, if they are known from its metadata
+    pub params: Vec<String>,
     pub index: Option<u32>,
 }
 
@@ -1497,6 +2578,17 @@ pub fn signature_help(
     source_span: Span<BytePos>,
     expr: &SpannedExpr<Symbol>,
     pos: BytePos,
+) -> Option<SignatureHelp> {
+    signature_help_with_metadata(env, &FnvMap::default(), source_span, expr, pos)
+}
+
+/// Like `signature_help` but also looks up the names of the function's parameters in `metadata`
+pub fn signature_help_with_metadata(
+    env: &TypeEnv,
+    metadata: &FnvMap<Symbol, Metadata>,
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<Symbol>,
+    pos: BytePos,
 ) -> Option<SignatureHelp> {
     complete_at((), source_span, expr, pos)
         .ok()
@@ -1535,7 +2627,12 @@ pub fn signature_help(
                         } else {
                             None
                         };
-                        SignatureHelp { name, typ, index }
+                        SignatureHelp {
+                            name,
+                            typ,
+                            params: parameter_names(metadata, func),
+                            index,
+                        }
                     }),
                     _ => None,
                 },
@@ -1557,6 +2654,7 @@ pub fn signature_help(
                         expr.value.try_type_of(env).ok().map(|typ| SignatureHelp {
                             name,
                             typ,
+                            params: parameter_names(metadata, expr),
                             index: if pos > expr.span.end() { Some(0) } else { None },
                         })
                     }
@@ -1566,6 +2664,64 @@ pub fn signature_help(
         })
 }
 
+/// Everything needed to show information about the symbol at a position
+#[derive(Debug, PartialEq)]
+pub struct Hover<'a> {
+    pub span: Span<BytePos>,
+    pub typ: Either<ArcKind, ArcType>,
+    pub comment: Option<&'a Comment>,
+    pub definition: Option<Definition>,
+}
+
+/// Returns the type, documentation comment and definition of the symbol at `pos`. `modules` is
+/// used to find definitions in other modules as in `find_definition`.
+pub fn hover<'a, 'e, T, F>(
+    env: &T,
+    metadata: &'a FnvMap<Symbol, Metadata>,
+    modules: F,
+    source_span: Span<BytePos>,
+    expr: &'e SpannedExpr<Symbol>,
+    pos: BytePos,
+) -> Option<Hover<'a>>
+where
+    T: TypeEnv,
+    F: FnMut(&str) -> Option<&'e SpannedExpr<Symbol>>,
+{
+    let extract = (SpanAt, TypeAt { env });
+    let (span, typ) = completion(extract, source_span, expr, pos).ok()?;
+    let comment = get_metadata(metadata, source_span, expr, pos)
+        .and_then(|metadata| metadata.comment.as_ref());
+    Some(Hover {
+        span,
+        typ,
+        comment,
+        definition: find_definition(modules, source_span, expr, pos),
+    })
+}
+
+fn parameter_names(metadata: &FnvMap<Symbol, Metadata>, func: &SpannedExpr<Symbol>) -> Vec<String> {
+    fn expr_metadata<'m>(
+        metadata: &'m FnvMap<Symbol, Metadata>,
+        expr: &SpannedExpr<Symbol>,
+    ) -> Option<&'m Metadata> {
+        match expr.value {
+            Expr::Ident(ref id) => metadata.get(&id.name),
+            Expr::Projection(ref expr, ref field, _) => expr_metadata(metadata, expr)
+                .and_then(|metadata| metadata.module.get(field.as_ref())),
+            _ => None,
+        }
+    }
+
+    expr_metadata(metadata, func).map_or_else(Vec::new, |metadata| {
+        metadata
+            .args
+            .iter()
+            .filter(|arg| arg.arg_type == ArgType::Explicit)
+            .map(|arg| arg.name.declared_name().to_string())
+            .collect()
+    })
+}
+
 pub fn get_metadata<'a>(
     env: &'a FnvMap<Symbol, Metadata>,
     source_span: Span<BytePos>,
@@ -0,0 +1,116 @@
+#[macro_use]
+extern crate collect_mac;
+extern crate env_logger;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use base::pos::Span;
+use base::types::{Field, Type};
+
+use completion::TextEdit;
+
+#[allow(unused)]
+mod support;
+use support::{intern, loc, MockEnv};
+
+#[test]
+fn add_type_annotation_to_let() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let abc = 1
+abc
+"#;
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let result = completion::add_type_annotation(expr.span, &expr, loc(text, 1, 5));
+    let end = loc(text, 1, 7);
+    let expected = Some(TextEdit {
+        module: None,
+        span: Span::new(end, end),
+        new_text: " : Int".to_string(),
+    });
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn dont_add_type_annotation_to_annotated_let() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let abc : Int = 1
+abc
+"#;
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let result = completion::add_type_annotation(expr.span, &expr, loc(text, 1, 5));
+    assert_eq!(result, None);
+}
+
+#[test]
+fn add_import_for_unresolved_name() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+abc
+"#;
+    let (expr, _result) = support::typecheck_partial_expr(text);
+
+    let module_type = Type::record(vec![], vec![Field::new(intern("abc"), Type::int())]);
+    let other_type = Type::record(vec![], vec![Field::new(intern("x"), Type::int())]);
+    let modules = vec![("example", &module_type), ("other", &other_type)];
+
+    let result = completion::add_import(
+        &MockEnv::new(),
+        modules,
+        expr.span,
+        &expr,
+        loc(text, 1, 1),
+    );
+    let start = expr.span.start();
+    let expected = vec![TextEdit {
+        module: None,
+        span: Span::new(start, start),
+        new_text: "let { abc } = import! example\n".to_string(),
+    }];
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn add_import_for_unresolved_operator() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+1 <+> 2
+"#;
+    let (expr, _result) = support::typecheck_partial_expr(text);
+
+    let module_type = Type::record(
+        vec![],
+        vec![Field::new(
+            intern("<+>"),
+            Type::function(vec![Type::int(), Type::int()], Type::int()),
+        )],
+    );
+    let modules = vec![("example", &module_type)];
+
+    let result = completion::add_import(
+        &MockEnv::new(),
+        modules,
+        expr.span,
+        &expr,
+        loc(text, 1, 3),
+    );
+    let start = expr.span.start();
+    let expected = vec![TextEdit {
+        module: None,
+        span: Span::new(start, start),
+        new_text: "let { (<+>) } = import! example\n".to_string(),
+    }];
+    assert_eq!(result, expected);
+}
@@ -384,6 +384,40 @@ abc
     assert_eq!(result, expected);
 }
 
+#[test]
+fn hover_at_variable() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+/// test
+let abc = 1
+abc
+"#;
+    let env = MockEnv::new();
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+
+    let hover = completion::hover(
+        &env,
+        &metadata_map,
+        |_| None,
+        expr.span,
+        &expr,
+        loc(text, 3, 1),
+    ).expect("hover");
+    assert_eq!(hover.span, Span::new(loc(text, 3, 0), loc(text, 3, 3)));
+    assert_eq!(hover.typ, Either::Right(Type::int()));
+    assert_eq!(hover.comment, Some(&line_comment("test")));
+    assert_eq!(
+        hover.definition,
+        Some(completion::Definition {
+            module: None,
+            span: Span::new(loc(text, 2, 4), loc(text, 2, 7)),
+        })
+    );
+}
+
 #[test]
 fn metadata_at_binop() {
     let _ = env_logger::try_init();
@@ -536,6 +570,76 @@ let { x, y } = { x = 1, y = 2 }
     assert_eq!(symbols.len(), 4);
 }
 
+#[test]
+fn outline_test() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let test = 1
+let dummy =
+    let test = 3
+    test
+type Abc a = a Int
+let { x, y } = { x = 1, y = 2 }
+1
+"#;
+
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    fn names(outline: &[completion::Outline]) -> Vec<(String, usize)> {
+        outline
+            .iter()
+            .map(|outline| {
+                let name = match outline.symbol.value {
+                    completion::CompletionSymbol::Value { name, .. }
+                    | completion::CompletionSymbol::Type { name, .. } => name.declared_name(),
+                };
+                (name.to_string(), outline.children.len())
+            })
+            .collect()
+    }
+
+    let outline = completion::outline(expr.span, &expr);
+    assert_eq!(
+        names(&outline),
+        vec![
+            ("test".to_string(), 0),
+            ("dummy".to_string(), 1),
+            ("Abc".to_string(), 0),
+        ]
+    );
+    assert_eq!(names(&outline[1].children), vec![("test".to_string(), 0)]);
+}
+
+#[test]
+fn inlay_hints_test() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let abc = 1
+let annotated : Int = 2
+let f = \x -> x #Int+ abc
+f annotated
+"#;
+
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let hints: Vec<_> = completion::inlay_hints(expr.span, &expr)
+        .into_iter()
+        .map(|hint| (hint.span, hint.value))
+        .collect();
+    assert_eq!(
+        hints,
+        vec![
+            (Span::new(loc(text, 1, 4), loc(text, 1, 7)), "Int".to_string()),
+            (Span::new(loc(text, 3, 4), loc(text, 3, 5)), "Int -> Int".to_string()),
+            (Span::new(loc(text, 3, 9), loc(text, 3, 10)), "Int".to_string()),
+        ]
+    );
+}
+
 #[test]
 fn completion_on_type() {
     let _ = env_logger::try_init();
@@ -36,6 +36,7 @@ test //
     let expected = Some(SignatureHelp {
         name: "test".to_string(),
         typ: Type::function(collect![typ("Int"), typ("String")], typ("Int")),
+        params: Vec::new(),
         index: Some(0),
     });
 
@@ -57,6 +58,7 @@ test 123//
     let expected = Some(SignatureHelp {
         name: "test".to_string(),
         typ: Type::function(collect![typ("Int"), typ("String")], typ("Int")),
+        params: Vec::new(),
         index: None,
     });
 
@@ -78,6 +80,7 @@ test 123 //
     let expected = Some(SignatureHelp {
         name: "test".to_string(),
         typ: Type::function(collect![typ("Int"), typ("String")], typ("Int")),
+        params: Vec::new(),
         index: Some(1),
     });
 
@@ -99,8 +102,38 @@ test { x = "" }
     let expected = Some(SignatureHelp {
         name: "".to_string(),
         typ: typ("String"),
+        params: Vec::new(),
         index: None,
     });
 
     assert_eq!(result, expected);
 }
+
+#[test]
+fn parameter_names_from_metadata() {
+    let _ = env_logger::try_init();
+
+    let expr_str = r#"
+/// A test function
+let test x y : Int -> String -> Int = x
+test 123 //
+"#;
+    let (expr, _result) = support::typecheck_partial_expr(expr_str);
+    let env = support::MockEnv::new();
+    let (_, metadata) = check::metadata::metadata(&env, &expr);
+    let result = completion::signature_help_with_metadata(
+        &env,
+        &metadata,
+        expr.span,
+        &expr,
+        loc(expr_str, 3, 9),
+    );
+    let expected = Some(SignatureHelp {
+        name: "test".to_string(),
+        typ: Type::function(collect![typ("Int"), typ("String")], typ("Int")),
+        params: vec!["x".to_string(), "y".to_string()],
+        index: Some(1),
+    });
+
+    assert_eq!(result, expected);
+}
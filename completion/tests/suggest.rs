@@ -184,6 +184,28 @@ record.
     assert_eq!(result, expected);
 }
 
+#[test]
+fn suggest_field_types_after_dot() {
+    let _ = env_logger::try_init();
+    let text = r#"
+let record = { aa = 1, ab = 2.0 }
+record.
+"#;
+    let result = suggest_types(text, loc(text, 2, 7));
+    let expected = Ok(vec![
+        Suggestion {
+            name: "aa".into(),
+            typ: Either::Right(Type::int()),
+        },
+        Suggestion {
+            name: "ab".into(),
+            typ: Either::Right(Type::float()),
+        },
+    ]);
+
+    assert_eq!(result, expected);
+}
+
 #[test]
 fn suggest_from_record_unpack() {
     let _ = env_logger::try_init();
@@ -0,0 +1,53 @@
+//! Comments are not part of the AST, the printer copies them from the source text between the
+//! nodes it prints. Comments in positions which the printer does not copy from (such as directly
+//! after the `with` of a `match`) are found here so that they can be attached to the expression
+//! following them instead of being dropped.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use codespan::ByteOffset;
+use pretty::{Doc, RefDoc};
+
+use base::fnv::FnvSet;
+use base::pos::{BytePos, Span};
+use base::source::Source;
+
+/// Returns the spans of the `comments` in `source` which are not printed by `doc`
+pub(crate) fn missing<'a>(
+    source: &Source,
+    comments: &[Span<BytePos>],
+    doc: &Doc<'a, RefDoc<'a, ()>>,
+) -> VecDeque<Span<BytePos>> {
+    let src = source.src();
+    let src_start = src.as_ptr() as usize;
+
+    let mut printed = FnvSet::default();
+    let mut stack = vec![doc];
+    while let Some(doc) = stack.pop() {
+        match *doc {
+            Doc::Append(ref l, ref r) => {
+                stack.push(r);
+                stack.push(l);
+            }
+            Doc::Group(ref doc) | Doc::Nest(_, ref doc) | Doc::Annotated(_, ref doc) => {
+                stack.push(doc)
+            }
+            // Comments are printed as slices of the source so where they come from can be
+            // recovered from their address
+            Doc::Text(Cow::Borrowed(text)) => {
+                let offset = (text.as_ptr() as usize).wrapping_sub(src_start);
+                if offset < src.len() {
+                    printed.insert(source.span().start() + ByteOffset::from(offset as i64));
+                }
+            }
+            Doc::Text(Cow::Owned(_)) | Doc::Nil | Doc::Space | Doc::Newline => (),
+        }
+    }
+
+    comments
+        .iter()
+        .filter(|span| !printed.contains(&span.start()))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,151 @@
+//! Conversion of a formatted file into the minimal set of line edits which turn the original
+//! source into the formatted source.
+use std::ops::Range;
+
+/// Replaces the text at `range` (byte offsets into the original source) with `new_text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
+/// Splits `s` into lines, keeping the line terminators so that the lines can be concatenated back
+/// into `s`
+fn lines(s: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if c == '\n' {
+            lines.push(&s[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start != s.len() {
+        lines.push(&s[start..]);
+    }
+    lines
+}
+
+/// One step of an edit script between two sequences of lines
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes a shortest edit script which turns `old` into `new` using Myers' algorithm. It takes
+/// O((N + M) * D) time and space where D is the number of lines which are deleted or inserted.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m;
+    // `v[k + offset]` is the furthest `x` (index into `old`) reached on diagonal `k = x - y`
+    let mut v = vec![0isize; 2 * offset as usize + 2];
+    let mut trace = Vec::new();
+    'search: for d in 0..offset + 1 {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk back from the end through the furthest reaching paths of each step
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let index = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Computes the edits which transform `original` into `formatted`. Unchanged lines are never part
+/// of an edit.
+pub(crate) fn diff(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let old = lines(original);
+    let new = lines(formatted);
+
+    // Formatting usually changes a few lines in the middle of a file so the lines which are the
+    // same at the start and the end are skipped before computing the edit script
+    let prefix = old
+        .iter()
+        .zip(&new)
+        .take_while(|&(old_line, new_line)| old_line == new_line)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(old_line, new_line)| old_line == new_line)
+        .count();
+    let mut offset = old[..prefix].iter().map(|line| line.len()).sum::<usize>();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut edits = Vec::new();
+    let mut edit: Option<TextEdit> = None;
+    let (mut i, mut j) = (0, 0);
+    for op in edit_script(old, new) {
+        if op == Op::Equal {
+            edits.extend(edit.take());
+            offset += old[i].len();
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let current = edit.get_or_insert_with(|| TextEdit {
+            range: offset..offset,
+            new_text: String::new(),
+        });
+        if op == Op::Insert {
+            current.new_text.push_str(new[j]);
+            j += 1;
+        } else {
+            offset += old[i].len();
+            current.range.end = offset;
+            i += 1;
+        }
+    }
+    edits.extend(edit);
+    edits
+}
+
+/// Returns true if `edit` touches any part of `range`
+pub(crate) fn overlaps(edit: &TextEdit, range: &Range<usize>) -> bool {
+    edit.range.start <= range.end && range.start <= edit.range.end
+}
@@ -11,12 +11,46 @@ extern crate pretty;
 use gluon::compiler_pipeline::*;
 use gluon::{Compiler, Error, Result, Thread};
 
+use std::ops::Range;
+
 use base::ast::{self, SpannedExpr};
 use base::pos::{BytePos, Span};
 use base::symbol::Symbol;
 
+mod comments;
+mod edit;
 mod pretty_print;
 
+pub use edit::TextEdit;
+
+/// Options controlling the layout produced by the formatter
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatSettings {
+    /// The width at which the formatter tries to break lines
+    pub max_width: usize,
+    /// The number of spaces used for each level of indentation
+    pub indent: usize,
+    /// Whether a comma is placed after the last field of a record spanning multiple lines
+    pub trailing_commas: bool,
+    /// Whether every record with fields is broken into one field per line, even if it would fit
+    /// on a single line
+    pub always_break_records: bool,
+    /// Whether the body of a match alternative is always placed on its own line after `->`
+    pub always_break_match_alternatives: bool,
+}
+
+impl Default for FormatSettings {
+    fn default() -> FormatSettings {
+        FormatSettings {
+            max_width: 100,
+            indent: 4,
+            trailing_commas: true,
+            always_break_records: false,
+            always_break_match_alternatives: false,
+        }
+    }
+}
+
 fn has_format_disabling_errors(file: &codespan::FileName, err: &Error) -> bool {
     match *err {
         Error::Multiple(ref errors) => errors
@@ -28,6 +62,24 @@ fn has_format_disabling_errors(file: &codespan::FileName, err: &Error) -> bool {
 }
 
 pub fn pretty_expr(input: &str, expr: &SpannedExpr<Symbol>) -> String {
+    pretty_expr_with_settings(&FormatSettings::default(), input, expr)
+}
+
+pub fn pretty_expr_with_settings(
+    settings: &FormatSettings,
+    input: &str,
+    expr: &SpannedExpr<Symbol>,
+) -> String {
+    let source = codespan::FileMap::new("test".into(), input.into());
+    pretty_source(settings, &source, expr)
+}
+
+fn pretty_source(
+    settings: &FormatSettings,
+    source: &codespan::FileMap,
+    expr: &SpannedExpr<Symbol>,
+) -> String {
+    let input = source.src();
     let newline = match input.find(|c: char| c == '\n' || c == '\r') {
         Some(i) => {
             if input[i..].starts_with("\r\n") {
@@ -41,17 +93,33 @@ pub fn pretty_expr(input: &str, expr: &SpannedExpr<Symbol>) -> String {
         None => "\n",
     };
 
-    let source = codespan::FileMap::new("test".into(), input.into());
+    let comments = gluon::parser::comment_spans(source);
     let arena = pretty::Arena::new();
-    let printer = pretty_print::Printer::new(&arena, &source);
-    printer.format(100, newline, &expr)
+    let printer = pretty_print::Printer::new(&arena, source, settings);
+    printer.format(settings.max_width, newline, &expr, &comments)
 }
 
+/// Formats `input` with the default settings, see `format_expr_with_settings`
 pub fn format_expr(
     compiler: &mut Compiler,
     thread: &Thread,
     file: &str,
     input: &str,
+) -> Result<String> {
+    format_expr_with_settings(compiler, thread, &FormatSettings::default(), file, input)
+}
+
+/// Formats `input`, the contents of `file`.
+///
+/// Every comment of `input` is kept. A comment in a position where the formatter does not
+/// otherwise keep comments, such as directly after the `with` of a `match`, is moved to just
+/// before the expression following it.
+pub fn format_expr_with_settings(
+    compiler: &mut Compiler,
+    thread: &Thread,
+    settings: &FormatSettings,
+    file: &str,
+    input: &str,
 ) -> Result<String> {
     let expr = match input.reparse_infix(compiler, thread, file, input) {
         Ok(expr) => expr.expr,
@@ -73,9 +141,41 @@ pub fn format_expr(
         }
     }
 
+    // The spans of `expr` are only valid in the file map which the compiler created for `file`
     let file_map = compiler.get_filemap(file).unwrap();
-    Ok(pretty_expr(
-        input,
+    Ok(pretty_source(
+        settings,
+        file_map,
         skip_implicit_prelude(file_map.span(), &expr),
     ))
 }
+
+/// Formats `input` and returns the edits which turn `input` into the formatted source instead of
+/// the whole formatted source. Lines which do not change are not part of any edit.
+pub fn format_edits(
+    compiler: &mut Compiler,
+    thread: &Thread,
+    settings: &FormatSettings,
+    file: &str,
+    input: &str,
+) -> Result<Vec<TextEdit>> {
+    let formatted = format_expr_with_settings(compiler, thread, settings, file, input)?;
+    Ok(edit::diff(input, &formatted))
+}
+
+/// Formats the lines of `input` touched by `range` (byte offsets into `input`), leaving every
+/// other line as is.
+pub fn format_range(
+    compiler: &mut Compiler,
+    thread: &Thread,
+    settings: &FormatSettings,
+    file: &str,
+    input: &str,
+    range: Range<usize>,
+) -> Result<Vec<TextEdit>> {
+    let edits = format_edits(compiler, thread, settings, file, input)?;
+    Ok(edits
+        .into_iter()
+        .filter(|edit| edit::overlaps(edit, &range))
+        .collect())
+}
@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::{iter, ops};
 
 use codespan::{ByteOffset, RawOffset};
@@ -12,7 +14,8 @@ use base::pos::{self, BytePos, HasSpan, Span, Spanned};
 use base::source;
 use base::types::{self, ArgType, Prec, Type};
 
-const INDENT: usize = 4;
+use comments;
+use FormatSettings;
 
 macro_rules! newlines_iter {
     ($self_:ident, $iterable:expr) => {
@@ -36,15 +39,24 @@ macro_rules! rev_newlines_iter {
 
 pub(super) struct Printer<'a, I: 'a> {
     printer: pretty_types::Printer<'a, I>,
+    settings: &'a FormatSettings,
+    /// Comments which were not printed by the first pass, see `comments::missing`
+    missing_comments: RefCell<VecDeque<Span<BytePos>>>,
 }
 
 impl<'a, I> Printer<'a, I>
 where
     I: AsRef<str>,
 {
-    pub(super) fn new(arena: &'a Arena<'a>, source: &'a source::Source) -> Self {
+    pub(super) fn new(
+        arena: &'a Arena<'a>,
+        source: &'a source::Source,
+        settings: &'a FormatSettings,
+    ) -> Self {
         Printer {
             printer: pretty_types::Printer::new(arena, source),
+            settings,
+            missing_comments: RefCell::default(),
         }
     }
 
@@ -53,9 +65,15 @@ where
         width: usize,
         newline: &'a str,
         expr: &'a SpannedExpr<I>,
+        comments: &[Span<BytePos>],
     ) -> String {
-        self.pretty_expr(expr)
-            .1
+        let mut doc = self.pretty_expr(expr);
+        let missing = comments::missing(self.source, comments, &doc.1);
+        if !missing.is_empty() {
+            *self.missing_comments.borrow_mut() = missing;
+            doc = self.pretty_expr(expr);
+        }
+        doc.1
             .pretty(width)
             .to_string()
             .lines()
@@ -66,6 +84,7 @@ where
     fn pretty_expr(&self, expr: &'a SpannedExpr<I>) -> DocBuilder<'a, Arena<'a>> {
         self.pretty_expr_with_shebang_line(expr)
             .append(self.comments(Span::new(expr.span.end(), self.source.span().end())))
+            .append(self.missing_comments_before(self.source.span().end()))
     }
 
     fn pretty_expr_with_shebang_line(&self, expr: &'a SpannedExpr<I>) -> DocBuilder<'a, Arena<'a>> {
@@ -98,6 +117,7 @@ where
 
         let pretty = |next: &'a SpannedExpr<_>| self.pretty_expr_(next.span.start(), next);
 
+        let missing_comments = self.missing_comments_before(expr.span.start());
         let comments = self.comments(Span::new(previous_end, expr.span.start()));
         let doc = match expr.value {
             Expr::App {
@@ -119,7 +139,7 @@ where
                             .append(pretty(arg))
                     });
                 pretty(func)
-                    .append(arena.concat(arg_iter).nest(INDENT))
+                    .append(arena.concat(arg_iter).nest(self.settings.indent))
                     .group()
             }
 
@@ -163,7 +183,7 @@ where
                     arena.text("if ").append(pretty(body)).group(),
                     arena.space(),
                     "then",
-                    space.clone().append(pretty(if_true)).nest(INDENT).group(),
+                    space.clone().append(pretty(if_true)).nest(self.settings.indent).group(),
                     space.clone(),
                     "else",
                     self.pretty_else_expr(space, if_false)
@@ -182,7 +202,7 @@ where
                         op.value.name.as_ref(),
                         " ",
                         pretty(rhs).group()
-                    ].nest(INDENT)
+                    ].nest(self.settings.indent)
                 ],
 
             Expr::Lambda(_) => {
@@ -214,7 +234,7 @@ where
                             Some(ref typ) => arena.text(": ")
                                 .append(types::pretty_print(self, typ))
                                 .append(self.space_after(typ.span().end()))
-                                .nest(INDENT),
+                                .nest(self.settings.indent),
                         },
                         "="
                     ];
@@ -246,8 +266,19 @@ where
                         chain![arena;
                             "| ",
                             self.pretty_pattern(&alt.pattern),
+                            match alt.guard {
+                                Some(ref guard) => chain![arena; " if ", pretty(guard)],
+                                None => arena.nil(),
+                            },
                             " ->",
-                            self.hang(arena.nil(), &alt.expr).group()
+                            if self.settings.always_break_match_alternatives {
+                                chain![arena;
+                                    arena.newline(),
+                                    pretty(&alt.expr)
+                                ].nest(self.settings.indent)
+                            } else {
+                                self.hang(arena.nil(), &alt.expr).group()
+                            }
                         ]
                     }).intersperse(arena.newline()))
                 ],
@@ -291,7 +322,7 @@ where
                         let mut type_doc = types::pretty_print(self, typ);
                         match **typ {
                             Type::Record(_) | Type::Variant(_) => (),
-                            _ => type_doc = type_doc.nest(INDENT),
+                            _ => type_doc = type_doc.nest(self.settings.indent),
                         }
                         chain![arena;
                             prefix,
@@ -321,7 +352,7 @@ where
                                         "=",
                                         arena.newline(),
                                         type_doc
-                                    ].nest(INDENT)
+                                    ].nest(self.settings.indent)
                                 }
                                 _ => {
                                     chain![arena;
@@ -353,11 +384,11 @@ where
                         self.pretty_expr_(bound.span.end(), body)
                     ],
             Expr::MacroExpansion { ref original, .. } => {
-                return self.pretty_expr_(previous_end, original)
+                return missing_comments.append(self.pretty_expr_(previous_end, original))
             }
             Expr::Error(_) => arena.text("<error>"),
         };
-        comments.append(doc)
+        missing_comments.append(comments).append(doc)
     }
 
     fn space(&self, span: Span<BytePos>) -> DocBuilder<'a, Arena<'a>> {
@@ -395,12 +426,12 @@ where
                     arena.text(" if ").append(pretty(body)).group(),
                     arena.space(),
                     "then",
-                    space.clone().append(pretty(if_true)).nest(INDENT).group(),
+                    space.clone().append(pretty(if_true)).nest(self.settings.indent).group(),
                     space.clone(),
                     "else",
                     self.pretty_else_expr(space, if_false)
                 ],
-            _ => space.append(pretty(if_false)).nest(INDENT).group(),
+            _ => space.append(pretty(if_false)).nest(self.settings.indent).group(),
         }
     }
 
@@ -432,6 +463,7 @@ where
                 ref types,
                 ref exprs,
                 ref base,
+                ref removed,
                 ..
             } => {
                 let ordered_iter = || expr.value.field_iter();
@@ -471,14 +503,21 @@ where
                 let newline_in_base = base.as_ref().map_or(false, |base| {
                     self.space_before(base.span.start()).1 != arena.nil().1
                 });
-                if newline_in_fields || newline_in_base | newline_from_doc_comment {
+                let break_from_settings = self.settings.always_break_records
+                    && (!exprs.is_empty() || !types.is_empty());
+                if newline_in_fields
+                    || newline_in_base
+                    || newline_from_doc_comment
+                    || break_from_settings
+                {
                     line = arena.newline();
                 }
 
                 let last_field_end = spans()
                     .last()
                     .map_or(expr.span.start() + 1.into(), |s| s.end());
-                let last_element_end = base.as_ref().map_or(last_field_end, |base| base.span.end());
+                let last_base_end = base.as_ref().map_or(last_field_end, |base| base.span.end());
+                let last_element_end = removed.last().map_or(last_base_end, |name| name.span.end());
 
                 let record = arena
                     .concat(self.comma_sep(
@@ -509,7 +548,8 @@ where
                         }),
                         |spanned| spanned.value,
                     ))
-                    .append(if (!exprs.is_empty() || !types.is_empty())
+                    .append(if self.settings.trailing_commas
+                        && (!exprs.is_empty() || !types.is_empty())
                         && line.1 == arena.newline().1
                     {
                         arena.text(",")
@@ -527,12 +567,20 @@ where
                                 },
                                 "..",
                                 self.space_before(base.span.start()),
-                                self.pretty_expr_(base.span.start(), base)
+                                self.pretty_expr_(base.span.start(), base),
+                                arena.concat(removed.iter().map(|name| {
+                                    chain![arena;
+                                        ",",
+                                        line.clone(),
+                                        "-",
+                                        pretty_types::ident(arena, name.value.as_ref())
+                                    ]
+                                }))
                             ]
                         }
                         None => arena.nil(),
                     })
-                    .nest(INDENT)
+                    .nest(self.settings.indent)
                     .append(
                         self.whitespace(Span::new(last_element_end, expr.span.end()), line.clone()),
                     )
@@ -655,7 +703,7 @@ where
                         ),
                     |spanned| spanned.value,
                 );
-                let doc = arena.concat(iter).nest(INDENT);
+                let doc = arena.concat(iter).nest(self.settings.indent);
                 chain![arena;
                     "{",
                     doc,
@@ -713,7 +761,7 @@ where
                     body
                 ].group();
                 if needs_indent {
-                    doc.nest(INDENT)
+                    doc.nest(self.settings.indent)
                 } else {
                     doc
                 }
@@ -725,7 +773,7 @@ where
                             arguments
                         ].group()
                         .append(body)
-                        .nest(INDENT),
+                        .nest(self.settings.indent),
                 ).group()
             }
         }
@@ -755,6 +803,34 @@ where
         self.comments_count(span).0
     }
 
+    /// Returns the comments missing from the first pass which end before `pos`, each comment is
+    /// only returned once
+    fn missing_comments_before(&self, pos: BytePos) -> DocBuilder<'a, Arena<'a>> {
+        let arena = self.arena;
+        let mut missing_comments = self.missing_comments.borrow_mut();
+        let mut doc = arena.nil();
+        while missing_comments
+            .front()
+            .map_or(false, |span| span.end() <= pos)
+        {
+            let span = missing_comments.pop_front().unwrap();
+            let comment = self.source.src_slice(span);
+            doc = if comment.starts_with("//") {
+                // Put line comments on a line of their own, where the printer keeps them when
+                // formatting the output again
+                let newline = if doc.1 == arena.nil().1 {
+                    arena.newline()
+                } else {
+                    arena.nil()
+                };
+                chain![arena; doc, newline, comment, arena.newline()]
+            } else {
+                chain![arena; doc, comment, arena.space()]
+            };
+        }
+        doc
+    }
+
     fn rev_comments(&self, span: Span<BytePos>) -> DocBuilder<'a, Arena<'a>> {
         let arena = self.arena;
         self.source
@@ -9,7 +9,7 @@ extern crate gluon_base as base;
 extern crate gluon_format as format;
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::Path;
 
@@ -23,6 +23,17 @@ fn format_expr(expr: &str) -> gluon::Result<String> {
     format::format_expr(&mut compiler, &thread, "test", expr)
 }
 
+fn format_expr_with_settings(
+    settings: &format::FormatSettings,
+    expr: &str,
+) -> gluon::Result<String> {
+    let mut compiler = Compiler::new();
+    let thread = VmBuilder::new()
+        .import_paths(Some(vec!["..".into()]))
+        .build();
+    format::format_expr_with_settings(&mut compiler, &thread, settings, "test", expr)
+}
+
 fn test_format(name: &str) {
     let _ = env_logger::try_init();
 
@@ -140,6 +151,40 @@ fn repl() {
     test_format("repl/src/repl.glu");
 }
 
+#[test]
+fn preserve_comments_in_std() {
+    let _ = env_logger::try_init();
+
+    let mut compiler = Compiler::new();
+    let thread = VmBuilder::new()
+        .import_paths(Some(vec!["..".into()]))
+        .build();
+    for entry in fs::read_dir("../std").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "glu") {
+            continue;
+        }
+        let name = Path::new("std").join(path.file_name().unwrap());
+        let name = name.to_str().unwrap();
+
+        let mut contents = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let out_str = format::format_expr(&mut compiler, &thread, name, &contents)
+            .unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(comments(&contents), comments(&out_str), "{}", name);
+    }
+}
+
+fn comments(source: &str) -> Vec<&str> {
+    gluon::parser::comment_spans(source)
+        .into_iter()
+        .map(|span| &source[span.start().to_usize() - 1..span.end().to_usize() - 1])
+        .collect()
+}
+
 #[test]
 fn dont_add_newline_for_let_literal() {
     let expr = r#"
@@ -171,6 +216,53 @@ f ?32 ""
     assert_eq!(&format_expr(expr).unwrap(), expr);
 }
 
+#[test]
+fn keep_comment_after_match_with() {
+    let expr = r#"
+match 1 with // the comment
+| _ -> 2
+"#;
+    let expected = r#"
+match 1 with
+| _ ->
+    // the comment
+    2
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expected, " ", 0);
+    assert_diff!(&format_expr(expected).unwrap(), expected, " ", 0);
+}
+
+#[test]
+fn keep_comments_before_match_alternative() {
+    let expr = r#"
+match x with
+// first
+| Some y -> y
+/* second */
+| None -> 0
+"#;
+    let expected = r#"
+match x with
+| Some y ->
+    // first
+    y
+| None -> /* second */ 0
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expected, " ", 0);
+    assert_diff!(&format_expr(expected).unwrap(), expected, " ", 0);
+}
+
+#[test]
+fn keep_comment_after_infix_operator() {
+    let expr = r#"
+1 + // the comment
+    2
+"#;
+    let formatted = format_expr(expr).unwrap();
+    assert!(formatted.contains("// the comment"), "{}", formatted);
+    assert_diff!(&format_expr(&formatted).unwrap(), &formatted, " ", 0);
+}
+
 #[test]
 fn preserve_comment_between_let_in() {
     let expr = r#"
@@ -327,6 +419,23 @@ fn preserve_comments_in_record_base() {
     assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
 }
 
+#[test]
+fn record_base_with_removed_fields() {
+    let expr = r#"
+let x = {
+    .. test,
+    -y
+}
+{
+    x = 1,
+    .. test,
+    -y,
+    -z
+}
+"#;
+    assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
+}
+
 #[test]
 fn small_record_in_let() {
     let expr = r#"
@@ -406,6 +515,25 @@ type TestCase a =
     );
 }
 
+#[test]
+fn open_variant_type() {
+    let expr = r#"
+type Base r =
+    | A Int
+    | ..r
+type Ext r =
+    | B String
+    | ..Base r
+()
+"#;
+    assert_diff!(
+        &format_expr(expr).unwrap_or_else(|err| panic!("{}", err)),
+        expr,
+        " ",
+        0
+    );
+}
+
 #[test]
 fn multiline_string() {
     let expr = r#"
@@ -416,3 +544,95 @@ x
 "#;
     assert_diff!(&format_expr(expr).unwrap(), expr, " ", 0);
 }
+
+#[test]
+fn record_with_custom_indent_and_no_trailing_comma() {
+    let _ = env_logger::try_init();
+
+    let settings = format::FormatSettings {
+        indent: 2,
+        trailing_commas: false,
+        always_break_records: true,
+        ..format::FormatSettings::default()
+    };
+    let expr = r#"
+let x = { a = 1, b = 2 }
+x
+"#;
+    let expected = r#"
+let x = {
+  a = 1,
+  b = 2
+}
+x
+"#;
+    assert_diff!(
+        &format_expr_with_settings(&settings, expr).unwrap(),
+        expected,
+        " ",
+        0
+    );
+}
+
+#[test]
+fn always_break_match_alternatives() {
+    let _ = env_logger::try_init();
+
+    let settings = format::FormatSettings {
+        always_break_match_alternatives: true,
+        ..format::FormatSettings::default()
+    };
+    let expr = r#"
+match None with
+| Some x -> x
+| None -> 0
+"#;
+    let expected = r#"
+match None with
+| Some x ->
+    x
+| None ->
+    0
+"#;
+    assert_diff!(
+        &format_expr_with_settings(&settings, expr).unwrap(),
+        expected,
+        " ",
+        0
+    );
+}
+
+#[test]
+fn format_only_the_edits_in_range() {
+    let _ = env_logger::try_init();
+
+    let settings = format::FormatSettings {
+        indent: 2,
+        trailing_commas: false,
+        always_break_records: true,
+        ..format::FormatSettings::default()
+    };
+    let expr = r#"
+let x = { a = 1, b = 2 }
+
+let y = { c = 3, d = 4 }
+x
+"#;
+    let mut compiler = Compiler::new();
+    let thread = VmBuilder::new()
+        .import_paths(Some(vec!["..".into()]))
+        .build();
+
+    let edits = format::format_edits(&mut compiler, &thread, &settings, "test", expr).unwrap();
+    assert_eq!(edits.len(), 2);
+
+    let edits =
+        format::format_range(&mut compiler, &thread, &settings, "test", expr, 30..35).unwrap();
+    assert_eq!(
+        edits,
+        vec![format::TextEdit {
+            range: 27..52,
+            new_text: "let y = {\n  c = 3,\n  d = 4\n}\n".to_string(),
+        }]
+    );
+}
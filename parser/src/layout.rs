@@ -458,6 +458,16 @@ where
                     }
                 }
 
+                // The `if` of a pattern guard (`| x if x > 0 -> ...`) has no `else` to close its
+                // context so it is closed by the `->` of the alternative instead
+                (&Token::RArrow, Context::If) => {
+                    self.indent_levels.pop();
+                    if let Some(Context::MatchClause) =
+                        self.indent_levels.last().map(|offside| offside.context)
+                    {
+                        self.scan_for_next_block(Context::Block { emit_semi: false })?
+                    }
+                }
                 (&Token::Equals, Context::Let)
                 | (&Token::RArrow, Context::Lambda)
                 | (&Token::RArrow, Context::MatchClause)
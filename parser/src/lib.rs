@@ -34,6 +34,7 @@ use token::{Token, Tokenizer};
 
 pub use infix::Error as InfixError;
 pub use layout::Error as LayoutError;
+pub use token::comment_spans;
 pub use token::Error as TokenizeError;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
@@ -414,6 +415,83 @@ pub fn parse_expr(
     parse_partial_expr(symbols, type_cache, input).map_err(|t| t.1)
 }
 
+/// The category of a token, as used when highlighting gluon source
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenKind {
+    Keyword,
+    Identifier,
+    Operator,
+    String,
+    Char,
+    Number,
+    DocComment,
+    /// Brackets, braces and parentheses
+    Delimiter,
+    /// Punctuation such as `,` and `:`
+    Punctuation,
+}
+
+/// Splits `input` into tokens, returning the kind and byte range of each. Tokenizing stops at the
+/// first invalid token (such as an unterminated string) so incomplete input can be highlighted
+/// up to that point. Comments other than documentation comments are skipped.
+pub fn classify_tokens(input: &str) -> Vec<(TokenKind, ::std::ops::Range<usize>)> {
+    let start = input.start_index().to_usize();
+    Tokenizer::new(input)
+        .take_while(|token| match *token {
+            // The tokenizer keeps returning `EOF` at the end of the input
+            Ok(ref token) => token.value != Token::EOF,
+            Err(_) => false,
+        })
+        .filter_map(|token| token.ok())
+        .filter_map(|token| {
+            let kind = match token.value {
+                Token::Identifier(_) => TokenKind::Identifier,
+                Token::Operator(_) => TokenKind::Operator,
+                Token::StringLiteral(_) => TokenKind::String,
+                Token::CharLiteral(_) | Token::ByteLiteral(_) => TokenKind::Char,
+                Token::IntLiteral(_) | Token::FloatLiteral(_) => TokenKind::Number,
+                Token::DocComment(_) => TokenKind::DocComment,
+                Token::And
+                | Token::Else
+                | Token::Forall
+                | Token::If
+                | Token::In
+                | Token::Let
+                | Token::Do
+                | Token::Match
+                | Token::Then
+                | Token::Type
+                | Token::With => TokenKind::Keyword,
+                Token::LBrace
+                | Token::LBracket
+                | Token::LParen
+                | Token::RBrace
+                | Token::RBracket
+                | Token::RParen => TokenKind::Delimiter,
+                Token::At
+                | Token::Colon
+                | Token::Comma
+                | Token::Dot
+                | Token::DotDot
+                | Token::Equals
+                | Token::Lambda
+                | Token::Pipe
+                | Token::RArrow
+                | Token::Question
+                | Token::AttributeOpen => TokenKind::Punctuation,
+                Token::ShebangLine(_)
+                | Token::OpenBlock
+                | Token::CloseBlock
+                | Token::Semi
+                | Token::EOF => return None,
+            };
+            let range = (token.span.start().absolute.to_usize() - start)
+                ..(token.span.end().absolute.to_usize() - start);
+            Some((kind, range))
+        })
+        .collect()
+}
+
 pub type LetOrExpr<Id> = Result<SpannedExpr<Id>, ValueBinding<Id>>;
 
 pub fn parse_partial_let_or_expr<Id, S>(
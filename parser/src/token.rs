@@ -146,6 +146,9 @@ quick_error! {
         UnexpectedEscapeCode(ch: char) {
             description("unexpected escape code")
         }
+        InvalidUnicodeEscape {
+            description("invalid unicode escape code, expected `\\u{...}` with 1 to 6 hex digits")
+        }
         UnterminatedCharLiteral {
             description("unterminated character literal")
         }
@@ -395,12 +398,38 @@ impl<'input> Tokenizer<'input> {
             Some((_, 'n')) => Ok('\n'),
             Some((_, 'r')) => Ok('\r'),
             Some((_, 't')) => Ok('\t'),
-            // TODO: Unicode escape codes
+            Some((start, 'u')) => self.unicode_escape_code(start),
             Some((start, ch)) => self.error(start, UnexpectedEscapeCode(ch)),
             None => self.eof_error(),
         }
     }
 
+    /// Parses the `{...}` part of a `\u{...}` escape code
+    fn unicode_escape_code(&mut self, start: Location) -> Result<char, SpError> {
+        match self.bump() {
+            Some((_, '{')) => (),
+            Some(_) => return self.error(start, InvalidUnicodeEscape),
+            None => return self.eof_error(),
+        }
+        let mut code = 0;
+        let mut digits = 0;
+        loop {
+            match self.bump() {
+                Some((_, '}')) if digits > 0 => break,
+                Some((_, ch)) if digits < 6 && ch.is_digit(16) => {
+                    code = code * 16 + ch.to_digit(16).unwrap();
+                    digits += 1;
+                }
+                Some(_) => return self.error(start, InvalidUnicodeEscape),
+                None => return self.eof_error(),
+            }
+        }
+        match ::std::char::from_u32(code) {
+            Some(ch) => Ok(ch),
+            None => self.error(start, InvalidUnicodeEscape),
+        }
+    }
+
     fn string_literal(&mut self, start: Location) -> Result<SpannedToken<'input>, SpError> {
         let mut string = String::new();
 
@@ -609,6 +638,55 @@ impl<'input> Iterator for Tokenizer<'input> {
     }
 }
 
+/// Returns the span of every comment in `input` in the order they appear. Doc comments are not
+/// included as they are tokens which the parser stores in the metadata of the AST. If `input`
+/// can't be tokenized only the comments before the error are returned.
+pub fn comment_spans<S>(input: &S) -> Vec<pos::Span<BytePos>>
+where
+    S: ?Sized + ::ParserSource,
+{
+    let src = input.src();
+    let start_index = input.start_index();
+    let offset = |pos: BytePos| (pos - ByteOffset::from(start_index.to_usize() as i64)).to_usize();
+
+    let mut spans = Vec::new();
+    let mut gap_start = input.start_index();
+    for token in Tokenizer::new(input) {
+        let token = match token {
+            Ok(token) => token,
+            Err(_) => break,
+        };
+        // Only whitespace and comments can appear between two tokens
+        let gap = &src[offset(gap_start)..offset(token.span.start().absolute)];
+        let mut i = 0;
+        while let Some(next) = gap[i..].find('/') {
+            let start = i + next;
+            let end = if gap[start..].starts_with("//") {
+                let end = gap[start..].find('\n').map_or(gap.len(), |end| start + end);
+                if gap[..end].ends_with('\r') {
+                    end - 1
+                } else {
+                    end
+                }
+            } else {
+                gap[start..]
+                    .find("*/")
+                    .map_or(gap.len(), |end| start + end + 2)
+            };
+            spans.push(pos::span(
+                gap_start + ByteOffset::from(start as i64),
+                gap_start + ByteOffset::from(end as i64),
+            ));
+            i = end;
+        }
+        if token.value == Token::EOF {
+            break;
+        }
+        gap_start = token.span.end().absolute;
+    }
+    spans
+}
+
 /// Converts partial hex literal (i.e. part after `0x` or `-0x`) to 64 bit signed integer.
 ///
 /// This is basically a copy and adaptation of `std::num::from_str_radix`.
@@ -802,6 +880,36 @@ mod test {
         );
     }
 
+    #[test]
+    fn string_literal_unicode_escape_codes() {
+        test(
+            r#""\u{e9}\u{1F600}" '\u{0301}'"#,
+            vec![
+                (
+                    r#"~~~~~~~~~~~~~~~~~           "#,
+                    StringLiteral("\u{e9}\u{1F600}".to_string()),
+                ),
+                (r#"                  ~~~~~~~~~~"#, CharLiteral('\u{301}')),
+            ],
+        );
+    }
+
+    #[test]
+    fn string_literal_invalid_unicode_escape_code() {
+        assert_eq!(
+            tokenizer(r#""\u{d800}""#).last(),
+            Some(error(loc(2), InvalidUnicodeEscape))
+        );
+        assert_eq!(
+            tokenizer(r#""\u{}""#).last(),
+            Some(error(loc(2), InvalidUnicodeEscape))
+        );
+        assert_eq!(
+            tokenizer(r#""\u0041""#).last(),
+            Some(error(loc(2), InvalidUnicodeEscape))
+        );
+    }
+
     #[test]
     fn string_literal_unterminated() {
         assert_eq!(
@@ -1077,4 +1185,18 @@ mod test {
             ],
         );
     }
+
+    #[test]
+    fn comment_spans_skip_literals_and_doc_comments() {
+        let input = "\"// not\" // a\r\n'/' /* b */ /// doc\nx //";
+        let comments: Vec<_> = super::comment_spans(input)
+            .into_iter()
+            .map(|span| {
+                let start = (span.start() - ByteOffset::from(1)).to_usize();
+                let end = (span.end() - ByteOffset::from(1)).to_usize();
+                &input[start..end]
+            })
+            .collect();
+        assert_eq!(comments, ["// a", "/* b */", "//"]);
+    }
 }
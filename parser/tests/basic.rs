@@ -241,6 +241,34 @@ match None with
     );
 }
 
+#[test]
+fn case_expr_guard() {
+    let _ = ::env_logger::try_init();
+    let text = r#"
+match x with
+    | y if y > 0 -> y
+    | _ -> 0"#;
+    let e = parse_clear_span!(text);
+    assert_eq!(
+        e,
+        no_loc(Expr::Match(
+            Box::new(id("x")),
+            vec![
+                Alternative {
+                    pattern: no_loc(Pattern::Ident(TypedIdent::new(intern("y")))),
+                    guard: Some(binop(id("y"), ">", int(0))),
+                    expr: id("y"),
+                },
+                Alternative {
+                    pattern: no_loc(Pattern::Ident(TypedIdent::new(intern("_")))),
+                    guard: None,
+                    expr: int(0),
+                },
+            ],
+        ))
+    );
+}
+
 #[test]
 fn array_expr() {
     let _ = ::env_logger::try_init();
@@ -783,6 +811,28 @@ fn doc_comment_on_record_field() {
                 value: Some(int(1)),
             }],
             base: None,
+            removed: Vec::new(),
+        })
+    )
+}
+
+#[test]
+fn record_removing_fields_of_base() {
+    let _ = ::env_logger::try_init();
+    let text = r"{ x = 1, .. r, -y, -Z }";
+    let e = parse_clear_span!(text);
+    assert_eq!(
+        e,
+        no_loc(Expr::Record {
+            typ: Type::hole(),
+            types: Vec::new(),
+            exprs: vec![ExprField {
+                metadata: Metadata::default(),
+                name: no_loc("x".into()),
+                value: Some(int(1)),
+            }],
+            base: Some(Box::new(id("r"))),
+            removed: vec![no_loc("y".into()), no_loc("Z".into())],
         })
     )
 }
@@ -842,3 +892,21 @@ fn parse_let_or_expr() {
         Err((_, err)) => panic!("{}", err),
     }
 }
+
+#[test]
+fn classify_tokens() {
+    use parser::TokenKind::*;
+
+    let _ = ::env_logger::try_init();
+
+    assert_eq!(
+        parser::classify_tokens(r#"let x = "a" 1"#),
+        vec![
+            (Keyword, 0..3),
+            (Identifier, 4..5),
+            (Punctuation, 6..7),
+            (String, 8..11),
+            (Number, 12..13),
+        ]
+    );
+}
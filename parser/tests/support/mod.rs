@@ -256,6 +256,7 @@ pub fn case(e: SpExpr, alts: Vec<(Pattern<String>, SpExpr)>) -> SpExpr {
         alts.into_iter()
             .map(|(p, e)| Alternative {
                 pattern: no_loc(p),
+                guard: None,
                 expr: e,
             })
             .collect(),
@@ -321,6 +322,7 @@ pub fn record_a(
             })
             .collect(),
         base: None,
+        removed: Vec::new(),
     })
 }
 
@@ -0,0 +1,639 @@
+//! A debug adapter which lets editors such as VS Code debug gluon scripts through the
+//! [Debug Adapter Protocol](https://microsoft.github.io/debug-adapter-protocol/).
+//!
+//! Requests which inspect or resume the script are forwarded to the debugger's pause function,
+//! which runs on the script's thread and answers them until the script is resumed.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{self, Value};
+
+use base::filename_to_module;
+use base::fnv::FnvMap;
+use base::pos::Line;
+use base::types::{ArcType, TypeEnv};
+use vm::compiler::CompiledFunction;
+use vm::debugger::{Debugger, Step};
+use vm::internal::ValuePrinter;
+use vm::thread::{DebugInfo, Thread};
+use vm::Variants;
+
+use gluon::compiler_pipeline::Compileable;
+use gluon::{Compiler, VmBuilder};
+
+/// The id of the only thread reported to the client
+const THREAD_ID: i64 = 1;
+
+#[derive(StructOpt)]
+#[structopt(about = "Runs a debug adapter speaking the Debug Adapter Protocol")]
+pub struct DebugAdapterOpt {
+    #[structopt(
+        long = "port",
+        help = "Listens for a client on this port instead of communicating over stdin and stdout"
+    )]
+    port: Option<u16>,
+}
+
+pub fn run(opt: &DebugAdapterOpt) -> io::Result<()> {
+    match opt.port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            let (stream, _) = listener.accept()?;
+            let writer = stream.try_clone()?;
+            serve(BufReader::new(stream), writer)
+        }
+        None => {
+            let stdin = io::stdin();
+            let stdin = stdin.lock();
+            serve(stdin, io::stdout())
+        }
+    }
+}
+
+fn serve<R, W>(mut reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write + Send + 'static,
+{
+    let client = Client::new(writer);
+    let (sender, receiver) = channel();
+    let stopped = Arc::new(Mutex::new(false));
+    let cwd = Arc::new(Mutex::new(
+        env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    ));
+    let mut handler = PauseHandler {
+        client: client.clone(),
+        requests: receiver,
+        stopped: stopped.clone(),
+        cwd: cwd.clone(),
+        last_step: Step::Continue,
+    };
+    let mut adapter = Adapter {
+        debugger: Debugger::new(move |thread, info| handler.on_pause(thread, info)),
+        client,
+        paused: sender,
+        stopped,
+        cwd,
+        program: None,
+        configured: false,
+        launched: false,
+        breakpoints: FnvMap::default(),
+    };
+    while let Some(request) = read_message(&mut reader)? {
+        if !adapter.handle(request) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a message framed by a `Content-Length` header. Returns `None` once the client has
+/// closed the stream.
+fn read_message<R>(reader: &mut R) -> io::Result<Option<Value>>
+where
+    R: BufRead,
+{
+    const CONTENT_LENGTH: &str = "Content-Length:";
+
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if header.starts_with(CONTENT_LENGTH) {
+            let length = header[CONTENT_LENGTH.len()..]
+                .trim()
+                .parse::<usize>()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            content_length = Some(length);
+        }
+    }
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+    })?;
+
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+struct Output {
+    writer: Box<Write + Send>,
+    seq: i64,
+}
+
+/// Handle for sending responses and events to the client from any thread
+#[derive(Clone)]
+struct Client {
+    output: Arc<Mutex<Output>>,
+}
+
+impl Client {
+    fn new<W>(writer: W) -> Client
+    where
+        W: Write + Send + 'static,
+    {
+        Client {
+            output: Arc::new(Mutex::new(Output {
+                writer: Box::new(writer),
+                seq: 0,
+            })),
+        }
+    }
+
+    fn send(&self, mut message: Value) {
+        let mut output = self.output.lock().unwrap();
+        output.seq += 1;
+        message["seq"] = Value::from(output.seq);
+        let content = message.to_string();
+        let writer = &mut output.writer;
+        let result = write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content);
+        if let Err(err) = result.and_then(|_| writer.flush()) {
+            error!("Unable to write to the debug client: {}", err);
+        }
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn respond(&self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::from(message),
+        }
+        self.send(response);
+    }
+}
+
+struct Adapter {
+    client: Client,
+    debugger: Debugger,
+    /// Forwards requests which can only be answered while the script is paused
+    paused: Sender<Value>,
+    /// Whether the script is paused. Requests are only forwarded while the lock is held so that
+    /// the pause function can reject any request which arrives after it resumed the script.
+    stopped: Arc<Mutex<bool>>,
+    /// The directory which modules are resolved from. Set by the `cwd` argument of `launch`
+    /// instead of changing the working directory of the whole process.
+    cwd: Arc<Mutex<PathBuf>>,
+    program: Option<String>,
+    configured: bool,
+    launched: bool,
+    /// The breakpoints last set by the client, indexed by module name
+    breakpoints: FnvMap<String, Vec<Line>>,
+}
+
+impl Adapter {
+    /// Handles `request`, returning `false` once the client has disconnected
+    fn handle(&mut self, request: Value) -> bool {
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let result = match &command[..] {
+            "initialize" => {
+                self.client.respond(
+                    &request,
+                    Ok(json!({ "supportsConfigurationDoneRequest": true })),
+                );
+                self.client.event("initialized", json!({}));
+                return true;
+            }
+            "launch" => self.launch(&request["arguments"]),
+            "setBreakpoints" => self.set_breakpoints(&request["arguments"]),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "main" }],
+            })),
+            "stackTrace" | "scopes" | "variables" | "continue" | "next" | "stepIn"
+            | "stepOut" => {
+                let stopped = self.stopped.lock().unwrap();
+                if *stopped {
+                    // Answered by the pause function
+                    let _ = self.paused.send(request);
+                    return true;
+                }
+                Err("The script is not stopped".to_string())
+            }
+            "disconnect" => {
+                self.client.respond(&request, Ok(json!({})));
+                return false;
+            }
+            _ => Err(format!("Unsupported request `{}`", command)),
+        };
+        self.client.respond(&request, result);
+        self.start_if_ready();
+        true
+    }
+
+    fn cwd(&self) -> PathBuf {
+        self.cwd.lock().unwrap().clone()
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        if let Some(cwd) = arguments["cwd"].as_str() {
+            let cwd = self.cwd().join(cwd);
+            if !cwd.is_dir() {
+                return Err(format!("`{}` is not a directory", cwd.display()));
+            }
+            *self.cwd.lock().unwrap() = cwd;
+        }
+        let program = arguments["program"]
+            .as_str()
+            .ok_or_else(|| "Missing `program` to launch".to_string())?;
+        self.program = Some(relative_path(&self.cwd(), program));
+        if arguments["stopOnEntry"].as_bool().unwrap_or(false) {
+            self.debugger.set_step(Step::Into);
+        }
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["source"]["path"]
+            .as_str()
+            .ok_or_else(|| "Missing `source.path`".to_string())?;
+        let cwd = self.cwd();
+        let module = filename_to_module(&relative_path(&cwd, path));
+
+        let lines = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        for &line in self.breakpoints.get(&module).into_iter().flat_map(|lines| lines) {
+            self.debugger.remove_breakpoint(&module, line);
+        }
+
+        let code_lines = code_lines(&cwd, &module, &cwd.join(path));
+        let mut breakpoints = Vec::new();
+        let results = lines
+            .iter()
+            .map(|&line| {
+                // Lines are 1-indexed in the protocol but 0-indexed in the debugger
+                let requested = Line::from(line.saturating_sub(1) as u32);
+                // A breakpoint on a line without code is moved to the next line which has code
+                let actual = match code_lines {
+                    Ok(ref code_lines) => code_lines.range(requested..).next().cloned(),
+                    Err(_) => None,
+                };
+                match actual {
+                    Some(actual) => {
+                        self.debugger.set_breakpoint(&module, actual);
+                        breakpoints.push(actual);
+                        json!({ "verified": true, "line": actual.to_usize() + 1 })
+                    }
+                    None => {
+                        let message = match code_lines {
+                            Ok(_) => "No code at or after this line".to_string(),
+                            Err(ref err) => err.clone(),
+                        };
+                        json!({ "verified": false, "line": line, "message": message })
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        self.breakpoints.insert(module, breakpoints);
+
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    /// Starts the script once it has been launched and the client is done configuring it
+    fn start_if_ready(&mut self) {
+        if self.launched || !self.configured {
+            return;
+        }
+        let program = match self.program.clone() {
+            Some(program) => program,
+            None => return,
+        };
+        self.launched = true;
+
+        let debugger = self.debugger.clone();
+        let client = self.client.clone();
+        let cwd = self.cwd();
+        thread::spawn(move || {
+            let vm = VmBuilder::new().import_paths(Some(vec![cwd])).build();
+            debugger.attach(&vm);
+            if let Err(err) = Compiler::new().run_io(true).load_file(&vm, &program) {
+                client.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", err) }),
+                );
+            }
+            client.event("terminated", json!({}));
+        });
+    }
+}
+
+/// Module names are resolved relative to `cwd` so paths from the client are made relative to it
+/// as well
+fn relative_path(cwd: &Path, path: &str) -> String {
+    let path = Path::new(path);
+    path.strip_prefix(cwd)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Compiles the module at `path` and returns the lines which have instructions, which are the
+/// only lines the debugger can stop at
+fn code_lines(cwd: &Path, module: &str, path: &Path) -> Result<BTreeSet<Line>, String> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("Unable to read `{}`: {}", path.display(), err))?;
+
+    let vm = VmBuilder::new()
+        .import_paths(Some(vec![cwd.to_owned()]))
+        .build();
+    let module = (&source[..])
+        .compile(&mut Compiler::new(), &vm, module, &source, None)
+        .map_err(|err| err.to_string())?
+        .module;
+
+    fn add_lines(function: &CompiledFunction, lines: &mut BTreeSet<Line>) {
+        let source_map = &function.debug_info.source_map;
+        lines.extend((0..function.instructions.len()).filter_map(|i| source_map.line(i)));
+        for inner in &function.inner_functions {
+            add_lines(inner, lines);
+        }
+    }
+    let mut lines = BTreeSet::new();
+    add_lines(&module.function, &mut lines);
+    Ok(lines)
+}
+
+struct PauseHandler {
+    client: Client,
+    requests: Receiver<Value>,
+    stopped: Arc<Mutex<bool>>,
+    cwd: Arc<Mutex<PathBuf>>,
+    last_step: Step,
+}
+
+impl PauseHandler {
+    fn on_pause(&mut self, thread: &Thread, info: &DebugInfo) -> Step {
+        let reason = if self.last_step == Step::Continue {
+            "breakpoint"
+        } else {
+            "step"
+        };
+        *self.stopped.lock().unwrap() = true;
+        self.client.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        );
+
+        while let Ok(request) = self.requests.recv() {
+            let step = match request["command"].as_str().unwrap_or("") {
+                "continue" => Step::Continue,
+                "next" => Step::Over,
+                "stepIn" => Step::Into,
+                "stepOut" => Step::Out,
+                _ => {
+                    let cwd = self.cwd.lock().unwrap().clone();
+                    let result = inspect(thread, info, &cwd, &request);
+                    self.client.respond(&request, result);
+                    continue;
+                }
+            };
+            self.resume();
+            self.client
+                .respond(&request, Ok(json!({ "allThreadsContinued": true })));
+            self.last_step = step;
+            return step;
+        }
+        // The client has disconnected so let the script run to completion
+        Step::Continue
+    }
+
+    fn resume(&mut self) {
+        *self.stopped.lock().unwrap() = false;
+        // Requests which were forwarded before the script was resumed can no longer be answered
+        while let Ok(request) = self.requests.try_recv() {
+            self.client
+                .respond(&request, Err("The script is not stopped".to_string()));
+        }
+    }
+}
+
+// Frames are identified by their stack level and each frame has two scopes, `level * 2 + 1` for
+// its locals and `level * 2 + 2` for its upvars (`0` means that a variable has no children)
+fn inspect(
+    thread: &Thread,
+    info: &DebugInfo,
+    cwd: &Path,
+    request: &Value,
+) -> Result<Value, String> {
+    let arguments = &request["arguments"];
+    match request["command"].as_str().unwrap_or("") {
+        "stackTrace" => {
+            let frames = (0..info.stack_info_len())
+                .filter_map(|level| info.stack_info(level).map(|frame| (level, frame)))
+                .map(|(level, frame)| {
+                    json!({
+                        "id": level,
+                        "name": frame.function_name().unwrap_or("<unknown>"),
+                        "source": source(cwd, frame.source_name()),
+                        "line": frame.line().map_or(0, |line| line.to_usize() + 1),
+                        "column": 1,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let total_frames = frames.len();
+            Ok(json!({ "stackFrames": frames, "totalFrames": total_frames }))
+        }
+        "scopes" => {
+            let level = arguments["frameId"]
+                .as_u64()
+                .ok_or_else(|| "Missing `frameId`".to_string())?;
+            Ok(json!({
+                "scopes": [
+                    { "name": "Locals", "variablesReference": level * 2 + 1, "expensive": false },
+                    { "name": "Upvars", "variablesReference": level * 2 + 2, "expensive": false },
+                ],
+            }))
+        }
+        "variables" => {
+            let reference = arguments["variablesReference"]
+                .as_u64()
+                .ok_or_else(|| "Missing `variablesReference`".to_string())?;
+            if reference == 0 {
+                return Ok(json!({ "variables": [] }));
+            }
+            let level = ((reference - 1) / 2) as usize;
+            let frame = info
+                .stack_info(level)
+                .ok_or_else(|| format!("No stack frame at level {}", level))?;
+
+            let env = thread.get_env();
+            let variables = if reference % 2 == 1 {
+                frame
+                    .locals()
+                    .map(|local| {
+                        variable(
+                            &*env,
+                            local.name.declared_name(),
+                            &local.typ,
+                            frame.local_value(local),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            } else if frame.line().is_some() {
+                // Only closures have line information and upvars
+                frame
+                    .upvars()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, upvar)| {
+                        frame
+                            .upvar_value(index)
+                            .map(|value| variable(&*env, &upvar.name, &upvar.typ, value))
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Ok(json!({ "variables": variables }))
+        }
+        command => Err(format!("Unsupported request `{}`", command)),
+    }
+}
+
+fn source(cwd: &Path, source_name: &str) -> Value {
+    let path = cwd.join(format!("{}.glu", source_name.replace('.', "/")));
+    json!({ "name": source_name, "path": path.to_string_lossy() })
+}
+
+fn variable(env: &TypeEnv, name: &str, typ: &ArcType, value: Variants) -> Value {
+    let value = ValuePrinter::new(env, typ, value)
+        .width(80)
+        .max_level(3)
+        .to_string();
+    json!({
+        "name": name,
+        "value": value,
+        "type": typ.to_string(),
+        "variablesReference": 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_framed_messages() {
+        let input = "Content-Length: 13\r\n\r\n{\"seq\": 1}   \
+                     Content-Length: 2\r\n\r\n{}";
+        let mut reader = input.as_bytes();
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn requests_fail_unless_the_script_is_stopped() {
+        let input = ["stackTrace", "continue", "next"]
+            .iter()
+            .enumerate()
+            .map(|(seq, command)| {
+                let content = json!({ "seq": seq, "type": "request", "command": command });
+                let content = content.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+            })
+            .collect::<String>();
+        let output = SharedBuffer::default();
+        serve(input.as_bytes(), output.clone()).unwrap();
+
+        let output = output.0.lock().unwrap();
+        let mut reader = &output[..];
+        for command in &["stackTrace", "continue", "next"] {
+            let response = read_message(&mut reader).unwrap().expect("response");
+            assert_eq!(response["command"], json!(command));
+            assert_eq!(response["success"], json!(false));
+        }
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn breakpoints_are_verified_against_the_compiled_lines() {
+        if env::var("GLUON_PATH").is_err() {
+            env::set_var("GLUON_PATH", "..");
+        }
+        let path = env::temp_dir().join("gluon_dap_breakpoints.glu");
+        File::create(&path)
+            .and_then(|mut file| file.write_all(b"let f x = x + 1\n\n\nf 2\n"))
+            .unwrap();
+
+        let content = json!({
+            "seq": 1,
+            "type": "request",
+            "command": "setBreakpoints",
+            "arguments": {
+                "source": { "path": path.to_string_lossy() },
+                "breakpoints": [{ "line": 2 }, { "line": 100 }],
+            },
+        }).to_string();
+        let input = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        let output = SharedBuffer::default();
+        serve(input.as_bytes(), output.clone()).unwrap();
+
+        let output = output.0.lock().unwrap();
+        let response = read_message(&mut &output[..]).unwrap().expect("response");
+        assert_eq!(response["success"], json!(true));
+        assert_eq!(
+            response["body"]["breakpoints"][0],
+            json!({ "verified": true, "line": 4 })
+        );
+        assert_eq!(response["body"]["breakpoints"][1]["verified"], json!(false));
+    }
+}
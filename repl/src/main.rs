@@ -11,9 +11,12 @@ extern crate env_logger;
 extern crate futures;
 #[macro_use]
 extern crate log;
+extern crate semver;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
+extern crate serde_json;
+#[macro_use]
 extern crate structopt;
 extern crate tokio_core;
 extern crate tokio_signal;
@@ -45,6 +48,8 @@ use gluon::vm::thread::ThreadInternal;
 use gluon::vm::Error as VMError;
 use gluon::{new_vm, Compiler, Error, Result, Thread};
 
+mod dap;
+mod package;
 mod repl;
 
 const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
@@ -115,12 +120,32 @@ pub struct FmtOpt {
     input: Vec<PathBuf>,
 }
 
+#[derive(StructOpt)]
+#[structopt(about = "Runs tests written with std.test")]
+pub struct TestOpt {
+    #[structopt(
+        name = "PATH",
+        parse(from_os_str),
+        help = "Test files or directories containing test files (defaults to `tests`)"
+    )]
+    input: Vec<PathBuf>,
+}
+
 #[derive(StructOpt)]
 pub enum SubOpt {
     #[structopt(name = "fmt", about = "Formats gluon source code")]
     Fmt(FmtOpt),
+    #[structopt(name = "test", about = "Runs tests written with std.test")]
+    Test(TestOpt),
     #[structopt(name = "doc", about = "Documents gluon source code")]
     Doc(::gluon_doc::Opt),
+    #[structopt(
+        name = "debug-adapter",
+        about = "Runs a debug adapter speaking the Debug Adapter Protocol"
+    )]
+    DebugAdapter(dap::DebugAdapterOpt),
+    #[structopt(name = "fetch", about = "Fetches the packages listed in gluon.json")]
+    Fetch(package::FetchOpt),
 }
 
 const LONG_VERSION: &str = concat!(crate_version!(), "\n", "commit: ", env!("GIT_HASH"));
@@ -134,6 +159,24 @@ pub struct Opt {
         long = "color", default_value = "auto", help = "Coloring: auto, always, always-ansi, never"
     )]
     color: Color,
+    #[structopt(long = "prompt", default_value = "> ", help = "The prompt shown by the repl")]
+    prompt: String,
+    #[structopt(
+        long = "edit-mode", default_value = "emacs", help = "Keybindings of the repl: emacs, vi"
+    )]
+    edit_mode: repl::EditMode,
+    #[structopt(
+        long = "history",
+        parse(from_os_str),
+        help = "File which the repl history is saved to"
+    )]
+    history: Option<PathBuf>,
+    #[structopt(
+        long = "init",
+        parse(from_os_str),
+        help = "Script run on startup, each field of the record it returns is bound in the repl"
+    )]
+    init: Option<PathBuf>,
     #[structopt(name = "FILE", help = "Executes each file as a gluon program")]
     input: Vec<String>,
     #[structopt(subcommand)]
@@ -147,6 +190,9 @@ where
 {
     for file in files {
         compiler.load_file(&vm, file.as_ref())?;
+        if let Some(warnings) = compiler.take_warnings() {
+            eprintln!("{}", warnings);
+        }
     }
     Ok(())
 }
@@ -211,6 +257,30 @@ fn fmt_stdio() -> Result<()> {
     Ok(())
 }
 
+fn run_tests(vm: &Thread, test_opt: &TestOpt) -> Result<()> {
+    let inputs = if test_opt.input.is_empty() {
+        vec![PathBuf::from("tests")]
+    } else {
+        test_opt.input.clone()
+    };
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            files.extend(gluon::testing::discover_test_files(&input)?);
+        } else {
+            files.push(input);
+        }
+    }
+
+    let report = gluon::testing::run_test_files(vm, &files)?;
+    print!("{}", report);
+    if report.is_success() {
+        Ok(())
+    } else {
+        Err(format!("{} tests failed", report.failed()).into())
+    }
+}
+
 fn run(
     opt: &Opt,
     compiler: &mut Compiler,
@@ -247,15 +317,32 @@ fn run(
                 fmt_stdio()?;
             }
         }
+        Some(SubOpt::Test(ref test_opt)) => {
+            package::add_package_paths(vm)?;
+            run_tests(vm, test_opt)?;
+        }
         Some(SubOpt::Doc(ref doc_opt)) => {
             let input = &doc_opt.input;
             let output = &doc_opt.output;
             gluon_doc::generate_for_path(&new_vm(), input, output)
                 .map_err(|err| format!("{}\n{}", err, err.backtrace()))?;
         }
+        Some(SubOpt::DebugAdapter(ref debug_opt)) => {
+            dap::run(debug_opt)?;
+        }
+        Some(SubOpt::Fetch(ref fetch_opt)) => {
+            package::fetch(fetch_opt)?;
+        }
         None => if opt.interactive {
-            repl::run(color)?;
+            repl::run(repl::Settings {
+                color,
+                prompt: opt.prompt.clone(),
+                edit_mode: opt.edit_mode,
+                history: opt.history.clone(),
+                init: opt.init.clone(),
+            })?;
         } else if !opt.input.is_empty() {
+            package::add_package_paths(vm)?;
             run_files(compiler, &vm, &opt.input)?;
         } else {
             write!(io::stderr(), "{}", Opt::clap().get_matches().usage())
@@ -0,0 +1,706 @@
+//! Dependency management for programs run with the `gluon` executable.
+//!
+//! Dependencies are listed in a `gluon.json` manifest in the directory `gluon` is run from.
+//!
+//! ```json
+//! {
+//!     "registry": "https://github.com/user/gluon_registry",
+//!     "dependencies": {
+//!         "local_lib": { "path": "../local_lib" },
+//!         "shared_lib": { "git": "https://github.com/user/shared_lib", "rev": "v1.0.0" },
+//!         "parser_lib": { "git": "https://github.com/user/parser_lib", "version": "^1.2" },
+//!         "json_lib": { "version": "0.3" }
+//!     }
+//! }
+//! ```
+//!
+//! A package is a directory laid out like an import path, so `import! shared_lib.parser` loads
+//! `shared_lib/parser.glu` from the package's directory. Packages may list their own
+//! dependencies in a manifest of their own. Git dependencies are cloned into `.gluon/packages`
+//! the first time they are needed, `gluon fetch` can be used to fetch them ahead of time. A clone
+//! is updated once the `rev` of its dependency changes.
+//!
+//! The versions of a package are the tags of its git repository which are semantic versions,
+//! such as `v1.2.0` or `1.2.0`. A dependency with a `version` requirement checks out the highest
+//! version which satisfies the requirements of every manifest depending on the package. A
+//! dependency with only a `version` is looked up in the registry named by the root manifest. A
+//! registry is a directory or a git repository containing a `<package>.json` file, such as
+//! `{ "git": "https://github.com/user/json_lib" }`, for each package it provides.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use semver::{Version, VersionReq};
+
+use gluon::import::Import;
+use gluon::{Result, Thread};
+
+pub const MANIFEST: &str = "gluon.json";
+
+/// Directory, relative to the root manifest, which git dependencies are cloned into
+const PACKAGE_DIR: &str = ".gluon/packages";
+
+/// Directory, relative to the root manifest, which a registry stored in git is cloned into
+const REGISTRY_DIR: &str = ".gluon/registry";
+
+/// File in the `.git` directory of a cloned package which records the url and revision that is
+/// checked out
+const FETCHED_FILE: &str = "gluon_fetched";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    /// Path or git url of the registry which dependencies without a source are looked up in. Only
+    /// the registry of the root manifest is used.
+    #[serde(default)]
+    pub registry: Option<String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Dependency {
+    Path {
+        path: PathBuf,
+    },
+    Git {
+        git: String,
+        #[serde(default)]
+        rev: Option<String>,
+        #[serde(default)]
+        version: Option<String>,
+    },
+    Registry {
+        version: String,
+    },
+}
+
+/// The entry of a package in a registry
+#[derive(Debug, Deserialize)]
+struct RegistryEntry {
+    git: String,
+}
+
+/// Where the modules of a resolved package are loaded from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    Git { url: String, rev: Option<String> },
+}
+
+/// A resolved dependency and the directory its modules are imported from
+#[derive(Debug, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub source: Source,
+    /// The selected version if the package were required by version
+    pub version: Option<Version>,
+    pub dir: PathBuf,
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Fetches the packages listed in gluon.json")]
+pub struct FetchOpt {
+    #[structopt(
+        long = "manifest-dir",
+        parse(from_os_str),
+        help = "Directory containing the gluon.json manifest (defaults to the current directory)"
+    )]
+    manifest_dir: Option<PathBuf>,
+}
+
+fn read_manifest(dir: &Path) -> Result<Option<Manifest>> {
+    let path = dir.join(MANIFEST);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(&path)?;
+    let manifest = ::serde_json::from_reader(file)
+        .map_err(|err| format!("Invalid manifest `{}`: {}", path.display(), err))?;
+    Ok(Some(manifest))
+}
+
+/// Package names are used as directory names so they are restricted to identifier characters
+fn check_name(name: &str) -> Result<()> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid package name `{}`, only letters, digits and `_` are allowed",
+            name
+        )
+        .into())
+    }
+}
+
+fn check_git_url(name: &str, url: &str) -> Result<()> {
+    // git would parse these as options
+    if url.starts_with('-') {
+        return Err(format!("Invalid git url `{}` for package `{}`", url, name).into());
+    }
+    Ok(())
+}
+
+fn read_fetched(path: &Path) -> Option<String> {
+    let mut fetched = String::new();
+    File::open(path).ok()?.read_to_string(&mut fetched).ok()?;
+    Some(fetched)
+}
+
+/// Clones the package `name` into `dir` and checks out `rev`, or the default branch if there is
+/// no `rev`. An existing clone is reused if it has `rev` checked out and updated otherwise.
+fn fetch_git(name: &str, url: &str, rev: Option<&str>, dir: &Path) -> Result<()> {
+    check_git_url(name, url)?;
+    if let Some(rev) = rev {
+        if rev.starts_with('-') {
+            return Err(format!("Invalid rev `{}` for package `{}`", rev, name).into());
+        }
+    }
+
+    let fetched_path = dir.join(".git").join(FETCHED_FILE);
+    let source = format!("{}\n{}", url, rev.unwrap_or(""));
+    match read_fetched(&fetched_path) {
+        Some(ref fetched) if *fetched == source => return Ok(()),
+        Some(ref fetched) if fetched.lines().next() == Some(url) => {
+            info!("Updating package `{}` from {}", name, url);
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(&["fetch", "--quiet", "--tags", "origin"])
+                .status()?;
+            if !status.success() {
+                return Err(format!("Could not update package `{}` from {}", name, url).into());
+            }
+        }
+        _ => {
+            info!("Fetching package `{}` from {}", name, url);
+            // Clones which were not completed or are of another url are fetched again
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+            if let Some(parent) = dir.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let status = Command::new("git")
+                .args(&["clone", "--quiet", "--", url])
+                .arg(dir)
+                .status()?;
+            if !status.success() {
+                return Err(format!("Could not clone package `{}` from {}", name, url).into());
+            }
+        }
+    }
+
+    let checkout = rev.unwrap_or("origin/HEAD");
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&["checkout", "--quiet", checkout, "--"])
+        .status()?;
+    if !status.success() {
+        // Don't reuse a checkout of the wrong revision
+        let _ = fs::remove_file(&fetched_path);
+        return Err(format!("Could not check out `{}` of package `{}`", checkout, name).into());
+    }
+    File::create(&fetched_path)?.write_all(source.as_bytes())?;
+    Ok(())
+}
+
+/// Returns the versions of the package `name` along with the tag of each version, the tags of the
+/// git repository at `url` which are semantic versions such as `v1.2.0` or `1.2.0`
+fn version_tags(name: &str, url: &str) -> Result<Vec<(Version, String)>> {
+    check_git_url(name, url)?;
+    let output = Command::new("git")
+        .args(&["ls-remote", "--quiet", "--tags", "--refs", "--", url])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Could not list the versions of package `{}` from {}",
+            name, url
+        )
+        .into());
+    }
+    let refs = String::from_utf8_lossy(&output.stdout);
+    Ok(refs
+        .lines()
+        .filter_map(|line| {
+            let tag = line.split('\t').nth(1)?;
+            let tag = if tag.starts_with("refs/tags/") {
+                &tag["refs/tags/".len()..]
+            } else {
+                return None;
+            };
+            let version = if tag.starts_with('v') { &tag[1..] } else { tag };
+            let version = Version::parse(version).ok()?;
+            Some((version, tag.to_string()))
+        })
+        .collect())
+}
+
+struct Resolver<'a> {
+    root: &'a Path,
+    registry: Option<String>,
+    /// The version requirements on each package, collected from every manifest depending on it
+    requirements: BTreeMap<String, Vec<VersionReq>>,
+    /// The version tags of the git repositories which have been listed
+    tags: BTreeMap<String, Vec<(Version, String)>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Records that the package `name` must match `version`. Returns the parsed requirement.
+    fn add_requirement(&mut self, name: &str, version: &str) -> Result<VersionReq> {
+        let requirement = VersionReq::parse(version).map_err(|err| {
+            format!(
+                "Invalid version requirement `{}` for package `{}`: {}",
+                version, name, err
+            )
+        })?;
+        let requirements = self
+            .requirements
+            .entry(name.to_string())
+            .or_insert_with(Vec::new);
+        if !requirements.contains(&requirement) {
+            requirements.push(requirement.clone());
+        }
+        Ok(requirement)
+    }
+
+    /// Returns the highest version, and its tag, of the package `name` stored at `url` which
+    /// matches all requirements on the package
+    fn select_version(&mut self, name: &str, url: &str) -> Result<(Version, String)> {
+        if !self.tags.contains_key(url) {
+            let tags = version_tags(name, url)?;
+            self.tags.insert(url.to_string(), tags);
+        }
+        let requirements = &self.requirements[name];
+        self.tags[url]
+            .iter()
+            .filter(|&&(ref version, _)| requirements.iter().all(|req| req.matches(version)))
+            .max_by(|l, r| l.0.cmp(&r.0))
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "No version of package `{}` in {} matches {}",
+                    name,
+                    url,
+                    requirements
+                        .iter()
+                        .map(|req| format!("`{}`", req))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .into()
+            })
+    }
+
+    /// Returns the git url of the package `name` in the registry
+    fn registry_url(&self, name: &str) -> Result<String> {
+        let registry = self.registry.as_ref().ok_or_else(|| {
+            format!(
+                "Package `{}` has no source and {} does not name a `registry`",
+                name, MANIFEST
+            )
+        })?;
+        let dir = self.root.join(registry);
+        let dir = if dir.is_dir() {
+            dir
+        } else {
+            let dir = self.root.join(REGISTRY_DIR);
+            fetch_git("registry", registry, None, &dir)?;
+            dir
+        };
+
+        let path = dir.join(format!("{}.json", name));
+        let file = File::open(&path)
+            .map_err(|_| format!("Package `{}` is not in the registry {}", name, registry))?;
+        let entry: RegistryEntry = ::serde_json::from_reader(file)
+            .map_err(|err| format!("Invalid registry entry `{}`: {}", path.display(), err))?;
+        Ok(entry.git)
+    }
+
+    /// Resolves every package with the version requirements found so far. Returns `None` if a
+    /// requirement which the already selected version does not match is found, in which case
+    /// the packages must be resolved again.
+    fn resolve_once(&mut self) -> Result<Option<Vec<Package>>> {
+        let mut packages: Vec<Package> = Vec::new();
+        let mut queue = Vec::new();
+        if let Some(manifest) = read_manifest(self.root)? {
+            queue.extend(
+                manifest
+                    .dependencies
+                    .into_iter()
+                    .map(|(name, dependency)| (self.root.to_owned(), name, dependency)),
+            );
+        }
+        queue.reverse();
+
+        while let Some((manifest_dir, name, dependency)) = queue.pop() {
+            check_name(&name)?;
+
+            let (source, requirement) = match dependency {
+                // Relative paths are relative to the manifest they are declared in
+                Dependency::Path { path } => {
+                    (Source::Path(manifest_dir.join(path).canonicalize()?), None)
+                }
+                Dependency::Git {
+                    git,
+                    rev,
+                    version: None,
+                } => (Source::Git { url: git, rev }, None),
+                Dependency::Git {
+                    git,
+                    rev: None,
+                    version: Some(version),
+                } => {
+                    let requirement = self.add_requirement(&name, &version)?;
+                    (
+                        Source::Git {
+                            url: git,
+                            rev: None,
+                        },
+                        Some(requirement),
+                    )
+                }
+                Dependency::Git { .. } => {
+                    return Err(format!(
+                        "Package `{}` may specify either a `rev` or a `version`, not both",
+                        name
+                    )
+                    .into())
+                }
+                Dependency::Registry { version } => {
+                    let requirement = self.add_requirement(&name, &version)?;
+                    let url = self.registry_url(&name)?;
+                    (Source::Git { url, rev: None }, Some(requirement))
+                }
+            };
+
+            if let Some(package) = packages.iter().find(|package| package.name == name) {
+                match (&package.source, &package.version, &source, &requirement) {
+                    (
+                        &Source::Git { ref url, .. },
+                        &Some(ref version),
+                        &Source::Git {
+                            url: ref other_url, ..
+                        },
+                        &Some(ref requirement),
+                    ) if url == other_url => {
+                        if requirement.matches(version) {
+                            continue;
+                        }
+                        // Another version must be selected now that this requirement is known
+                        return Ok(None);
+                    }
+                    _ if package.version.is_none()
+                        && requirement.is_none()
+                        && package.source == source =>
+                    {
+                        continue
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Package `{}` is required from different sources: {:?} and {:?}",
+                            name, package.source, source
+                        )
+                        .into())
+                    }
+                }
+            }
+
+            let (source, version) = match source {
+                Source::Git { url, .. } if requirement.is_some() => {
+                    let (version, tag) = self.select_version(&name, &url)?;
+                    (
+                        Source::Git {
+                            url,
+                            rev: Some(tag),
+                        },
+                        Some(version),
+                    )
+                }
+                source => (source, None),
+            };
+
+            let dir = match source {
+                Source::Path(ref path) => path.clone(),
+                Source::Git { ref url, ref rev } => {
+                    let dir = self.root.join(PACKAGE_DIR).join(&name);
+                    fetch_git(&name, url, rev.as_ref().map(|rev| &rev[..]), &dir)?;
+                    dir
+                }
+            };
+
+            if let Some(manifest) = read_manifest(&dir)? {
+                let mut dependencies = manifest
+                    .dependencies
+                    .into_iter()
+                    .map(|(name, dependency)| (dir.clone(), name, dependency))
+                    .collect::<Vec<_>>();
+                dependencies.reverse();
+                queue.extend(dependencies);
+            }
+
+            packages.push(Package {
+                name,
+                source,
+                version,
+                dir,
+            });
+        }
+        Ok(Some(packages))
+    }
+}
+
+/// Resolves every package which the manifest in `root` depends on, directly or through other
+/// packages, fetching any git dependencies which have not been fetched yet. Returns an empty list
+/// if `root` has no manifest.
+pub fn resolve(root: &Path) -> Result<Vec<Package>> {
+    let registry = match read_manifest(root)? {
+        Some(manifest) => manifest.registry,
+        None => return Ok(Vec::new()),
+    };
+    let mut resolver = Resolver {
+        root,
+        registry,
+        requirements: BTreeMap::new(),
+        tags: BTreeMap::new(),
+    };
+    // Every pass which does not finish has found a new requirement so this terminates once all
+    // requirements are known
+    loop {
+        if let Some(packages) = resolver.resolve_once()? {
+            return Ok(packages);
+        }
+    }
+}
+
+/// Lets `vm` import the packages listed in the manifest of the current directory, if there is
+/// one
+pub fn add_package_paths(vm: &Thread) -> Result<()> {
+    let packages = resolve(&env::current_dir()?)?;
+    if packages.is_empty() {
+        return Ok(());
+    }
+    let import = vm.get_macros().get("import");
+    let import = import
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .ok_or_else(|| "Packages require the import macro".to_string())?;
+    for package in packages {
+        debug!(
+            "Importing package `{}` from `{}`",
+            package.name,
+            package.dir.display()
+        );
+        import.add_path(package.dir);
+    }
+    Ok(())
+}
+
+pub fn fetch(opt: &FetchOpt) -> Result<()> {
+    let root = match opt.manifest_dir {
+        Some(ref dir) => dir.clone(),
+        None => env::current_dir()?,
+    };
+    if !root.join(MANIFEST).exists() {
+        return Err(format!("No {} found in `{}`", MANIFEST, root.display()).into());
+    }
+    for package in resolve(&root)? {
+        match package.version {
+            Some(version) => println!("{} {} {}", package.name, version, package.dir.display()),
+            None => println!("{} {}", package.name, package.dir.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(&[
+                "-c",
+                "user.name=gluon",
+                "-c",
+                "user.email=gluon@example.com",
+            ])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// Creates a git repository in `dir` with a commit tagged by each of `tags`
+    fn git_package(dir: &Path, tags: &[&str]) -> String {
+        fs::create_dir_all(dir).unwrap();
+        git(dir, &["init", "--quiet"]);
+        for tag in tags {
+            write_file(&dir.join("version.glu"), &format!("{:?}", tag));
+            git(dir, &["add", "."]);
+            git(dir, &["commit", "--quiet", "-m", tag]);
+            git(dir, &["tag", tag]);
+        }
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn read_file(path: &Path) -> String {
+        let mut contents = String::new();
+        File::open(path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn resolve_path_dependencies() {
+        let root = env::temp_dir().join("gluon_resolve_path_dependencies");
+        let _ = fs::remove_dir_all(&root);
+        write_file(
+            &root.join("app").join(MANIFEST),
+            r#"{ "dependencies": { "a": { "path": "../a" }, "b": { "path": "../b" } } }"#,
+        );
+        write_file(
+            &root.join("a").join(MANIFEST),
+            r#"{ "dependencies": { "b": { "path": "../b" } } }"#,
+        );
+        write_file(&root.join("b").join("b.glu"), "1");
+
+        let packages = resolve(&root.join("app")).unwrap_or_else(|err| panic!("{}", err));
+        let names = packages
+            .iter()
+            .map(|package| &package.name[..])
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(packages[1].dir, root.join("b").canonicalize().unwrap());
+    }
+
+    #[test]
+    fn resolve_conflicting_dependencies() {
+        let root = env::temp_dir().join("gluon_resolve_conflicting_dependencies");
+        let _ = fs::remove_dir_all(&root);
+        write_file(
+            &root.join("app").join(MANIFEST),
+            r#"{ "dependencies": { "a": { "path": "../a" }, "b": { "path": "../b" } } }"#,
+        );
+        write_file(
+            &root.join("a").join(MANIFEST),
+            r#"{ "dependencies": { "b": { "path": "../a" } } }"#,
+        );
+        fs::create_dir_all(root.join("b")).unwrap();
+
+        let err = resolve(&root.join("app")).unwrap_err().to_string();
+        assert!(
+            err.contains("Package `b` is required from different sources"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn reject_package_names_which_are_not_identifiers() {
+        let root = env::temp_dir().join("gluon_reject_package_names");
+        let _ = fs::remove_dir_all(&root);
+        write_file(
+            &root.join(MANIFEST),
+            r#"{ "dependencies": { "../a": { "git": "https://example.com/a" } } }"#,
+        );
+
+        let err = resolve(&root).unwrap_err().to_string();
+        assert!(err.contains("Invalid package name `../a`"), "{}", err);
+        assert!(!root.join(PACKAGE_DIR).exists());
+    }
+
+    #[test]
+    fn reject_git_arguments_which_look_like_options() {
+        let root = env::temp_dir().join("gluon_reject_git_options");
+        let _ = fs::remove_dir_all(&root);
+        write_file(
+            &root.join(MANIFEST),
+            r#"{ "dependencies": { "a": { "git": "--upload-pack=touch x" } } }"#,
+        );
+        let err = resolve(&root).unwrap_err().to_string();
+        assert!(err.contains("Invalid git url"), "{}", err);
+
+        write_file(
+            &root.join(MANIFEST),
+            r#"{ "dependencies": { "a": { "git": "https://example.com/a", "rev": "--orphan" } } }"#,
+        );
+        let err = resolve(&root).unwrap_err().to_string();
+        assert!(err.contains("Invalid rev"), "{}", err);
+    }
+
+    #[test]
+    fn resolve_the_highest_version_matching_every_requirement() {
+        let root = env::temp_dir().join("gluon_resolve_versions");
+        let _ = fs::remove_dir_all(&root);
+        let url = git_package(&root.join("a_repo"), &["v1.0.0", "v1.2.0", "v2.0.0"]);
+        write_file(
+            &root.join("app").join(MANIFEST),
+            &format!(
+                r#"{{ "dependencies": {{ "a": {{ "git": {:?}, "version": "^1.0" }}, "b": {{ "path": "../b" }} }} }}"#,
+                url
+            ),
+        );
+        write_file(
+            &root.join("b").join(MANIFEST),
+            &format!(
+                r#"{{ "dependencies": {{ "a": {{ "git": {:?}, "version": "<1.2" }} }} }}"#,
+                url
+            ),
+        );
+
+        let packages = resolve(&root.join("app")).unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(packages[0].name, "a");
+        assert_eq!(packages[0].version, Some(Version::parse("1.0.0").unwrap()));
+        assert_eq!(
+            read_file(&packages[0].dir.join("version.glu")),
+            r#""v1.0.0""#
+        );
+    }
+
+    #[test]
+    fn resolve_registry_dependencies() {
+        let root = env::temp_dir().join("gluon_resolve_registry");
+        let _ = fs::remove_dir_all(&root);
+        let url = git_package(&root.join("a_repo"), &["0.9.0", "1.0.0", "1.2.0", "2.0.0"]);
+        write_file(
+            &root.join("registry").join("a.json"),
+            &format!(r#"{{ "git": {:?} }}"#, url),
+        );
+        write_file(
+            &root.join("app").join(MANIFEST),
+            r#"{ "registry": "../registry", "dependencies": { "a": { "version": "1" } } }"#,
+        );
+
+        let packages = resolve(&root.join("app")).unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(packages[0].version, Some(Version::parse("1.2.0").unwrap()));
+        assert_eq!(
+            read_file(&packages[0].dir.join("version.glu")),
+            r#""1.2.0""#
+        );
+
+        write_file(
+            &root.join("app").join(MANIFEST),
+            r#"{ "registry": "../registry", "dependencies": { "a": { "version": "^3" } } }"#,
+        );
+        let err = resolve(&root.join("app")).unwrap_err().to_string();
+        assert!(err.contains("No version of package `a`"), "{}", err);
+    }
+}
@@ -4,20 +4,29 @@ extern crate rustyline;
 
 extern crate gluon_completion as completion;
 
+use std::borrow::Cow;
 use std::error::Error as StdError;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::Instant;
 
 use futures::sync::mpsc;
 use futures::{Future, Sink, Stream};
 
-use base::ast::{Expr, Pattern, SpannedPattern, Typed};
+use base::ast::{
+    expr_to_path, walk_mut_expr, Expr, MutVisitor, Pattern, SpannedExpr, SpannedPattern, Typed,
+    TypedIdent,
+};
 use base::error::InFile;
-use base::kind::Kind;
-use base::pos;
-use base::symbol::{Symbol, SymbolModule};
-use base::types::ArcType;
-use parser::parse_partial_let_or_expr;
+use base::kind::{ArcKind, Kind, KindCache, KindEnv};
+use base::pos::{self, BytePos};
+use base::resolve;
+use base::symbol::{Symbol, SymbolModule, SymbolRef, Symbols};
+use base::types::{ArcType, Type};
+use parser::{self, parse_partial_let_or_expr, Error as ParseError, TokenKind};
 use vm::api::de::De;
 use vm::api::generic::A;
 use vm::api::ser::Ser;
@@ -27,11 +36,13 @@ use vm::api::{
 };
 use vm::future::FutureValue;
 use vm::internal::ValuePrinter;
-use vm::thread::{Context, RootStr, RootedValue, Thread, ThreadInternal};
+use vm::thread::{Context, HookFlags, RootStr, RootedValue, Thread, ThreadInternal};
 use vm::{self, Error as VMError, Result as VMResult};
 
+use gluon::check::kindcheck::KindCheck;
+use gluon::check::typecheck::TypeError;
 use gluon::compiler_pipeline::{Executable, ExecuteValue};
-use gluon::import::add_extern_module;
+use gluon::import::{add_extern_module, Import};
 use gluon::{Compiler, Error as GluonError, Result as GluonResult, RootedThread};
 
 use codespan_reporting::termcolor;
@@ -50,24 +61,55 @@ fn type_of_expr(args: WithVM<RootStr>) -> IO<Result<String, String>> {
     })
 }
 
+fn alias_kind(vm: &Thread, name: &str) -> Result<ArcKind, String> {
+    match vm.find_type_info(name) {
+        Ok(ref alias) => Ok(alias.params().iter().rev().fold(Kind::typ(), |acc, arg| {
+            Kind::function(arg.kind.clone(), acc)
+        })),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+/// Looks up the kinds of the type names in a type entered in the repl
+struct ReplKindEnv<'a>(&'a Thread);
+
+impl<'a> KindEnv for ReplKindEnv<'a> {
+    fn find_kind(&self, type_name: &SymbolRef) -> Option<ArcKind> {
+        alias_kind(self.0, type_name.definition_name()).ok()
+    }
+}
+
+/// Returns the kind of a type (`Result String`)
+fn type_kind(vm: &Thread, typ: &str) -> Result<ArcKind, String> {
+    // Types are only parsed as part of an expression so parse the type as an annotation
+    let source = format!("let _ : {} = () in ()", typ);
+    let expr = Compiler::new()
+        .parse_expr(vm.global_env().type_cache(), "<kind>", &source)
+        .map_err(|err| err.to_string())?;
+    let mut typ = match expr.value {
+        Expr::LetBindings(ref binds, _) => binds[0].typ.clone(),
+        _ => None,
+    }.ok_or_else(|| "Expected a type".to_string())?;
+
+    let env = ReplKindEnv(vm);
+    let symbols = Symbols::new();
+    let mut kindcheck = KindCheck::new(&env, &symbols, KindCache::new());
+    kindcheck
+        .infer_kind(&mut typ)
+        .map_err(|err| TypeError::from(err.value).to_string())
+}
+
 fn find_kind(args: WithVM<RootStr>) -> IO<Result<String, String>> {
     let vm = args.vm;
-    let args = args.value.trim();
-    IO::Value(match vm.find_type_info(args) {
-        Ok(ref alias) => {
-            let kind = alias.params().iter().rev().fold(Kind::typ(), |acc, arg| {
-                Kind::function(arg.kind.clone(), acc)
-            });
-            Ok(format!("{}", kind))
-        }
-        Err(err) => Err(format!("{}", err)),
-    })
+    IO::Value(type_kind(vm, &args.value).map(|kind| kind.to_string()))
 }
 
 fn find_info(args: WithVM<RootStr>) -> IO<Result<String, String>> {
+    IO::Value(info(args.vm, args.value.trim()))
+}
+
+fn info(vm: &Thread, args: &str) -> Result<String, String> {
     use std::fmt::Write;
-    let vm = args.vm;
-    let args = args.value.trim();
     let env = vm.get_env();
     let mut buffer = String::new();
     match env.find_type_info(args) {
@@ -88,7 +130,7 @@ fn find_info(args: WithVM<RootStr>) -> IO<Result<String, String>> {
                 Ok((_, typ)) => {
                     write!(&mut buffer, "{}: {}", args, typ).unwrap();
                 }
-                Err(_) => return IO::Value(Err(format!("{}", err))),
+                Err(_) => return Err(format!("{}", err)),
             }
         }
     }
@@ -101,11 +143,27 @@ fn find_info(args: WithVM<RootStr>) -> IO<Result<String, String>> {
             write!(&mut buffer, "\n/// {}", line).unwrap();
         }
     }
-    IO::Value(Ok(buffer))
+    Ok(buffer)
+}
+
+fn find_doc(args: WithVM<RootStr>) -> IO<Result<String, String>> {
+    use std::fmt::Write;
+    let vm = args.vm;
+    let name = args.value.trim();
+    IO::Value(info(vm, name).map(|mut buffer| {
+        let env = vm.get_env();
+        let maybe_definition = env
+            .get_metadata(name)
+            .ok()
+            .and_then(|metadata| metadata.definition.as_ref());
+        if let Some(definition) = maybe_definition {
+            write!(&mut buffer, "\nDefined at {}", definition).unwrap();
+        }
+        buffer
+    }))
 }
 
 fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonResult<Vec<String>> {
-    use base::pos::BytePos;
     use gluon::compiler_pipeline::*;
 
     let mut compiler = Compiler::new();
@@ -121,10 +179,26 @@ fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonRe
 
     // Only need the typechecker to fill infer the types as best it can regardless of errors
     let _ = (&mut expr).typecheck(&mut compiler, thread, &name, fileinput);
+    ReplaceImport.visit_expr(&mut expr);
+
     let file_map = compiler
         .get_filemap(&name)
         .ok_or_else(|| VMError::from("FileMap is missing for completion".to_string()))?;
-    let suggestions = completion::suggest(
+
+    // Let module paths be completed from the same places that the import macro looks in
+    let opt_macro = thread.get_macros().get("import");
+    let query = match opt_macro
+        .as_ref()
+        .and_then(|mac| mac.downcast_ref::<Import>())
+    {
+        Some(import) => completion::SuggestionQuery {
+            paths: import.paths.read().unwrap().clone(),
+            modules: import.modules(),
+            ..completion::SuggestionQuery::default()
+        },
+        None => completion::SuggestionQuery::default(),
+    };
+    let suggestions = query.suggest(
         &*thread.get_env(),
         file_map.span(),
         &expr,
@@ -139,11 +213,133 @@ fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonRe
         .collect())
 }
 
-struct Completer(RootedThread);
+/// Macros are not expanded before completing so `import! module` is replaced by the global it
+/// would expand to, which lets the module path be completed
+struct ReplaceImport;
+
+impl<'a> MutVisitor<'a> for ReplaceImport {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &mut SpannedExpr<Symbol>) {
+        let replacement = match expr.value {
+            Expr::App {
+                ref func, ref args, ..
+            } => match func.value {
+                Expr::Ident(ref id) if id.name.declared_name() == "import!" && args.len() == 1 => {
+                    let mut path = "@".to_string();
+                    expr_to_path(&args[0], &mut path).ok().map(|_| {
+                        Expr::Ident(TypedIdent {
+                            name: Symbol::from(path),
+                            typ: Type::hole(),
+                        })
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match replacement {
+            Some(replacement) => expr.value = replacement,
+            None => walk_mut_expr(self, expr),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const MATCHING_BRACKET: &str = "\x1b[1;36m";
+
+fn token_style(kind: TokenKind) -> Option<&'static str> {
+    Some(match kind {
+        TokenKind::Keyword => "\x1b[35m",
+        TokenKind::String | TokenKind::Char => "\x1b[32m",
+        TokenKind::Number => "\x1b[36m",
+        TokenKind::Operator => "\x1b[33m",
+        TokenKind::DocComment => "\x1b[90m",
+        TokenKind::Identifier | TokenKind::Delimiter | TokenKind::Punctuation => return None,
+    })
+}
+
+/// Returns the positions of the bracket just before (or at) `pos` and of the bracket matching it
+fn matching_bracket(
+    line: &str,
+    tokens: &[(TokenKind, Range<usize>)],
+    pos: usize,
+) -> Option<(usize, usize)> {
+    let delimiters = tokens
+        .iter()
+        .filter(|&&(kind, _)| kind == TokenKind::Delimiter)
+        .map(|&(_, ref range)| range.start)
+        .collect::<Vec<_>>();
+    let current = delimiters
+        .iter()
+        .position(|&start| start + 1 == pos)
+        .or_else(|| delimiters.iter().position(|&start| start == pos))?;
+
+    let bytes = line.as_bytes();
+    let bracket = bytes[delimiters[current]];
+    let (other, forward) = match bracket {
+        b'(' => (b')', true),
+        b'[' => (b']', true),
+        b'{' => (b'}', true),
+        b')' => (b'(', false),
+        b']' => (b'[', false),
+        b'}' => (b'{', false),
+        _ => return None,
+    };
+    let candidates: Box<Iterator<Item = &usize>> = if forward {
+        Box::new(delimiters[current + 1..].iter())
+    } else {
+        Box::new(delimiters[..current].iter().rev())
+    };
+    let mut depth = 0;
+    for &start in candidates {
+        if bytes[start] == bracket {
+            depth += 1;
+        } else if bytes[start] == other {
+            if depth == 0 {
+                return Some((delimiters[current], start));
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Colors the keywords, literals and operators of `line` as well as the bracket matching the one
+/// at the cursor
+fn highlight(line: &str, pos: usize) -> String {
+    let tokens = parser::classify_tokens(line);
+    let matching = matching_bracket(line, &tokens, pos);
+
+    let mut highlighted = String::with_capacity(line.len());
+    let mut end = 0;
+    for &(kind, ref range) in &tokens {
+        let style = match matching {
+            Some((l, r)) if range.start == l || range.start == r => Some(MATCHING_BRACKET),
+            _ => token_style(kind),
+        };
+        if let Some(style) = style {
+            highlighted.push_str(&line[end..range.start]);
+            highlighted.push_str(style);
+            highlighted.push_str(&line[range.clone()]);
+            highlighted.push_str(RESET);
+            end = range.end;
+        }
+    }
+    highlighted.push_str(&line[end..]);
+    highlighted
+}
+
+struct Helper {
+    thread: RootedThread,
+    highlight: bool,
+}
+
+impl rustyline::completion::Completer for Helper {
+    type Candidate = String;
 
-impl rustyline::completion::Completer for Completer {
     fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
-        let result = complete(&self.0, "<repl>", line, pos);
+        let result = complete(&self.thread, "<repl>", line, pos);
 
         // Get the start of the completed identifier
         let ident_start = line[..pos]
@@ -153,6 +349,25 @@ impl rustyline::completion::Completer for Completer {
     }
 }
 
+impl rustyline::hint::Hinter for Helper {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+}
+
+impl rustyline::highlight::Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        // Commands are not gluon code
+        if self.highlight && !line.starts_with(':') {
+            Cow::Owned(highlight(line, pos))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+}
+
+impl rustyline::Helper for Helper {}
+
 macro_rules! impl_userdata {
     ($name:ident) => {
         impl ::gluon::vm::api::Userdata for $name {}
@@ -174,7 +389,9 @@ macro_rules! impl_userdata {
 }
 
 struct Editor {
-    editor: Mutex<rustyline::Editor<Completer>>,
+    editor: Mutex<rustyline::Editor<Helper>>,
+    /// The file which history is loaded from and saved to
+    history: Option<PathBuf>,
 }
 
 impl_userdata!{ Editor }
@@ -218,19 +435,65 @@ fn app_dir_root() -> Result<PathBuf, Box<StdError>> {
     )?)
 }
 
-fn new_editor(vm: WithVM<()>) -> IO<Editor> {
-    let mut editor = rustyline::Editor::new();
+/// Selects the keybindings of the line editor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditMode {
+    Emacs,
+    Vi,
+}
+
+impl ::std::str::FromStr for EditMode {
+    type Err = &'static str;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "emacs" => EditMode::Emacs,
+            "vi" => EditMode::Vi,
+            _ => return Err("Expected one of emacs, vi"),
+        })
+    }
+}
 
-    let history_result =
-        app_dir_root().and_then(|path| Ok(editor.load_history(&*path.join("history"))?));
+pub struct Settings {
+    pub color: Color,
+    pub prompt: String,
+    pub edit_mode: EditMode,
+    /// Defaults to `history` in the application directory
+    pub history: Option<PathBuf>,
+    /// Defaults to `init.glu` in the application directory, if it exists
+    pub init: Option<PathBuf>,
+}
 
-    if let Err(err) = history_result {
-        warn!("Unable to load history: {}", err);
+fn new_editor(vm: RootedThread, settings: &Settings) -> Editor {
+    let edit_mode = match settings.edit_mode {
+        EditMode::Emacs => rustyline::config::EditMode::Emacs,
+        EditMode::Vi => rustyline::config::EditMode::Vi,
+    };
+    let config = rustyline::config::Config::builder()
+        .edit_mode(edit_mode)
+        .build();
+    let mut editor = rustyline::Editor::with_config(config);
+
+    let history = settings
+        .history
+        .clone()
+        .or_else(|| app_dir_root().ok().map(|path| path.join("history")));
+
+    if let Some(ref history) = history {
+        // The history file does not exist the first time the repl is started
+        if history.exists() {
+            if let Err(err) = editor.load_history(history) {
+                warn!("Unable to load history: {}", err);
+            }
+        }
     }
-    editor.set_completer(Some(Completer(vm.vm.root_thread())));
-    IO::Value(Editor {
+    editor.set_helper(Some(Helper {
+        thread: vm,
+        highlight: settings.color != Color::Never,
+    }));
+    Editor {
         editor: Mutex::new(editor),
-    })
+        history,
+    }
 }
 
 fn readline(editor: &Editor, prompt: &str) -> IO<Result<String, ReadlineError>> {
@@ -250,26 +513,87 @@ fn readline(editor: &Editor, prompt: &str) -> IO<Result<String, ReadlineError>>
     IO::Value(Ok(input))
 }
 
+/// Returns `false` if `input` is the start of an expression which continues on the next line, that
+/// is, if it can only fail to parse because of errors at the end of the input
+fn is_complete(input: &str) -> bool {
+    let mut symbols = Symbols::new();
+    let mut module = SymbolModule::new("line".into(), &mut symbols);
+    // Spans of a `str` start at 1
+    let end = BytePos::from(1 + input.trim_right().len() as u32);
+    match parse_partial_let_or_expr(&mut module, input) {
+        Ok(_) => true,
+        Err((_, errors)) => !errors.iter().any(|err| match err.value {
+            ParseError::UnexpectedEof(_) => true,
+            _ => err.span.start() >= end,
+        }),
+    }
+}
+
 fn new_cpu_pool(size: usize) -> IO<CpuPool> {
     IO::Value(CpuPool(self::futures_cpupool::CpuPool::new(size)))
 }
 
+/// Statistics about the evaluation of a line, returned to the repl loop if `:set +stats` has been
+/// used
+struct EvalStats {
+    thread: RootedThread,
+    start: Instant,
+    total_allocated: usize,
+}
+
+impl EvalStats {
+    fn start(thread: &Thread) -> EvalStats {
+        {
+            // No hook function is set so the `COUNT_FLAG` hook is never called and only counts the
+            // executed instructions
+            let mut context = thread.context();
+            context.set_hook_mask(HookFlags::COUNT_FLAG);
+            context.set_hook_count(usize::max_value());
+        }
+        EvalStats {
+            thread: thread.root_thread(),
+            start: Instant::now(),
+            total_allocated: thread.heap_stats().total_allocated,
+        }
+    }
+
+    fn finish(self) -> String {
+        let elapsed = self.start.elapsed();
+        let instructions = {
+            let mut context = self.thread.context();
+            context.set_hook_mask(HookFlags::empty());
+            context.instructions_since_count()
+        };
+        let millis =
+            elapsed.as_secs() as f64 * 1000.0 + f64::from(elapsed.subsec_nanos()) / 1_000_000.0;
+        format!(
+            "time: {:.3}ms, instructions: {}, allocated: {} bytes",
+            millis,
+            instructions,
+            self.thread.heap_stats().total_allocated - self.total_allocated
+        )
+    }
+}
+
 fn eval_line(
     De(color): De<::Color>,
+    stats: bool,
     WithVM { vm, value: line }: WithVM<&str>,
-) -> PrimitiveFuture<IO<()>> {
+) -> PrimitiveFuture<IO<Option<String>>> {
+    let stats = if stats {
+        Some(EvalStats::start(vm))
+    } else {
+        None
+    };
     eval_line_(vm.root_thread(), line)
         .then(move |result| {
-            FutureValue::sync(Ok(match result {
-                Ok(x) => IO::Value(x),
-                Err((compiler, err)) => {
-                    let mut stderr = termcolor::StandardStream::stderr(color.into());
-                    if let Err(err) = err.emit(&mut stderr, compiler.code_map()) {
-                        eprintln!("{}", err);
-                    }
-                    IO::Value(())
+            if let Err((compiler, err)) = result {
+                let mut stderr = termcolor::StandardStream::stderr(color.into());
+                if let Err(err) = err.emit(&mut stderr, compiler.code_map()) {
+                    eprintln!("{}", err);
                 }
-            }))
+            }
+            FutureValue::sync(Ok(IO::Value(stats.map(EvalStats::finish))))
         })
         .boxed()
 }
@@ -324,8 +648,14 @@ fn eval_line_(
         }
     };
     future
-        .map_err(|x| (compiler, x))
-        .map(move |ExecuteValue { value, typ, .. }| {
+        .then(move |result| {
+            let ExecuteValue { value, typ, .. } = match result {
+                Ok(value) => value,
+                Err(err) => return FutureValue::sync(Err((compiler, err))),
+            };
+            if let Some(warnings) = compiler.take_warnings() {
+                eprintln!("{}", warnings);
+            }
             let vm = value.vm();
             let env = vm.global_env().get_env();
             println!(
@@ -334,10 +664,70 @@ fn eval_line_(
                     .width(80)
                     .max_level(5)
             );
+            FutureValue::sync(Ok(()))
         })
         .boxed()
 }
 
+/// Binds each field of the record `value` as a global
+fn set_record_globals(vm: &Thread, typ: &ArcType, value: &RootedValue<&Thread>) -> VMResult<()> {
+    let resolved_type = resolve::remove_aliases_cow(&*vm.get_env(), typ).into_owned();
+    let fields = resolved_type
+        .row_iter()
+        .zip(::vm::dynamic::field_iter(value, typ, vm));
+    for (field, (field_value, field_type)) in fields {
+        vm.set_global(
+            Symbol::from(format!("@{}", field.name.declared_name())),
+            field_type,
+            Default::default(),
+            field_value.get_value(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Evaluates the file at `filename`, binding the result to the name of the file as well as binding
+/// each of its fields if it is a record. Any modules it imports which have changed since they were
+/// last loaded are recompiled.
+fn load_file(WithVM { vm, value: filename }: WithVM<&str>) -> IO<String> {
+    match load_file_(vm, filename) {
+        Ok(msg) => IO::Value(msg),
+        Err(err) => IO::Exception(err),
+    }
+}
+
+fn load_file_(vm: &Thread, filename: &str) -> Result<String, String> {
+    let path = Path::new(filename);
+    let name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid file name `{}`", filename))?;
+
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("Unable to read `{}`: {}", filename, err))?;
+
+    let mut compiler = Compiler::new().run_io(true);
+    let result = source
+        .run_expr(&mut compiler, vm.root_thread(), name, &source, None)
+        .wait();
+    let ExecuteValue { value, typ, .. } = result.map_err(|err| {
+        err.emit_string(compiler.code_map())
+            .unwrap_or_else(|_| err.to_string())
+    })?;
+
+    let value = value.as_ref();
+    vm.set_global(
+        Symbol::from(format!("@{}", name)),
+        typ.clone(),
+        Default::default(),
+        value.get_value(),
+    ).map_err(|err| err.to_string())?;
+    set_record_globals(vm, &typ, &value).map_err(|err| err.to_string())?;
+    Ok(format!("{} : {}", name, typ))
+}
+
 fn set_globals(
     vm: &Thread,
     pattern: &SpannedPattern<Symbol>,
@@ -440,20 +830,10 @@ fn finish_or_interrupt(
 }
 
 fn save_history(editor: &Editor) -> IO<()> {
-    let history_result = app_dir_root().and_then(|path| {
-        editor
-            .editor
-            .lock()
-            .unwrap()
-            .save_history(&*path.join("history"))
-            .map_err(|err| {
-                let err: Box<StdError> = Box::new(err);
-                err
-            })
-    });
-
-    if let Err(err) = history_result {
-        warn!("Unable to load history: {}", err);
+    if let Some(ref history) = editor.history {
+        if let Err(err) = editor.editor.lock().unwrap().save_history(history) {
+            warn!("Unable to save history: {}", err);
+        }
     }
     IO::Value(())
 }
@@ -467,7 +847,6 @@ fn load_rustyline(vm: &Thread) -> vm::Result<vm::ExternModule> {
         record!(
             type Editor => Editor,
             type CpuPool => CpuPool,
-            new_editor => primitive!(1 new_editor),
             readline => primitive!(2 readline),
             save_history => primitive!(1 save_history)
         ),
@@ -480,8 +859,11 @@ fn load_repl(vm: &Thread) -> vm::Result<vm::ExternModule> {
         record!(
             type_of_expr => primitive!(1 type_of_expr),
             find_info => primitive!(1 find_info),
+            find_doc => primitive!(1 find_doc),
             find_kind => primitive!(1 find_kind),
-            eval_line => primitive!(2 eval_line),
+            eval_line => primitive!(3 eval_line),
+            load_file => primitive!(1 load_file),
+            is_complete => primitive!(1 is_complete),
             finish_or_interrupt => primitive!(3 finish_or_interrupt),
             new_cpu_pool => primitive!(1 new_cpu_pool)
         ),
@@ -511,20 +893,53 @@ fn compile_repl(compiler: &mut Compiler, vm: &Thread) -> Result<(), GluonError>
     Ok(())
 }
 
+/// Runs the init script at `path` and binds each field of the record it returns as a global
+fn load_init(
+    core: &mut ::tokio_core::reactor::Core,
+    vm: &RootedThread,
+    path: &Path,
+) -> Result<(), Box<StdError + Send + Sync>> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+
+    let mut compiler = Compiler::new().run_io(true);
+    let name = "init";
+    let result = core.run(source.run_expr(&mut compiler, vm.clone(), name, &source, None));
+    let ExecuteValue { value, typ, .. } =
+        result.map_err(|err| err.emit_string(compiler.code_map()).unwrap())?;
+
+    set_record_globals(vm, &typ, &value.as_ref())?;
+    Ok(())
+}
+
 #[allow(dead_code)]
-pub fn run(color: Color) -> Result<(), Box<StdError + Send + Sync>> {
+pub fn run(settings: Settings) -> Result<(), Box<StdError + Send + Sync>> {
     let mut core = ::tokio_core::reactor::Core::new()?;
 
     let vm = ::gluon::VmBuilder::new()
         .event_loop(Some(core.remote()))
         .build();
+    ::package::add_package_paths(&vm).map_err(|err| err.to_string())?;
 
     let mut compiler = Compiler::new();
     compile_repl(&mut compiler, &vm).map_err(|err| err.emit_string(compiler.code_map()).unwrap())?;
 
-    let mut repl: OwnedFunction<fn(Ser<Color>) -> IO<()>> = vm.get_global("repl")?;
+    let init = settings.init.clone().or_else(|| {
+        app_dir_root()
+            .ok()
+            .map(|path| path.join("init.glu"))
+            .and_then(|path| if path.exists() { Some(path) } else { None })
+    });
+    if let Some(init) = init {
+        debug!("Loading init script `{}`", init.display());
+        load_init(&mut core, &vm, &init)?;
+    }
+
+    let editor = new_editor(vm.clone(), &settings);
+    let mut repl: OwnedFunction<fn(Ser<Color>, String, Editor) -> IO<()>> =
+        vm.get_global("repl")?;
     debug!("Starting repl");
-    core.run(repl.call_async(Ser(color)))?;
+    core.run(repl.call_async(Ser(settings.color), settings.prompt, editor))?;
 
     Ok(())
 }
@@ -556,7 +971,8 @@ mod tests {
         let _ = ::env_logger::try_init();
         let vm = new_vm();
         compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
-        let repl: Result<FunctionRef<fn(Color) -> IO<()>>, _> = vm.get_global("repl");
+        let repl: Result<FunctionRef<fn(Color, String, Editor) -> IO<()>>, _> =
+            vm.get_global("repl");
         assert!(repl.is_ok(), "{}", repl.err().unwrap());
     }
 
@@ -583,6 +999,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn find_kind_of_applied_type() {
+        let _ = ::env_logger::try_init();
+        let vm = new_vm();
+        compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
+        let mut find_kind: FunctionRef<QueryFn> = vm.get_global("repl.prim.find_kind").unwrap();
+        assert_eq!(
+            find_kind.call("std.result.Result String"),
+            Ok(IO::Value(Ok("Type -> Type".into())))
+        );
+        match find_kind.call("std.prelude.Semigroup std.prelude.Semigroup") {
+            Ok(IO::Value(Err(_))) => (),
+            x => assert!(false, "{:?}", x),
+        }
+        assert_eq!(
+            find_kind.call("Int -> std.result.Result String Int"),
+            Ok(IO::Value(Ok("Type".into())))
+        );
+    }
+
     #[test]
     fn find_info() {
         let _ = ::env_logger::try_init();
@@ -603,6 +1039,90 @@ mod tests {
         }
     }
 
+    #[test]
+    fn incomplete_input() {
+        assert!(is_complete("1 + 2"));
+        assert!(is_complete("let x = 1"));
+        assert!(!is_complete("let f x ="));
+        assert!(!is_complete("match x with"));
+        assert!(is_complete("match x with\n| _ -> 1"));
+        assert!(is_complete("1 +)"));
+    }
+
+    #[test]
+    fn find_doc() {
+        let _ = ::env_logger::try_init();
+        let vm = new_vm();
+        compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
+        let mut find_doc: FunctionRef<QueryFn> = vm.get_global("repl.prim.find_doc").unwrap();
+        let line = include_str!("../../std/map.glu")
+            .lines()
+            .position(|line| line.starts_with("let singleton "))
+            .expect("singleton")
+            + 1;
+        let location = format!("Defined at std.map:{}", line);
+        match find_doc.call("std.map.singleton") {
+            Ok(IO::Value(Ok(ref doc))) if doc.ends_with(&location) => (),
+            x => assert!(false, "{:?}", x),
+        }
+    }
+
+    #[test]
+    fn init_script_binds_record_fields() {
+        use std::io::Write;
+
+        let _ = ::env_logger::try_init();
+        let mut core = ::tokio_core::reactor::Core::new().unwrap();
+        let vm = new_vm();
+
+        let path = ::std::env::temp_dir().join("gluon_repl_init.glu");
+        File::create(&path)
+            .and_then(|mut file| file.write_all(b"let double x = x * 2\n{ double }"))
+            .unwrap();
+        load_init(&mut core, &vm, &path).unwrap_or_else(|err| panic!("{}", err));
+
+        let mut double: FunctionRef<fn(i32) -> i32> = vm.get_global("double").unwrap();
+        assert_eq!(double.call(3), Ok(6));
+    }
+
+    #[test]
+    fn reload_file() {
+        use std::io::Write;
+
+        let _ = ::env_logger::try_init();
+        let vm = new_vm();
+
+        let path = ::std::env::temp_dir().join("gluon_repl_reload.glu");
+        let path = path.to_str().unwrap();
+        let write = |source: &str| {
+            File::create(path)
+                .and_then(|mut file| file.write_all(source.as_bytes()))
+                .unwrap();
+        };
+
+        write("let value = 1\n{ value }");
+        load_file_(&vm, path).unwrap_or_else(|err| panic!("{}", err));
+        let value: i32 = vm.get_global("value").unwrap();
+        assert_eq!(value, 1);
+
+        write("let value = 2\n{ value }");
+        load_file_(&vm, path).unwrap_or_else(|err| panic!("{}", err));
+        let value: i32 = vm.get_global("value").unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn highlight_line() {
+        assert_eq!(
+            highlight("let x = \"a\"", 0),
+            "\x1b[35mlet\x1b[0m x = \x1b[32m\"a\"\x1b[0m"
+        );
+        assert_eq!(
+            highlight("f (g [1])", 9),
+            "f \x1b[1;36m(\x1b[0mg [\x1b[36m1\x1b[0m]\x1b[1;36m)\x1b[0m"
+        );
+    }
+
     #[test]
     fn complete_repl_empty() {
         let _ = ::env_logger::try_init();
@@ -610,4 +1130,19 @@ mod tests {
         compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
         complete(&vm, "<repl>", "", 0).unwrap_or_else(|err| panic!("{}", err));
     }
+
+    #[test]
+    fn complete_module_path_after_import() {
+        let _ = ::env_logger::try_init();
+        let vm = new_vm();
+        compile_repl(&mut Compiler::new(), &vm).unwrap_or_else(|err| panic!("{}", err));
+        let line = "import! std.p";
+        let suggestions =
+            complete(&vm, "<repl>", line, line.len()).unwrap_or_else(|err| panic!("{}", err));
+        assert!(
+            suggestions.iter().any(|name| name == "prelude"),
+            "{:?}",
+            suggestions
+        );
+    }
 }
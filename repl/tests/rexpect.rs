@@ -139,3 +139,24 @@ fn arrays() {
     repl.test("let array = import! std.array", None);
     repl.test("array.len [1, 2, 3]", Some("3"));
 }
+
+#[test]
+fn multi_line_input() {
+    let mut repl = REPL::new();
+
+    for line in &["let add1 x =", "    x + 1"] {
+        repl.session.send_line(line).unwrap();
+        repl.session.exp_string("... ").unwrap();
+    }
+    repl.test("", None);
+    repl.test("add1 2", Some("3"));
+}
+
+#[test]
+fn evaluation_stats() {
+    let mut repl = REPL::new();
+
+    repl.test(":set +stats", None);
+    repl.test("1 + 2", Some("instructions: "));
+    repl.test(":set -stats", None);
+}
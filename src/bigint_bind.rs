@@ -0,0 +1,246 @@
+//! Module containing bindings to the `num-bigint` library.
+
+extern crate num_bigint;
+extern crate num_traits;
+
+use std::cmp::Ordering;
+
+use futures::{future, Future};
+
+use self::num_bigint::Sign;
+use self::num_traits::{pow, Signed, ToPrimitive, Zero};
+
+use base::ast::{Expr, Literal, SpannedExpr, TypedIdent};
+use base::pos;
+use base::symbol::Symbol;
+use base::types::Type;
+
+use vm::api::{RuntimeResult, Userdata, VmType};
+use vm::gc::{Gc, Traverseable};
+use vm::macros::{Error as MacroError, Macro, MacroExpander, MacroFuture};
+use vm::thread::Thread;
+use vm::types::VmInt;
+use vm::{self, ExternModule};
+
+#[derive(Clone, Debug)]
+struct BigInt(num_bigint::BigInt);
+
+impl Userdata for BigInt {}
+
+impl VmType for BigInt {
+    type Type = BigInt;
+}
+
+impl Traverseable for BigInt {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+fn from_int(i: VmInt) -> BigInt {
+    BigInt(i.into())
+}
+
+fn to_int(i: &BigInt) -> Option<VmInt> {
+    i.0.to_isize()
+}
+
+fn parse(s: &str) -> Result<BigInt, ()> {
+    s.parse().map(BigInt).map_err(|_| ())
+}
+
+fn from_literal(s: &str) -> RuntimeResult<BigInt, String> {
+    match s.parse() {
+        Ok(i) => RuntimeResult::Return(BigInt(i)),
+        Err(_) => RuntimeResult::Panic(format!("Invalid BigInt literal `{}`", s)),
+    }
+}
+
+fn from_str_radix(s: &str, radix: VmInt) -> Result<BigInt, ()> {
+    if radix < 2 || radix > 36 {
+        return Err(());
+    }
+    num_bigint::BigInt::parse_bytes(s.as_bytes(), radix as u32)
+        .map(BigInt)
+        .ok_or(())
+}
+
+fn show(i: &BigInt) -> String {
+    i.0.to_string()
+}
+
+fn to_str_radix(i: &BigInt, radix: VmInt) -> RuntimeResult<String, String> {
+    if radix < 2 || radix > 36 {
+        return RuntimeResult::Panic(format!("Radix {} is not between 2 and 36", radix));
+    }
+    RuntimeResult::Return(i.0.to_str_radix(radix as u32))
+}
+
+fn add(l: &BigInt, r: &BigInt) -> BigInt {
+    BigInt(&l.0 + &r.0)
+}
+
+fn sub(l: &BigInt, r: &BigInt) -> BigInt {
+    BigInt(&l.0 - &r.0)
+}
+
+fn mul(l: &BigInt, r: &BigInt) -> BigInt {
+    BigInt(&l.0 * &r.0)
+}
+
+fn div(l: &BigInt, r: &BigInt) -> RuntimeResult<BigInt, String> {
+    if r.0.is_zero() {
+        RuntimeResult::Panic("Attempted to divide by zero".to_string())
+    } else {
+        RuntimeResult::Return(BigInt(&l.0 / &r.0))
+    }
+}
+
+fn rem(l: &BigInt, r: &BigInt) -> RuntimeResult<BigInt, String> {
+    if r.0.is_zero() {
+        RuntimeResult::Panic("Attempted to divide by zero".to_string())
+    } else {
+        RuntimeResult::Return(BigInt(&l.0 % &r.0))
+    }
+}
+
+fn negate(i: &BigInt) -> BigInt {
+    BigInt(-&i.0)
+}
+
+fn abs(i: &BigInt) -> BigInt {
+    BigInt(i.0.abs())
+}
+
+fn signum(i: &BigInt) -> VmInt {
+    match i.0.sign() {
+        Sign::Minus => -1,
+        Sign::NoSign => 0,
+        Sign::Plus => 1,
+    }
+}
+
+/// `pow` refuses to compute results which may need more bits than this
+const MAX_POW_BITS: usize = 1 << 20;
+
+fn pow_(base: &BigInt, exp: VmInt) -> RuntimeResult<BigInt, String> {
+    if exp < 0 {
+        return RuntimeResult::Panic(format!("Negative exponent {}", exp));
+    }
+    let exp = exp as usize;
+    // 0, 1 and -1 stay small no matter the exponent
+    let bits = base.0.bits();
+    if bits > 1 && bits.saturating_mul(exp) > MAX_POW_BITS {
+        return RuntimeResult::Panic(format!(
+            "Raising a {} bit integer to the power of {} may exceed the limit of {} bits",
+            bits, exp, MAX_POW_BITS
+        ));
+    }
+    RuntimeResult::Return(BigInt(pow(base.0.clone(), exp)))
+}
+
+fn eq(l: &BigInt, r: &BigInt) -> bool {
+    l.0 == r.0
+}
+
+fn compare(l: &BigInt, r: &BigInt) -> Ordering {
+    l.0.cmp(&r.0)
+}
+
+/// The `bigint!` macro which creates a `BigInt` from an integer literal or from a string literal
+/// containing an integer of any size, `bigint! "85070591730234615847396907784232501249"`. The
+/// literal is checked when the macro is expanded so invalid literals are reported as compile
+/// errors.
+pub struct BigIntMacro;
+
+impl Macro for BigIntMacro {
+    fn expand(&self, macros: &mut MacroExpander, args: Vec<SpannedExpr<Symbol>>) -> MacroFuture {
+        fn get_literal(args: &[SpannedExpr<Symbol>]) -> Result<String, MacroError> {
+            if args.len() != 1 {
+                return Err("Expected bigint to get 1 argument".into());
+            }
+            let literal = match args[0].value {
+                Expr::Literal(Literal::Int(i)) => i.to_string(),
+                Expr::Literal(Literal::String(ref s)) => s.clone(),
+                _ => return Err("Expected an integer or string literal".into()),
+            };
+            match literal.parse::<num_bigint::BigInt>() {
+                Ok(_) => Ok(literal),
+                Err(_) => Err(format!("`{}` is not an integer", literal).into()),
+            }
+        }
+
+        let literal = match get_literal(&args) {
+            Ok(literal) => literal,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let import = match macros.vm.get_macros().get("import") {
+            Some(import) => import,
+            None => {
+                let err: MacroError = "bigint requires the import macro".into();
+                return Box::new(future::err(err));
+            }
+        };
+
+        let span = args[0].span;
+        let projection = move |expr: SpannedExpr<Symbol>, field: &str| {
+            pos::spanned(
+                span,
+                Expr::Projection(Box::new(expr), Symbol::from(field), Type::hole()),
+            )
+        };
+        // `import! std.bigint.prim` so the macro works without `std.bigint` being in scope
+        let path = projection(
+            projection(
+                pos::spanned(span, Expr::Ident(TypedIdent::new(Symbol::from("std")))),
+                "bigint",
+            ),
+            "prim",
+        );
+        Box::new(import.expand(macros, vec![path]).map(move |prim| {
+            pos::spanned(
+                span,
+                Expr::App {
+                    func: Box::new(projection(prim, "from_literal")),
+                    implicit_args: Vec::new(),
+                    args: vec![pos::spanned(span, Expr::Literal(Literal::String(literal)))],
+                },
+            )
+        }))
+    }
+}
+
+mod std {
+    pub mod bigint {
+        pub use bigint_bind as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<BigInt>("BigInt", &[])?;
+
+    ExternModule::new(
+        vm,
+        record!{
+            type BigInt => BigInt,
+            from_int => primitive!(1 std::bigint::prim::from_int),
+            to_int => primitive!(1 std::bigint::prim::to_int),
+            parse => primitive!(1 std::bigint::prim::parse),
+            from_literal => primitive!(1 std::bigint::prim::from_literal),
+            from_str_radix => primitive!(2 std::bigint::prim::from_str_radix),
+            show => primitive!(1 std::bigint::prim::show),
+            to_str_radix => primitive!(2 std::bigint::prim::to_str_radix),
+            add => primitive!(2 std::bigint::prim::add),
+            sub => primitive!(2 std::bigint::prim::sub),
+            mul => primitive!(2 std::bigint::prim::mul),
+            div => primitive!(2 std::bigint::prim::div),
+            rem => primitive!(2 std::bigint::prim::rem),
+            negate => primitive!(1 std::bigint::prim::negate),
+            abs => primitive!(1 std::bigint::prim::abs),
+            signum => primitive!(1 std::bigint::prim::signum),
+            pow => named_primitive!(2, "std.bigint.prim.pow", std::bigint::prim::pow_),
+            eq => primitive!(2 std::bigint::prim::eq),
+            compare => primitive!(2 std::bigint::prim::compare)
+        },
+    )
+}
@@ -18,13 +18,13 @@ use either::Either;
 use base::ast::SpannedExpr;
 use base::error::{Errors, InFile};
 use base::fnv::FnvMap;
-use base::metadata::Metadata;
+use base::metadata::{Metadata, MetadataEnv};
 use base::resolve;
 use base::source::Source;
 use base::symbol::{Name, NameBuf, Symbol, SymbolModule};
 use base::types::{ArcType, Type};
 
-use check::{metadata, rename};
+use check::{exhaustiveness, metadata, rename};
 
 use vm::compiler::CompiledModule;
 use vm::core;
@@ -100,19 +100,26 @@ impl<'s> MacroExpandable for &'s str {
         file: &str,
         expr_str: &str,
     ) -> SalvageResult<MacroValue<Self::Expr>> {
-        compiler
-            .parse_expr(macros.vm.global_env().type_cache(), file, self)
-            .map_err(|err| (None, err.into()))
-            .and_then(|mut expr| {
-                let result = (&mut expr)
-                    .expand_macro_with(compiler, macros, file, expr_str)
-                    .map(|_| ())
-                    .map_err(|(value, err)| (value.map(|_| ()), err));
-                if let Err((value, err)) = result {
-                    return Err((value.map(|_| MacroValue { expr }), err));
-                }
-                Ok(MacroValue { expr })
-            })
+        // The parser recovers from syntax errors so the rest of the pipeline can run on the
+        // partial expression and report any errors it finds as well
+        let (mut expr, parse_error) =
+            match compiler.parse_partial_expr(macros.vm.global_env().type_cache(), file, self) {
+                Ok(expr) => (expr, None),
+                Err((Some(expr), err)) => (expr, Some(Error::from(err))),
+                Err((None, err)) => return Err((None, err.into())),
+            };
+        let result = (&mut expr)
+            .expand_macro_with(compiler, macros, file, expr_str)
+            .map(|_| ())
+            .map_err(|(value, err)| (value.map(|_| ()), err));
+        match (parse_error, result) {
+            (None, Ok(())) => Ok(MacroValue { expr }),
+            (Some(err), Ok(())) => Err((Some(MacroValue { expr }), err)),
+            (parse_error, Err((value, err))) => Err((
+                value.map(|_| MacroValue { expr }),
+                Errors::from(parse_error.into_iter().chain(Some(err)).collect::<Vec<_>>()).into(),
+            )),
+        }
     }
 }
 
@@ -243,6 +250,20 @@ pub struct WithMetadata<E> {
     pub metadata: Metadata,
 }
 
+/// Extracts the metadata of `expr`, including where its bindings are defined if the source of
+/// `file` is known
+fn extract_metadata(
+    compiler: &Compiler,
+    env: &MetadataEnv,
+    file: &str,
+    expr: &SpannedExpr<Symbol>,
+) -> (Metadata, FnvMap<Symbol, Metadata>) {
+    match compiler.get_filemap(file) {
+        Some(file_map) => metadata::metadata_with_definitions(env, expr, file, &**file_map),
+        None => metadata::metadata(env, expr),
+    }
+}
+
 pub trait MetadataExtractable: Sized {
     type Expr: BorrowMut<SpannedExpr<Symbol>>;
 
@@ -298,13 +319,14 @@ where
 
     fn extract_metadata(
         mut self,
-        _compiler: &mut Compiler,
+        compiler: &mut Compiler,
         thread: &Thread,
-        _file: &str,
+        file: &str,
         _expr_str: &str,
     ) -> SalvageResult<WithMetadata<Self::Expr>> {
         let env = thread.get_env();
-        let (metadata, metadata_map) = metadata::metadata(&*env, self.expr.borrow_mut());
+        let (metadata, metadata_map) =
+            extract_metadata(compiler, &*env, file, self.expr.borrow_mut());
         Ok(WithMetadata {
             expr: self.expr,
             metadata,
@@ -510,10 +532,17 @@ where
             })?
         };
 
+        {
+            let env = thread.get_env();
+            // Only the warnings of the latest expression are kept so that a `Compiler` which is
+            // reused without taking the warnings does not accumulate them
+            compiler.warnings = exhaustiveness::check_matches(&*env, expr.borrow());
+        }
+
         // Some metadata requires typechecking so recompute it if full metadata is required
         let (metadata, metadata_map) = if compiler.full_metadata {
             let env = thread.get_env();
-            metadata::metadata(&*env, expr.borrow_mut())
+            extract_metadata(compiler, &*env, file, expr.borrow_mut())
         } else {
             (metadata, metadata_map)
         };
@@ -593,7 +622,7 @@ where
 
                 debug!("Translation returned: {}", expr);
 
-                core::optimize::optimize(&translator.allocator, expr)
+                core::optimize::optimize(&*env, &translator.allocator, expr)
             };
 
             let name = Name::new(filename);
@@ -604,6 +633,7 @@ where
             );
             let source = Source::new(expr_str);
 
+            let optimize_bytecode = compiler.optimize_bytecode;
             let mut compiler = Compiler::new(
                 &*env,
                 thread.global_env(),
@@ -612,7 +642,11 @@ where
                 filename.to_string(),
                 compiler.emit_debug_info,
             );
-            compiler.compile_expr(expr)?
+            let mut module = compiler.compile_expr(expr)?;
+            if optimize_bytecode {
+                ::vm::peephole::optimize(&mut module.function);
+            }
+            module
         };
         module.function.id = Symbol::from(filename);
         Ok(CompileValue {
@@ -789,6 +823,11 @@ where
 #[cfg(feature = "serde")]
 pub struct Precompiled<D>(pub D);
 
+/// The version of the bytecode format written by `compile_to`. The version is written ahead of the
+/// module so bytecode written with a different version is rejected before the rest of it is read
+/// and needs to be compiled again.
+pub const BYTECODE_VERSION: u32 = 3;
+
 #[cfg_attr(feature = "serde_derive_state", derive(DeserializeState, SerializeState))]
 #[cfg_attr(
     feature = "serde_derive_state", serde(deserialize_state = "::vm::serialization::DeSeed")
@@ -804,6 +843,54 @@ pub struct Module {
     pub module: CompiledModule,
 }
 
+#[cfg(feature = "serde")]
+impl<'de, D> Precompiled<D>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    fn deserialize_module(self, vm: &Thread) -> Result<Module> {
+        use std::fmt;
+
+        use serde::de::{Error as DeError, SeqAccess, Visitor};
+        use vm::serialization::DeSeed;
+
+        // Bytecode is a `(version, module)` pair. The version is checked before the module is
+        // read since the module may not even be readable if the format has changed
+        struct BytecodeVisitor(DeSeed);
+
+        impl<'de> Visitor<'de> for BytecodeVisitor {
+            type Value = Module;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("bytecode")
+            }
+
+            fn visit_seq<V>(mut self, mut seq: V) -> StdResult<Self::Value, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let version: u32 = seq.next_element()?
+                    .ok_or_else(|| V::Error::invalid_length(0, &self))?;
+                if version != BYTECODE_VERSION {
+                    return Err(V::Error::custom(format!(
+                        "bytecode version {} is not supported (expected version {})",
+                        version, BYTECODE_VERSION
+                    )));
+                }
+                seq.next_element_seed(::serde::de::Seed::new(&mut self.0))?
+                    .ok_or_else(|| V::Error::invalid_length(1, &self))
+            }
+        }
+
+        let module = self.0
+            .deserialize_tuple(2, BytecodeVisitor(DeSeed::new(vm)))
+            .map_err(|err| err.to_string())?;
+        // The bytecode may come from anywhere so check that it can't corrupt the vm
+        ::vm::verifier::verify(&module.module)?;
+        Ok(module)
+    }
+}
+
 #[cfg(feature = "serde")]
 impl<'vm, 'de, D> Executable<'vm, ()> for Precompiled<D>
 where
@@ -822,13 +909,7 @@ where
     where
         T: Send + VmRoot<'vm>,
     {
-        use vm::serialization::DeSeed;
-
-        let module: Module = try_future!(
-            DeSeed::new(&vm)
-                .deserialize(self.0)
-                .map_err(|err| err.to_string())
-        );
+        let module = try_future!(self.deserialize_module(&vm));
         let module_id = module.module.function.id.clone();
         if filename != module_id.as_ref() {
             return FutureValue::sync(Err(format!(
@@ -863,23 +944,24 @@ where
     where
         T: Send + VmRoot<'vm>,
     {
-        use vm::internal::Global;
-        use vm::serialization::DeSeed;
-
-        let Global {
-            metadata,
+        let Module {
             typ,
-            value,
-            id: _,
-        } = try_future!(
-            DeSeed::new(&vm)
-                .deserialize(self.0)
-                .map_err(|err| err.to_string())
-        );
+            metadata,
+            module,
+            ..
+        } = try_future!(self.deserialize_module(&vm));
         let id = compiler.symbols.symbol(format!("@{}", name));
-        try_future!(vm.set_global(id, typ, metadata, value,));
-        info!("Loaded module `{}`", name);
-        FutureValue::sync(Ok(())).boxed()
+        let name = name.to_string();
+
+        let closure = try_future!(vm.global_env().new_global_thunk(module));
+        execute(vm, |vm| vm.call_thunk(closure))
+            .map_err(Error::from)
+            .and_then(move |(vm, value)| {
+                try_future!(vm.set_global(id, typ, metadata, value));
+                info!("Loaded module `{}`", name);
+                FutureValue::sync(Ok(()))
+            })
+            .boxed()
     }
 }
 
@@ -898,7 +980,7 @@ where
     S::Error: 'static,
     T: Compileable<E>,
 {
-    use serde::ser::SerializeState;
+    use serde::ser::{Seeded, SerializeTuple};
     use vm::serialization::SeSeed;
     let CompileValue {
         expr: _,
@@ -914,9 +996,15 @@ where
         metadata,
         module,
     };
-    module
-        .serialize_state(serializer, &SeSeed::new())
-        .map_err(Either::Right)
+    let seed = SeSeed::new();
+    let mut bytecode = serializer.serialize_tuple(2).map_err(Either::Right)?;
+    bytecode
+        .serialize_element(&BYTECODE_VERSION)
+        .map_err(Either::Right)?;
+    bytecode
+        .serialize_element(&Seeded::new(&seed, &module))
+        .map_err(Either::Right)?;
+    bytecode.end().map_err(Either::Right)
 }
 
 pub fn run_io<'vm, T, E>(
@@ -0,0 +1,218 @@
+//! Module containing file system primitives.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use vm::api::{Userdata, VmType, IO};
+use vm::gc::{Gc, Traverseable};
+use vm::thread::Thread;
+use vm::types::VmInt;
+use vm::{self, ExternModule};
+
+// Reading files with `open_file` and `read_file` or `read_file_to_string` is provided by `std.io`
+
+fn read_file_to_bytes(path: &str) -> IO<Vec<u8>> {
+    IO::from(fs::read(path))
+}
+
+fn write_file(path: &str, contents: &[u8]) -> IO<()> {
+    IO::from(fs::write(path, contents))
+}
+
+fn write_string(path: &str, contents: &str) -> IO<()> {
+    IO::from(fs::write(path, contents))
+}
+
+fn append_file(path: &str, contents: &[u8]) -> IO<()> {
+    IO::from(
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents)),
+    )
+}
+
+/// A file opened for writing
+struct FileWriter(Mutex<BufWriter<File>>);
+
+impl Userdata for FileWriter {}
+
+impl fmt::Debug for FileWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FileWriter")
+    }
+}
+
+impl VmType for FileWriter {
+    type Type = FileWriter;
+}
+
+impl Traverseable for FileWriter {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+fn create_writer(path: &str) -> IO<FileWriter> {
+    IO::from(File::create(path).map(|file| FileWriter(Mutex::new(BufWriter::new(file)))))
+}
+
+fn open_appender(path: &str) -> IO<FileWriter> {
+    IO::from(
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map(|file| FileWriter(Mutex::new(BufWriter::new(file)))),
+    )
+}
+
+fn write(writer: &FileWriter, bytes: &[u8]) -> IO<()> {
+    IO::from(writer.0.lock().unwrap().write_all(bytes))
+}
+
+fn write_str(writer: &FileWriter, s: &str) -> IO<()> {
+    IO::from(writer.0.lock().unwrap().write_all(s.as_bytes()))
+}
+
+fn flush(writer: &FileWriter) -> IO<()> {
+    IO::from(writer.0.lock().unwrap().flush())
+}
+
+fn read_dir(path: &str) -> IO<Vec<String>> {
+    IO::from(fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.path().to_string_lossy().into_owned()))
+            .collect()
+    }))
+}
+
+fn create_dir(path: &str) -> IO<()> {
+    IO::from(fs::create_dir(path))
+}
+
+fn create_dir_all(path: &str) -> IO<()> {
+    IO::from(fs::create_dir_all(path))
+}
+
+fn remove_file(path: &str) -> IO<()> {
+    IO::from(fs::remove_file(path))
+}
+
+fn remove_dir(path: &str) -> IO<()> {
+    IO::from(fs::remove_dir(path))
+}
+
+fn remove_dir_all(path: &str) -> IO<()> {
+    IO::from(fs::remove_dir_all(path))
+}
+
+fn rename(from: &str, to: &str) -> IO<()> {
+    IO::from(fs::rename(from, to))
+}
+
+fn copy(from: &str, to: &str) -> IO<()> {
+    IO::from(fs::copy(from, to).map(|_| ()))
+}
+
+fn exists(path: &str) -> IO<bool> {
+    IO::Value(Path::new(path).exists())
+}
+
+field_decl! { is_file, is_dir, len, readonly }
+
+type Metadata = record_type!{
+    is_file => bool,
+    is_dir => bool,
+    len => VmInt,
+    readonly => bool
+};
+
+fn metadata(path: &str) -> IO<Metadata> {
+    IO::from(fs::metadata(path).map(|metadata| {
+        record_no_decl!{
+            is_file => metadata.is_file(),
+            is_dir => metadata.is_dir(),
+            len => metadata.len() as VmInt,
+            readonly => metadata.permissions().readonly()
+        }
+    }))
+}
+
+fn path_join(path: &str, other: &str) -> String {
+    Path::new(path).join(other).to_string_lossy().into_owned()
+}
+
+fn path_parent(path: &str) -> Option<String> {
+    Path::new(path)
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+}
+
+fn path_file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+fn path_file_stem(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+}
+
+fn path_extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+}
+
+fn path_is_absolute(path: &str) -> bool {
+    Path::new(path).is_absolute()
+}
+
+mod std {
+    pub mod fs {
+        pub use fs as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<FileWriter>("FileWriter", &[])?;
+
+    ExternModule::new(
+        vm,
+        record!{
+            type FileWriter => FileWriter,
+            read_file_to_bytes => primitive!(1 std::fs::prim::read_file_to_bytes),
+            write_file => primitive!(2 std::fs::prim::write_file),
+            write_string => primitive!(2 std::fs::prim::write_string),
+            append_file => primitive!(2 std::fs::prim::append_file),
+            create_writer => primitive!(1 std::fs::prim::create_writer),
+            open_appender => primitive!(1 std::fs::prim::open_appender),
+            write => primitive!(2 std::fs::prim::write),
+            write_str => primitive!(2 std::fs::prim::write_str),
+            flush => primitive!(1 std::fs::prim::flush),
+            read_dir => primitive!(1 std::fs::prim::read_dir),
+            create_dir => primitive!(1 std::fs::prim::create_dir),
+            create_dir_all => primitive!(1 std::fs::prim::create_dir_all),
+            remove_file => primitive!(1 std::fs::prim::remove_file),
+            remove_dir => primitive!(1 std::fs::prim::remove_dir),
+            remove_dir_all => primitive!(1 std::fs::prim::remove_dir_all),
+            rename => primitive!(2 std::fs::prim::rename),
+            copy => primitive!(2 std::fs::prim::copy),
+            exists => primitive!(1 std::fs::prim::exists),
+            metadata => primitive!(1 std::fs::prim::metadata),
+            path_join => primitive!(2 std::fs::prim::path_join),
+            path_parent => primitive!(1 std::fs::prim::path_parent),
+            path_file_name => primitive!(1 std::fs::prim::path_file_name),
+            path_file_stem => primitive!(1 std::fs::prim::path_file_stem),
+            path_extension => primitive!(1 std::fs::prim::path_extension),
+            path_is_absolute => primitive!(1 std::fs::prim::path_is_absolute)
+        },
+    )
+}
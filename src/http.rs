@@ -0,0 +1,188 @@
+//! Module containing a http server which handles requests with gluon functions.
+//!
+//! A gluon function of type `Request -> IO Response` can either be started as a server with
+//! `std.http.server.listen` or be mounted on a `hyper` server created in Rust by wrapping it in a
+//! `HttpService`.
+
+extern crate hyper;
+
+use futures::future::Either;
+use futures::sync::oneshot;
+use futures::{future, Future, Stream};
+
+use self::hyper::server::{Http, Request as HyperRequest, Response as HyperResponse, Service};
+use self::hyper::StatusCode;
+
+use vm::api::{FutureResult, Getable, OwnedFunction, WithVM, IO};
+use vm::thread::Thread;
+use vm::types::VmInt;
+use vm::{self, ExternModule};
+
+field_decl! { method, uri, headers, body, status }
+
+pub type Headers = Vec<(String, String)>;
+
+/// The gluon representation of a http request
+pub type Request = record_type!{
+    method => String,
+    uri => String,
+    headers => Headers,
+    body => Vec<u8>
+};
+
+/// The gluon representation of a http response
+pub type Response = record_type!{
+    status => VmInt,
+    headers => Headers,
+    body => Vec<u8>
+};
+
+pub type Handler = fn(Request) -> IO<Response>;
+
+/// A `hyper` service which handles each request by calling a gluon function
+#[derive(Clone)]
+pub struct HttpService {
+    handler: OwnedFunction<Handler>,
+}
+
+impl HttpService {
+    pub fn new(handler: OwnedFunction<Handler>) -> HttpService {
+        HttpService { handler }
+    }
+}
+
+fn internal_server_error(message: &str) -> HyperResponse {
+    error!("{}", message);
+    HyperResponse::new().with_status(StatusCode::InternalServerError)
+}
+
+fn to_hyper_response(response: Response) -> HyperResponse {
+    let record_p!{ status, headers, body } = response;
+    let valid_status = if status >= 100 && status < 600 {
+        StatusCode::try_from(status as u16).ok()
+    } else {
+        None
+    };
+    let status = match valid_status {
+        Some(status) => status,
+        None => return internal_server_error(&format!("Invalid status code {}", status)),
+    };
+    let mut response = HyperResponse::new().with_status(status);
+    for (name, value) in headers {
+        response.headers_mut().append_raw(name, value);
+    }
+    response.with_body(body)
+}
+
+impl Service for HttpService {
+    type Request = HyperRequest;
+    type Response = HyperResponse;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = HyperResponse, Error = hyper::Error> + 'static>;
+
+    fn call(&self, request: HyperRequest) -> Self::Future {
+        let (method, uri, _, request_headers, body) = request.deconstruct();
+        let headers = request_headers
+            .iter()
+            .map(|header| (header.name().to_string(), header.value_string()))
+            .collect::<Headers>();
+        let mut handler = self.handler.clone();
+        Box::new(body.concat2().and_then(move |body| {
+            let request = record_no_decl!{
+                method => method.to_string(),
+                uri => uri.to_string(),
+                headers => headers,
+                body => body.to_vec()
+            };
+            handler.call_async(request).then(|result| {
+                Ok::<_, hyper::Error>(match result {
+                    Ok(IO::Value(response)) => to_hyper_response(response),
+                    Ok(IO::Exception(err)) => internal_server_error(&err),
+                    Err(err) => internal_server_error(&err.to_string()),
+                })
+            })
+        }))
+    }
+}
+
+/// Starts a server on `addr` (for example `127.0.0.1:8080`) which handles each request with
+/// `handler`. The server runs on the event loop of the vm so the vm must have been created with
+/// one.
+fn listen(
+    addr: &str,
+    WithVM { vm, value: handler }: WithVM<OwnedFunction<Handler>>,
+) -> FutureResult<Box<Future<Item = IO<()>, Error = vm::Error> + Send + 'static>> {
+    // Handle the requests on a separate thread as the calling thread is suspended until the server
+    // stops
+    let thread = match vm.new_thread() {
+        Ok(thread) => thread,
+        Err(err) => return FutureResult(Box::new(future::err(err))),
+    };
+    let event_loop = match thread.get_event_loop() {
+        Some(event_loop) => event_loop,
+        None => {
+            return FutureResult(Box::new(future::ok(IO::Exception(
+                "A http server requires the vm to have an event loop".into(),
+            ))))
+        }
+    };
+    let handler: OwnedFunction<Handler> = Getable::from_value(&thread, handler.get_variant());
+    let service = HttpService::new(handler);
+    let addr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(err) => {
+            return FutureResult(Box::new(future::ok(IO::Exception(format!(
+                "Invalid address `{}`: {}",
+                addr, err
+            )))))
+        }
+    };
+
+    let (sender, receiver) = oneshot::channel();
+    event_loop.spawn(move |handle| {
+        let handle = handle.clone();
+        let server = Http::new()
+            .serve_addr_handle(&addr, &handle, move || Ok(service.clone()))
+            .map_err(|err| err.to_string());
+        let stopped = match server {
+            Ok(server) => Either::A(
+                server
+                    .for_each(move |connection| {
+                        handle.spawn(connection.map(|_| ()).map_err(|err| error!("{}", err)));
+                        Ok(())
+                    })
+                    .then(|result| Ok::<_, ()>(result.map_err(|err| err.to_string()))),
+            ),
+            Err(err) => Either::B(future::ok::<_, ()>(Err(err))),
+        };
+        stopped.and_then(|result| {
+            let _ = sender.send(match result {
+                Ok(()) => IO::Value(()),
+                Err(err) => IO::Exception(err),
+            });
+            Ok(())
+        })
+    });
+    FutureResult(Box::new(receiver.map_err(|_| {
+        vm::Error::Message("The http server was cancelled".into())
+    })))
+}
+
+mod std {
+    pub mod http {
+        pub mod server {
+            pub use http as prim;
+        }
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record!{
+            listen => primitive!(2 std::http::server::prim::listen)
+        },
+    )
+}
@@ -2,13 +2,15 @@
 
 use std::any::Any;
 use std::borrow::Cow;
-use std::collections::hash_map::Entry;
-use std::fs::File;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::Read;
 use std::mem;
 use std::path::PathBuf;
-use std::sync::{Mutex, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
 
 use futures::sync::oneshot;
 use futures::{future, Future};
@@ -57,8 +59,11 @@ quick_error! {
 }
 
 macro_rules! std_libs {
-    ($($file: expr,)*) => {
-        [$((concat!("std.", $file), include_str!(concat!("../std/", $file, ".glu")))),*]
+    ($($file: expr),* ; $($module: expr => $path: expr,)*) => {
+        [
+            $((concat!("std.", $file), include_str!(concat!("../std/", $file, ".glu"))),)*
+            $((concat!("std.", $module), include_str!(concat!("../std/", $path, ".glu"))),)*
+        ]
     }
 }
 // Include the standard library distribution in the binary
@@ -71,8 +76,13 @@ static STD_LIBS: &[(&str, &str)] = &std_libs!(
     "float",
     "int",
     "byte",
+    "bytes",
     "char",
     "io",
+    "fs",
+    "process",
+    "net",
+    "log",
     "list",
     "map",
     "option",
@@ -82,6 +92,8 @@ static STD_LIBS: &[(&str, &str)] = &std_libs!(
     "stream",
     "string",
     "thread",
+    "sync",
+    "actor",
     "test",
     "unit",
     "writer",
@@ -94,17 +106,23 @@ static STD_LIBS: &[(&str, &str)] = &std_libs!(
     "monoid",
     "semigroup",
     "reference",
+    "weak",
     "show",
     "traversable",
     "group",
     "category",
     "num",
+    "json",
+    "bigint";
+    // Modules in sub directories
+    "http.server" => "http/server",
+    "test.property" => "test/property",
 );
 
 // When testing we use the files as-is in the repository to avoid recompiling after they are
 // changed
 #[cfg(feature = "test")]
-static STD_LIBS: &[(&str, &str)] = &std_libs!();
+static STD_LIBS: &[(&str, &str)] = &std_libs!(;);
 
 pub trait Importer: Any + Clone + Sync + Send {
     fn import(
@@ -144,10 +162,12 @@ impl Importer for DefaultImporter {
                 return Ok(());
             }
 
+            // The module may yield if the thread has a yield interval set so drive it to
+            // completion instead of requiring a synchronous result
             result.and_then(|value| {
                 value
                     .load_script(compiler, vm, modulename, input, ())
-                    .sync_or_error()
+                    .wait()
             })
         };
 
@@ -155,11 +175,124 @@ impl Importer for DefaultImporter {
     }
 }
 
+/// Resolves the source code of modules which are not part of the standard library, allowing
+/// modules to be served from other places than the filesystem.
+pub trait SourceLoader: Send + Sync {
+    /// Returns the source of `module` or `None` if it could not be found. `filename` is the path
+    /// of the module relative to the import paths (`std/list.glu` for `std.list`).
+    fn load_source(
+        &self,
+        paths: &[PathBuf],
+        module: &str,
+        filename: &str,
+    ) -> io::Result<Option<String>>;
+
+    /// Returns the time the source of `module` was last modified, if the loader can tell. The
+    /// import macro compares it against the time recorded when the module was compiled, skipping
+    /// the re-read of sources which have not changed.
+    fn modified(&self, _paths: &[PathBuf], _module: &str, _filename: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Loads modules from the first import path containing the module's file
+#[derive(Clone, Debug, Default)]
+pub struct FileSystemLoader;
+
+impl SourceLoader for FileSystemLoader {
+    fn load_source(
+        &self,
+        paths: &[PathBuf],
+        _module: &str,
+        filename: &str,
+    ) -> io::Result<Option<String>> {
+        for path in paths {
+            let mut file = match File::open(path.join(filename)) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            let mut buffer = String::new();
+            file.read_to_string(&mut buffer)?;
+            return Ok(Some(buffer));
+        }
+        Ok(None)
+    }
+
+    fn modified(&self, paths: &[PathBuf], _module: &str, filename: &str) -> Option<SystemTime> {
+        paths
+            .iter()
+            .filter_map(|path| fs::metadata(path.join(filename)).ok())
+            .next()
+            .and_then(|metadata| metadata.modified().ok())
+    }
+}
+
 enum UnloadedModule {
     Source(Cow<'static, str>),
     Extern(ExternModule),
 }
 
+/// Information about a module compiled from a source file, used to decide whether the compiled
+/// module can be reused on the next import
+struct CachedModule {
+    /// Hash of the source the module was compiled from
+    hash: u64,
+    /// Modification time of the source, `None` if it is unknown or too recent to be trusted
+    modified: Option<SystemTime>,
+    /// The modules imported by this module
+    dependencies: Vec<String>,
+}
+
+/// A file which is written again within the timestamp granularity of the filesystem may keep its
+/// modification time even though its contents changed. Modification times are therefore only
+/// trusted once they are at least this many seconds old (the granularity of FAT filesystems).
+const MODIFIED_GRANULARITY_SECS: u64 = 2;
+
+fn trusted_modified(modified: Option<SystemTime>) -> Option<SystemTime> {
+    modified.and_then(|modified| match SystemTime::now().duration_since(modified) {
+        Ok(age) if age.as_secs() >= MODIFIED_GRANULARITY_SECS => Some(modified),
+        _ => None,
+    })
+}
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Name of the function which the dynamic library of a native module must export. It must have
+/// the signature of a `NativeModuleLoader`, that is
+/// `#[no_mangle] pub extern "C" fn gluon_load_module(vm: &Thread, module: &mut Option<vm::Result<ExternModule>>)`.
+pub const NATIVE_MODULE_SYMBOL: &str = "gluon_load_module";
+
+/// Name of the static which the dynamic library of a native module must export to identify the
+/// version of gluon and of the Rust compiler it was built with, that is
+/// `#[no_mangle] pub static GLUON_PLUGIN_ABI: &str = gluon::import::NATIVE_MODULE_ABI;`.
+pub const NATIVE_MODULE_ABI_SYMBOL: &str = "GLUON_PLUGIN_ABI";
+
+/// The version of gluon and of the Rust compiler which a native module must be built with to be
+/// loaded. Rust does not have a stable ABI so the module may only be called if both match the
+/// application loading it.
+pub const NATIVE_MODULE_ABI: &str = concat!(
+    "gluon ",
+    env!("CARGO_PKG_VERSION"),
+    ", ",
+    env!("GLUON_RUSTC_VERSION")
+);
+
+/// The type of the `NATIVE_MODULE_SYMBOL` function. The loaded module is written to the second
+/// argument as Rust types can not be returned through the C ABI.
+pub type NativeModuleLoader = extern "C" fn(&Thread, &mut Option<::vm::Result<ExternModule>>);
+
+const NATIVE_MODULE_PREFIX: &str = "native.";
+
+fn module_filename(modulename: &str) -> String {
+    let mut filename = modulename.replace(".", "/");
+    filename.push_str(".glu");
+    filename
+}
+
 /// Macro which rewrites occurances of `import! "filename"` to a load of that file if it is not
 /// already loaded and then a global access to the loaded module
 pub struct Import<I = DefaultImporter> {
@@ -167,8 +300,21 @@ pub struct Import<I = DefaultImporter> {
     pub loaders: RwLock<FnvMap<String, ExternLoader>>,
     pub importer: I,
 
+    /// Sources of modules which are compiled into the host application
+    embedded: RwLock<FnvMap<String, &'static str>>,
+
+    source_loader: RwLock<Arc<SourceLoader>>,
+
     /// Map of modules currently being loaded
     loading: Mutex<FnvMap<String, future::Shared<oneshot::Receiver<()>>>>,
+
+    /// Modules which were loaded from files, so they can be recompiled if the file changes
+    cache: RwLock<FnvMap<String, CachedModule>>,
+
+    /// Libraries of the loaded native modules. These must be kept loaded as long as the modules
+    /// may be used.
+    #[cfg(all(feature = "native_modules", not(target_arch = "wasm32")))]
+    libraries: Mutex<Vec<::libloading::Library>>,
 }
 
 impl<I> Import<I> {
@@ -178,7 +324,12 @@ impl<I> Import<I> {
             paths: RwLock::new(vec![PathBuf::from(".")]),
             loaders: RwLock::default(),
             importer: importer,
+            embedded: RwLock::default(),
+            source_loader: RwLock::new(Arc::new(FileSystemLoader)),
             loading: Mutex::default(),
+            cache: RwLock::default(),
+            #[cfg(all(feature = "native_modules", not(target_arch = "wasm32")))]
+            libraries: Mutex::default(),
         }
     }
 
@@ -191,6 +342,12 @@ impl<I> Import<I> {
         *self.paths.write().unwrap() = paths;
     }
 
+    /// Sets the loader used to retrieve the source of modules which are not part of the standard
+    /// library (default: `FileSystemLoader`)
+    pub fn set_source_loader(&self, source_loader: Arc<SourceLoader>) {
+        *self.source_loader.write().unwrap() = source_loader;
+    }
+
     pub fn add_loader(&self, module: &str, loader: ExternLoader) {
         self.loaders
             .write()
@@ -198,11 +355,21 @@ impl<I> Import<I> {
             .insert(String::from(module), loader);
     }
 
+    /// Adds a module whose source is part of the host application, letting it be imported without
+    /// any files being present
+    pub fn add_embedded_module(&self, module: &str, source: &'static str) {
+        self.embedded
+            .write()
+            .unwrap()
+            .insert(String::from(module), source);
+    }
+
     pub fn modules(&self) -> Vec<Cow<'static, str>> {
         STD_LIBS
             .iter()
             .map(|t| Cow::Borrowed(t.0))
             .chain(self.loaders.read().unwrap().keys().cloned().map(Cow::Owned))
+            .chain(self.embedded.read().unwrap().keys().cloned().map(Cow::Owned))
             .collect()
     }
 
@@ -212,8 +379,6 @@ impl<I> Import<I> {
         module: &str,
         filename: &str,
     ) -> Result<UnloadedModule, MacroError> {
-        let mut buffer = String::new();
-
         // Retrieve the source, first looking in the standard library included in the
         // binary
 
@@ -221,6 +386,9 @@ impl<I> Import<I> {
         if let Some(tup) = std_file {
             return Ok(UnloadedModule::Source(Cow::Borrowed(tup.1)));
         }
+        if let Some(source) = self.embedded.read().unwrap().get(module) {
+            return Ok(UnloadedModule::Source(Cow::Borrowed(*source)));
+        }
         Ok(match std_file {
             Some(tup) => UnloadedModule::Source(Cow::Borrowed(tup.1)),
             None => {
@@ -231,18 +399,14 @@ impl<I> Import<I> {
                         return Ok(UnloadedModule::Extern(value));
                     }
                 }
+                if module.starts_with(NATIVE_MODULE_PREFIX) {
+                    let value = self.load_native_module(vm, module)?;
+                    return Ok(UnloadedModule::Extern(value));
+                }
                 let paths = self.paths.read().unwrap();
-                let file = paths
-                    .iter()
-                    .filter_map(|p| {
-                        let base = p.join(filename);
-                        match File::open(&base) {
-                            Ok(file) => Some(file),
-                            Err(_) => None,
-                        }
-                    })
-                    .next();
-                let mut file = file.ok_or_else(|| {
+                let source_loader = self.source_loader.read().unwrap().clone();
+                let source = source_loader.load_source(&paths, module, filename)?;
+                let source = source.ok_or_else(|| {
                     Error::String(format!(
                         "Could not find module '{}'. Searched {}.",
                         module,
@@ -252,12 +416,139 @@ impl<I> Import<I> {
                             .format(", ")
                     ))
                 })?;
-                file.read_to_string(&mut buffer)?;
-                UnloadedModule::Source(Cow::Owned(buffer))
+                UnloadedModule::Source(Cow::Owned(source))
             }
         })
     }
 
+    /// Loads `native.foo.bar` from a dynamic library called `foo_bar` (`libfoo_bar.so` on linux)
+    /// in one of the import paths by calling the `NATIVE_MODULE_SYMBOL` function it exports.
+    ///
+    /// The library must be built with the same compiler and version of gluon as the host which is
+    /// checked through the `NATIVE_MODULE_ABI_SYMBOL` static before anything else in the library
+    /// is used.
+    #[cfg(all(feature = "native_modules", not(target_arch = "wasm32")))]
+    fn load_native_module(&self, vm: &Thread, module: &str) -> Result<ExternModule, MacroError> {
+        use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+        let name = &module[NATIVE_MODULE_PREFIX.len()..];
+        // `native.foo.bar` and `native.foo_bar` would both be loaded from `foo_bar`
+        if name.contains('_') {
+            return Err(Error::String(format!(
+                "Invalid native module '{}'. The library of `native.foo.bar` is called `foo_bar` \
+                 so native module names may not contain `_`",
+                module
+            )).into());
+        }
+        let filename = format!("{}{}{}", DLL_PREFIX, name.replace(".", "_"), DLL_SUFFIX);
+        let path = {
+            let paths = self.paths.read().unwrap();
+            let path = paths.iter().map(|p| p.join(&filename)).find(|p| p.exists());
+            path.ok_or_else(|| {
+                Error::String(format!(
+                    "Could not find native module '{}'. Searched for `{}` in {}.",
+                    module,
+                    filename,
+                    paths
+                        .iter()
+                        .map(|p| format!("`{}`", p.display()))
+                        .format(", ")
+                ))
+            })?
+        };
+
+        let library = ::libloading::Library::new(&path)?;
+        let value = unsafe {
+            let abi = library
+                .get::<*const &str>(NATIVE_MODULE_ABI_SYMBOL.as_bytes())
+                .map_err(|_| {
+                    Error::String(format!(
+                        "Native module '{}' (`{}`) does not export `{}`",
+                        module,
+                        path.display(),
+                        NATIVE_MODULE_ABI_SYMBOL
+                    ))
+                })?;
+            if **abi != NATIVE_MODULE_ABI {
+                return Err(Error::String(format!(
+                    "Native module '{}' (`{}`) was built with `{}` but `{}` is required",
+                    module,
+                    path.display(),
+                    **abi,
+                    NATIVE_MODULE_ABI
+                )).into());
+            }
+
+            let load = library.get::<NativeModuleLoader>(NATIVE_MODULE_SYMBOL.as_bytes())?;
+            let mut result = None;
+            load(vm, &mut result);
+            result.ok_or_else(|| {
+                Error::String(format!("Native module '{}' did not load a module", module))
+            })??
+        };
+        // The module refers to functions in the library so it must never be unloaded
+        self.libraries.lock().unwrap().push(library);
+        Ok(value)
+    }
+
+    #[cfg(any(not(feature = "native_modules"), target_arch = "wasm32"))]
+    fn load_native_module(&self, _vm: &Thread, module: &str) -> Result<ExternModule, MacroError> {
+        Err(Error::String(format!(
+            "Could not load native module '{}'. Native modules require the `native_modules` \
+             feature",
+            module
+        )).into())
+    }
+
+    fn source_modified(&self, modulename: &str, filename: &str) -> Option<SystemTime> {
+        let paths = self.paths.read().unwrap();
+        let source_loader = self.source_loader.read().unwrap().clone();
+        let modified = source_loader.modified(&paths, modulename, filename);
+        trusted_modified(modified)
+    }
+
+    /// Returns `true` if `modulename` was compiled from a file which has changed since it was
+    /// loaded or if any of the modules it imports are stale.
+    ///
+    /// The result is remembered for the rest of the macro expansion so each module is checked at
+    /// most once, no matter how many modules import it.
+    fn is_stale(&self, vm: &Thread, macros: &mut MacroExpander, modulename: &str) -> bool {
+        if let Some(&stale) = get_state(macros).stale.get(modulename) {
+            return stale;
+        }
+        let (hash, modified, dependencies) = match self.cache.read().unwrap().get(modulename) {
+            Some(cached) => (cached.hash, cached.modified, cached.dependencies.clone()),
+            None => return false,
+        };
+
+        let filename = module_filename(modulename);
+        let current_modified = self.source_modified(modulename, &filename);
+        // Only read and hash the source if the modification time can't tell that it is unchanged
+        let changed = (modified.is_none() || modified != current_modified) && {
+            match self.get_unloaded_module(vm, modulename, &filename) {
+                Ok(UnloadedModule::Source(source)) => {
+                    let changed = hash_source(&source) != hash;
+                    if !changed {
+                        if let Some(cached) = self.cache.write().unwrap().get_mut(modulename) {
+                            cached.modified = current_modified;
+                        }
+                    }
+                    changed
+                }
+                _ => true,
+            }
+        };
+
+        let stale = changed
+            || dependencies
+                .iter()
+                .any(|dependency| self.is_stale(vm, macros, dependency));
+        get_state(macros)
+            .stale
+            .insert(modulename.to_string(), stale);
+        stale
+    }
+
     pub fn load_module(
         &self,
         compiler: &mut Compiler,
@@ -271,8 +562,7 @@ impl<I> Import<I> {
     {
         assert!(module_id.is_global());
         let modulename = module_id.name().definition_name();
-        let mut filename = modulename.replace(".", "/");
-        filename.push_str(".glu");
+        let filename = module_filename(modulename);
         {
             let state = get_state(macros);
             if state.visited.iter().any(|m| **m == *filename) {
@@ -307,13 +597,21 @@ impl<I> Import<I> {
                 }
             }
         };
-        if vm.global_env().global_exists(module_id.definition_name()) {
+        if vm.global_env().global_exists(module_id.definition_name())
+            && !self.is_stale(vm, macros, module_id.definition_name())
+        {
             let _ = sender.send(());
             get_state(macros).visited.pop();
             return Ok(None);
         }
 
         let result = self.load_module_(compiler, vm, macros, module_id, &filename, span);
+        if result.is_ok() {
+            // Modules importing this module later in the expansion must not recompile it again
+            get_state(macros)
+                .stale
+                .insert(module_id.definition_name().to_string(), false);
+        }
 
         let _ = sender.send(());
 
@@ -338,6 +636,9 @@ impl<I> Import<I> {
         use compiler_pipeline::*;
 
         let modulename = module_id.name().definition_name();
+        // Read before the source so a change made while the module is compiled is detected by the
+        // next import
+        let modified = self.source_modified(&modulename, &filename);
         // Retrieve the source, first looking in the standard library included in the
         // binary
         let unloaded_module = self
@@ -361,8 +662,15 @@ impl<I> Import<I> {
 
                 let mut prev_errors = mem::replace(&mut macros.errors, Errors::new());
 
+                get_state(macros)
+                    .dependencies
+                    .push((modulename.to_string(), Vec::new()));
                 let result =
                     file_contents.expand_macro_with(compiler, macros, &modulename, &file_contents);
+                let (_, dependencies) = get_state(macros)
+                    .dependencies
+                    .pop()
+                    .expect("Dependencies of the module being loaded");
 
                 let has_errors =
                     macros.errors.has_errors() || result.is_err() || macros.error_in_expr;
@@ -394,6 +702,18 @@ impl<I> Import<I> {
                     &file_contents,
                     macro_result.expr,
                 )?;
+
+                // Modules embedded in the binary can't change so only files need to be tracked
+                if let Cow::Owned(_) = file_contents {
+                    self.cache.write().unwrap().insert(
+                        modulename.to_string(),
+                        CachedModule {
+                            hash: hash_source(&file_contents),
+                            modified,
+                            dependencies,
+                        },
+                    );
+                }
             }
         }
         Ok(())
@@ -456,6 +776,47 @@ pub fn add_extern_module(thread: &Thread, name: &str, loader: ExternLoader) {
     import.add_loader(name, loader);
 }
 
+/// Adds a module written in gluon to `thread`, letting it be loaded with `import! name` without
+/// shipping its source file alongside the application.
+///
+/// ```
+/// extern crate gluon;
+///
+/// use gluon::Compiler;
+/// use gluon::import::add_embedded_module;
+///
+/// fn main_() -> gluon::Result<()> {
+///     let thread = gluon::new_vm();
+///     // Usually the source would be included with `include_str!("my/module.glu")`
+///     add_embedded_module(&thread, "my.module", r#"{ message = "Hello World!" }"#);
+///     let script = r#"
+///         let module = import! my.module
+///         module.message
+///     "#;
+///     let (result, _) = Compiler::new().run_expr::<String>(&thread, "example", script)?;
+///     assert_eq!(result, "Hello World!");
+///     Ok(())
+/// }
+/// fn main() {
+///     if let Err(err) = main_() {
+///         panic!("{}", err)
+///     }
+/// }
+/// ```
+pub fn add_embedded_module(thread: &Thread, name: &str, source: &'static str) {
+    let opt_macro = thread.get_macros().get("import");
+    let import = opt_macro
+        .as_ref()
+        .and_then(|mac| mac.downcast_ref::<Import>())
+        .unwrap_or_else(|| {
+            ice!(
+                "Can't add an embedded module with a import macro. \
+                 Did you mean to create this `Thread` with `gluon::new_vm`"
+            )
+        });
+    import.add_embedded_module(name, source);
+}
+
 fn get_state<'m>(macros: &'m mut MacroExpander) -> &'m mut State {
     macros
         .state
@@ -464,6 +825,8 @@ fn get_state<'m>(macros: &'m mut MacroExpander) -> &'m mut State {
             Box::new(State {
                 visited: Vec::new(),
                 modules_with_errors: FnvMap::default(),
+                dependencies: Vec::new(),
+                stale: FnvMap::default(),
             })
         })
         .downcast_mut::<State>()
@@ -473,6 +836,10 @@ fn get_state<'m>(macros: &'m mut MacroExpander) -> &'m mut State {
 struct State {
     visited: Vec<String>,
     modules_with_errors: FnvMap<String, Expr<Symbol>>,
+    /// The modules currently being loaded along with the modules they have imported so far
+    dependencies: Vec<(String, Vec<String>)>,
+    /// Whether the modules checked so far during this expansion need to be recompiled
+    stale: FnvMap<String, bool>,
 }
 
 impl<I> Macro for Import<I>
@@ -517,9 +884,13 @@ where
             format!("@{}", modulename)
         });
 
-        // Only load the script if it is not already loaded
+        if let Some(&mut (_, ref mut dependencies)) = get_state(macros).dependencies.last_mut() {
+            dependencies.push(modulename.clone());
+        }
+
+        // Only load the script if it is not already loaded (or if it has changed since)
         debug!("Import '{}' {:?}", modulename, get_state(macros).visited);
-        if !vm.global_env().global_exists(&modulename) {
+        if !vm.global_env().global_exists(&modulename) || self.is_stale(vm, macros, &modulename) {
             if let Some(expr) = get_state(macros)
                 .modules_with_errors
                 .get(&modulename)
@@ -57,7 +57,11 @@ fn open_file(s: &str) -> IO<GluonFile> {
     }
 }
 
-fn read_file<'vm>(file: WithVM<'vm, &GluonFile>, count: usize) -> IO<Array<'vm, u8>> {
+fn read_file<'vm>(file: WithVM<'vm, &GluonFile>, count: VmInt) -> IO<Array<'vm, u8>> {
+    if count < 0 {
+        return IO::Exception(format!("Cannot read a negative number of bytes ({})", count));
+    }
+    let count = count as usize;
     let WithVM { vm, value: file } = file;
     let mut file = file.0.lock().unwrap();
     let mut buffer = Vec::with_capacity(count);
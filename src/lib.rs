@@ -11,6 +11,8 @@ extern crate env_logger;
 
 extern crate codespan;
 extern crate codespan_reporting;
+#[macro_use]
+extern crate collect_mac;
 pub extern crate either;
 extern crate futures;
 extern crate itertools;
@@ -20,6 +22,8 @@ extern crate log;
 extern crate quick_error;
 #[cfg(not(target_arch = "wasm32"))]
 extern crate tokio_core;
+#[cfg(all(feature = "native_modules", not(target_arch = "wasm32")))]
+extern crate libloading;
 
 #[cfg(feature = "serde_derive_state")]
 #[macro_use]
@@ -34,13 +38,25 @@ pub extern crate gluon_parser as parser;
 #[macro_use]
 pub extern crate gluon_vm as vm;
 
+#[cfg(feature = "bigint")]
+pub mod bigint_bind;
 pub mod compiler_pipeline;
+#[cfg(all(feature = "fs", not(target_arch = "wasm32")))]
+pub mod fs;
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+pub mod http;
 pub mod import;
 pub mod io;
+#[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+pub mod net;
+#[cfg(all(feature = "process", not(target_arch = "wasm32")))]
+pub mod process;
 #[cfg(all(feature = "rand", not(target_arch = "wasm32")))]
 pub mod rand_bind;
 #[cfg(feature = "regex")]
 pub mod regex_bind;
+pub mod testing;
+pub mod workspace;
 
 pub use vm::thread::{RootedThread, Thread};
 
@@ -50,9 +66,12 @@ use either::Either;
 
 use std::env;
 use std::error::Error as StdError;
+use std::mem;
 use std::path::PathBuf;
 use std::result::Result as StdResult;
 use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
 
 use base::ast::{self, SpannedExpr};
 use base::error::{Errors, InFile};
@@ -63,13 +82,16 @@ use base::pos::{self, BytePos, Span, Spanned};
 use base::symbol::{Symbol, SymbolModule, Symbols};
 use base::types::{ArcType, TypeCache};
 
+use check::exhaustiveness::{SpannedWarning, Warning};
+
 use compiler_pipeline::*;
-use import::{add_extern_module, DefaultImporter, Import};
+use import::{add_extern_module, DefaultImporter, Import, SourceLoader};
 use vm::api::{Getable, Hole, OpaqueValue, VmType};
 use vm::compiler::CompiledModule;
 use vm::future::{BoxFutureValue, FutureValue};
 use vm::macros;
-use vm::thread::ThreadInternal;
+use vm::thread::{IntOverflow, ThreadInternal};
+use vm::types::VmIndex;
 use vm::Variants;
 
 quick_error! {
@@ -206,8 +228,10 @@ pub struct Compiler {
     index_map: FnvMap<String, BytePos>,
     implicit_prelude: bool,
     emit_debug_info: bool,
+    optimize_bytecode: bool,
     run_io: bool,
     full_metadata: bool,
+    warnings: Vec<SpannedWarning>,
 }
 
 impl Default for Compiler {
@@ -239,8 +263,10 @@ impl Compiler {
             index_map: FnvMap::default(),
             implicit_prelude: true,
             emit_debug_info: true,
+            optimize_bytecode: true,
             run_io: false,
             full_metadata: false,
+            warnings: Vec::new(),
         }
     }
 
@@ -257,6 +283,13 @@ impl Compiler {
         emit_debug_info set_emit_debug_info: bool
     }
 
+    option!{
+        /// Sets whether the emitted bytecode is improved by peephole optimizations such as
+        /// constant folding (see `vm::peephole`).
+        /// (default: true)
+        optimize_bytecode set_optimize_bytecode: bool
+    }
+
     option!{
         /// Sets whether `IO` expressions are evaluated.
         /// (default: false)
@@ -273,6 +306,18 @@ impl Compiler {
         &self.code_map
     }
 
+    /// Returns the warnings, such as `match` expressions which do not handle every value, found
+    /// while typechecking the last expression compiled with this `Compiler`. Compiling another
+    /// expression discards the warnings which have not been taken.
+    pub fn take_warnings(&mut self) -> Option<InFile<Warning>> {
+        if self.warnings.is_empty() {
+            None
+        } else {
+            let warnings = mem::replace(&mut self.warnings, Vec::new());
+            Some(InFile::new(self.code_map.clone(), Errors::from(warnings)))
+        }
+    }
+
     pub fn update_filemap<S>(&mut self, file: &str, source: S) -> Option<Arc<codespan::FileMap>>
     where
         S: Into<String>,
@@ -387,7 +432,12 @@ impl Compiler {
             .map(|result| result.module)
     }
 
-    /// Compiles the source code `expr_str` into bytecode serialized using `serializer`
+    /// Compiles the source code `expr_str` into bytecode serialized using `serializer`.
+    ///
+    /// The bytecode contains the compiled functions together with the type and metadata of the
+    /// module so it can be cached and later loaded with `load_bytecode` without parsing or
+    /// typechecking the source again. Bytecode starts with `compiler_pipeline::BYTECODE_VERSION`
+    /// and bytecode from other versions fails to load before the rest of it is read.
     #[cfg(feature = "serialization")]
     pub fn compile_to_bytecode<S>(
         &mut self,
@@ -533,9 +583,38 @@ impl Compiler {
             .wait()
     }
 
+    /// Compiles and runs the expression in `expr_str` like `run_expr` but stops it with
+    /// `vm::Error::TimedOut` if it runs for longer than `timeout`, including the time spent
+    /// compiling it. The deadline is checked on each function call so long running rust
+    /// functions can exceed it.
+    ///
+    /// Not available on wasm as there is no clock to check the deadline against.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_expr_with_timeout<'vm, T>(
+        &mut self,
+        vm: &'vm Thread,
+        name: &str,
+        expr_str: &str,
+        timeout: Duration,
+    ) -> Result<(T, ArcType)>
+    where
+        T: Getable<'vm> + VmType + Send + 'vm,
+    {
+        let previous_deadline = vm.context().deadline();
+        vm.context().set_deadline(Some(Instant::now() + timeout));
+        let result = self.run_expr(vm, name, expr_str);
+        vm.context().set_deadline(previous_deadline);
+        result
+    }
+
     /// Compiles and runs the expression in `expr_str`. If successful the value from running the
     /// expression is returned
     ///
+    /// The returned future can be spawned on an executor such as tokio's. By default the
+    /// expression runs until it completes or waits on a future returned from a rust function,
+    /// set a yield interval with `Context::set_yield_interval` to let long running expressions
+    /// yield to other tasks on the executor.
+    ///
     /// # Examples
     ///
     /// Import from gluon's standard library and evaluate a string
@@ -652,6 +731,10 @@ pub struct VmBuilder {
     #[cfg(not(target_arch = "wasm32"))]
     event_loop: Option<::tokio_core::reactor::Remote>,
     import_paths: Option<Vec<PathBuf>>,
+    source_loader: Option<Arc<SourceLoader>>,
+    memory_limit: Option<usize>,
+    max_stack_size: Option<VmIndex>,
+    int_overflow: Option<IntOverflow>,
 }
 
 impl VmBuilder {
@@ -672,6 +755,34 @@ impl VmBuilder {
         import_paths set_import_paths: Option<Vec<PathBuf>>
     }
 
+    option!{
+        /// Sets the loader which retrieves the source of imported modules, allowing modules to be
+        /// served from other places than the filesystem
+        /// (default: import::FileSystemLoader)
+        source_loader set_source_loader: Option<Arc<SourceLoader>>
+    }
+
+    option!{
+        /// Sets the maximum number of bytes the root thread, and any thread spawned from it, may
+        /// allocate
+        /// (default: None)
+        memory_limit set_memory_limit: Option<usize>
+    }
+
+    option!{
+        /// Sets the maximum number of values on the stack of the root thread, and any thread
+        /// spawned from it
+        /// (default: vm::thread::DEFAULT_MAX_STACK_SIZE)
+        max_stack_size set_max_stack_size: Option<VmIndex>
+    }
+
+    option!{
+        /// Sets how integer arithmetic behaves on overflow in the root thread, and any thread
+        /// spawned from it
+        /// (default: IntOverflow::Wrap)
+        int_overflow set_int_overflow: Option<IntOverflow>
+    }
+
     pub fn build(self) -> RootedThread {
         #[cfg(target_arch = "wasm32")]
         let vm = RootedThread::new();
@@ -687,6 +798,9 @@ impl VmBuilder {
         if let Some(import_paths) = self.import_paths {
             import.set_paths(import_paths);
         }
+        if let Some(source_loader) = self.source_loader {
+            import.set_source_loader(source_loader);
+        }
 
         if let Ok(gluon_path) = env::var("GLUON_PATH") {
             import.add_path(gluon_path);
@@ -705,17 +819,38 @@ impl VmBuilder {
         add_extern_module(&vm, "std.string.prim", ::vm::primitives::load_string);
         add_extern_module(&vm, "std.char.prim", ::vm::primitives::load_char);
         add_extern_module(&vm, "std.array.prim", ::vm::primitives::load_array);
+        add_extern_module(&vm, "std.bytes.prim", ::vm::primitives::load_bytes);
 
         add_extern_module(&vm, "std.lazy", ::vm::lazy::load);
         add_extern_module(&vm, "std.reference.prim", ::vm::reference::load);
+        add_extern_module(&vm, "std.weak.prim", ::vm::weak::load);
 
         add_extern_module(&vm, "std.channel", ::vm::channel::load_channel);
         add_extern_module(&vm, "std.thread.prim", ::vm::channel::load_thread);
+        add_extern_module(&vm, "std.sync.prim", ::vm::sync::load);
         add_extern_module(&vm, "std.debug", ::vm::debug::load);
+        add_extern_module(&vm, "std.log.prim", ::vm::debug::load_log);
+        add_extern_module(&vm, "std.doc", ::vm::debug::load_doc);
+        add_extern_module(&vm, "std.test.prim", ::testing::load);
         add_extern_module(&vm, "std.io.prim", ::io::load);
 
         load_regex(&vm);
+        load_bigint(&vm);
         load_random(&vm);
+        load_fs(&vm);
+        load_process(&vm);
+        load_net(&vm);
+        load_http(&vm);
+
+        if let Some(memory_limit) = self.memory_limit {
+            vm.set_memory_limit(memory_limit);
+        }
+        if let Some(max_stack_size) = self.max_stack_size {
+            vm.context().set_max_stack_size(max_stack_size);
+        }
+        if let Some(int_overflow) = self.int_overflow {
+            vm.context().set_int_overflow(int_overflow);
+        }
 
         vm
     }
@@ -727,6 +862,15 @@ pub fn new_vm() -> RootedThread {
     VmBuilder::default().build()
 }
 
+#[cfg(feature = "bigint")]
+fn load_bigint(vm: &Thread) {
+    add_extern_module(&vm, "std.bigint.prim", ::bigint_bind::load);
+    vm.get_macros()
+        .insert(String::from("bigint"), ::bigint_bind::BigIntMacro);
+}
+#[cfg(not(feature = "bigint"))]
+fn load_bigint(_: &Thread) {}
+
 #[cfg(feature = "regex")]
 fn load_regex(vm: &Thread) {
     add_extern_module(&vm, "std.regex", ::regex_bind::load);
@@ -741,6 +885,34 @@ fn load_random(vm: &Thread) {
 #[cfg(any(not(feature = "rand"), target_arch = "wasm32"))]
 fn load_random(_: &Thread) {}
 
+#[cfg(all(feature = "fs", not(target_arch = "wasm32")))]
+fn load_fs(vm: &Thread) {
+    add_extern_module(&vm, "std.fs.prim", ::fs::load);
+}
+#[cfg(any(not(feature = "fs"), target_arch = "wasm32"))]
+fn load_fs(_: &Thread) {}
+
+#[cfg(all(feature = "process", not(target_arch = "wasm32")))]
+fn load_process(vm: &Thread) {
+    add_extern_module(&vm, "std.process.prim", ::process::load);
+}
+#[cfg(any(not(feature = "process"), target_arch = "wasm32"))]
+fn load_process(_: &Thread) {}
+
+#[cfg(all(feature = "net", not(target_arch = "wasm32")))]
+fn load_net(vm: &Thread) {
+    add_extern_module(&vm, "std.net.prim", ::net::load);
+}
+#[cfg(any(not(feature = "net"), target_arch = "wasm32"))]
+fn load_net(_: &Thread) {}
+
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+fn load_http(vm: &Thread) {
+    add_extern_module(&vm, "std.http.server.prim", ::http::load);
+}
+#[cfg(any(not(feature = "http"), target_arch = "wasm32"))]
+fn load_http(_: &Thread) {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
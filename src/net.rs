@@ -0,0 +1,425 @@
+//! Module containing TCP and UDP sockets.
+//!
+//! If the vm has an event loop the sockets are registered with it and every operation which waits
+//! on the network runs on the event loop, suspending the calling gluon thread instead of blocking
+//! it. Without an event loop the sockets are ordinary, blocking sockets.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{self, Shutdown, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use futures::sync::oneshot;
+use futures::{future, Async, Future, IntoFuture, Poll};
+
+use tokio_core::net as evented;
+use tokio_core::reactor::{Handle, Remote};
+
+use vm::api::{PrimitiveFuture, Userdata, VmType, WithVM, IO};
+use vm::future::FutureValue;
+use vm::gc::{Gc, Traverseable};
+use vm::thread::Thread;
+use vm::types::VmInt;
+use vm::{self, ExternModule};
+
+#[derive(Clone)]
+enum TcpListener {
+    Blocking(Arc<net::TcpListener>),
+    Evented(Remote, Arc<Mutex<evented::TcpListener>>),
+}
+
+impl Userdata for TcpListener {}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TcpListener")
+    }
+}
+
+impl VmType for TcpListener {
+    type Type = TcpListener;
+}
+
+impl Traverseable for TcpListener {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+#[derive(Clone)]
+enum TcpStream {
+    Blocking(Arc<net::TcpStream>),
+    Evented(Remote, Arc<Mutex<evented::TcpStream>>),
+}
+
+impl Userdata for TcpStream {}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TcpStream")
+    }
+}
+
+impl VmType for TcpStream {
+    type Type = TcpStream;
+}
+
+impl Traverseable for TcpStream {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+#[derive(Clone)]
+enum UdpSocket {
+    Blocking(Arc<net::UdpSocket>),
+    Evented(Remote, Arc<Mutex<evented::UdpSocket>>),
+}
+
+impl Userdata for UdpSocket {}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UdpSocket")
+    }
+}
+
+impl VmType for UdpSocket {
+    type Type = UdpSocket;
+}
+
+impl Traverseable for UdpSocket {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+field_decl! { data, addr }
+
+type Datagram = record_type!{
+    data => Vec<u8>,
+    addr => String
+};
+
+fn ready<T>(result: io::Result<T>) -> PrimitiveFuture<IO<T>> {
+    FutureValue::Value(Ok(IO::from(result)))
+}
+
+/// Runs the future created by `f` on the event loop, resolving once it has finished
+fn on_event_loop<F, R>(remote: &Remote, f: F) -> PrimitiveFuture<IO<R::Item>>
+where
+    F: FnOnce(&Handle) -> R + Send + 'static,
+    R: IntoFuture<Error = io::Error>,
+    R::Future: 'static,
+    R::Item: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    remote.spawn(move |handle| {
+        f(handle).into_future().then(move |result| {
+            let _ = sender.send(result);
+            Ok(())
+        })
+    });
+    FutureValue::Future(Box::new(
+        receiver
+            .map(IO::from)
+            .map_err(|_| vm::Error::Message("The event loop was shut down".into())),
+    ))
+}
+
+/// Turns the `WouldBlock` error of a socket registered with the event loop into `NotReady`. The
+/// socket has scheduled the current task to be notified once it is ready.
+fn poll_io<T>(result: io::Result<T>) -> Poll<T, io::Error> {
+    match result {
+        Ok(value) => Ok(Async::Ready(value)),
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+        Err(err) => Err(err),
+    }
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` did not resolve to an address", addr),
+        )
+    })
+}
+
+fn read_buffer(count: VmInt) -> io::Result<Vec<u8>> {
+    if count < 0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Cannot read a negative number of bytes ({})", count),
+        ))
+    } else {
+        Ok(vec![0; count as usize])
+    }
+}
+
+fn truncated(mut buffer: Vec<u8>, len: usize) -> Vec<u8> {
+    buffer.truncate(len);
+    buffer
+}
+
+fn tcp_bind(WithVM { vm, value: addr }: WithVM<&str>) -> PrimitiveFuture<IO<TcpListener>> {
+    let remote = match vm.get_event_loop() {
+        Some(remote) => remote,
+        None => {
+            return ready(
+                net::TcpListener::bind(addr).map(|listener| TcpListener::Blocking(Arc::new(listener))),
+            )
+        }
+    };
+    let addr = match resolve(addr) {
+        Ok(addr) => addr,
+        Err(err) => return ready(Err(err)),
+    };
+    let listener_remote = remote.clone();
+    on_event_loop(&remote, move |handle| {
+        evented::TcpListener::bind(&addr, handle).map(|listener| {
+            TcpListener::Evented(listener_remote, Arc::new(Mutex::new(listener)))
+        })
+    })
+}
+
+fn tcp_accept(listener: &TcpListener) -> PrimitiveFuture<IO<TcpStream>> {
+    match *listener {
+        TcpListener::Blocking(ref listener) => ready(
+            listener
+                .accept()
+                .map(|(stream, _)| TcpStream::Blocking(Arc::new(stream))),
+        ),
+        TcpListener::Evented(ref remote, ref listener) => {
+            let listener = listener.clone();
+            let stream_remote = remote.clone();
+            on_event_loop(remote, move |_| {
+                future::poll_fn(move || {
+                    let (stream, _) = match poll_io(listener.lock().unwrap().accept())? {
+                        Async::Ready(accepted) => accepted,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    };
+                    Ok(Async::Ready(TcpStream::Evented(
+                        stream_remote.clone(),
+                        Arc::new(Mutex::new(stream)),
+                    )))
+                })
+            })
+        }
+    }
+}
+
+fn tcp_listener_addr(listener: &TcpListener) -> IO<String> {
+    let addr = match *listener {
+        TcpListener::Blocking(ref listener) => listener.local_addr(),
+        TcpListener::Evented(_, ref listener) => listener.lock().unwrap().local_addr(),
+    };
+    IO::from(addr.map(|addr| addr.to_string()))
+}
+
+fn tcp_connect(WithVM { vm, value: addr }: WithVM<&str>) -> PrimitiveFuture<IO<TcpStream>> {
+    let remote = match vm.get_event_loop() {
+        Some(remote) => remote,
+        None => {
+            return ready(
+                net::TcpStream::connect(addr).map(|stream| TcpStream::Blocking(Arc::new(stream))),
+            )
+        }
+    };
+    let addr = match resolve(addr) {
+        Ok(addr) => addr,
+        Err(err) => return ready(Err(err)),
+    };
+    let stream_remote = remote.clone();
+    on_event_loop(&remote, move |handle| {
+        evented::TcpStream::connect(&addr, handle)
+            .map(|stream| TcpStream::Evented(stream_remote, Arc::new(Mutex::new(stream))))
+    })
+}
+
+/// Reads at most `count` bytes, returning an empty array once the stream is closed
+fn tcp_read(stream: &TcpStream, count: VmInt) -> PrimitiveFuture<IO<Vec<u8>>> {
+    let mut buffer = match read_buffer(count) {
+        Ok(buffer) => buffer,
+        Err(err) => return ready(Err(err)),
+    };
+    match *stream {
+        TcpStream::Blocking(ref stream) => {
+            let result = (&**stream).read(&mut buffer);
+            ready(result.map(|bytes_read| truncated(buffer, bytes_read)))
+        }
+        TcpStream::Evented(ref remote, ref stream) => {
+            let stream = stream.clone();
+            on_event_loop(remote, move |_| {
+                future::poll_fn(move || {
+                    let bytes_read = match poll_io(stream.lock().unwrap().read(&mut buffer))? {
+                        Async::Ready(bytes_read) => bytes_read,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    };
+                    let buffer = mem::replace(&mut buffer, Vec::new());
+                    Ok(Async::Ready(truncated(buffer, bytes_read)))
+                })
+            })
+        }
+    }
+}
+
+fn tcp_write(stream: &TcpStream, bytes: &[u8]) -> PrimitiveFuture<IO<()>> {
+    match *stream {
+        TcpStream::Blocking(ref stream) => ready((&**stream).write_all(bytes)),
+        TcpStream::Evented(ref remote, ref stream) => {
+            let stream = stream.clone();
+            let bytes = bytes.to_owned();
+            let mut written = 0;
+            on_event_loop(remote, move |_| {
+                future::poll_fn(move || {
+                    while written < bytes.len() {
+                        match poll_io(stream.lock().unwrap().write(&bytes[written..]))? {
+                            Async::Ready(0) => {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::WriteZero,
+                                    "failed to write the whole buffer",
+                                ))
+                            }
+                            Async::Ready(n) => written += n,
+                            Async::NotReady => return Ok(Async::NotReady),
+                        }
+                    }
+                    Ok(Async::Ready(()))
+                })
+            })
+        }
+    }
+}
+
+fn tcp_shutdown(stream: &TcpStream) -> IO<()> {
+    IO::from(match *stream {
+        TcpStream::Blocking(ref stream) => stream.shutdown(Shutdown::Both),
+        TcpStream::Evented(_, ref stream) => stream.lock().unwrap().shutdown(Shutdown::Both),
+    })
+}
+
+fn tcp_peer_addr(stream: &TcpStream) -> IO<String> {
+    let addr = match *stream {
+        TcpStream::Blocking(ref stream) => stream.peer_addr(),
+        TcpStream::Evented(_, ref stream) => stream.lock().unwrap().peer_addr(),
+    };
+    IO::from(addr.map(|addr| addr.to_string()))
+}
+
+fn udp_bind(WithVM { vm, value: addr }: WithVM<&str>) -> PrimitiveFuture<IO<UdpSocket>> {
+    let remote = match vm.get_event_loop() {
+        Some(remote) => remote,
+        None => {
+            return ready(
+                net::UdpSocket::bind(addr).map(|socket| UdpSocket::Blocking(Arc::new(socket))),
+            )
+        }
+    };
+    let addr = match resolve(addr) {
+        Ok(addr) => addr,
+        Err(err) => return ready(Err(err)),
+    };
+    let socket_remote = remote.clone();
+    on_event_loop(&remote, move |handle| {
+        evented::UdpSocket::bind(&addr, handle)
+            .map(|socket| UdpSocket::Evented(socket_remote, Arc::new(Mutex::new(socket))))
+    })
+}
+
+fn udp_send_to(socket: &UdpSocket, bytes: &[u8], addr: &str) -> PrimitiveFuture<IO<VmInt>> {
+    match *socket {
+        UdpSocket::Blocking(ref socket) => {
+            ready(socket.send_to(bytes, addr).map(|sent| sent as VmInt))
+        }
+        UdpSocket::Evented(ref remote, ref socket) => {
+            let addr = match resolve(addr) {
+                Ok(addr) => addr,
+                Err(err) => return ready(Err(err)),
+            };
+            let socket = socket.clone();
+            let bytes = bytes.to_owned();
+            on_event_loop(remote, move |_| {
+                future::poll_fn(move || {
+                    let sent = match poll_io(socket.lock().unwrap().send_to(&bytes, &addr))? {
+                        Async::Ready(sent) => sent,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    };
+                    Ok(Async::Ready(sent as VmInt))
+                })
+            })
+        }
+    }
+}
+
+fn datagram(buffer: Vec<u8>, (bytes_read, addr): (usize, SocketAddr)) -> Datagram {
+    record_no_decl!{
+        data => truncated(buffer, bytes_read),
+        addr => addr.to_string()
+    }
+}
+
+/// Receives a single datagram of at most `count` bytes
+fn udp_recv_from(socket: &UdpSocket, count: VmInt) -> PrimitiveFuture<IO<Datagram>> {
+    let mut buffer = match read_buffer(count) {
+        Ok(buffer) => buffer,
+        Err(err) => return ready(Err(err)),
+    };
+    match *socket {
+        UdpSocket::Blocking(ref socket) => {
+            let result = socket.recv_from(&mut buffer);
+            ready(result.map(|received| datagram(buffer, received)))
+        }
+        UdpSocket::Evented(ref remote, ref socket) => {
+            let socket = socket.clone();
+            on_event_loop(remote, move |_| {
+                future::poll_fn(move || {
+                    let received = match poll_io(socket.lock().unwrap().recv_from(&mut buffer))? {
+                        Async::Ready(received) => received,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    };
+                    let buffer = mem::replace(&mut buffer, Vec::new());
+                    Ok(Async::Ready(datagram(buffer, received)))
+                })
+            })
+        }
+    }
+}
+
+fn udp_local_addr(socket: &UdpSocket) -> IO<String> {
+    let addr = match *socket {
+        UdpSocket::Blocking(ref socket) => socket.local_addr(),
+        UdpSocket::Evented(_, ref socket) => socket.lock().unwrap().local_addr(),
+    };
+    IO::from(addr.map(|addr| addr.to_string()))
+}
+
+mod std {
+    pub mod net {
+        pub use net as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<TcpListener>("TcpListener", &[])?;
+    vm.register_type::<TcpStream>("TcpStream", &[])?;
+    vm.register_type::<UdpSocket>("UdpSocket", &[])?;
+
+    ExternModule::new(
+        vm,
+        record!{
+            type TcpListener => TcpListener,
+            type TcpStream => TcpStream,
+            type UdpSocket => UdpSocket,
+            tcp_bind => primitive!(1 std::net::prim::tcp_bind),
+            tcp_accept => primitive!(1 std::net::prim::tcp_accept),
+            tcp_listener_addr => primitive!(1 std::net::prim::tcp_listener_addr),
+            tcp_connect => primitive!(1 std::net::prim::tcp_connect),
+            tcp_read => primitive!(2 std::net::prim::tcp_read),
+            tcp_write => primitive!(2 std::net::prim::tcp_write),
+            tcp_shutdown => primitive!(1 std::net::prim::tcp_shutdown),
+            tcp_peer_addr => primitive!(1 std::net::prim::tcp_peer_addr),
+            udp_bind => primitive!(1 std::net::prim::udp_bind),
+            udp_send_to => primitive!(3 std::net::prim::udp_send_to),
+            udp_recv_from => primitive!(2 std::net::prim::udp_recv_from),
+            udp_local_addr => primitive!(1 std::net::prim::udp_local_addr)
+        },
+    )
+}
@@ -0,0 +1,122 @@
+//! Module containing functions for spawning subprocesses.
+
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::process::{self, Command, Stdio};
+use std::thread;
+
+use futures::sync::oneshot;
+use futures::Future;
+
+use vm::api::{PrimitiveFuture, IO};
+use vm::future::FutureValue;
+use vm::thread::Thread;
+use vm::types::VmInt;
+use vm::{self, ExternModule};
+
+field_decl! { status, stdout, stderr }
+
+type Output = record_type!{
+    status => Option<VmInt>,
+    stdout => String,
+    stderr => String
+};
+
+fn to_output(output: process::Output) -> Output {
+    record_no_decl!{
+        status => output.status.code().map(|code| code as VmInt),
+        stdout => String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr => String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+}
+
+/// Runs `command` to completion, writing `input` to its stdin and capturing stdout and stderr
+fn run<S>(command: &str, args: &[S], input: Option<String>) -> io::Result<process::Output>
+where
+    S: AsRef<OsStr>,
+{
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Write stdin from another thread so that a child which fills its stdout before reading all
+    // of its input can not deadlock
+    let writer = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            Ok(Ok(())) => (),
+            // The child exiting without reading all of its input is not an error
+            Ok(Err(ref err)) if err.kind() == io::ErrorKind::BrokenPipe => (),
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "stdin writer panicked")),
+        }
+    }
+    Ok(output)
+}
+
+fn output(command: &str, args: Vec<&str>) -> IO<Output> {
+    IO::from(run(command, &args, None).map(to_output))
+}
+
+fn output_with_stdin(command: &str, args: Vec<&str>, input: &str) -> IO<Output> {
+    IO::from(run(command, &args, Some(input.to_string())).map(to_output))
+}
+
+/// Runs `command` with the stdio of the current process, returning its exit code
+fn status(command: &str, args: Vec<&str>) -> IO<Option<VmInt>> {
+    IO::from(
+        Command::new(command)
+            .args(&args)
+            .status()
+            .map(|status| status.code().map(|code| code as VmInt)),
+    )
+}
+
+/// Like `output_with_stdin` but the process is waited on from a separate thread so that the
+/// calling gluon thread is not blocked while the process runs
+fn spawn_output(command: &str, args: Vec<String>, input: &str) -> PrimitiveFuture<IO<Output>> {
+    let (sender, receiver) = oneshot::channel();
+    let command = command.to_string();
+    let input = input.to_string();
+    thread::spawn(move || {
+        let _ = sender.send(run(&command, &args, Some(input)));
+    });
+    FutureValue::Future(Box::new(
+        receiver
+            .map(|result| IO::from(result.map(to_output)))
+            .map_err(|_| vm::Error::Message("The process thread was cancelled".into())),
+    ))
+}
+
+mod std {
+    pub mod process {
+        pub use process as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record!{
+            output => primitive!(2 std::process::prim::output),
+            output_with_stdin => primitive!(3 std::process::prim::output_with_stdin),
+            status => primitive!(2 std::process::prim::status),
+            spawn_output => primitive!(3 std::process::prim::spawn_output)
+        },
+    )
+}
@@ -33,12 +33,51 @@ fn next_float(_: ()) -> IO<f64> {
     IO::Value(rand::thread_rng().gen())
 }
 
+// `gen_range` panics on an empty range so the ranges are checked before calling it
+
 fn gen_int_range(low: VmInt, high: VmInt) -> IO<VmInt> {
+    if low >= high {
+        return IO::Exception(format!("Empty range {}..{}", low, high));
+    }
+    IO::Value(rand::thread_rng().gen_range(low, high))
+}
+
+fn gen_float_range(low: f64, high: f64) -> IO<f64> {
+    if !(low < high) {
+        return IO::Exception(format!("Empty range {}..{}", low, high));
+    }
     IO::Value(rand::thread_rng().gen_range(low, high))
 }
 
-type RngNext<G> = record_type!{
-    value => VmInt,
+fn sample_indices(len: VmInt, amount: VmInt) -> IO<Vec<VmInt>> {
+    match check_sample(len, amount) {
+        Ok(()) => IO::Value(sample_indices_(&mut rand::thread_rng(), len, amount)),
+        Err(err) => IO::Exception(err),
+    }
+}
+
+fn check_sample(len: VmInt, amount: VmInt) -> Result<(), String> {
+    if amount < 0 || amount > len {
+        Err(format!("Can't sample {} elements from {} elements", amount, len))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `amount` distinct indices in `0..len` in random order using a partial Fisher-Yates
+/// shuffle
+fn sample_indices_<R: Rng>(rng: &mut R, len: VmInt, amount: VmInt) -> Vec<VmInt> {
+    let mut indices: Vec<VmInt> = (0..len).collect();
+    for i in 0..amount as usize {
+        let j = rng.gen_range(i, len as usize);
+        indices.swap(i, j);
+    }
+    indices.truncate(amount as usize);
+    indices
+}
+
+type RngNext<T, G> = record_type!{
+    value => T,
     gen => G
 };
 
@@ -55,7 +94,26 @@ fn xor_shift_new(seed: &[VmInt]) -> RuntimeResult<XorShiftRng, String> {
     }
 }
 
-fn xor_shift_next(gen: &XorShiftRng) -> RngNext<XorShiftRng> {
+/// Creates a generator from a single integer by expanding it with splitmix64
+fn xor_shift_from_int(seed: VmInt) -> XorShiftRng {
+    let mut state = seed as u64;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    let mut words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    // An all zero seed is not allowed by xorshift
+    if words.iter().all(|&word| word == 0) {
+        words[0] = 1;
+    }
+    XorShiftRng(self::rand::XorShiftRng::from_seed(words))
+}
+
+fn xor_shift_next(gen: &XorShiftRng) -> RngNext<VmInt, XorShiftRng> {
     let mut gen = gen.clone();
     record_no_decl!{
         value => gen.0.gen(),
@@ -63,6 +121,59 @@ fn xor_shift_next(gen: &XorShiftRng) -> RngNext<XorShiftRng> {
     }
 }
 
+fn xor_shift_next_float(gen: &XorShiftRng) -> RngNext<f64, XorShiftRng> {
+    let mut gen = gen.clone();
+    record_no_decl!{
+        value => gen.0.gen(),
+        gen => gen
+    }
+}
+
+fn xor_shift_int_range(
+    gen: &XorShiftRng,
+    low: VmInt,
+    high: VmInt,
+) -> RuntimeResult<RngNext<VmInt, XorShiftRng>, String> {
+    if low >= high {
+        return RuntimeResult::Panic(format!("Empty range {}..{}", low, high));
+    }
+    let mut gen = gen.clone();
+    RuntimeResult::Return(record_no_decl!{
+        value => gen.0.gen_range(low, high),
+        gen => gen
+    })
+}
+
+fn xor_shift_float_range(
+    gen: &XorShiftRng,
+    low: f64,
+    high: f64,
+) -> RuntimeResult<RngNext<f64, XorShiftRng>, String> {
+    if !(low < high) {
+        return RuntimeResult::Panic(format!("Empty range {}..{}", low, high));
+    }
+    let mut gen = gen.clone();
+    RuntimeResult::Return(record_no_decl!{
+        value => gen.0.gen_range(low, high),
+        gen => gen
+    })
+}
+
+fn xor_shift_sample_indices(
+    gen: &XorShiftRng,
+    len: VmInt,
+    amount: VmInt,
+) -> RuntimeResult<RngNext<Vec<VmInt>, XorShiftRng>, String> {
+    if let Err(err) = check_sample(len, amount) {
+        return RuntimeResult::Panic(err);
+    }
+    let mut gen = gen.clone();
+    RuntimeResult::Return(record_no_decl!{
+        value => sample_indices_(&mut gen.0, len, amount),
+        gen => gen
+    })
+}
+
 mod std {
     pub mod random {
         pub use rand_bind as prim;
@@ -81,8 +192,15 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
             next_int => primitive!(1 std::random::prim::next_int),
             next_float => primitive!(1 std::random::prim::next_float),
             gen_int_range => primitive!(2 std::random::prim::gen_int_range),
+            gen_float_range => primitive!(2 std::random::prim::gen_float_range),
+            sample_indices => primitive!(2 std::random::prim::sample_indices),
             xor_shift_new => primitive!(1 std::random::prim::xor_shift_new),
-            xor_shift_next => primitive!(1 std::random::prim::xor_shift_next)
+            xor_shift_from_int => primitive!(1 std::random::prim::xor_shift_from_int),
+            xor_shift_next => primitive!(1 std::random::prim::xor_shift_next),
+            xor_shift_next_float => primitive!(1 std::random::prim::xor_shift_next_float),
+            xor_shift_int_range => primitive!(3 std::random::prim::xor_shift_int_range),
+            xor_shift_float_range => primitive!(3 std::random::prim::xor_shift_float_range),
+            xor_shift_sample_indices => primitive!(3 std::random::prim::xor_shift_sample_indices)
         },
     )
 }
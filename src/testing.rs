@@ -0,0 +1,510 @@
+//! Runner for tests written with `std.test`.
+//!
+//! A test file is a gluon program which evaluates to a `TestCase`. `run_test_files` runs every
+//! test of each file on a separate thread and collects the outcomes in a `TestReport`.
+//!
+//! This module also provides the random generation and shrinking of primitive values used by the
+//! property tests in `std.test.property`.
+
+use std::char;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use base::types::{ArcType, Type};
+
+use vm::api::{
+    Getable, Hole, OpaqueValue, OwnedFunction, RuntimeResult, Userdata, ValueRef, VmType,
+};
+use vm::gc::{Gc, Traverseable};
+use vm::thread::{RootedThread, Thread, ThreadInternal};
+use vm::types::VmInt;
+use vm::{self, ExternModule, Variants};
+
+use {Compiler, Result};
+
+type TestFn = OwnedFunction<fn(()) -> OpaqueValue<RootedThread, Hole>>;
+
+/// Rust representation of `std.test.TestCase`
+enum TestCase {
+    Test { name: String, test: TestFn },
+    Group { name: String, tests: Vec<TestCase> },
+}
+
+impl VmType for TestCase {
+    type Type = TestCase;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let typ = vm
+            .find_type_info("std.test.TestCase")
+            .unwrap_or_else(|err| panic!("{}", err))
+            .into_type();
+        Type::app(typ, collect![vm.global_env().type_cache().hole()])
+    }
+}
+
+impl<'vm> Getable<'vm> for TestCase {
+    fn from_value(vm: &'vm Thread, value: Variants) -> TestCase {
+        match value.as_ref() {
+            ValueRef::Data(data) => {
+                let field = |i| data.get_variant(i).expect("TestCase field");
+                let name = String::from_value(vm, field(0));
+                match data.tag() {
+                    0 => TestCase::Test {
+                        name,
+                        test: TestFn::from_value(vm, field(1)),
+                    },
+                    _ => TestCase::Group {
+                        name,
+                        tests: Vec::from_value(vm, field(1)),
+                    },
+                }
+            }
+            _ => ice!("ValueRef is not a TestCase"),
+        }
+    }
+}
+
+/// The outcome of a single test
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    /// The name of the file the test is in followed by the names of the groups containing the test
+    /// and the name of the test itself
+    pub name: String,
+    /// The failed assertions or the error which stopped the test, `None` if the test passed
+    pub error: Option<String>,
+}
+
+/// The outcomes of all tests which have been run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.len() - self.failed()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.error.is_some())
+            .count()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            let outcome = if result.error.is_some() { "FAILED" } else { "ok" };
+            writeln!(f, "test {} ... {}", result.name, outcome)?;
+        }
+        if !self.is_success() {
+            writeln!(f, "\nfailures:")?;
+            for result in &self.results {
+                if let Some(ref error) = result.error {
+                    writeln!(f, "\n---- {} ----\n{}", result.name, error)?;
+                }
+            }
+        }
+        writeln!(
+            f,
+            "\ntest result: {}. {} passed; {} failed",
+            if self.is_success() { "ok" } else { "FAILED" },
+            self.passed(),
+            self.failed()
+        )
+    }
+}
+
+/// Returns the gluon files directly inside `dir`, sorted by name
+pub fn discover_test_files<P>(dir: P) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("glu") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Runs the tests of every `.glu` file directly inside `dir`
+pub fn run_test_dir<P>(vm: &Thread, dir: P) -> Result<TestReport>
+where
+    P: AsRef<Path>,
+{
+    let files = discover_test_files(dir)?;
+    run_test_files(vm, &files)
+}
+
+/// Runs the tests of each file. A file which fails to compile is reported as a failed test.
+pub fn run_test_files<P>(vm: &Thread, files: &[P]) -> Result<TestReport>
+where
+    P: AsRef<Path>,
+{
+    // `TestCase` refers to `std.test` so it must be loaded before any test file is compiled
+    Compiler::new().run_expr::<OpaqueValue<&Thread, Hole>>(vm, "<test>", "import! std.test")?;
+
+    let mut report = TestReport::default();
+    for file in files {
+        let file = file.as_ref();
+        let name = file.display().to_string();
+        match load_test_file(vm, file) {
+            Ok(test_case) => run_test_case(vm, &name, test_case, &mut report.results),
+            Err(err) => report.results.push(TestResult {
+                name,
+                error: Some(err.to_string()),
+            }),
+        }
+    }
+    Ok(report)
+}
+
+fn load_test_file(vm: &Thread, file: &Path) -> Result<TestCase> {
+    let mut text = String::new();
+    File::open(file)?.read_to_string(&mut text)?;
+    let name = file.display().to_string();
+    let (test_case, _) = Compiler::new().run_expr::<TestCase>(vm, &name, &text)?;
+    Ok(test_case)
+}
+
+fn run_test_case(vm: &Thread, prefix: &str, test_case: TestCase, results: &mut Vec<TestResult>) {
+    match test_case {
+        TestCase::Test { name, test } => {
+            let name = format!("{} / {}", prefix, name);
+            let error = run_test(vm, test).err().map(|err| err.to_string());
+            results.push(TestResult { name, error });
+        }
+        TestCase::Group { name, tests } => {
+            let prefix = format!("{} / {}", prefix, name);
+            for test_case in tests {
+                run_test_case(vm, &prefix, test_case, results);
+            }
+        }
+    }
+}
+
+fn run_test(vm: &Thread, test: TestFn) -> vm::Result<()> {
+    // Run each test on its own thread so that a failing test does not leave anything behind on the
+    // stack of the other tests
+    let thread = vm.new_thread()?;
+    let mut test = TestFn::from_value(&thread, test.get_variant());
+    let mut run: OwnedFunction<fn(OpaqueValue<RootedThread, Hole>) -> ()> =
+        thread.get_global("std.test.run")?;
+    let result = test.call(())?;
+    run.call(result)
+}
+
+/// Formats the difference between two values which were expected to be equal. Lines which only
+/// exist in `left` are prefixed with `-` and lines which only exist in `right` with `+`.
+fn diff(left: &str, right: &str) -> String {
+    let left_lines: Vec<_> = left.lines().collect();
+    let right_lines: Vec<_> = right.lines().collect();
+
+    // `common[i][j]` is the length of the longest common subsequence of `left_lines[i..]` and
+    // `right_lines[j..]`
+    let mut common = vec![vec![0; right_lines.len() + 1]; left_lines.len() + 1];
+    for i in (0..left_lines.len()).rev() {
+        for j in (0..right_lines.len()).rev() {
+            common[i][j] = if left_lines[i] == right_lines[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left_lines.len() || j < right_lines.len() {
+        if i < left_lines.len() && j < right_lines.len() && left_lines[i] == right_lines[j] {
+            output.push_str(&format!("  {}\n", left_lines[i]));
+            i += 1;
+            j += 1;
+        } else if j == right_lines.len()
+            || (i < left_lines.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            output.push_str(&format!("- {}\n", left_lines[i]));
+            i += 1;
+        } else {
+            output.push_str(&format!("+ {}\n", right_lines[j]));
+            j += 1;
+        }
+    }
+
+    // Point out where single line values start to differ
+    if left_lines.len() == 1 && right_lines.len() == 1 {
+        let column = left
+            .chars()
+            .zip(right.chars())
+            .take_while(|&(l, r)| l == r)
+            .count();
+        output.push_str(&format!("  {}^\n", " ".repeat(column)));
+    }
+    output
+}
+
+/// A splitmix64 generator which produces the inputs of property tests. The generator is never
+/// mutated, each generated value is returned together with the next state.
+#[derive(Clone, Debug)]
+struct TestRng(u64);
+
+impl Userdata for TestRng {}
+
+impl VmType for TestRng {
+    type Type = TestRng;
+}
+
+impl Traverseable for TestRng {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+impl TestRng {
+    fn next_u64(&self) -> (u64, TestRng) {
+        let state = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31), TestRng(state))
+    }
+
+    /// Returns a value in the inclusive range `[0, bound]`
+    fn below(&self, bound: u64) -> (u64, TestRng) {
+        let (value, gen) = self.next_u64();
+        match bound.checked_add(1) {
+            Some(len) => (value % len, gen),
+            None => (value, gen),
+        }
+    }
+
+    fn next_float(&self) -> (f64, TestRng) {
+        let (value, gen) = self.next_u64();
+        // Use the upper 53 bits to get an uniformly distributed float in `[0, 1)`
+        ((value >> 11) as f64 / (1u64 << 53) as f64, gen)
+    }
+
+    fn next_char(&self) -> (char, TestRng) {
+        let (kind, gen) = self.below(9);
+        // Mostly generate printable ASCII as that is what most properties are interested in but
+        // throw in arbitrary unicode scalar values now and then
+        if kind < 9 {
+            let (c, gen) = gen.below(0x7E - 0x20);
+            (char::from(0x20 + c as u8), gen)
+        } else {
+            let (c, gen) = gen.below(0x10_FFFF - 0x800);
+            let c = 0x800 + c as u32;
+            (char::from_u32(c).unwrap_or('\u{FFFD}'), gen)
+        }
+    }
+}
+
+field_decl! { value, gen }
+
+type Generated<T> = record_type!{
+    value => T,
+    gen => TestRng
+};
+
+fn generated<T>((value, gen): (T, TestRng)) -> Generated<T> {
+    record_no_decl!{
+        value => value,
+        gen => gen
+    }
+}
+
+fn rng_from_seed(seed: VmInt) -> TestRng {
+    TestRng(seed as u64)
+}
+
+/// Returns an integer in the inclusive range `[low, high]`
+fn gen_int(gen: &TestRng, low: VmInt, high: VmInt) -> RuntimeResult<Generated<VmInt>, String> {
+    if low > high {
+        return RuntimeResult::Panic(format!("Empty range {}..={}", low, high));
+    }
+    let (offset, gen) = gen.below(high.wrapping_sub(low) as u64);
+    RuntimeResult::Return(generated((low.wrapping_add(offset as VmInt), gen)))
+}
+
+/// Returns a float in the range `[low, high)`
+fn gen_float(gen: &TestRng, low: f64, high: f64) -> RuntimeResult<Generated<f64>, String> {
+    if !(low <= high) {
+        return RuntimeResult::Panic(format!("Empty range {}..{}", low, high));
+    }
+    let (value, gen) = gen.next_float();
+    RuntimeResult::Return(generated((low + value * (high - low), gen)))
+}
+
+fn gen_bool(gen: &TestRng) -> Generated<bool> {
+    let (value, gen) = gen.below(1);
+    generated((value == 1, gen))
+}
+
+fn gen_char(gen: &TestRng) -> Generated<char> {
+    generated(gen.next_char())
+}
+
+/// Returns a string of at most `size` characters
+fn gen_string(gen: &TestRng, size: VmInt) -> Generated<String> {
+    let (len, mut gen) = gen.below(size.max(0) as u64);
+    let mut string = String::new();
+    for _ in 0..len {
+        let (c, next) = gen.next_char();
+        string.push(c);
+        gen = next;
+    }
+    generated((string, gen))
+}
+
+/// Returns smaller integers to try in place of `i`, the simplest candidates first
+fn shrink_int(i: VmInt) -> Vec<VmInt> {
+    let mut candidates = Vec::new();
+    if i == 0 {
+        return candidates;
+    }
+    candidates.push(0);
+    if i < 0 && i != VmInt::min_value() {
+        candidates.push(-i);
+    }
+    // Move towards `i` by halving the distance
+    let mut distance = i / 2;
+    while distance != 0 {
+        let candidate = i - distance;
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+        distance /= 2;
+    }
+    candidates
+}
+
+fn shrink_float(f: f64) -> Vec<f64> {
+    let mut candidates = Vec::new();
+    if f == 0.0 || f.is_nan() {
+        return candidates;
+    }
+    candidates.push(0.0);
+    if f < 0.0 {
+        candidates.push(-f);
+    }
+    if f.trunc() != f && f.is_finite() {
+        candidates.push(f.trunc());
+    }
+    if f.is_finite() && (f / 2.0) != 0.0 {
+        candidates.push(f / 2.0);
+    }
+    candidates
+}
+
+fn shrink_char(c: char) -> Vec<char> {
+    "abc"
+        .chars()
+        .chain(if c.is_uppercase() { Some('A') } else { None })
+        .filter(|&candidate| candidate < c)
+        .collect()
+}
+
+/// Returns the strings which can be created by removing chunks of characters from `s` or by
+/// shrinking one of its characters
+fn shrink_string(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut candidates = Vec::new();
+    let mut chunk = chars.len();
+    while chunk > 0 {
+        let mut start = 0;
+        while start + chunk <= chars.len() {
+            let candidate: String = chars[..start]
+                .iter()
+                .chain(&chars[start + chunk..])
+                .collect();
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+            start += chunk;
+        }
+        chunk /= 2;
+    }
+    for (i, &c) in chars.iter().enumerate() {
+        for smaller in shrink_char(c) {
+            let mut candidate = chars.clone();
+            candidate[i] = smaller;
+            candidates.push(candidate.into_iter().collect());
+        }
+    }
+    candidates
+}
+
+mod std {
+    pub mod test {
+        pub use testing as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<TestRng>("TestRng", &[])?;
+
+    ExternModule::new(
+        vm,
+        record!{
+            type TestRng => TestRng,
+            diff => primitive!(2 std::test::prim::diff),
+            rng_from_seed => primitive!(1 std::test::prim::rng_from_seed),
+            gen_int => primitive!(3 std::test::prim::gen_int),
+            gen_float => primitive!(3 std::test::prim::gen_float),
+            gen_bool => primitive!(1 std::test::prim::gen_bool),
+            gen_char => primitive!(1 std::test::prim::gen_char),
+            gen_string => primitive!(2 std::test::prim::gen_string),
+            shrink_int => primitive!(1 std::test::prim::shrink_int),
+            shrink_float => primitive!(1 std::test::prim::shrink_float),
+            shrink_char => primitive!(1 std::test::prim::shrink_char),
+            shrink_string => primitive!(1 std::test::prim::shrink_string)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_single_line() {
+        assert_eq!(diff("[1, 2, 3]", "[1, 2, 4]"), "- [1, 2, 3]\n+ [1, 2, 4]\n         ^\n");
+    }
+
+    #[test]
+    fn diff_multiple_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nc\nd"), "  a\n- b\n  c\n+ d\n");
+    }
+
+    #[test]
+    fn gen_int_stays_in_range() {
+        let mut gen = rng_from_seed(7);
+        for _ in 0..1000 {
+            let record_p!{ value, next } = match gen_int(&gen, -3, 3) {
+                RuntimeResult::Return(generated) => generated,
+                RuntimeResult::Panic(err) => panic!("{}", err),
+            };
+            assert!(value >= -3 && value <= 3, "{}", value);
+            gen = next;
+        }
+    }
+
+    #[test]
+    fn shrink_int_moves_towards_zero() {
+        assert_eq!(shrink_int(0), Vec::<VmInt>::new());
+        assert_eq!(shrink_int(10), vec![0, 5, 8, 9]);
+        assert_eq!(shrink_int(-4), vec![0, 4, -2, -3]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Typechecking of every gluon file in a project at once, so that a change which breaks the
+//! modules depending on it is noticed even if those modules are not open in an editor.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use base::filename_to_module;
+
+use {Compiler, Error, Result, Thread};
+
+/// The outcome of typechecking a single file of a workspace
+#[derive(Debug)]
+pub struct FileDiagnostics {
+    pub path: PathBuf,
+    /// The name of the module the file was checked as (`std.prelude` for `std/prelude.glu`)
+    pub module: String,
+    /// The errors found in the file, `None` if it typechecked successfully
+    pub error: Option<Error>,
+}
+
+/// Returns every gluon file in `root` and its subdirectories, sorted by path
+pub fn discover_files<P>(root: P) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    fn discover(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                discover(&path, files)?;
+            } else if path.extension().and_then(|ext| ext.to_str()) == Some("glu") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    discover(root.as_ref(), &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Typechecks every gluon file under each of `roots`, naming the modules by their path relative
+/// to the root they were found in. Modules which are imported are loaded into `vm` the first time
+/// they are needed and are only recompiled afterwards if their source changes.
+pub fn check_workspace<P>(
+    compiler: &mut Compiler,
+    vm: &Thread,
+    roots: &[P],
+) -> Result<Vec<FileDiagnostics>>
+where
+    P: AsRef<Path>,
+{
+    let mut diagnostics = Vec::new();
+    for root in roots {
+        let root = root.as_ref();
+        for path in discover_files(root)? {
+            let module = {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                filename_to_module(&relative.display().to_string())
+            };
+
+            let mut source = String::new();
+            File::open(&path)?.read_to_string(&mut source)?;
+
+            let error = compiler.typecheck_str(vm, &module, &source, None).err();
+            diagnostics.push(FileDiagnostics {
+                path,
+                module,
+                error,
+            });
+        }
+    }
+    Ok(diagnostics)
+}
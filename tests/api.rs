@@ -10,10 +10,26 @@ extern crate gluon_vm;
 use futures::future::lazy;
 use futures::{Future, IntoFuture};
 
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use gluon::base::types::{Alias, ArcType, Type};
-use gluon::import::{add_extern_module, Import};
+use gluon::import::{
+    add_embedded_module, add_extern_module, FileSystemLoader, Import, SourceLoader,
+};
 use gluon::vm::api::de::De;
-use gluon::vm::api::{FunctionRef, FutureResult, Userdata, VmType, IO};
+use gluon::vm::api::{
+    Finalizer, FunctionRef, FutureResult, Getable, Hole, MarshalError, OpaqueValue, OwnedFunction,
+    RwUserdata, Userdata, ValueRef, VmType, IO,
+};
 use gluon::vm::thread::{Root, RootStr, RootedThread, Thread, Traverseable};
 use gluon::vm::types::VmInt;
 use gluon::vm::{Error, ExternModule};
@@ -58,6 +74,37 @@ fn call_function() {
     assert_eq!(result, 20.);
 }
 
+#[test]
+fn owned_function_outlives_thread_borrow() {
+    let _ = ::env_logger::try_init();
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    struct Callback {
+        function: OwnedFunction<fn(VmInt) -> VmInt>,
+    }
+
+    let expr = r"
+        let add10 : Int -> Int = \x -> x #Int+ 10 in add10
+    ";
+    let mut callback = {
+        let vm = make_vm();
+        load_script(&vm, "add10", &expr).unwrap_or_else(|err| panic!("{}", err));
+        Callback {
+            function: vm.get_global("add10").unwrap(),
+        }
+    };
+    assert_send_sync(&callback.function);
+
+    // The function stays rooted through collections
+    callback.function.vm().collect();
+
+    let result = ::std::thread::spawn(move || callback.function.call(2).unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(result, 12);
+}
+
 #[test]
 fn root_data() {
     let _ = ::env_logger::try_init();
@@ -91,6 +138,83 @@ fn root_data() {
     assert_eq!(result, 124);
 }
 
+#[test]
+fn mutate_userdata() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let { increment, get } = import! test
+        \counter ->
+            let _ = increment counter
+            let _ = increment counter
+            get counter
+    "#;
+    let vm = make_vm();
+    fn increment(counter: &RwUserdata<VmInt>) {
+        *counter.write() += 1;
+    }
+    fn get(counter: &RwUserdata<VmInt>) -> VmInt {
+        *counter.read()
+    }
+    vm.register_type::<RwUserdata<VmInt>>("Counter", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+
+    add_extern_module(&vm, "test", |thread| {
+        ExternModule::new(
+            thread,
+            record!{
+                increment => primitive!(1 increment),
+                get => primitive!(1 get),
+            },
+        )
+    });
+
+    load_script(&vm, "script_fn", expr).unwrap_or_else(|err| panic!("{}", err));
+    let mut script_fn: FunctionRef<fn(RwUserdata<VmInt>) -> VmInt> =
+        vm.get_global("script_fn").unwrap();
+    let result = script_fn.call(RwUserdata::new(40)).unwrap();
+    assert_eq!(result, 42);
+}
+
+#[test]
+fn finalize_userdata() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug)]
+    struct Test(Arc<AtomicUsize>);
+    impl Userdata for Test {
+        fn finalize(&mut self) -> Option<Finalizer> {
+            let finalized = self.0.clone();
+            Some(Box::new(move |_: &Thread| {
+                finalized.fetch_add(1, Ordering::SeqCst);
+            }))
+        }
+    }
+    impl Traverseable for Test {}
+    impl VmType for Test {
+        type Type = Test;
+    }
+
+    let vm = make_vm();
+    vm.register_type::<Test>("Test", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+
+    load_script(&vm, "script_fn", r#"\_ -> 1"#).unwrap_or_else(|err| panic!("{}", err));
+    let mut script_fn: FunctionRef<fn(Test) -> VmInt> = vm.get_global("script_fn").unwrap();
+
+    let finalized = Arc::new(AtomicUsize::new(0));
+    script_fn.call(Test(finalized.clone())).unwrap();
+    vm.collect();
+    assert_eq!(finalized.load(Ordering::SeqCst), 1);
+
+    // Values which are still alive when their thread is dropped are finalized as well
+    let thread = vm.new_thread().unwrap();
+    thread.push(Test(finalized.clone())).unwrap();
+    drop(thread);
+    vm.collect();
+    assert_eq!(finalized.load(Ordering::SeqCst), 2);
+}
+
 #[test]
 fn root_string() {
     let _ = ::env_logger::try_init();
@@ -143,6 +267,69 @@ fn array() {
     assert_eq!(result, expected);
 }
 
+#[test]
+fn bytes_to_and_from_vec() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let reverse_bytes = import! reverse_bytes
+        reverse_bytes [1b, 2b, 3b]
+    "#;
+    fn reverse_bytes(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.reverse();
+        bytes
+    }
+
+    let vm = make_vm();
+    add_extern_module(&vm, "reverse_bytes", |thread| {
+        ExternModule::new(thread, primitive!(1 reverse_bytes))
+    });
+
+    let result = Compiler::new()
+        .run_expr::<Vec<u8>>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expected = (vec![3, 2, 1], Type::array(Type::byte()));
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn pointers_forward_to_their_contents() {
+    let _ = ::env_logger::try_init();
+
+    let expr = r#"
+        let { sum, share, share_sync } = import! pointers
+        let (x, y) = share_sync (share 1) "a"
+        sum (Some 1) (x, y) [2, 3]
+    "#;
+    fn sum(x: Box<Option<VmInt>>, y: Rc<(VmInt, String)>, z: Arc<Vec<VmInt>>) -> VmInt {
+        x.unwrap_or(0) + y.0 + z.iter().sum::<VmInt>()
+    }
+    fn share(x: VmInt) -> Rc<VmInt> {
+        Rc::new(x)
+    }
+    fn share_sync(x: Box<VmInt>, y: String) -> Arc<(VmInt, String)> {
+        Arc::new((*x, y))
+    }
+
+    let vm = make_vm();
+    add_extern_module(&vm, "pointers", |thread| {
+        ExternModule::new(
+            thread,
+            record!{
+                sum => primitive!(3 sum),
+                share => primitive!(1 share),
+                share_sync => primitive!(2 share_sync)
+            },
+        )
+    });
+
+    let result = Compiler::new()
+        .run_expr::<VmInt>(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, (7, Type::int()));
+}
+
 #[test]
 fn return_finished_future() {
     let _ = ::env_logger::try_init();
@@ -341,3 +528,414 @@ fn use_type_from_type_field() {
         .unwrap_or_else(|err| panic!("{}", err));
     assert_eq!(actual, Test::B("abc".to_string()));
 }
+
+#[test]
+fn function_with_twelve_arguments() {
+    let _ = ::env_logger::try_init();
+
+    fn sum(
+        a: VmInt,
+        b: VmInt,
+        c: VmInt,
+        d: VmInt,
+        e: VmInt,
+        f: VmInt,
+        g: VmInt,
+        h: VmInt,
+        i: VmInt,
+        j: VmInt,
+        k: VmInt,
+        l: VmInt,
+    ) -> VmInt {
+        a + b + c + d + e + f + g + h + i + j + k + l
+    }
+
+    let vm = make_vm();
+    add_extern_module(&vm, "sum", |vm| ExternModule::new(vm, primitive!(12 sum)));
+
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(
+            &vm,
+            "test",
+            "let sum = import! sum in sum 1 2 3 4 5 6 7 8 9 10 11 12",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 78);
+
+    type Sum = fn(
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+        VmInt,
+    ) -> VmInt;
+    let (mut sum, _) = Compiler::new()
+        .run_expr::<FunctionRef<Sum>>(&vm, "test", "import! sum")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(sum.call(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12).unwrap(), 78);
+}
+
+#[test]
+fn borrowed_strings_in_arguments() {
+    let _ = ::env_logger::try_init();
+
+    fn join(strings: Vec<&str>, separator: Option<&str>) -> String {
+        strings.join(separator.unwrap_or(""))
+    }
+
+    let vm = make_vm();
+    add_extern_module(&vm, "join", |vm| ExternModule::new(vm, primitive!(2 join)));
+
+    let (result, _) = Compiler::new()
+        .run_expr::<String>(
+            &vm,
+            "test",
+            r#"let join = import! join in join ["a", "b"] (Some ", ")"#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "a, b");
+}
+
+#[test]
+fn call_any_function() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let (add, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(VmInt, VmInt) -> VmInt>>(&vm, "add", r"\x y -> x #Int+ y")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let args = ["1", "2"]
+        .iter()
+        .map(|expr| {
+            Compiler::new()
+                .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "arg", expr)
+                .unwrap_or_else(|err| panic!("{}", err))
+                .0
+        })
+        .collect::<Vec<_>>();
+
+    let result = add.call_any(&args).unwrap_or_else(|err| panic!("{}", err));
+    match result.get_ref() {
+        ValueRef::Int(i) => assert_eq!(i, 3),
+        x => panic!("Expected an integer, got {:?}", x),
+    }
+
+    let err = add.call_any(&args[..1]).unwrap_err().to_string();
+    assert!(err.contains("Expected 2 arguments but got 1"), "{}", err);
+
+    let (string, _) = Compiler::new()
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "arg", r#""a""#)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let err = add
+        .call_any(&[args[0].clone(), string])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("Argument 2 does not have the type `Int`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn call_any_io_function() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let (add_one, _) = Compiler::new()
+        .run_expr::<FunctionRef<fn(VmInt) -> IO<VmInt>>>(
+            &vm,
+            "add_one",
+            r"let io = import! std.io in \x -> io.applicative.wrap (x #Int+ 1)",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (arg, _) = Compiler::new()
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "arg", "1")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let result = add_one
+        .call_any(&[arg])
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result.get_ref() {
+        ValueRef::Int(i) => assert_eq!(i, 2),
+        x => panic!("Expected an integer, got {:?}", x),
+    }
+}
+
+#[test]
+fn try_from_value_reports_nested_mismatches() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let (value, _) = Compiler::new()
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "test", r#"Some (1, "a")"#)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let value = value.get_variant();
+
+    assert_eq!(
+        <Option<(VmInt, String)>>::try_from_value(&vm, value),
+        Ok(Some((1, "a".to_string())))
+    );
+    match <Option<(VmInt, VmInt)>>::try_from_value(&vm, value) {
+        Err(MarshalError::UnexpectedValue(expected, _)) => assert_eq!(expected, "an Int"),
+        x => panic!("Expected an error, got {:?}", x),
+    }
+    match <Result<String, VmInt>>::try_from_value(&vm, value) {
+        Err(MarshalError::UnexpectedValue(expected, _)) => assert_eq!(expected, "a String"),
+        x => panic!("Expected an error, got {:?}", x),
+    }
+
+    let (array, _) = Compiler::new()
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "test", "[1, 2]")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let array = array.get_variant();
+    assert_eq!(<Vec<VmInt>>::try_from_value(&vm, array), Ok(vec![1, 2]));
+    match <Vec<String>>::try_from_value(&vm, array) {
+        Err(MarshalError::UnexpectedValue(expected, _)) => assert_eq!(expected, "a String"),
+        x => panic!("Expected an error, got {:?}", x),
+    }
+}
+
+#[test]
+fn changed_modules_are_recompiled() {
+    let _ = ::env_logger::try_init();
+
+    let dir = env::temp_dir().join("gluon_changed_modules_are_recompiled");
+    fs::create_dir_all(&dir).unwrap();
+    let write_module = |name: &str, source: &str| {
+        let mut file = File::create(dir.join(name)).unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+    };
+    write_module("cache_dependency.glu", "1");
+    write_module("cache_module.glu", "import! cache_dependency");
+
+    let vm = make_vm();
+    vm.get_macros()
+        .get("import")
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .expect("Import macro")
+        .add_path(dir.clone());
+
+    let run = || {
+        Compiler::new()
+            .run_expr::<VmInt>(&vm, "test", "import! cache_module")
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0
+    };
+    assert_eq!(run(), 1);
+    assert_eq!(run(), 1);
+
+    // `cache_module` is recompiled as well since it depends on the changed module
+    write_module("cache_dependency.glu", "2");
+    assert_eq!(run(), 2);
+}
+
+struct MapLoader(HashMap<String, String>);
+
+impl SourceLoader for MapLoader {
+    fn load_source(
+        &self,
+        paths: &[PathBuf],
+        module: &str,
+        filename: &str,
+    ) -> io::Result<Option<String>> {
+        match self.0.get(module) {
+            Some(source) => Ok(Some(source.clone())),
+            // The standard library is only read from the filesystem when testing
+            None if module.starts_with("std.") => {
+                FileSystemLoader.load_source(paths, module, filename)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[test]
+fn modules_from_custom_source_loader() {
+    let _ = ::env_logger::try_init();
+
+    let mut modules = HashMap::new();
+    modules.insert("embedded.value".to_string(), "1 #Int+ 2".to_string());
+    let vm = ::gluon::VmBuilder::new()
+        .source_loader(Some(Arc::new(MapLoader(modules))))
+        .build();
+
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "test", "import! embedded.value")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 3);
+
+    let err = Compiler::new()
+        .run_expr::<VmInt>(&vm, "test", "import! embedded.missing")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("Could not find module 'embedded.missing'"),
+        "{}",
+        err
+    );
+}
+
+/// Loads `counted.*` modules from memory, counting the number of times they are read and reporting
+/// them as never modified
+struct CountingLoader {
+    modules: HashMap<String, String>,
+    loads: Arc<AtomicUsize>,
+}
+
+impl SourceLoader for CountingLoader {
+    fn load_source(
+        &self,
+        paths: &[PathBuf],
+        module: &str,
+        filename: &str,
+    ) -> io::Result<Option<String>> {
+        match self.modules.get(module) {
+            Some(source) => {
+                self.loads.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(source.clone()))
+            }
+            None => FileSystemLoader.load_source(paths, module, filename),
+        }
+    }
+
+    fn modified(&self, paths: &[PathBuf], module: &str, filename: &str) -> Option<SystemTime> {
+        if self.modules.contains_key(module) {
+            Some(UNIX_EPOCH)
+        } else {
+            FileSystemLoader.modified(paths, module, filename)
+        }
+    }
+}
+
+#[test]
+fn unmodified_modules_are_not_read_again() {
+    let _ = ::env_logger::try_init();
+
+    let loads = Arc::new(AtomicUsize::new(0));
+    let mut modules = HashMap::new();
+    modules.insert("counted.shared".to_string(), "1".to_string());
+    modules.insert("counted.left".to_string(), "import! counted.shared".to_string());
+    modules.insert("counted.right".to_string(), "import! counted.shared".to_string());
+    let vm = ::gluon::VmBuilder::new()
+        .source_loader(Some(Arc::new(CountingLoader {
+            modules,
+            loads: loads.clone(),
+        })))
+        .build();
+
+    let run = || {
+        let expr = r#"
+            let left = import! counted.left
+            let right = import! counted.right
+            left #Int+ right
+        "#;
+        Compiler::new()
+            .run_expr::<VmInt>(&vm, "test", expr)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0
+    };
+    assert_eq!(run(), 2);
+    assert_eq!(loads.load(Ordering::SeqCst), 3);
+
+    // The modification times are unchanged so none of the modules are read again
+    assert_eq!(run(), 2);
+    assert_eq!(loads.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn embedded_module() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    add_embedded_module(&vm, "embedded.dependency", "1");
+    add_embedded_module(
+        &vm,
+        "embedded.module",
+        "let x = import! embedded.dependency\nx #Int+ 2",
+    );
+
+    let (result, _) = Compiler::new()
+        .run_expr::<VmInt>(&vm, "test", "import! embedded.module")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 3);
+}
+
+/// Creates a directory which `vm` imports modules from, removing any modules left by earlier runs
+fn module_dir(vm: &Thread, name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    vm.get_macros()
+        .get("import")
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .expect("Import macro")
+        .add_path(dir.clone());
+    dir
+}
+
+fn write_module(dir: &Path, name: &str, source: &str) {
+    let mut file = File::create(dir.join(name)).unwrap();
+    file.write_all(source.as_bytes()).unwrap();
+}
+
+#[test]
+fn cyclic_imports_report_the_cycle() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let dir = module_dir(&vm, "gluon_cyclic_imports_report_the_cycle");
+    write_module(&dir, "cycle_a.glu", "import! cycle_b");
+    write_module(&dir, "cycle_b.glu", "import! cycle_c");
+    write_module(&dir, "cycle_c.glu", "import! cycle_a");
+
+    let err = Compiler::new()
+        .run_expr::<VmInt>(&vm, "test", "import! cycle_a")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("`cycle_a.glu -> cycle_b.glu -> cycle_c.glu -> cycle_a.glu`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn check_workspace_reports_errors_per_file() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let dir = module_dir(&vm, "gluon_check_workspace_reports_errors_per_file");
+    fs::create_dir(dir.join("nested")).unwrap();
+    write_module(&dir, "nested/ok.glu", "1");
+    write_module(&dir, "workspace_lib.glu", r#"{ value = "" }"#);
+    write_module(
+        &dir,
+        "workspace_user.glu",
+        "let lib = import! workspace_lib\nlib.value #Int+ 1",
+    );
+
+    let diagnostics =
+        gluon::workspace::check_workspace(&mut Compiler::new(), &vm, &[&dir]).unwrap();
+    let modules: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| (&diagnostic.module[..], diagnostic.error.is_some()))
+        .collect();
+    assert_eq!(
+        modules,
+        vec![
+            ("nested.ok", false),
+            ("workspace_lib", false),
+            ("workspace_user", true),
+        ]
+    );
+}
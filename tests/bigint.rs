@@ -0,0 +1,103 @@
+#![cfg(feature = "bigint")]
+extern crate env_logger;
+extern crate gluon;
+
+use gluon::vm::api::{Hole, OpaqueValue};
+use gluon::{new_vm, Compiler, RootedThread};
+
+#[test]
+fn bigint_arithmetic_does_not_overflow() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let bigint @ { ? } = import! std.bigint
+        let { (|>) } = import! std.function
+        let { unwrap } = import! std.option
+        let { unwrap_ok } = import! std.result
+        let int = import! std.int
+
+        let max = bigint.from_int int.max_value
+        let product = max * max
+        let expected = bigint.parse "85070591730234615847396907784232501249" |> unwrap_ok
+        if product == expected && unwrap (bigint.to_int (product / max)) == int.max_value then
+            show product
+        else
+            "unexpected result"
+        "#;
+    let result = Compiler::new()
+        .run_expr_async::<String>(&thread, "<top>", text)
+        .sync_or_error();
+
+    assert_eq!(
+        result.unwrap_or_else(|err| panic!("{}", err)).0,
+        "85070591730234615847396907784232501249"
+    );
+}
+
+#[test]
+fn bigint_literals() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let bigint @ { ? } = import! std.bigint
+        show (bigint! "85070591730234615847396907784232501249" + bigint! 1)
+        "#;
+    let result = Compiler::new()
+        .run_expr_async::<String>(&thread, "<top>", text)
+        .sync_or_error();
+
+    assert_eq!(
+        result.unwrap_or_else(|err| panic!("{}", err)).0,
+        "85070591730234615847396907784232501250"
+    );
+}
+
+#[test]
+fn invalid_bigint_literal_is_a_compile_error() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        bigint! "12x"
+        "#;
+    let result = Compiler::new()
+        .run_expr_async::<OpaqueValue<RootedThread, Hole>>(&thread, "<top>", text)
+        .sync_or_error();
+
+    let err = result.err().expect("Expected a compile error").to_string();
+    assert!(err.contains("`12x` is not an integer"), "{}", err);
+}
+
+#[test]
+fn bigint_pow_with_a_huge_result_is_an_error() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let bigint = import! std.bigint
+        bigint.pow (bigint.from_int 3) 100000000
+        "#;
+    let result = Compiler::new()
+        .run_expr_async::<OpaqueValue<RootedThread, Hole>>(&thread, "<top>", text)
+        .sync_or_error();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn bigint_division_by_zero_is_an_error() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let bigint @ { ? } = import! std.bigint
+        bigint.from_int 1 / bigint.from_int 0
+        "#;
+    let result = Compiler::new()
+        .run_expr_async::<OpaqueValue<RootedThread, Hole>>(&thread, "<top>", text)
+        .sync_or_error();
+
+    assert!(result.is_err());
+}
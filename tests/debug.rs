@@ -6,12 +6,16 @@ extern crate gluon;
 
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use futures::{Async, Future};
 
 use gluon::base::pos::Line;
 use gluon::base::types::{ArcType, Type};
+use gluon::vm::api::ValueRef;
 use gluon::vm::compiler::UpvarInfo;
+use gluon::vm::debugger::{Debugger, Step};
+use gluon::vm::profiler::Profiler;
 use gluon::vm::thread::{HookFlags, ThreadInternal};
 use gluon::{new_vm, Compiler};
 
@@ -55,6 +59,80 @@ fn function_hook() {
     );
 }
 
+#[test]
+fn return_hook() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let functions = Arc::new(Mutex::new(Vec::new()));
+    {
+        let functions = functions.clone();
+        let mut context = thread.context();
+        context.set_hook(Some(Box::new(move |_, debug_context| {
+            let stack_info = debug_context.stack_info(0).unwrap();
+            functions.lock().unwrap().push((
+                debug_context.state(),
+                stack_info
+                    .function_symbol()
+                    .expect("function_symbol")
+                    .declared_name()
+                    .to_string(),
+            ));
+            Ok(Async::Ready(()))
+        })));
+        context.set_hook_mask(HookFlags::CALL_FLAG | HookFlags::RETURN_FLAG);
+    }
+    Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&thread, "test", SIMPLE_EXPR)
+        .unwrap();
+
+    // `g` and `test` do not return themselves as they tail call into `f`
+    assert_eq!(
+        *functions.lock().unwrap(),
+        vec![
+            (HookFlags::CALL_FLAG, "test".to_string()),
+            (HookFlags::CALL_FLAG, "g".to_string()),
+            (HookFlags::CALL_FLAG, "f".to_string()),
+            (HookFlags::RETURN_FLAG, "f".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn count_hook() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let indexes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let indexes = indexes.clone();
+        let mut context = thread.context();
+        context.set_hook(Some(Box::new(move |_, debug_context| {
+            let stack_info = debug_context.stack_info(0).unwrap();
+            indexes.lock().unwrap().push(stack_info.instruction_index());
+            Ok(Async::Ready(()))
+        })));
+        context.set_hook_mask(HookFlags::COUNT_FLAG);
+        context.set_hook_count(1);
+    }
+    Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&thread, "test", "let x = 1 in x #Int+ 2")
+        .unwrap();
+
+    let all_instructions = indexes.lock().unwrap().clone();
+    assert_eq!(all_instructions, (0..all_instructions.len()).collect::<Vec<_>>());
+
+    indexes.lock().unwrap().clear();
+    thread.context().set_hook_count(2);
+    Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&thread, "test", "let x = 1 in x #Int+ 2")
+        .unwrap();
+    assert_eq!(indexes.lock().unwrap().len(), all_instructions.len() / 2);
+}
+
 fn run_line_hook_test(source: &str) -> Vec<Line> {
     let thread = new_vm();
     {
@@ -471,3 +549,93 @@ fn implicit_prelude_variable_names() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn debugger_breakpoint_and_step() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let paused = Arc::new(Mutex::new(Vec::new()));
+    let debugger = {
+        let paused = paused.clone();
+        Debugger::new(move |_, debug_context| {
+            let stack_info = debug_context.stack_info(0).unwrap();
+            // Only the integer locals are checked, the top level also has `f` in scope
+            let locals = stack_info
+                .locals()
+                .filter_map(|local| match stack_info.local_value(local).as_ref() {
+                    ValueRef::Int(i) => Some((local.name.declared_name().to_string(), i)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            paused
+                .lock()
+                .unwrap()
+                .push((stack_info.line().unwrap(), locals));
+            Step::Over
+        })
+    };
+    debugger.set_breakpoint("test", Line::from(2));
+    debugger.attach(&thread);
+
+    let source = r#"let f x =
+    let y = x #Int+ 1
+    y
+let z = f 1
+z
+"#;
+    let (result, _) = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&thread, "test", source)
+        .unwrap();
+    assert_eq!(result, 2);
+
+    // Stepping over from the breakpoint pauses again when `f` has returned
+    let paused = paused.lock().unwrap();
+    assert_eq!(
+        paused[0],
+        (
+            Line::from(2),
+            vec![("x".to_string(), 1), ("y".to_string(), 2)]
+        )
+    );
+    assert_eq!(paused.len(), 2);
+    assert_eq!(paused[1].0, Line::from(4));
+}
+
+#[test]
+fn profiler_samples_call_stacks() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let profiler = Profiler::new(Duration::from_millis(0));
+    profiler.attach(&thread);
+
+    let source = r#"
+let spin n =
+    if n #Int== 0 then 0
+    else spin (n #Int- 1)
+spin 100000
+"#;
+    Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<i32>(&thread, "test", source)
+        .unwrap();
+
+    let profile = profiler.profile();
+    assert!(profile.samples() > 0);
+    assert_eq!(profile.functions()[0].0.declared_name(), "spin");
+
+    let mut folded = Vec::new();
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    // `spin` is tail called so it replaces the frame of the top level expression
+    assert!(
+        folded.lines().any(|line| {
+            let stack = line.rsplitn(2, ' ').nth(1).unwrap();
+            stack.ends_with("spin")
+        }),
+        "{}",
+        folded
+    );
+}
@@ -98,3 +98,39 @@ fn undefined_infix() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn type_errors_are_reported_after_syntax_errors() {
+    let _ = ::env_logger::try_init();
+
+    let text = r#"
+let x =
+let y : Int = "abc"
+y
+"#;
+
+    let vm = support::make_vm();
+    let result = Compiler::new().typecheck_str(&vm, "test", text, None);
+    match result {
+        Err(Error::Multiple(errors)) => {
+            let errors_string = errors.to_string();
+            let errors: Vec<_> = errors.into_iter().collect();
+            assert!(
+                errors.iter().any(|err| match *err {
+                    Error::Parse(_) => true,
+                    _ => false,
+                }),
+                errors_string
+            );
+            assert!(
+                errors.iter().any(|err| match *err {
+                    Error::Typecheck(_) => true,
+                    _ => false,
+                }),
+                errors_string
+            );
+        }
+        Err(err) => panic!("Expected syntax and type errors, got: {}", err),
+        Ok(_) => panic!("Expected syntax and type errors"),
+    }
+}
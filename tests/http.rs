@@ -1,4 +1,12 @@
 extern crate curl;
+#[cfg(feature = "http")]
+extern crate futures;
+#[cfg(feature = "http")]
+extern crate gluon;
+#[cfg(feature = "http")]
+extern crate hyper;
+#[cfg(feature = "http")]
+extern crate tokio_core;
 
 use std::cmp;
 use std::io::Write;
@@ -71,3 +79,66 @@ fn echo() {
     assert_eq!(easy.response_code(), Ok(200));
     assert_eq!(result, Ok("test message".to_string()));
 }
+
+#[cfg(feature = "http")]
+#[test]
+fn std_http_server() {
+    use std::str;
+
+    use futures::{Future, Stream};
+    use gluon::vm::api::{OwnedFunction, IO};
+    use gluon::{Compiler, VmBuilder};
+    use hyper::Client;
+    use tokio_core::reactor::Core;
+
+    let mut core = Core::new().unwrap();
+    let thread = VmBuilder::new().event_loop(Some(core.remote())).build();
+
+    let text = r#"
+        let server = import! std.http.server
+        let io = import! std.io
+
+        let handler request : server.Request -> IO server.Response =
+            if request.uri == "/echo" then
+                io.applicative.wrap (server.response 200 request.body)
+            else
+                io.applicative.wrap (server.ok "Hello World")
+
+        \addr -> server.listen addr handler
+    "#;
+    let (mut listen, _) = Compiler::new()
+        .run_expr::<OwnedFunction<fn(String) -> IO<()>>>(&thread, "http_server", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let port = 2347;
+    core.handle().spawn(
+        listen
+            .call_async(format!("127.0.0.1:{}", port))
+            .map(|_| ())
+            .map_err(|err| panic!("{}", err)),
+    );
+
+    let handle = core.handle();
+    let body = core
+        .run(
+            Client::new(&handle)
+                .get(format!("http://localhost:{}", port).parse().unwrap())
+                .and_then(|response| response.body().concat2()),
+        )
+        .unwrap();
+    assert_eq!(str::from_utf8(&body).unwrap(), "Hello World");
+
+    let mut request = hyper::Request::new(
+        hyper::Method::Post,
+        format!("http://localhost:{}/echo", port).parse().unwrap(),
+    );
+    request.set_body(hyper::Body::from("test"));
+    let body = core
+        .run(
+            Client::new(&handle)
+                .request(request)
+                .and_then(|response| response.body().concat2()),
+        )
+        .unwrap();
+    assert_eq!(str::from_utf8(&body).unwrap(), "test");
+}
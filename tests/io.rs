@@ -43,6 +43,190 @@ fn read_file() {
     }
 }
 
+#[test]
+fn write_and_read_back_file() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let fs = import! std.fs
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+        let { flat_map } = import! std.monad
+
+        let dir = fs.path.join "target" "fs_test"
+        let file = fs.path.join dir "test.txt"
+
+        do _ = fs.create_dir_all dir
+        do _ = fs.write_string file "abc"
+        do _ = fs.append_file file [100b]
+        do contents = fs.read_file_to_string file
+        do metadata = fs.metadata file
+        do _ = fs.remove_dir_all dir
+        do exists = fs.exists file
+        wrap (contents, metadata.len, exists)
+        "#;
+    let result = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<(String, i64, bool)>>(&thread, "<top>", text);
+
+    match result {
+        Ok((IO::Value(value), _)) => assert_eq!(value, ("abcd".to_string(), 4, false)),
+        Ok((IO::Exception(err), _)) => assert!(false, "{}", err),
+        Err(err) => assert!(false, "{}", err),
+    }
+}
+
+#[cfg(all(unix, feature = "process"))]
+#[test]
+fn process_output_with_stdin() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let process = import! std.process
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+        let { flat_map } = import! std.monad
+
+        do output = process.output_with_stdin "cat" [] "abc"
+        do status = process.status "sh" ["-c", "exit 3"]
+        wrap (output.stdout, output.status, status)
+        "#;
+    let result = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<(String, Option<i32>, Option<i32>)>>(&thread, "<top>", text);
+
+    match result {
+        Ok((IO::Value(value), _)) => assert_eq!(value, ("abc".to_string(), Some(0), Some(3))),
+        Ok((IO::Exception(err), _)) => assert!(false, "{}", err),
+        Err(err) => assert!(false, "{}", err),
+    }
+}
+
+#[cfg(feature = "net")]
+const NET_ROUNDTRIP: &str = r#"
+    let { tcp, udp } = import! std.net
+    let string = import! std.string
+    let io @ { ? } = import! std.io
+    let { wrap } = io.applicative
+    let { flat_map } = import! std.monad
+
+    do listener = tcp.bind "127.0.0.1:0"
+    do addr = tcp.listener_addr listener
+    do client = tcp.connect addr
+    do server = tcp.accept listener
+    do _ = tcp.write client (string.as_bytes "ping")
+    do tcp_bytes = tcp.read server 4
+
+    do receiver = udp.bind "127.0.0.1:0"
+    do receiver_addr = udp.local_addr receiver
+    do sender = udp.bind "127.0.0.1:0"
+    do _ = udp.send_to sender (string.as_bytes "pong") receiver_addr
+    do datagram = udp.recv_from receiver 16
+
+    wrap (string.from_utf8 tcp_bytes, string.from_utf8 datagram.data)
+"#;
+
+#[cfg(feature = "net")]
+#[test]
+fn tcp_and_udp_roundtrip() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let result = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<(Result<String, ()>, Result<String, ()>)>>(&thread, "<top>", NET_ROUNDTRIP);
+
+    match result {
+        Ok((IO::Value(value), _)) => {
+            assert_eq!(value, (Ok("ping".to_string()), Ok("pong".to_string())))
+        }
+        Ok((IO::Exception(err), _)) => assert!(false, "{}", err),
+        Err(err) => assert!(false, "{}", err),
+    }
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn tcp_and_udp_roundtrip_on_event_loop() {
+    let _ = ::env_logger::try_init();
+
+    let mut core = self::tokio_core::reactor::Core::new().unwrap();
+    let vm = make_async_vm(Some(core.remote()));
+    let (result, _) = core.run(
+        Compiler::new()
+            .run_io(true)
+            .run_expr_async::<IO<(Result<String, ()>, Result<String, ()>)>>(
+                &vm,
+                "<top>",
+                NET_ROUNDTRIP,
+            ),
+    ).unwrap_or_else(|err| panic!("{}", err));
+
+    match result {
+        IO::Value(value) => assert_eq!(value, (Ok("ping".to_string()), Ok("pong".to_string()))),
+        IO::Exception(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn read_file_with_a_negative_count_is_an_error() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let fs = import! std.fs
+        let io @ { ? } = import! std.io
+        let { flat_map } = import! std.monad
+
+        do file = fs.open_file "Cargo.toml"
+        fs.read_file file (-1)
+        "#;
+    let result = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<OpaqueValue<&Thread, Hole>>>(&thread, "<top>", text);
+
+    match result {
+        Ok((IO::Exception(err), _)) => assert!(err.contains("negative"), "{}", err),
+        Ok((IO::Value(_), _)) => assert!(false, "Expected an exception"),
+        Err(err) => assert!(err.to_string().contains("negative"), "{}", err),
+    }
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn thread_rng_empty_range_is_an_io_error() {
+    let _ = ::env_logger::try_init();
+
+    let thread = new_vm();
+    let text = r#"
+        let random = import! std.random
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+
+        let int_range = io.functor.map (\_ -> "") (random.thread_rng.gen_int_range 5 5)
+        let float_range = io.functor.map (\_ -> "") (random.thread_rng.gen_float_range 1.0 0.0)
+        let { flat_map } = import! std.monad
+        do int_err = io.catch int_range wrap
+        do float_err = io.catch float_range wrap
+        wrap (int_err, float_err)
+        "#;
+    let result = Compiler::new()
+        .run_io(true)
+        .run_expr::<IO<(String, String)>>(&thread, "<top>", text);
+
+    match result {
+        // The caught message is followed by a stacktrace
+        Ok((IO::Value((int_err, float_err)), _)) => assert_eq!(
+            (int_err.lines().next(), float_err.lines().next()),
+            (Some("Empty range 5..5"), Some("Empty range 1..0"))
+        ),
+        Ok((IO::Exception(err), _)) => assert!(false, "{}", err),
+        Err(err) => assert!(false, "{}", err),
+    }
+}
+
 test_expr!{ no_io_eval,
 r#"
 let { error } = import! std.prim
@@ -1,12 +1,20 @@
 extern crate env_logger;
+extern crate futures;
 extern crate gluon;
 
 mod support;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::executor::{self, Notify};
+use futures::Async;
+
 use gluon::vm::api::{Hole, OpaqueValue};
 use gluon::vm::thread::ThreadInternal;
 use gluon::vm::Error as VMError;
-use gluon::{Compiler, Error, Thread};
+use gluon::{Compiler, Error, Thread, VmBuilder};
 
 use support::make_vm;
 
@@ -30,6 +38,34 @@ fn out_of_memory() {
     }
 }
 
+#[test]
+fn memory_limit_is_inherited_by_child_threads() {
+    let _ = ::env_logger::try_init();
+
+    let vm = VmBuilder::new()
+        .import_paths(Some(vec![".".into()]))
+        .memory_limit(Some(1_000_000))
+        .build();
+    let child = vm.new_thread().unwrap();
+
+    let expr = r#"
+        type List = | Cons Int List | Nil
+        let grow xs = grow (Cons 1 xs)
+        grow Nil
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&child, "example", expr);
+
+    match result {
+        Err(Error::VM(VMError::OutOfMemory {
+            limit: 1_000_000, ..
+        })) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
 #[test]
 fn stack_overflow() {
     let _ = ::env_logger::try_init();
@@ -48,3 +84,181 @@ fn stack_overflow() {
         Ok(_) => panic!("Expected an error"),
     }
 }
+
+#[test]
+fn deep_recursion_overflows_the_stack() {
+    let _ = ::env_logger::try_init();
+
+    let vm = VmBuilder::new()
+        .import_paths(Some(vec![".".into()]))
+        .max_stack_size(Some(10_000))
+        .build();
+    let child = vm.new_thread().unwrap();
+
+    let expr = r#"
+        let sum n = if n #Int== 0 then 0 else n #Int+ sum (n #Int- 1)
+        sum 100000
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&child, "example", expr);
+
+    match result {
+        Err(Error::VM(VMError::StackOverflow(10_000))) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn mutually_recursive_tail_calls_do_not_overflow_the_stack() {
+    let _ = ::env_logger::try_init();
+
+    let vm = VmBuilder::new()
+        .import_paths(Some(vec![".".into()]))
+        .max_stack_size(Some(10_000))
+        .build();
+    let child = vm.new_thread().unwrap();
+
+    let expr = r#"
+        let { Bool } = import! std.types
+        let is_even n = if n #Int== 0 then True else is_odd (n #Int- 1)
+        and is_odd n = if n #Int== 0 then False else is_even (n #Int- 1)
+        is_odd 100001
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<bool>(&child, "example", expr);
+
+    match result {
+        Ok((true, _)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected `True`"),
+    }
+}
+
+#[test]
+fn out_of_fuel() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.context().set_fuel(Some(1000));
+
+    let expr = r#"
+        let loop x = loop (x #Int+ 1)
+        loop 0
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "example", expr);
+
+    match result {
+        Err(Error::VM(VMError::OutOfFuel)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+    assert_eq!(vm.context().fuel(), Some(0));
+
+    // Refueling lets the same loop continue where it stopped
+    vm.context().set_fuel(Some(1000));
+    match vm.resume() {
+        Err(VMError::OutOfFuel) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    };
+}
+
+#[test]
+fn yield_outside_of_a_task() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.context().set_fuel(Some(100));
+
+    let expr = r#"
+        let loop x = loop (x #Int+ 1)
+        loop 0
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<OpaqueValue<&Thread, Hole>>(&vm, "example", expr);
+    match result {
+        Err(Error::VM(VMError::OutOfFuel)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    // Resuming is not done from a futures task so yielding must not try to notify one
+    vm.context().set_fuel(None);
+    vm.context().set_yield_interval(Some(10));
+    match vm.resume() {
+        Ok(Async::NotReady) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(Async::Ready(_)) => panic!("Expected the thread to yield"),
+    };
+}
+
+#[test]
+fn timeout() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    let expr = r#"
+        let loop x = loop (x #Int+ 1)
+        loop 0
+    "#;
+    let result = Compiler::new()
+        .implicit_prelude(false)
+        .run_expr_with_timeout::<OpaqueValue<&Thread, Hole>>(
+            &vm,
+            "example",
+            expr,
+            Duration::from_millis(50),
+        );
+
+    match result {
+        Err(Error::VM(VMError::TimedOut)) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+    assert_eq!(vm.context().deadline(), None);
+}
+
+#[test]
+fn yield_interval() {
+    let _ = ::env_logger::try_init();
+
+    struct CountNotify(AtomicUsize);
+
+    impl Notify for CountNotify {
+        fn notify(&self, _: usize) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let vm = make_vm();
+    vm.context().set_yield_interval(Some(10));
+
+    let expr = r#"
+        let loop n = if n #Int== 0 then 0 else loop (n #Int- 1)
+        loop 100
+    "#;
+    let mut task = executor::spawn(
+        Compiler::new()
+            .implicit_prelude(false)
+            .run_expr_async::<i32>(&vm, "example", expr),
+    );
+
+    let notify = Arc::new(CountNotify(AtomicUsize::new(0)));
+    let result = loop {
+        match task.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready((value, _))) => break value,
+            Ok(Async::NotReady) => (),
+            Err(err) => panic!("{}", err),
+        }
+    };
+    assert_eq!(result, 0);
+    // The thread should have yielded roughly once every 10 calls
+    assert!(notify.0.load(Ordering::SeqCst) >= 5);
+}
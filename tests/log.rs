@@ -0,0 +1,82 @@
+extern crate gluon;
+extern crate log;
+
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use gluon::{new_vm, Compiler};
+
+/// A log record with the parts which `std.log` sets
+#[derive(Debug, PartialEq)]
+struct Logged {
+    level: Level,
+    target: String,
+    line: Option<u32>,
+    message: String,
+}
+
+struct TestLogger(Mutex<Vec<Logged>>);
+
+impl Log for TestLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.lock().unwrap().push(Logged {
+            level: record.level(),
+            target: record.target().to_string(),
+            line: record.line(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn log_to_rust_logger() {
+    let logger: &'static TestLogger = Box::leak(Box::new(TestLogger(Mutex::new(Vec::new()))));
+    log::set_logger(logger).unwrap();
+    log::set_max_level(LevelFilter::Info);
+
+    let text = r#"
+let log = import! std.log
+log.debug "ignored"
+log.info "hello"
+log.error "world"
+()
+"#;
+    let vm = new_vm();
+    Compiler::new()
+        .implicit_prelude(false)
+        .run_expr::<()>(&vm, "test_log", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    // Only check the records from the script as the compiler may log records of its own
+    let logged: Vec<_> = logger
+        .0
+        .lock()
+        .unwrap()
+        .drain(..)
+        .filter(|logged| logged.target == "test_log")
+        .collect();
+    assert_eq!(
+        logged,
+        vec![
+            Logged {
+                level: Level::Info,
+                target: "test_log".to_string(),
+                line: Some(4),
+                message: "hello".to_string(),
+            },
+            Logged {
+                level: Level::Error,
+                target: "test_log".to_string(),
+                line: Some(5),
+                message: "world".to_string(),
+            },
+        ]
+    );
+}
@@ -33,3 +33,25 @@ let { List, of }  = import! std.list
     assert!(env.get_metadata("test.of").is_ok());
     assert!(env.get_metadata("test.List").is_ok());
 }
+
+#[test]
+fn comment_from_gluon() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+    let text = r#"
+let doc = import! std.doc
+let list = import! std.list
+(doc.comment "std.list.of", doc.comment "std.list.missing")
+"#;
+    let ((of_comment, missing_comment), _) = Compiler::new()
+        .run_expr::<(Option<String>, Option<String>)>(&vm, "test", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let of_comment = of_comment.expect("Comment of `std.list.of`");
+    assert!(
+        of_comment.starts_with("Constructs a list from an array"),
+        "{}",
+        of_comment
+    );
+    assert_eq!(missing_comment, None);
+}
@@ -7,6 +7,7 @@ use std::thread::spawn;
 use gluon::vm::api::FunctionRef;
 use gluon::vm::api::OpaqueValue;
 use gluon::vm::channel::{ChannelRecord, Receiver, Sender};
+use gluon::vm::sync::{AtomicInt, Mutex};
 use gluon::RootedThread;
 use gluon::{new_vm, Compiler, Error};
 
@@ -81,3 +82,233 @@ fn parallel_() -> Result<(), Error> {
     handle1.join().unwrap()?;
     handle2.join().unwrap()
 }
+
+#[test]
+fn recv_wait_blocks_until_a_value_is_sent() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    // Load the module so that the channel types are known before the first expression is checked
+    compiler
+        .run_expr::<()>(&vm, "<top>", " let _ = import! std.channel in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (value, _) = compiler
+        .run_expr(
+            &vm,
+            "<top>",
+            " let { channel } = import! std.channel in channel 0 ",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let record_p!{ sender, receiver }: ChannelRecord<
+        OpaqueValue<RootedThread, Sender<i32>>,
+        OpaqueValue<RootedThread, Receiver<i32>>,
+    > = value;
+
+    let child = vm.new_thread().unwrap();
+    let handle = spawn(move || -> Result<(), Error> {
+        let expr = r#"
+        let { send } = import! std.channel
+        \sender -> send sender 1
+        "#;
+        let mut compiler = Compiler::new();
+        let mut f: FunctionRef<fn(OpaqueValue<RootedThread, Sender<i32>>)> =
+            compiler.run_expr(&child, "<top>", expr)?.0;
+        Ok(f.call(sender)?)
+    });
+
+    let expr = r#"
+    let { recv_wait } = import! std.channel
+    recv_wait
+    "#;
+    let mut recv_wait: FunctionRef<fn(OpaqueValue<RootedThread, Receiver<i32>>) -> i32> =
+        compiler
+            .run_expr(&vm, "<top>", expr)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0;
+    assert_eq!(recv_wait.call(receiver.clone()), Ok(1));
+    handle.join().unwrap().unwrap_or_else(|err| panic!("{}", err));
+
+    let expr = r#"
+    let { recv_timeout } = import! std.channel
+    \receiver -> recv_timeout receiver 10
+    "#;
+    let mut recv_timeout: FunctionRef<
+        fn(OpaqueValue<RootedThread, Receiver<i32>>) -> Result<i32, ()>,
+    > = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    assert_eq!(recv_timeout.call(receiver), Ok(Err(())));
+}
+
+#[test]
+fn recv_wait_without_other_threads_is_an_error() {
+    let vm = new_vm();
+
+    let expr = r#"
+    let { channel, send, recv_wait } = import! std.channel
+    let { sender, receiver } = channel 0
+    let x = recv_wait receiver
+    send sender 1
+    x
+    "#;
+    let result = Compiler::new().run_expr::<i32>(&vm, "<top>", expr);
+    match result {
+        Ok(_) => panic!("Expected recv_wait to fail"),
+        Err(err) => {
+            let err = err.to_string();
+            assert!(err.contains("recv_wait would block forever"), "{}", err);
+        }
+    }
+}
+
+#[test]
+fn sync_primitives_shared_between_threads() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    // Load the module so that the sync types are known before the first expression is checked
+    compiler
+        .run_expr::<()>(&vm, "<top>", " let _ = import! std.sync in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expr = r#"
+    let { mutex, atomic_int } = import! std.sync
+    (mutex.new 0, atomic_int.new 0)
+    "#;
+    let ((counter, hits), _): (
+        (
+            OpaqueValue<RootedThread, Mutex<i32>>,
+            OpaqueValue<RootedThread, AtomicInt>,
+        ),
+        _,
+    ) = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let child = vm.new_thread().unwrap();
+            let (counter, hits) = (counter.clone(), hits.clone());
+            spawn(move || -> Result<(), Error> {
+                let expr = r#"
+                let { mutex, atomic_int } = import! std.sync
+                let go counter hits n =
+                    if n == 0 then ()
+                    else
+                        mutex.modify counter (\x -> x + 1)
+                        atomic_int.fetch_add hits 1
+                        go counter hits (n - 1)
+                \counter hits -> go counter hits 100
+                "#;
+                let mut compiler = Compiler::new();
+                let mut f: FunctionRef<
+                    fn(
+                        OpaqueValue<RootedThread, Mutex<i32>>,
+                        OpaqueValue<RootedThread, AtomicInt>,
+                    ),
+                > = compiler.run_expr(&child, "<top>", expr)?.0;
+                Ok(f.call(counter, hits)?)
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap().unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    let expr = r#"
+    let { mutex, atomic_int } = import! std.sync
+    \counter hits -> mutex.read counter + atomic_int.load hits
+    "#;
+    let mut total: FunctionRef<
+        fn(OpaqueValue<RootedThread, Mutex<i32>>, OpaqueValue<RootedThread, AtomicInt>) -> i32,
+    > = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    assert_eq!(total.call(counter, hits), Ok(800));
+}
+
+#[test]
+fn mutex_values_outlive_the_thread_which_stored_them() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    compiler
+        .run_expr::<()>(&vm, "<top>", " let _ = import! std.sync in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expr = r#"
+    let { mutex } = import! std.sync
+    mutex.new ""
+    "#;
+    let (lock, _): (OpaqueValue<RootedThread, Mutex<String>>, _) = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    {
+        let child = vm.new_thread().unwrap();
+        let expr = r#"
+        let { mutex } = import! std.sync
+        let string = import! std.string
+        \lock -> mutex.modify lock (\x -> string.append x "abc")
+        "#;
+        let mut append: FunctionRef<fn(OpaqueValue<RootedThread, Mutex<String>>)> = Compiler::new()
+            .run_expr(&child, "<top>", expr)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .0;
+        append
+            .call(lock.clone())
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    let expr = r#"
+    let { mutex } = import! std.sync
+    \lock -> mutex.read lock
+    "#;
+    let mut get: FunctionRef<fn(OpaqueValue<RootedThread, Mutex<String>>) -> String> = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    assert_eq!(get.call(lock), Ok("abc".to_string()));
+}
+
+#[test]
+fn mutex_is_released_when_modify_fails() {
+    let vm = new_vm();
+    let mut compiler = Compiler::new();
+
+    compiler
+        .run_expr::<()>(&vm, "<top>", " let _ = import! std.sync in () ")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let expr = r#"
+    let { mutex } = import! std.sync
+    mutex.new 1
+    "#;
+    let (lock, _): (OpaqueValue<RootedThread, Mutex<i32>>, _) = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let child = vm.new_thread().unwrap();
+    let lock2 = lock.clone();
+    let handle = spawn(move || -> Result<(), Error> {
+        let expr = r#"
+        let { mutex } = import! std.sync
+        \lock -> mutex.modify lock (\_ -> error "fail")
+        "#;
+        let mut fail: FunctionRef<fn(OpaqueValue<RootedThread, Mutex<i32>>)> =
+            Compiler::new().run_expr(&child, "<top>", expr)?.0;
+        Ok(fail.call(lock2)?)
+    });
+    assert!(handle.join().unwrap().is_err());
+
+    let expr = r#"
+    let { mutex } = import! std.sync
+    \lock ->
+        mutex.modify lock (\x -> x + 1)
+        mutex.read lock
+    "#;
+    let mut increment: FunctionRef<fn(OpaqueValue<RootedThread, Mutex<i32>>) -> i32> = compiler
+        .run_expr(&vm, "<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .0;
+    assert_eq!(increment.call(lock), Ok(2));
+}
@@ -22,13 +22,66 @@ match True with
 fn non_exhaustive_pattern() {
     let _ = ::env_logger::try_init();
     let text = r"
-type AB = | A | B in
-match A with
+type AB = | A Int | B in
+match A 1 with
 | B -> True
 ";
     let mut vm = make_vm();
     let result = Compiler::new().run_expr::<bool>(&mut vm, "<top>", text);
-    assert!(result.is_err());
+    match result {
+        Err(err) => {
+            let err = err.to_string();
+            assert!(
+                err.contains(
+                    "Unmatched pattern in `<top>` for a value of type `<top>.AB` at Line: 3, \
+                     Column: 7: A 1"
+                ),
+                "{}",
+                err
+            );
+        }
+        Ok(_) => panic!("Expected an unmatched pattern error"),
+    }
+}
+
+#[test]
+fn non_exhaustive_pattern_warning() {
+    let _ = ::env_logger::try_init();
+    let text = r"
+type AB = | A | B in
+match B with
+| B -> True
+";
+    let mut vm = make_vm();
+    let mut compiler = Compiler::new();
+    let result = compiler.run_expr::<bool>(&mut vm, "<top>", text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let warnings = compiler.take_warnings().expect("Non-exhaustive match warning");
+    assert!(
+        warnings.to_string().contains("Non-exhaustive match"),
+        "{}",
+        warnings
+    );
+    assert!(compiler.take_warnings().is_none());
+}
+
+#[test]
+fn warnings_are_replaced_by_the_next_expression() {
+    let _ = ::env_logger::try_init();
+    let text = r"
+type AB = | A | B in
+match B with
+| B -> True
+";
+    let mut vm = make_vm();
+    let mut compiler = Compiler::new();
+    let result = compiler.run_expr::<bool>(&mut vm, "<top>", text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let result = compiler.run_expr::<bool>(&mut vm, "<top>", "True");
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    assert!(compiler.take_warnings().is_none());
 }
 
 test_expr!{ match_record_pattern,
@@ -188,3 +241,20 @@ match (Some 10, 1) with
 "#,
 10
 }
+
+test_expr!{ match_on_open_variant,
+r#"
+type Base r = | A Int | ..r
+type Ext r = | B String | ..Base r
+let get_a : Base r -> Int = \x ->
+    match x with
+    | A i -> i
+    | _ -> 0
+let get_ext : Ext r -> Int = \x ->
+    match x with
+    | B _ -> 1
+    | _ -> get_a x
+get_a (A 10) #Int+ get_ext (A 20) #Int+ get_ext (B "")
+"#,
+31
+}
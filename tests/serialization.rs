@@ -200,3 +200,67 @@ fn roundtrip_thread() {
         .unwrap_or_else(|err| panic!("{}", err));
     roundtrip(&thread, &value);
 }
+
+#[test]
+fn load_bytecode_as_module() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let mut buffer = Vec::new();
+    {
+        let mut serializer = serde_json::Serializer::new(&mut buffer);
+        Compiler::new()
+            .compile_to_bytecode(&thread, "cached", "let x = 1 in { x, y = \"abc\" }", &mut serializer)
+            .unwrap()
+    }
+
+    let thread2 = new_vm();
+    {
+        let mut deserializer = serde_json::Deserializer::from_slice(&buffer);
+        Compiler::new()
+            .load_bytecode(&thread2, "cached", &mut deserializer)
+            .wait()
+            .unwrap();
+    }
+    let (result, _) = Compiler::new()
+        .run_expr::<(i32, String)>(
+            &thread2,
+            "test",
+            "let { x, y } = import! cached in (x, y)",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, (1, "abc".to_string()));
+}
+
+#[test]
+fn reject_bytecode_from_other_versions() {
+    use gluon::compiler_pipeline::BYTECODE_VERSION;
+
+    let thread = new_vm();
+    let mut buffer = Vec::new();
+    {
+        let mut serializer = serde_json::Serializer::new(&mut buffer);
+        Compiler::new()
+            .compile_to_bytecode(&thread, "cached", "1", &mut serializer)
+            .unwrap()
+    }
+    let mut json: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+    json[0] = (BYTECODE_VERSION + 1).into();
+    let buffer = serde_json::to_vec(&json).unwrap();
+
+    let mut deserializer = serde_json::Deserializer::from_slice(&buffer);
+    let result = Compiler::new()
+        .load_bytecode(&thread, "cached", &mut deserializer)
+        .wait();
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("bytecode version"), "{}", err);
+
+    // The module of an unsupported version is never read so it does not need to be valid
+    let buffer = format!("[{}, null]", BYTECODE_VERSION + 1);
+    let mut deserializer = serde_json::Deserializer::from_str(&buffer);
+    let result = Compiler::new()
+        .load_bytecode(&thread, "cached", &mut deserializer)
+        .wait();
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("bytecode version"), "{}", err);
+}
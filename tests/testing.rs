@@ -0,0 +1,50 @@
+extern crate env_logger;
+extern crate gluon;
+
+use std::fs::{self, File};
+use std::io::Write;
+
+use gluon::new_vm;
+use gluon::testing::run_test_files;
+
+#[test]
+fn run_test_file_with_passing_and_failing_tests() {
+    let _ = ::env_logger::try_init();
+
+    let text = r#"
+let { assert_eq, expect, test, group, ? } = import! std.test
+let { (<|) } = import! std.function
+let { ? } = import! std.int
+
+group "numbers" [
+    test "pass" <| \_ -> assert_eq 1 1,
+    test "fail" <| \_ -> assert_eq 1 2,
+    test "expect" <| \_ -> expect "not positive" (0 > 1),
+]
+"#;
+    fs::create_dir_all("target/testing").unwrap();
+    let filename = "target/testing/numbers.glu";
+    File::create(filename)
+        .unwrap()
+        .write_all(text.as_bytes())
+        .unwrap();
+
+    let vm = new_vm();
+    let report = run_test_files(&vm, &[filename]).unwrap_or_else(|err| panic!("{}", err));
+
+    assert_eq!(report.passed(), 1);
+    assert_eq!(report.failed(), 2);
+    let names: Vec<_> = report.results.iter().map(|result| &result.name[..]).collect();
+    assert_eq!(
+        names,
+        [
+            "target/testing/numbers.glu / numbers / pass",
+            "target/testing/numbers.glu / numbers / fail",
+            "target/testing/numbers.glu / numbers / expect",
+        ]
+    );
+    let error = report.results[1].error.as_ref().unwrap();
+    assert!(error.contains("- 1\n+ 2"), "{}", error);
+    let error = report.results[2].error.as_ref().unwrap();
+    assert!(error.contains("Expectation failed: not positive"), "{}", error);
+}
@@ -11,6 +11,7 @@ use base::types::{arg_iter, ArcType, BuiltinType, Type, TypeEnv};
 
 use api::{Getable, ValueRef, VmType};
 use thread::{RootedThread, RootedValue, Thread, ThreadInternal};
+use types::variant_constructor;
 use {Error as VmError, Result, Variants};
 
 use serde::de::{
@@ -565,7 +566,7 @@ impl<'de, 't, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de, 't> {
                     values.iter().map(|variant| (variant, &args[0])),
                 )),
             (ValueRef::Data(data), &Type::Variant(ref row)) => {
-                match row.row_iter().nth(data.tag() as usize) {
+                match variant_constructor(row, data.tag()) {
                     Some(field) => {
                         let iter = (0..data.len())
                             .map(|i| data.get_variant(i).unwrap())
@@ -778,9 +779,7 @@ impl<'a, 'b, 'de, 't> de::Deserializer<'de> for &'b mut Enum<'a, 'de, 't> {
         let typ = resolve::remove_aliases_cow(self.de.state.env, self.de.typ);
         match **typ {
             Type::Variant(ref variants) => {
-                let variant = variants
-                    .row_iter()
-                    .nth(tag as usize)
+                let variant = variant_constructor(variants, tag)
                     .ok_or_else(|| Self::Error::custom("Unable to deserialize tag"))?;
                 visitor.visit_str(variant.name.as_ref())
             }
@@ -828,7 +827,7 @@ impl<'de, 'a, 't> VariantAccess<'de> for Enum<'a, 'de, 't> {
         let typ = resolve::remove_aliases_cow(self.de.state.env, self.de.typ);
         match (self.de.input.as_ref(), &**typ) {
             (ValueRef::Data(data), &Type::Variant(ref row)) => {
-                match row.row_iter().nth(data.tag() as usize) {
+                match variant_constructor(row, data.tag()) {
                     Some(field) => seed.deserialize(&mut Deserializer {
                         input: data.get_variant(0).ok_or_else(|| {
                             VmError::Message("Expected variant to have a value argument".into())
@@ -0,0 +1,169 @@
+//! Conversions between `serde_json::Value` and the `std.json.Value` type
+
+use std::result::Result as StdResult;
+
+use serde_json::{Number, Value as JsonValue};
+
+use api::{Getable, MarshalError, Pushable, ValueRef, VmType};
+use base::types::ArcType;
+use thread::{Context, Thread};
+use types::VmTag;
+use value::{Value, ValueRepr};
+use {Result, Variants};
+
+const NULL: VmTag = 0;
+const BOOL: VmTag = 1;
+const INT: VmTag = 2;
+const FLOAT: VmTag = 3;
+const STRING: VmTag = 4;
+const ARRAY: VmTag = 5;
+const OBJECT: VmTag = 6;
+
+impl VmType for JsonValue {
+    type Type = Self;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        vm.find_type_info("std.json.Value")
+            .unwrap_or_else(|_| {
+                panic!("Could not find type 'std.json.Value'. Is `std.json` imported?")
+            })
+            .into_type()
+    }
+}
+
+impl<'vm> Pushable<'vm> for JsonValue {
+    fn push(self, thread: &'vm Thread, context: &mut Context) -> Result<()> {
+        let (tag, arg) = match self {
+            JsonValue::Null => {
+                context.stack.push(ValueRepr::Tag(NULL));
+                return Ok(());
+            }
+            JsonValue::Bool(b) => (BOOL, push_pop(b, thread, context)?),
+            JsonValue::Number(n) => match n.as_i64() {
+                Some(i) => (INT, push_pop(i, thread, context)?),
+                None => (
+                    FLOAT,
+                    push_pop(n.as_f64().unwrap_or(0.), thread, context)?,
+                ),
+            },
+            JsonValue::String(s) => (STRING, push_pop(s, thread, context)?),
+            JsonValue::Array(array) => (ARRAY, push_pop(array, thread, context)?),
+            JsonValue::Object(object) => {
+                let entries: Vec<_> = object.into_iter().map(|(k, v)| Entry(k, v)).collect();
+                (OBJECT, push_pop(entries, thread, context)?)
+            }
+        };
+        let value = context.new_data(thread, tag, &[arg])?;
+        context.stack.push(value);
+        Ok(())
+    }
+}
+
+impl<'vm> Getable<'vm> for JsonValue {
+    fn from_value(vm: &'vm Thread, value: Variants) -> Self {
+        Self::try_from_value(vm, value).unwrap_or_else(|err| ice!("{}", err))
+    }
+
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+        let data = match value.as_ref() {
+            ValueRef::Data(data) => data,
+            actual => return Err(MarshalError::unexpected_value("a Json value", actual)),
+        };
+        let arg = || data.get_variant(0).ok_or(MarshalError::MissingIndex(0));
+        Ok(match data.tag() {
+            NULL => JsonValue::Null,
+            BOOL => JsonValue::Bool(bool::try_from_value(vm, arg()?)?),
+            INT => JsonValue::Number(i64::try_from_value(vm, arg()?)?.into()),
+            FLOAT => Number::from_f64(f64::try_from_value(vm, arg()?)?)
+                .map(JsonValue::Number)
+                // NaN and infinities can't be represented in JSON
+                .unwrap_or(JsonValue::Null),
+            STRING => JsonValue::String(String::try_from_value(vm, arg()?)?),
+            ARRAY => JsonValue::Array(
+                array_iter(arg()?)?
+                    .map(|value| JsonValue::try_from_value(vm, value))
+                    .collect::<StdResult<_, _>>()?,
+            ),
+            OBJECT => JsonValue::Object(
+                array_iter(arg()?)?
+                    .map(|entry| {
+                        let Entry(key, value) = Entry::try_from_value(vm, entry)?;
+                        Ok((key, value))
+                    })
+                    .collect::<StdResult<_, _>>()?,
+            ),
+            tag => return Err(MarshalError::UnexpectedTag(tag)),
+        })
+    }
+}
+
+/// A `{ key : String, value : Value }` field of a JSON object
+struct Entry(String, JsonValue);
+
+impl<'vm> Pushable<'vm> for Entry {
+    fn push(self, thread: &'vm Thread, context: &mut Context) -> Result<()> {
+        let key = push_pop(self.0, thread, context)?;
+        context.stack.push(key);
+        let value = push_pop(self.1, thread, context)?;
+        let key = context.stack.pop();
+        let record = context.new_record(thread, &[key, value], &["key", "value"])?;
+        context.stack.push(record);
+        Ok(())
+    }
+}
+
+impl<'vm> Getable<'vm> for Entry {
+    fn from_value(vm: &'vm Thread, value: Variants) -> Self {
+        Self::try_from_value(vm, value).unwrap_or_else(|err| ice!("{}", err))
+    }
+
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+        let data = match value.as_ref() {
+            ValueRef::Data(data) => data,
+            actual => return Err(MarshalError::unexpected_value("an object entry", actual)),
+        };
+        let field = |name: &str| {
+            data.lookup_field(vm, name)
+                .ok_or_else(|| MarshalError::MissingField(name.to_string()))
+        };
+        Ok(Entry(
+            String::try_from_value(vm, field("key")?)?,
+            JsonValue::try_from_value(vm, field("value")?)?,
+        ))
+    }
+}
+
+fn push_pop<'vm, T>(value: T, thread: &'vm Thread, context: &mut Context) -> Result<Value>
+where
+    T: Pushable<'vm>,
+{
+    value.push(thread, context)?;
+    Ok(context.stack.pop())
+}
+
+fn array_iter(value: Variants) -> StdResult<::value::Iter, MarshalError> {
+    match value.as_ref() {
+        ValueRef::Array(array) => Ok(array.iter()),
+        actual => Err(MarshalError::unexpected_value("an Array", actual)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use thread::{RootedThread, ThreadInternal};
+
+    #[test]
+    fn json_round_trip() {
+        let thread = RootedThread::new();
+        let json: JsonValue = ::serde_json::from_str(
+            r#"{ "a": [1, 2.5, "three", null], "b": { "c": true } }"#,
+        ).unwrap();
+
+        let mut context = thread.context();
+        json.clone().push(&thread, &mut context).unwrap();
+        let value = context.stack.pop();
+        let result = JsonValue::try_from_value(&thread, unsafe { Variants::new(&value) });
+        assert_eq!(result, Ok(json));
+    }
+}
@@ -33,6 +33,26 @@ macro_rules! primitive_cast {
 
         $name as fn(_, _, _, _, _, _, _) -> _
     };
+    (8, $name:expr) => {
+
+        $name as fn(_, _, _, _, _, _, _, _) -> _
+    };
+    (9, $name:expr) => {
+
+        $name as fn(_, _, _, _, _, _, _, _, _) -> _
+    };
+    (10, $name:expr) => {
+
+        $name as fn(_, _, _, _, _, _, _, _, _, _) -> _
+    };
+    (11, $name:expr) => {
+
+        $name as fn(_, _, _, _, _, _, _, _, _, _, _) -> _
+    };
+    (12, $name:expr) => {
+
+        $name as fn(_, _, _, _, _, _, _, _, _, _, _, _) -> _
+    };
 }
 
 /// Creates a `GluonFunction` from a function implementing `VMFunction`
@@ -82,6 +102,26 @@ macro_rules! primitive {
 
         named_primitive!(7, stringify!($name), $name)
     };
+    (8 $name:expr) => {
+
+        named_primitive!(8, stringify!($name), $name)
+    };
+    (9 $name:expr) => {
+
+        named_primitive!(9, stringify!($name), $name)
+    };
+    (10 $name:expr) => {
+
+        named_primitive!(10, stringify!($name), $name)
+    };
+    (11 $name:expr) => {
+
+        named_primitive!(11, stringify!($name), $name)
+    };
+    (12 $name:expr) => {
+
+        named_primitive!(12, stringify!($name), $name)
+    };
 }
 
 #[macro_export]
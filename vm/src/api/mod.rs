@@ -1,17 +1,18 @@
 //! The marshalling api
 use base::scoped_map::ScopedMap;
 use base::symbol::{Symbol, Symbols};
-use base::types::{self, ArcType, Type};
+use base::resolve::AliasRemover;
+use base::types::{self, ArcType, BuiltinType, Type};
 use compiler::{CompiledFunction, CompiledModule};
 use future::FutureValue;
 use gc::{DataDef, Gc, GcPtr, Move, Traverseable};
 use stack::{Lock, StackFrame};
 use thread::ThreadInternal;
 use thread::{self, Context, RootedThread, VmRoot};
-use types::{Instruction, VmIndex, VmInt, VmTag};
+use types::{find_variant_constructor, variant_constructor, Instruction, VmIndex, VmInt, VmTag};
 use value::{
-    ArrayDef, ArrayRepr, Cloner, ClosureData, DataStruct, Def, ExternFunction, GcStr, Value,
-    ValueArray, ValueRepr,
+    ArrayDef, ArrayRepr, ClosureData, DataStruct, Def, ExternFunction, GcStr, Value, ValueArray,
+    ValueRepr,
 };
 use vm::{self, Root, RootStr, RootedValue, Status, Thread};
 use {forget_lifetime, Error, Result, Variants};
@@ -22,11 +23,13 @@ use std::cmp::Ordering;
 use std::fmt;
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::rc::Rc;
 use std::result::Result as StdResult;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use futures::{Async, Future};
 
-pub use value::Userdata;
+pub use value::{Cloner, Finalizer, Userdata};
 
 #[cfg(feature = "serde")]
 use serde::de::{Deserialize, Deserializer};
@@ -35,6 +38,8 @@ use serde::de::{Deserialize, Deserializer};
 pub mod mac;
 #[cfg(feature = "serde")]
 pub mod de;
+#[cfg(feature = "serde_json")]
+pub mod json;
 #[cfg(feature = "serde")]
 pub mod ser;
 #[cfg(feature = "serde")]
@@ -191,6 +196,21 @@ impl<'a> Data<'a> {
         }
     }
 
+    /// Returns the name of the constructor of this variant. `type_name` is the fully qualified
+    /// name of the variant type this value belongs to, which is used to map the tag of the value
+    /// to the constructor name.
+    ///
+    /// Returns `None` if `type_name` is not a variant type or if the tag is out of bounds.
+    pub fn constructor_name(&self, thread: &Thread, type_name: &str) -> Option<String> {
+        let alias = thread.find_type_info(type_name).ok()?;
+        let typ = alias.typ();
+        match **typ.remove_forall() {
+            Type::Variant(ref row) => variant_constructor(row, self.tag())
+                .map(|field| field.name.declared_name().to_string()),
+            _ => None,
+        }
+    }
+
     /// Retrieves the field `name` from this record.
     pub fn lookup_field(&self, thread: &Thread, name: &str) -> Option<Variants<'a>> {
         match self.0 {
@@ -206,6 +226,22 @@ impl<'a> Data<'a> {
     }
 }
 
+/// Returns the tag of the constructor named `constructor` of the variant type `type_name` (the
+/// fully qualified name of the type), the inverse of `Data::constructor_name`.
+///
+/// Returns `None` if `type_name` is not a variant type or if it has no such constructor.
+pub fn constructor_tag(thread: &Thread, type_name: &str, constructor: &str) -> Option<VmTag> {
+    let alias = thread.find_type_info(type_name).ok()?;
+    let typ = alias.typ();
+    match **typ.remove_forall() {
+        Type::Variant(ref row) => {
+            find_variant_constructor(row, |field| field.name.declared_name() == constructor)
+                .map(|(tag, _)| tag)
+        }
+        _ => None,
+    }
+}
+
 /// Marker type representing a hole
 pub struct Hole(());
 
@@ -320,6 +356,10 @@ impl<'vm, T> Getable<'vm> for Generic<T> {
     fn from_value(_: &'vm Thread, value: Variants) -> Generic<T> {
         Generic::from(value.get_value())
     }
+    // Any value can be stored in a `Generic` so this can never fail
+    fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<Generic<T>, MarshalError> {
+        Ok(Generic::from(value.get_value()))
+    }
 }
 
 impl<T> Traverseable for Generic<T> {
@@ -512,6 +552,35 @@ pub trait Pushable<'vm>: AsyncPushable<'vm> {
     }
 }
 
+quick_error! {
+    /// Error returned by `Getable::try_from_value` when a value does not have the shape that
+    /// the rust type expects
+    #[derive(Debug, PartialEq)]
+    pub enum MarshalError {
+        UnexpectedValue(expected: &'static str, actual: String) {
+            display("Expected {} but got `{}`. Do the type definitions match?", expected, actual)
+        }
+        MissingField(field: String) {
+            display("Cannot find the field `{}`. Do the type definitions match?", field)
+        }
+        MissingIndex(index: usize) {
+            display("Value does not contain data at index `{}`. Do the type definitions match?", index)
+        }
+        UnexpectedTag(tag: VmTag) {
+            display("Unexpected tag: `{}`. Do the type definitions match?", tag)
+        }
+        UnexpectedConstructor(name: String) {
+            display("Unexpected constructor: `{}`. Do the type definitions match?", name)
+        }
+    }
+}
+
+impl MarshalError {
+    pub fn unexpected_value(expected: &'static str, actual: ValueRef) -> MarshalError {
+        MarshalError::UnexpectedValue(expected, format!("{:?}", actual))
+    }
+}
+
 /// Trait which allows rust values to be retrieved from the virtual machine
 pub trait Getable<'vm>: Sized {
     /// unsafe version of from_value which allows references to the internal of GcPtr's to be
@@ -520,6 +589,15 @@ pub trait Getable<'vm>: Sized {
         Self::from_value(vm, value)
     }
     fn from_value(vm: &'vm Thread, value: Variants) -> Self;
+
+    /// Fallible version of `from_value` which returns an error instead of panicking if `value`
+    /// does not have the expected shape.
+    ///
+    /// The default implementation defers to `from_value`, implementations which can fail should
+    /// override this method.
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+        Ok(Self::from_value(vm, value))
+    }
 }
 
 pub fn convert<'vm, T, U>(thread: &'vm Thread, t: T) -> Result<U>
@@ -544,6 +622,61 @@ impl<'vm, T: vm::Userdata> Pushable<'vm> for T {
     }
 }
 
+/// Userdata which can be mutated from extern functions. Since userdata can only be borrowed
+/// immutably from the virtual machine `RwUserdata` wraps the value in a `RwLock` so that it can
+/// be accessed through `read` and `write`.
+///
+/// The wrapped value is not traversed by the garbage collector so it must not contain any values
+/// managed by the virtual machine.
+///
+/// ```rust,ignore
+/// fn increment(counter: &RwUserdata<i32>) {
+///     *counter.write() += 1;
+/// }
+/// ```
+#[derive(Default)]
+pub struct RwUserdata<T>(RwLock<T>);
+
+impl<T> RwUserdata<T> {
+    pub fn new(value: T) -> RwUserdata<T> {
+        RwUserdata(RwLock::new(value))
+    }
+
+    /// Locks the value for reading
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        self.0.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Locks the value for writing
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        self.0.write().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0.into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwUserdata<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.try_read() {
+            Ok(value) => write!(f, "RwUserdata({:?})", *value),
+            Err(_) => write!(f, "RwUserdata(<locked>)"),
+        }
+    }
+}
+
+impl<T> Traverseable for RwUserdata<T> {}
+
+impl<T> vm::Userdata for RwUserdata<T> where T: Any + fmt::Debug + Send + Sync {}
+
+impl<T> VmType for RwUserdata<T>
+where
+    T: Any + fmt::Debug + Send + Sync,
+{
+    type Type = Self;
+}
+
 impl<'vm> Getable<'vm> for Value {
     fn from_value(_vm: &'vm Thread, value: Variants) -> Self {
         value.get_value()
@@ -674,6 +807,12 @@ impl<'vm> Getable<'vm> for u8 {
             _ => ice!("ValueRef is not a Byte"),
         }
     }
+    fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<u8, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Byte(i) => Ok(i),
+            actual => Err(MarshalError::unexpected_value("a Byte", actual)),
+        }
+    }
 }
 
 macro_rules! int_impls {
@@ -695,6 +834,12 @@ macro_rules! int_impls {
                     _ => ice!("expected ValueRef to be an Int, got {:?}", value.as_ref()),
                 }
             }
+            fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+                match value.as_ref() {
+                    ValueRef::Int(i) => Ok(i as $id),
+                    actual => Err(MarshalError::unexpected_value("an Int", actual)),
+                }
+            }
         }
         )*
     };
@@ -718,6 +863,12 @@ impl<'vm> Getable<'vm> for f64 {
             _ => ice!("ValueRef is not a Float"),
         }
     }
+    fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<f64, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Float(f) => Ok(f),
+            actual => Err(MarshalError::unexpected_value("a Float", actual)),
+        }
+    }
 }
 impl VmType for bool {
     type Type = Self;
@@ -743,6 +894,12 @@ impl<'vm> Getable<'vm> for bool {
             _ => ice!("ValueRef is not a Bool"),
         }
     }
+    fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<bool, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Data(data) => Ok(data.tag() == 1),
+            actual => Err(MarshalError::unexpected_value("a Bool", actual)),
+        }
+    }
 }
 
 impl VmType for Ordering {
@@ -805,6 +962,12 @@ impl<'vm> Getable<'vm> for String {
             _ => ice!("ValueRef is not a String"),
         }
     }
+    fn try_from_value(_: &'vm Thread, value: Variants) -> StdResult<String, MarshalError> {
+        match value.as_ref() {
+            ValueRef::String(i) => Ok(String::from(&i[..])),
+            actual => Err(MarshalError::unexpected_value("a String", actual)),
+        }
+    }
 }
 impl<'vm> Pushable<'vm> for String {
     fn push(self, thread: &'vm Thread, context: &mut Context) -> Result<()> {
@@ -931,6 +1094,105 @@ where
     }
 }
 
+impl<'vm, T> Getable<'vm> for Vec<T>
+where
+    T: Getable<'vm>,
+{
+    // Forwarded so that elements such as `&str` can borrow from the array instead of being copied
+    unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> Vec<T> {
+        match value.as_ref() {
+            ValueRef::Array(array) => array
+                .iter()
+                .map(|v| T::from_value_unsafe(vm, v))
+                .collect(),
+            _ => ice!("ValueRef is not an Array"),
+        }
+    }
+    fn from_value(vm: &'vm Thread, value: Variants) -> Vec<T> {
+        match value.as_ref() {
+            ValueRef::Array(array) => array.iter().map(|v| T::from_value(vm, v)).collect(),
+            _ => ice!("ValueRef is not an Array"),
+        }
+    }
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Vec<T>, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Array(array) => array.iter().map(|v| T::try_from_value(vm, v)).collect(),
+            actual => Err(MarshalError::unexpected_value("an Array", actual)),
+        }
+    }
+}
+
+// `Pushable` can't be implemented for `Box<T>` as it would overlap with the `Userdata` impl
+// (other crates may implement `Userdata` for `Box<TheirType>`). Boxed values can still be
+// pushed with `(*value).push(vm, context)`.
+impl<T: VmType> VmType for Box<T> {
+    type Type = T::Type;
+    fn make_forall_type(vm: &Thread) -> ArcType {
+        T::make_forall_type(vm)
+    }
+    fn make_type(vm: &Thread) -> ArcType {
+        T::make_type(vm)
+    }
+    fn extra_args() -> VmIndex {
+        T::extra_args()
+    }
+}
+
+impl<'vm, T: Getable<'vm>> Getable<'vm> for Box<T> {
+    unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> Self {
+        Box::new(T::from_value_unsafe(vm, value))
+    }
+    fn from_value(vm: &'vm Thread, value: Variants) -> Self {
+        Box::new(T::from_value(vm, value))
+    }
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+        T::try_from_value(vm, value).map(Box::new)
+    }
+}
+
+macro_rules! shared_pointer_impls {
+    ($($ptr: ident)+) => {$(
+        impl<T: VmType> VmType for $ptr<T> {
+            type Type = T::Type;
+            fn make_forall_type(vm: &Thread) -> ArcType {
+                T::make_forall_type(vm)
+            }
+            fn make_type(vm: &Thread) -> ArcType {
+                T::make_type(vm)
+            }
+            fn extra_args() -> VmIndex {
+                T::extra_args()
+            }
+        }
+
+        impl<'vm, T> Pushable<'vm> for $ptr<T>
+        where
+            T: Pushable<'vm> + Clone,
+        {
+            fn push(self, thread: &'vm Thread, context: &mut Context) -> Result<()> {
+                // Only clone the value if the pointer is shared
+                $ptr::try_unwrap(self)
+                    .unwrap_or_else(|ptr| (*ptr).clone())
+                    .push(thread, context)
+            }
+        }
+
+        impl<'vm, T: Getable<'vm>> Getable<'vm> for $ptr<T> {
+            unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> Self {
+                $ptr::new(T::from_value_unsafe(vm, value))
+            }
+            fn from_value(vm: &'vm Thread, value: Variants) -> Self {
+                $ptr::new(T::from_value(vm, value))
+            }
+            fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Self, MarshalError> {
+                T::try_from_value(vm, value).map($ptr::new)
+            }
+        }
+    )+};
+}
+
+shared_pointer_impls!{ Rc Arc }
+
 impl<'s, T: VmType> VmType for *const T {
     type Type = T::Type;
     fn make_type(vm: &Thread) -> ArcType {
@@ -981,6 +1243,16 @@ impl<'vm, T: Pushable<'vm>> Pushable<'vm> for Option<T> {
     }
 }
 impl<'vm, T: Getable<'vm>> Getable<'vm> for Option<T> {
+    unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> Option<T> {
+        match value.as_ref() {
+            ValueRef::Data(data) => if data.tag() == 0 {
+                None
+            } else {
+                Some(T::from_value_unsafe(vm, data.get_variant(0).unwrap()))
+            },
+            _ => ice!("ValueRef is not an Option"),
+        }
+    }
     fn from_value(vm: &'vm Thread, value: Variants) -> Option<T> {
         match value.as_ref() {
             ValueRef::Data(data) => if data.tag() == 0 {
@@ -991,6 +1263,17 @@ impl<'vm, T: Getable<'vm>> Getable<'vm> for Option<T> {
             _ => ice!("ValueRef is not an Option"),
         }
     }
+    fn try_from_value(vm: &'vm Thread, value: Variants) -> StdResult<Option<T>, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Data(data) => if data.tag() == 0 {
+                Ok(None)
+            } else {
+                let variant = data.get_variant(0).ok_or(MarshalError::MissingIndex(0))?;
+                T::try_from_value(vm, variant).map(Some)
+            },
+            actual => Err(MarshalError::unexpected_value("an Option", actual)),
+        }
+    }
 }
 
 impl<T: VmType, E: VmType> VmType for StdResult<T, E>
@@ -1035,6 +1318,16 @@ impl<'vm, T: Pushable<'vm>, E: Pushable<'vm>> Pushable<'vm> for StdResult<T, E>
 }
 
 impl<'vm, T: Getable<'vm>, E: Getable<'vm>> Getable<'vm> for StdResult<T, E> {
+    unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> StdResult<T, E> {
+        match value.as_ref() {
+            ValueRef::Data(data) => match data.tag() {
+                0 => Err(E::from_value_unsafe(vm, data.get_variant(0).unwrap())),
+                1 => Ok(T::from_value_unsafe(vm, data.get_variant(0).unwrap())),
+                _ => ice!("ValueRef has a wrong tag: {}", data.tag()),
+            },
+            _ => ice!("ValueRef is not a StdResult"),
+        }
+    }
     fn from_value(vm: &'vm Thread, value: Variants) -> StdResult<T, E> {
         match value.as_ref() {
             ValueRef::Data(data) => match data.tag() {
@@ -1045,6 +1338,22 @@ impl<'vm, T: Getable<'vm>, E: Getable<'vm>> Getable<'vm> for StdResult<T, E> {
             _ => ice!("ValueRef is not a StdResult"),
         }
     }
+    fn try_from_value(
+        vm: &'vm Thread,
+        value: Variants,
+    ) -> StdResult<StdResult<T, E>, MarshalError> {
+        match value.as_ref() {
+            ValueRef::Data(data) => {
+                let variant = || data.get_variant(0).ok_or(MarshalError::MissingIndex(0));
+                match data.tag() {
+                    0 => E::try_from_value(vm, variant()?).map(Err),
+                    1 => T::try_from_value(vm, variant()?).map(Ok),
+                    tag => Err(MarshalError::UnexpectedTag(tag)),
+                }
+            }
+            actual => Err(MarshalError::unexpected_value("a Result", actual)),
+        }
+    }
 }
 
 /// Wrapper around a `Future` which can be used as a return value to let the virtual machine know
@@ -1468,6 +1777,23 @@ macro_rules! define_tuple {
 
         #[allow(non_snake_case)]
         impl<'vm, $($id: Getable<'vm>),+> Getable<'vm> for ($($id),+) {
+            #[allow(unused_assignments)]
+            unsafe fn from_value_unsafe(vm: &'vm Thread, value: Variants) -> ($($id),+) {
+                match value.as_ref() {
+                    ValueRef::Data(v) => {
+                        assert!(v.len() == count!($($id),+));
+                        let mut i = 0;
+                        ( $(
+                            {
+                                let a = $id::from_value_unsafe(vm, v.get_variant(i).unwrap());
+                                i += 1;
+                                a
+                            }
+                        ),+ )
+                    }
+                    _ => ice!("ValueRef is not a Tuple"),
+                }
+            }
             #[allow(unused_assignments)]
             fn from_value(vm: &'vm Thread, value: Variants) -> ($($id),+) {
                 match value.as_ref() {
@@ -1481,6 +1807,27 @@ macro_rules! define_tuple {
                     _ => ice!("ValueRef is not a Tuple"),
                 }
             }
+            #[allow(unused_assignments)]
+            fn try_from_value(
+                vm: &'vm Thread,
+                value: Variants,
+            ) -> StdResult<($($id),+), MarshalError> {
+                match value.as_ref() {
+                    ValueRef::Data(v) => {
+                        let mut i = 0;
+                        Ok(( $(
+                            {
+                                let variant = v.get_variant(i)
+                                    .ok_or(MarshalError::MissingIndex(i))?;
+                                let a = $id::try_from_value(vm, variant)?;
+                                i += 1;
+                                a
+                            }
+                        ),+ ))
+                    }
+                    actual => Err(MarshalError::unexpected_value("a Tuple", actual)),
+                }
+            }
         }
 
         #[allow(non_snake_case)]
@@ -1599,21 +1946,28 @@ pub mod record {
             type_fields: &mut Vec<types::Field<Symbol, Alias<Symbol, ArcType>>>,
         ) {
             let typ = H::make_type(vm);
-            let name = {
-                let mut self_symbol = None;
-                types::walk_type(&typ, |typ: &ArcType| {
-                    if self_symbol.is_none() {
-                        match **typ {
-                            Type::Ident(ref id) if id.definition_name() == F::name() => {
-                                self_symbol = Some(id.clone())
-                            }
-                            _ => (),
-                        }
-                    }
-                });
-                self_symbol.unwrap_or_else(|| Symbol::from(F::name()))
-            };
             let args = F::args();
+            let mut self_symbol = None;
+            // An alias with a different name than the type it refers to (`Reference` for `Ref`)
+            // must use the generics of that type as its parameters for it to be expandable. An
+            // alias of the type itself is never expanded.
+            let mut generics = Vec::new();
+            types::walk_type(&typ, |typ: &ArcType| match **typ {
+                Type::Ident(ref id) if self_symbol.is_none() && id.definition_name() == F::name() => {
+                    self_symbol = Some(id.clone())
+                }
+                Type::Generic(ref gen)
+                    if args.iter().any(|arg| *arg == gen.id.declared_name())
+                        && generics.iter().all(|g: &Generic<Symbol>| g.id != gen.id) =>
+                {
+                    generics.push(gen.clone())
+                }
+                _ => (),
+            });
+            if self_symbol.is_some() {
+                generics.clear();
+            }
+            let name = self_symbol.unwrap_or_else(|| Symbol::from(F::name()));
             assert!(
                 name.definition_name().starts_with(char::is_uppercase),
                 "{}",
@@ -1625,10 +1979,16 @@ pub mod record {
                     name,
                     args.iter()
                         .map(|arg| {
-                            Generic::new(
-                                Symbol::from(*arg),
-                                vm.global_env().type_cache().kind_cache.typ(),
-                            )
+                            generics
+                                .iter()
+                                .find(|gen| gen.id.declared_name() == *arg)
+                                .cloned()
+                                .unwrap_or_else(|| {
+                                    Generic::new(
+                                        Symbol::from(*arg),
+                                        vm.global_env().type_cache().kind_cache.typ(),
+                                    )
+                                })
                         })
                         .collect(),
                     typ,
@@ -1837,6 +2197,9 @@ fn make_type<T: ?Sized + VmType>(vm: &Thread) -> ArcType {
 
 /// Type which represents a function reference in gluon
 pub type FunctionRef<'vm, F> = Function<&'vm Thread, F>;
+/// A gluon function which keeps both the function and the thread it was retrieved from alive.
+/// Unlike `FunctionRef` it does not borrow the thread so it can be stored in other structures,
+/// sent to other threads (it is `Send + Sync`) and called at any later point.
 pub type OwnedFunction<F> = Function<RootedThread, F>;
 
 /// Type which represents an function in gluon
@@ -1875,6 +2238,154 @@ where
     }
 }
 
+impl<T, F> Function<T, F>
+where
+    T: Deref<Target = Thread>,
+    F: VmType + FunctionType,
+{
+    /// Calls the function with arguments only known at runtime, returning the result without
+    /// converting it. Functions returning `IO` have the action run, just as `call` does.
+    ///
+    /// Returns an error without calling the function if the number of arguments differs from the
+    /// number of arguments in the type of the function or if an argument does not have the shape
+    /// of its type.
+    pub fn call_any<U>(&self, args: &[OpaqueValue<U, Hole>]) -> Result<OpaqueValue<&Thread, Hole>>
+    where
+        U: Deref<Target = Thread>,
+    {
+        let vm = self.value.vm();
+        let typ = F::make_type(vm);
+        let arg_types = types::arg_iter(typ.remove_forall()).collect::<Vec<_>>();
+        if args.len() != arg_types.len() {
+            return Err(Error::Message(format!(
+                "Expected {} arguments but got {}",
+                arg_types.len(),
+                args.len()
+            )));
+        }
+        for (i, (arg, arg_type)) in args.iter().zip(&arg_types).enumerate() {
+            check_value_type(vm, arg.get_variant(), arg_type).map_err(|err| {
+                Error::Message(format!(
+                    "Argument {} does not have the type `{}`: {}",
+                    i + 1,
+                    arg_type,
+                    err
+                ))
+            })?;
+        }
+
+        let mut context = vm.context();
+        context.stack.push(self.value.get_variant());
+        for arg in args {
+            let value = {
+                let full_clone = !vm.can_share_values_with(&mut context.gc, arg.vm());
+                let mut cloner = Cloner::new(vm, &mut context.gc);
+                if full_clone {
+                    cloner.force_full_clone();
+                }
+                cloner.deep_clone(&arg.get_variant().get_value())?
+            };
+            context.stack.push(value);
+        }
+        // `IO` actions take an extra argument which runs them
+        let extra_args = F::arguments() - args.len() as VmIndex;
+        for _ in 0..extra_args {
+            0.push(&vm, &mut context)?;
+        }
+        match vm.call_function(context, F::arguments())? {
+            Async::Ready(context) => {
+                let value = context.unwrap().stack.pop();
+                Ok(OpaqueValue::from_value(vm.root_value(value)))
+            }
+            Async::NotReady => Err(Error::Message("Unexpected async".into())),
+        }
+    }
+}
+
+/// Checks that `value` has the shape of a value of type `typ`. Only what is visible at runtime is
+/// checked so values of generic, opaque and userdata types are always accepted.
+fn check_value_type(vm: &Thread, value: Variants, typ: &ArcType) -> StdResult<(), MarshalError> {
+    let typ = {
+        let env = vm.get_env();
+        match AliasRemover::new().remove_aliases(&*env, typ.clone()) {
+            Ok(typ) => typ,
+            // Recursive types can not be unfolded further
+            Err(_) => return Ok(()),
+        }
+    };
+    let actual = value.as_ref();
+    let mismatch = |expected| Err(MarshalError::unexpected_value(expected, actual));
+    match **typ.remove_forall() {
+        Type::Builtin(BuiltinType::Int) | Type::Builtin(BuiltinType::Char) => match actual {
+            ValueRef::Int(_) => Ok(()),
+            _ => mismatch("an Int"),
+        },
+        Type::Builtin(BuiltinType::Byte) => match actual {
+            ValueRef::Byte(_) => Ok(()),
+            _ => mismatch("a Byte"),
+        },
+        Type::Builtin(BuiltinType::Float) => match actual {
+            ValueRef::Float(_) => Ok(()),
+            _ => mismatch("a Float"),
+        },
+        Type::Builtin(BuiltinType::String) => match actual {
+            ValueRef::String(_) => Ok(()),
+            _ => mismatch("a String"),
+        },
+        Type::App(ref ctor, ref args) => match **ctor {
+            Type::Builtin(BuiltinType::Array) if args.len() == 1 => match actual {
+                ValueRef::Array(array) => {
+                    for i in 0..array.len() {
+                        if let Some(element) = array.get(i) {
+                            check_value_type(vm, element, &args[0])?;
+                        }
+                    }
+                    Ok(())
+                }
+                _ => mismatch("an Array"),
+            },
+            Type::Builtin(BuiltinType::Function) => match actual {
+                ValueRef::Closure(_) | ValueRef::Internal => Ok(()),
+                _ => mismatch("a function"),
+            },
+            _ => Ok(()),
+        },
+        Type::Function(..) => match actual {
+            ValueRef::Closure(_) | ValueRef::Internal => Ok(()),
+            _ => mismatch("a function"),
+        },
+        Type::Record(ref row) => match actual {
+            ValueRef::Data(data) => {
+                for field in row.row_iter() {
+                    let name = field.name.declared_name();
+                    let value = data
+                        .lookup_field(vm, name)
+                        .ok_or_else(|| MarshalError::MissingField(name.to_string()))?;
+                    check_value_type(vm, value, &field.typ)?;
+                }
+                Ok(())
+            }
+            _ => mismatch("a Record"),
+        },
+        Type::Variant(ref row) => match actual {
+            ValueRef::Data(data) => {
+                let constructor = variant_constructor(row, data.tag())
+                    .ok_or_else(|| MarshalError::UnexpectedTag(data.tag()))?;
+                let arg_types = types::arg_iter(constructor.typ.remove_forall());
+                for (i, arg_type) in arg_types.enumerate() {
+                    let value = data
+                        .get_variant(i)
+                        .ok_or_else(|| MarshalError::MissingIndex(i))?;
+                    check_value_type(vm, value, arg_type)?;
+                }
+                Ok(())
+            }
+            _ => mismatch("a Variant"),
+        },
+        _ => Ok(()),
+    }
+}
+
 impl<T, F> Clone for Function<T, F>
 where
     T: Deref<Target = Thread> + Clone,
@@ -2182,6 +2693,11 @@ make_vm_function!(A, B, C, D);
 make_vm_function!(A, B, C, D, E);
 make_vm_function!(A, B, C, D, E, F);
 make_vm_function!(A, B, C, D, E, F, G);
+make_vm_function!(A, B, C, D, E, F, G, H);
+make_vm_function!(A, B, C, D, E, F, G, H, I);
+make_vm_function!(A, B, C, D, E, F, G, H, I, J);
+make_vm_function!(A, B, C, D, E, F, G, H, I, J, K);
+make_vm_function!(A, B, C, D, E, F, G, H, I, J, K, L);
 
 pub struct TypedBytecode<T> {
     id: Symbol,
@@ -7,7 +7,7 @@ use api::{Pushable, VmType};
 use base::types::ArcType;
 use interner::InternedStr;
 use serde::ser::{self, Serialize};
-use thread::{Context, Thread, ThreadInternal};
+use thread::{Context, RootedThread, RootedValue, Thread, ThreadInternal};
 use types::{VmIndex, VmTag};
 use value::{Def, RecordDef, ValueRepr};
 use {Error, Result};
@@ -154,6 +154,22 @@ where
     }
 }
 
+/// Serializes `value` into a gluon value which is rooted in `thread`.
+///
+/// This is the inverse of `de::from_value` and can be used with any `T: Serialize`, without
+/// requiring a `Pushable` implementation.
+pub fn to_value<T>(thread: &Thread, value: &T) -> Result<RootedValue<RootedThread>>
+where
+    T: ?Sized + Serialize,
+{
+    let value = {
+        let mut context = thread.context();
+        Ser(value).push(thread, &mut context)?;
+        context.stack.pop()
+    };
+    Ok(thread.root_value(value))
+}
+
 impl ser::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
@@ -569,6 +585,7 @@ impl<'a, 'vm> ser::SerializeStructVariant for RecordSerializer<'a, 'vm> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use api::ValueRef;
     use thread::{RootedThread, ThreadInternal};
     use value::Value;
 
@@ -586,4 +603,18 @@ mod tests {
         let thread = RootedThread::new();
         assert_eq!(to_value(&thread, &true).unwrap(), Value::tag(1));
     }
+
+    #[test]
+    fn rooted_value() {
+        let thread = RootedThread::new();
+        let value = super::to_value(&thread, &(1, "test")).unwrap();
+        let value = value.get_variant();
+        match value.as_ref() {
+            ValueRef::Data(data) => {
+                assert_eq!(data.get(0), Some(ValueRef::Int(1)));
+                assert_eq!(data.get(1), Some(ValueRef::String("test")));
+            }
+            _ => panic!("Expected a tuple"),
+        }
+    }
 }
@@ -1,7 +1,10 @@
 use std::any::Any;
 use std::collections::VecDeque;
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 use futures::sync::oneshot;
 use futures::Future;
@@ -21,11 +24,18 @@ use value::{Callable, GcStr, Userdata, ValueRepr};
 use vm::{RootedThread, Status, Thread};
 use {Error, ExternModule, Result as VmResult};
 
+/// The values which have been sent but not yet received. `available` is notified whenever a value
+/// is sent so that receivers on other threads can wait for values.
+struct Queue<T> {
+    values: Mutex<VecDeque<T>>,
+    available: Condvar,
+}
+
 pub struct Sender<T> {
     // No need to traverse this thread reference as any thread having a reference to this `Sender`
     // would also directly own a reference to the `Thread`
     thread: GcPtr<Thread>,
-    queue: Arc<Mutex<VecDeque<T>>>,
+    queue: Arc<Queue<T>>,
 }
 
 impl<T> Userdata for Sender<T>
@@ -39,7 +49,7 @@ where
     T: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", *self.queue.lock().unwrap())
+        write!(f, "{:?}", *self.queue.values.lock().unwrap())
     }
 }
 
@@ -51,18 +61,19 @@ impl<T> Traverseable for Sender<T> {
 
 impl<T> Sender<T> {
     fn send(&self, value: T) {
-        self.queue.lock().unwrap().push_back(value);
+        self.queue.values.lock().unwrap().push_back(value);
+        self.queue.available.notify_one();
     }
 }
 
 impl<T: Traverseable> Traverseable for Receiver<T> {
     fn traverse(&self, gc: &mut Gc) {
-        self.queue.lock().unwrap().traverse(gc);
+        self.queue.values.lock().unwrap().traverse(gc);
     }
 }
 
 pub struct Receiver<T> {
-    queue: Arc<Mutex<VecDeque<T>>>,
+    queue: Arc<Queue<T>>,
 }
 
 impl<T> Userdata for Receiver<T>
@@ -76,13 +87,39 @@ where
     T: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", *self.queue.lock().unwrap())
+        write!(f, "{:?}", *self.queue.values.lock().unwrap())
     }
 }
 
 impl<T> Receiver<T> {
     fn try_recv(&self) -> Result<T, ()> {
-        self.queue.lock().unwrap().pop_front().ok_or(())
+        self.queue.values.lock().unwrap().pop_front().ok_or(())
+    }
+
+    /// Blocks until a value has been sent or, if `timeout` is given, until it has elapsed
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recv_timeout(&self, timeout: Option<Duration>) -> Result<T, ()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut values = self.queue.values.lock().unwrap();
+        loop {
+            if let Some(value) = values.pop_front() {
+                return Ok(value);
+            }
+            values = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(());
+                    }
+                    self.queue
+                        .available
+                        .wait_timeout(values, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.queue.available.wait(values).unwrap(),
+            };
+        }
     }
 }
 
@@ -131,7 +168,10 @@ fn channel(
 ) -> ChannelRecord<Sender<Generic<A>>, Receiver<Generic<A>>> {
     let sender = Sender {
         thread: unsafe { GcPtr::from_raw(vm) },
-        queue: Arc::new(Mutex::new(VecDeque::new())),
+        queue: Arc::new(Queue {
+            values: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+        }),
     };
     let receiver = Receiver {
         queue: sender.queue.clone(),
@@ -143,6 +183,62 @@ fn recv(receiver: &Receiver<Generic<A>>) -> Result<Generic<A>, ()> {
     receiver.try_recv().map_err(|_| ())
 }
 
+/// Blocks the calling thread until a value is sent. As no other gluon code runs on the calling
+/// thread while it waits, the value must be sent from a thread running in parallel. Fails instead
+/// of blocking forever if the channel is empty and no such thread exists.
+fn recv_wait(
+    WithVM { vm, value: receiver }: WithVM<&Receiver<Generic<A>>>,
+) -> RuntimeResult<Generic<A>, String> {
+    if let Ok(value) = receiver.try_recv() {
+        return RuntimeResult::Return(value);
+    }
+    if !vm.has_other_threads() {
+        return RuntimeResult::Panic(
+            "recv_wait would block forever as there is no other thread which could send a value"
+                .into(),
+        );
+    }
+    match wait(receiver, "recv_wait", None) {
+        RuntimeResult::Return(value) => RuntimeResult::Return(
+            value.expect("Waiting without a timeout always receives a value"),
+        ),
+        RuntimeResult::Panic(err) => RuntimeResult::Panic(err),
+    }
+}
+
+/// Like `recv_wait` but gives up with `Err ()` if no value has been sent after `ms` milliseconds
+fn recv_timeout(
+    receiver: &Receiver<Generic<A>>,
+    ms: VmInt,
+) -> RuntimeResult<Result<Generic<A>, ()>, String> {
+    wait(
+        receiver,
+        "recv_timeout",
+        Some(Duration::from_millis(ms.max(0) as u64)),
+    )
+}
+
+#[cfg(target_arch = "wasm32")]
+fn wait(
+    receiver: &Receiver<Generic<A>>,
+    name: &str,
+    _timeout: Option<Duration>,
+) -> RuntimeResult<Result<Generic<A>, ()>, String> {
+    match receiver.try_recv() {
+        Ok(value) => RuntimeResult::Return(Ok(value)),
+        Err(()) => RuntimeResult::Panic(format!("{} is not supported on wasm", name)),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wait(
+    receiver: &Receiver<Generic<A>>,
+    _name: &str,
+    timeout: Option<Duration>,
+) -> RuntimeResult<Result<Generic<A>, ()>, String> {
+    RuntimeResult::Return(receiver.recv_timeout(timeout))
+}
+
 fn send(sender: &Sender<Generic<A>>, value: Generic<A>) -> Result<(), ()> {
     unsafe {
         let value = sender
@@ -326,8 +422,13 @@ fn new_thread(WithVM { vm, .. }: WithVM<()>) -> IO<RootedThread> {
     }
 }
 
+#[cfg(target_arch = "wasm32")]
+fn sleep(_ms: VmInt) -> IO<()> {
+    IO::Exception("sleep is not supported on wasm".to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn sleep(ms: VmInt) -> IO<()> {
-    use std::time::Duration;
     ::std::thread::sleep(Duration::from_millis(ms as u64));
     IO::Value(())
 }
@@ -352,9 +453,11 @@ pub fn load_channel<'vm>(vm: &'vm Thread) -> VmResult<ExternModule> {
         vm,
         record!{
             type Sender a => Sender<A>,
-            type Receiver a => Sender<A>,
+            type Receiver a => Receiver<A>,
             channel => primitive!(1 std::channel::channel),
             recv => primitive!(1 std::channel::recv),
+            recv_wait => primitive!(1 std::channel::recv_wait),
+            recv_timeout => primitive!(2 std::channel::recv_timeout),
             send => primitive!(2 std::channel::send),
         },
     )
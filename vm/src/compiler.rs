@@ -1,7 +1,7 @@
 use self::Variable::*;
 use base::ast::{self, DisplayEnv, Literal, Typed, TypedIdent};
 use base::kind::{ArcKind, KindEnv};
-use base::pos::Line;
+use base::pos::{Column, Line};
 use base::resolve;
 use base::scoped_map::ScopedMap;
 use base::source::Source;
@@ -52,6 +52,10 @@ pub struct DebugInfo {
     #[cfg_attr(feature = "serde_derive", serde(state))]
     pub upvars: Vec<UpvarInfo>,
     pub source_name: String,
+    /// Types which instructions refer to by index, such as the type of the value passed to
+    /// `@unmatched_pattern`
+    #[cfg_attr(feature = "serde_derive", serde(state_with = "::serialization::borrow"))]
+    pub types: Vec<ArcType>,
 }
 
 #[derive(Debug)]
@@ -120,6 +124,7 @@ impl CompiledFunction {
                 local_map: LocalMap::new(),
                 upvars: Vec::new(),
                 source_name: source_name,
+                types: Vec::new(),
             },
         }
     }
@@ -135,6 +140,8 @@ struct FunctionEnv {
     free_vars: Vec<(Symbol, ArcType)>,
     /// The line where instructions are currently being emitted
     current_line: Line,
+    /// The column where instructions are currently being emitted
+    current_column: Column,
     emit_debug_info: bool,
     function: CompiledFunction,
 }
@@ -221,6 +228,7 @@ impl FunctionEnv {
             stack_size: 0,
             function: CompiledFunction::new(args, id, typ, source_name),
             current_line: Line::from(0),
+            current_column: Column::from(0),
             emit_debug_info: emit_debug_info,
         }
     }
@@ -244,7 +252,11 @@ impl FunctionEnv {
             self.function
                 .debug_info
                 .source_map
-                .emit(self.function.instructions.len() - 1, self.current_line);
+                .emit(
+                    self.function.instructions.len() - 1,
+                    self.current_line,
+                    self.current_column,
+                );
         }
     }
 
@@ -255,6 +267,12 @@ impl FunctionEnv {
         self.function.max_stack_size = max(self.function.max_stack_size, self.stack_size);
     }
 
+    /// Emits a call of the function and `args` arguments on the top of the stack.
+    ///
+    /// Calls in tail position are emitted as `TailCall` which replaces the frame of the current
+    /// function with the frame of the called function. Gluon guarantees that tail calls do not
+    /// grow the stack so this must be used for every call in tail position, including calls in
+    /// the branches of `match` expressions and on the right hand side of `&&` and `||`.
     fn emit_call(&mut self, args: VmIndex, tail_position: bool) {
         let i = if tail_position {
             TailCall(args)
@@ -369,16 +387,15 @@ impl CompilerEnv for TypeInfos {
         self.id_to_type
             .iter()
             .filter_map(|(_, ref alias)| match **alias.unresolved_type() {
-                Type::Variant(ref row) => row
-                    .row_iter()
-                    .enumerate()
-                    .find(|&(_, field)| field.name == *id),
+                Type::Variant(ref row) => {
+                    find_variant_constructor(row, |field| field.name == *id)
+                }
                 _ => None,
             })
             .next()
             .map(|(tag, field)| {
                 (
-                    Variable::Constructor(tag as VmTag, count_function_args(&field.typ)),
+                    Variable::Constructor(tag, count_function_args(&field.typ)),
                     field.typ.clone(),
                 )
             })
@@ -449,18 +466,14 @@ impl<'a> Compiler<'a> {
         self.stack_constructors
             .iter()
             .filter_map(|(_, typ)| match **typ {
-                Type::Variant(ref row) => row
-                    .row_iter()
-                    .enumerate()
-                    .find(|&(_, field)| field.name == *id),
+                Type::Variant(ref row) => {
+                    find_variant_constructor(row, |field| field.name == *id)
+                }
                 _ => None,
             })
             .next()
             .map(|(tag, field)| {
-                Constructor(
-                    tag as VmIndex,
-                    types::arg_iter(&field.typ).count() as VmIndex,
-                )
+                Constructor(tag, types::arg_iter(&field.typ).count() as VmIndex)
             })
             .or_else(|| {
                 current
@@ -512,11 +525,10 @@ impl<'a> Compiler<'a> {
     fn find_tag(&self, typ: &ArcType, constructor: &Symbol) -> Option<VmTag> {
         let x = resolve::remove_aliases_cow(self, typ);
         match **x {
-            Type::Variant(ref row) => row
-                .row_iter()
-                .enumerate()
-                .find(|&(_, field)| field.name == *constructor)
-                .map(|(tag, _)| tag as VmTag),
+            Type::Variant(ref row) => {
+                find_variant_constructor(row, |field| field.name == *constructor)
+                    .map(|(tag, _)| tag)
+            }
             _ => None,
         }
     }
@@ -568,8 +580,9 @@ impl<'a> Compiler<'a> {
     fn update_line(&mut self, function: &mut FunctionEnvs, expr: CExpr) {
         // Don't update the current_line for macro expanded code as the lines in that code do not
         // come from this module
-        if let Some(current_line) = self.source.line_number_at_byte(expr.span().start()) {
-            function.current_line = current_line;
+        if let Some(location) = Source::location(self.source, expr.span().start()) {
+            function.current_line = location.line;
+            function.current_column = location.column;
         }
     }
 
@@ -638,9 +651,9 @@ impl<'a> Compiler<'a> {
                             );
                         }
                         for (i, closure) in closures.iter().enumerate() {
-                            if let Some(current_line) = self.source.line_number_at_byte(closure.pos)
-                            {
-                                function.current_line = current_line;
+                            if let Some(location) = Source::location(self.source, closure.pos) {
+                                function.current_line = location.line;
+                                function.current_column = location.column;
                             }
                             function.stack.enter_scope();
 
@@ -676,6 +689,11 @@ impl<'a> Compiler<'a> {
                         return Ok(None);
                     }
 
+                    if id.name.as_ref() == "@unmatched_pattern" {
+                        self.compile_unmatched_pattern(func, args, function)?;
+                        return Ok(None);
+                    }
+
                     if let Some(Constructor(tag, num_args)) = self.find(&id.name, function) {
                         for arg in args {
                             self.compile(arg, function, false)?;
@@ -771,7 +789,7 @@ impl<'a> Compiler<'a> {
                         Pattern::Constructor(_, ref args) => {
                             function.function.instructions[start_index] =
                                 CJump(function.function.instructions.len() as VmIndex);
-                            function.emit(Split);
+                            function.emit(Split(args.len() as VmIndex));
                             for arg in args.iter() {
                                 function.push_stack_var(self, arg.name.clone(), arg.typ.clone());
                             }
@@ -824,10 +842,9 @@ impl<'a> Compiler<'a> {
                     }
                     Type::Variant(ref variants) => {
                         function.emit(Construct {
-                            tag: variants
-                                .row_iter()
-                                .position(|field| field.name == id.name)
-                                .unwrap() as VmTag,
+                            tag: find_variant_constructor(variants, |field| field.name == id.name)
+                                .unwrap()
+                                .0,
                             args: exprs.len() as u32,
                         });
                     }
@@ -838,6 +855,43 @@ impl<'a> Compiler<'a> {
         Ok(None)
     }
 
+    /// Compiles the call made when no alternative of a `match` matched. The location and type of
+    /// the matched expression are only known here so they are passed as extra arguments, the type
+    /// as an index into the debug info of the function so the value can be printed with it.
+    fn compile_unmatched_pattern(
+        &mut self,
+        func: CExpr,
+        args: &[Expr],
+        function: &mut FunctionEnvs,
+    ) -> Result<()> {
+        assert!(args.len() == 1, "Invalid unmatched pattern application");
+        let value = &args[0];
+
+        let mut message = format!(
+            "Unmatched pattern in `{}` for a value of type `{}`",
+            self.source_name,
+            value.env_type_of(self)
+        );
+        if let Some(location) = Source::location(self.source, func.span().start()) {
+            message.push_str(&format!(" at {}", location));
+        }
+
+        let type_index = function.function.debug_info.types.len();
+        function
+            .function
+            .debug_info
+            .types
+            .push(value.env_type_of(self));
+
+        self.compile(func, function, false)?;
+        function.emit_string(self.intern(&message)?);
+        function.emit(PushInt(type_index as isize));
+        self.compile(value, function, false)?;
+        // Not a tail call as `@unmatched_pattern` looks up the type in the calling function
+        function.emit_call(3, false);
+        Ok(())
+    }
+
     fn compile_primitive(
         &mut self,
         op: &Symbol,
@@ -955,7 +1009,7 @@ impl<'a> Compiler<'a> {
                                 function.new_stack_var(self, field_name, field.typ.clone());
                             }
                         } else {
-                            function.emit(Split);
+                            function.emit(Split(number_of_fields as VmIndex));
                             for field in typ.row_iter() {
                                 let (name, typ) =
                                     match fields.iter().find(|tup| tup.0.name.name_eq(&field.name))
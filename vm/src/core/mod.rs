@@ -416,6 +416,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                     .iter()
                     .map(|alt| Equation {
                         patterns: vec![&alt.pattern],
+                        guard: alt.guard.as_ref().map(|guard| self.translate_alloc(guard)),
                         result: self.translate_alloc(&alt.expr),
                     })
                     .collect();
@@ -433,6 +434,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                 ref typ,
                 ref exprs,
                 ref base,
+                ref removed,
                 ..
             } => {
                 let mut binder = Binder::default();
@@ -477,13 +479,16 @@ impl<'a, 'e> Translator<'a, 'e> {
 
                 let defined_fields: FnvSet<&str> = exprs
                     .iter()
-                    .map(|field| field.name.value.declared_name())
+                    .map(|field| &field.name)
+                    .chain(removed)
+                    .map(|name| name.value.declared_name())
                     .collect();
                 args.extend(base_binding.as_ref().into_iter().flat_map(
                     |&(base_ident_expr, ref base_type)| {
                         base_type
                             .row_iter()
-                            // Only load fields that aren't named in this record constructor
+                            // Only load fields that aren't named or removed in this record
+                            // constructor
                             .filter(|field| !defined_fields.contains(field.name.declared_name()))
                             .map(move |field| {
                                 self.project_expr(
@@ -630,6 +635,7 @@ impl<'a, 'e> Translator<'a, 'e> {
                             bind_expr,
                             &[Equation {
                                 patterns: vec![&bind.name],
+                                guard: None,
                                 result: tail,
                             }],
                         );
@@ -797,6 +803,9 @@ pub struct PatternTranslator<'a, 'e: 'a>(&'a Translator<'a, 'e>);
 #[derive(Clone, PartialEq, Debug)]
 struct Equation<'a, 'p> {
     patterns: Vec<&'p SpannedPattern<Symbol>>,
+    /// Expression which must evaluate to `True` for the equation to match, falling through to
+    /// the following equations otherwise
+    guard: Option<&'a Expr<'a>>,
     result: &'a Expr<'a>,
 }
 
@@ -957,15 +966,15 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
             // (since those need to be solved first) and then the remaining_patterns
             let new_equations = equations
                 .iter()
-                .map(|equation| (&equation.patterns[1..], equation.result))
                 .zip(&temp)
-                .map(|((remaining_equations, result), first)| Equation {
+                .map(|(equation, first)| Equation {
                     patterns: first
                         .iter()
                         .map(|pattern| &**pattern)
-                        .chain(remaining_equations.iter().cloned())
+                        .chain(equation.patterns[1..].iter().cloned())
                         .collect(),
-                    result,
+                    guard: equation.guard,
+                    result: equation.result,
                 })
                 .collect::<Vec<_>>();
 
@@ -1018,11 +1027,14 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
             }
         }
 
-        // Check if all the constructors of the variant are matched on
-        let complete = groups.len()
-            == remove_aliases_cow(&self.0.env, &variables[0].env_type_of(&self.0.env))
-                .row_iter()
-                .count();
+        // Check if all the constructors of the variant are matched on. An open variant may
+        // contain values from constructors which are not known so it is never complete.
+        let complete = {
+            let typ = variables[0].env_type_of(&self.0.env);
+            let typ = remove_aliases_cow(&self.0.env, &typ);
+            let mut iter = typ.row_iter();
+            groups.len() == iter.by_ref().count() && **iter.current_type() == Type::EmptyRow
+        };
 
         let new_alts = group_order
             .into_iter()
@@ -1047,6 +1059,7 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
                                 .iter()
                                 .chain(equation.patterns.iter().cloned().skip(1))
                                 .collect(),
+                            guard: equation.guard,
                             result: equation.result,
                         }
                     })
@@ -1111,6 +1124,7 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
                 .iter()
                 .map(|equation| Equation {
                     patterns: equation.patterns[1..].to_owned(),
+                    guard: equation.guard,
                     result: equation.result,
                 })
                 .collect::<Vec<_>>(),
@@ -1191,6 +1205,7 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
                     .iter()
                     .map(|equation| Equation {
                         patterns: equation.patterns.iter().cloned().skip(1).collect(),
+                        guard: equation.guard,
                         result: equation.result,
                     })
                     .collect::<Vec<_>>();
@@ -1242,20 +1257,22 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
         expr: &'a Expr<'a>,
         equations: &[Equation<'a, 'p>],
     ) -> Expr<'a> {
-        let arena = &self.0.allocator.arena;
-        let error = arena.alloc(Expr::Ident(
-            TypedIdent::new(Symbol::from("@error")),
-            Span::default(),
-        ));
-        let args = arena.alloc_extend(
-            Some(Expr::Const(
-                Literal::String("Unmatched pattern".into()),
-                Span::default(),
-            )).into_iter(),
-        );
-        let default = arena.alloc(Expr::Call(error, args));
+        // If no alternative matches, `@unmatched_pattern` reports the value being matched on
+        let translator = self.0;
+        let unmatched_pattern = move |variable: &'a Expr<'a>| -> &'a Expr<'a> {
+            let arena = &translator.allocator.arena;
+            let error = arena.alloc(Expr::Ident(
+                TypedIdent::new(Symbol::from("@unmatched_pattern")),
+                expr.span(),
+            ));
+            let args = arena.alloc_extend(Some(variable.clone()).into_iter());
+            arena.alloc(Expr::Call(error, args))
+        };
         match *expr {
-            Expr::Ident(..) => self.translate(default, &[expr], equations).clone(),
+            Expr::Ident(..) => {
+                let default = unmatched_pattern(expr);
+                self.translate(default, &[expr], equations).clone()
+            }
             _ => {
                 let name = TypedIdent {
                     name: Symbol::from("match_pattern"),
@@ -1266,6 +1283,7 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
                     .allocator
                     .arena
                     .alloc(Expr::Ident(name.clone(), expr.span()));
+                let default = unmatched_pattern(id_expr);
                 Expr::Let(
                     LetBinding {
                         name: name,
@@ -1307,10 +1325,7 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
             .group_by(|equation| varcon(&equation.patterns.first().expect("Pattern").value));
 
         let expr = match variables.first() {
-            None => equations
-                .first()
-                .map(|equation| equation.result)
-                .unwrap_or(default),
+            None => self.translate_guards(default, equations),
             Some(_) => {
                 fn bind_variables<'b>(
                     env: &PrimitiveEnv,
@@ -1377,6 +1392,48 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
         binder.into_expr_ref(arena, expr)
     }
 
+    // Selects the result of the first of `equations` (which have no patterns left) whose guard
+    // holds, falling through to `default` if there is no such equation
+    //
+    // | _ if guard1 -> expr1
+    // | _ -> expr2
+    // // ==>
+    // match guard1 with
+    // | True -> expr1
+    // | False -> expr2
+    fn translate_guards<'p>(
+        &mut self,
+        default: &'a Expr<'a>,
+        equations: &[Equation<'a, 'p>],
+    ) -> &'a Expr<'a> {
+        let (equation, rest) = match equations.split_first() {
+            Some(x) => x,
+            None => return default,
+        };
+        let guard = match equation.guard {
+            Some(guard) => guard,
+            None => return equation.result,
+        };
+        let alts: SmallVec<[_; 2]> = collect![
+            Alternative {
+                pattern: Pattern::Constructor(self.0.bool_constructor(true), vec![]),
+                expr: equation.result,
+            },
+            Alternative {
+                pattern: Pattern::Constructor(self.0.bool_constructor(false), vec![]),
+                expr: self.translate_guards(default, rest),
+            },
+        ];
+        let expr = Expr::Match(
+            guard,
+            self.0
+                .allocator
+                .alternative_arena
+                .alloc_extend(alts.into_iter()),
+        );
+        self.0.allocator.arena.alloc(expr)
+    }
+
     fn extract_ident(&self, index: usize, pattern: &ast::Pattern<Symbol>) -> TypedIdent<Symbol> {
         get_ident(pattern).unwrap_or_else(|| TypedIdent {
             name: Symbol::from(format!("pattern_{}", index)),
@@ -1448,9 +1505,16 @@ impl<'a, 'e> PatternTranslator<'a, 'e> {
                     }
                 }
                 ast::Pattern::As(..) => unreachable!(),
-                ast::Pattern::Ident(ref id) => if core_pattern.is_none() {
-                    core_pattern = Some(Pattern::Ident(id.clone()));
-                },
+                ast::Pattern::Ident(ref id) => {
+                    if let Some(Pattern::Ident(ref first)) = core_pattern {
+                        // Equations after the first can be reached if the first has a guard so
+                        // their identifier must refer to the same variable
+                        replacements.insert(id.name.clone(), first.name.clone());
+                    }
+                    if core_pattern.is_none() {
+                        core_pattern = Some(Pattern::Ident(id.clone()));
+                    }
+                }
                 ast::Pattern::Tuple { ref typ, ref elems } => {
                     for (i, (elem, field_type)) in elems.iter().zip(typ.row_iter()).enumerate() {
                         if !add_duplicate_ident(
@@ -1651,7 +1715,7 @@ mod tests {
     }
 
     #[derive(Debug)]
-    struct PatternEq<'a>(&'a Expr<'a>);
+    pub struct PatternEq<'a>(pub &'a Expr<'a>);
 
     impl<'a> fmt::Display for PatternEq<'a> {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -1958,6 +2022,23 @@ mod tests {
         check_translation(expr_str, expected_str);
     }
 
+    #[test]
+    fn match_guard() {
+        let expr_str = r#"
+            match x with
+            | y if y -> 1
+            | _ -> 2
+        "#;
+
+        let expected_str = r#"
+            match x with
+            | True -> 1
+            | False -> 2
+            end
+        "#;
+        check_translation(expr_str, expected_str);
+    }
+
     #[test]
     fn match_string_literal() {
         let expr_str = r#"
@@ -1,12 +1,16 @@
 use std::marker::PhantomData;
 
-use base::ast::TypedIdent;
+use base::ast::{Literal, Typed, TypedIdent};
+use base::fnv::FnvMap;
 use base::merge::{merge_fn, merge_iter};
-use base::pos;
+use base::pos::{self, BytePos, Span};
 use base::symbol::Symbol;
-use base::types::{ArcType, Field};
+use base::types::{ArcType, Field, PrimitiveEnv, Type};
 
 use core::{Allocator, Alternative, CExpr, Closure, Expr, LetBinding, Named, Pattern};
+use gc::GcPtr;
+use types::Instruction::{self, *};
+use value::{ClosureData, Value, ValueRepr};
 
 pub trait ExprProducer<'a, 'b>: Visitor<'a, 'b> {
     fn new(allocator: &'a Allocator<'a>) -> Self;
@@ -159,11 +163,465 @@ impl<'a> Visitor<'a, 'a> for RecognizeUnnecessaryAllocation<'a> {
     }
 }
 
-pub fn optimize<'a>(allocator: &'a Allocator<'a>, expr: &'a Expr<'a>) -> &'a Expr<'a> {
+/// Functions which are compiled to more instructions than this are never inlined
+pub const INLINE_SIZE_LIMIT: usize = 20;
+
+/// The environment which the values of globals are retrieved from when inlining
+pub trait OptimizeEnv: PrimitiveEnv {
+    /// Returns the value of the global `name` if it has been loaded
+    fn find_global_value(&self, name: &str) -> Option<Value>;
+}
+
+pub fn optimize<'a, E>(env: &E, allocator: &'a Allocator<'a>, expr: &'a Expr<'a>) -> &'a Expr<'a>
+where
+    E: OptimizeEnv,
+{
     let mut optimizer = RecognizeUnnecessaryAllocation {
         allocator: allocator,
     };
-    optimizer.visit_expr(expr).unwrap_or(expr)
+    let expr = optimizer.visit_expr(expr).unwrap_or(expr);
+
+    let mut inliner = Inline {
+        env: env,
+        allocator: allocator,
+        values: FnvMap::default(),
+        records: FnvMap::default(),
+    };
+    inliner.visit_expr(expr).unwrap_or(expr)
+}
+
+/// Inlines calls to small functions and projections out of records which are known when
+/// compiling.
+///
+/// The functions worth inlining, such as `(+)` or the fields of instance records, are globals of
+/// other modules which only exist as compiled values by the time this module is translated.
+/// Calls to them are inlined by translating the instructions of the function back into core,
+/// which is only done for functions that do nothing but access fields of their arguments and
+/// upvariables and apply primitive operators to them. Any other call is left as is.
+struct Inline<'a, 'e, E: 'e> {
+    env: &'e E,
+    allocator: &'a Allocator<'a>,
+    /// Variables which are bound to a global or to a value inside a global
+    values: FnvMap<Symbol, Value>,
+    /// Variables which are bound to a record constructed in this module
+    records: FnvMap<Symbol, CExpr<'a>>,
+}
+
+/// A value on the stack of a function which is being inlined
+#[derive(Clone)]
+enum Inlined<'a> {
+    /// A value which is known when compiling
+    Value(Value),
+    /// A value which is only known when running
+    Expr(CExpr<'a>),
+}
+
+impl<'a> Inlined<'a> {
+    /// Returns true if evaluating the value can be duplicated or skipped without changing the
+    /// program
+    fn is_atom(&self) -> bool {
+        match *self {
+            Inlined::Value(_) => true,
+            Inlined::Expr(expr) => is_atom(expr),
+        }
+    }
+}
+
+fn is_atom(expr: CExpr) -> bool {
+    match *expr {
+        Expr::Const(..) | Expr::Ident(..) => true,
+        _ => false,
+    }
+}
+
+impl<'a, 'e, E> Visitor<'a, 'a> for Inline<'a, 'e, E>
+where
+    E: OptimizeEnv,
+{
+    type Producer = SameLifetime<'a>;
+
+    fn visit_expr(&mut self, expr: &'a Expr<'a>) -> Option<&'a Expr<'a>> {
+        match *expr {
+            Expr::Let(ref bind, _) => {
+                if let Named::Expr(bind_expr) = bind.expr {
+                    self.bind(&bind.name.name, bind_expr);
+                }
+                walk_expr_alloc(self, expr)
+            }
+            Expr::Match(scrutinee, alts) if alts.len() == 1 => {
+                if let Some(constant) = self
+                    .known_value(expr)
+                    .and_then(|value| self.constant(&value, expr.span()))
+                {
+                    return Some(constant);
+                }
+                if let Some(new_expr) = self.project_record(scrutinee, &alts[0]) {
+                    return Some(self.visit_expr(new_expr).unwrap_or(new_expr));
+                }
+                match alts[0].pattern {
+                    Pattern::Ident(ref id) => self.bind(&id.name, scrutinee),
+                    Pattern::Record(ref fields) => {
+                        if let Some(record) = self.known_value(scrutinee) {
+                            for field in fields {
+                                if let Some(value) = field_value(&record, &field.0.name) {
+                                    let name = field.1.as_ref().unwrap_or(&field.0.name);
+                                    self.values.insert(name.clone(), value);
+                                }
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+                walk_expr_alloc(self, expr)
+            }
+            Expr::Call(..) => {
+                let new_expr = walk_expr_alloc(self, expr);
+                match *new_expr.unwrap_or(expr) {
+                    Expr::Call(f, args) => self.inline_call(f, args, expr.span()).or(new_expr),
+                    _ => new_expr,
+                }
+            }
+            _ => walk_expr_alloc(self, expr),
+        }
+    }
+
+    fn detach_allocator(&self) -> Option<&'a Allocator<'a>> {
+        Some(self.allocator)
+    }
+}
+
+impl<'a, 'e, E> Inline<'a, 'e, E>
+where
+    E: OptimizeEnv,
+{
+    fn bind(&mut self, name: &Symbol, expr: CExpr<'a>) {
+        if let Some(value) = self.known_value(expr) {
+            self.values.insert(name.clone(), value);
+        } else if let Some(record) = self.known_record(expr) {
+            self.records.insert(name.clone(), record);
+        }
+    }
+
+    /// Returns the value of `expr` if it is a global or a projection out of one
+    fn known_value(&self, expr: CExpr) -> Option<Value> {
+        match *expr {
+            Expr::Ident(ref id, _) => match self.values.get(&id.name) {
+                Some(value) => Some(value.clone()),
+                None if id.name.is_global() => {
+                    self.env.find_global_value(id.name.definition_name())
+                }
+                None => None,
+            },
+            // `record.field` is translated to `match record with { field } -> field`
+            Expr::Match(record, alts) if alts.len() == 1 => {
+                match (&alts[0].pattern, alts[0].expr) {
+                    (&Pattern::Record(ref fields), &Expr::Ident(ref id, _))
+                        if fields.len() == 1 =>
+                    {
+                        let (ref field, ref binding) = fields[0];
+                        if *binding.as_ref().unwrap_or(&field.name) != id.name {
+                            return None;
+                        }
+                        self.known_value(record)
+                            .and_then(|record| field_value(&record, &field.name))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the expression which constructs the record `expr` refers to
+    fn known_record(&self, expr: CExpr<'a>) -> Option<CExpr<'a>> {
+        match *expr {
+            Expr::Data(..) => Some(expr),
+            Expr::Ident(ref id, _) => self.records.get(&id.name).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Replaces a match on a record constructed in this module with bindings of its fields,
+    /// provided each of the fields is an atom so it does not matter where it is evaluated
+    fn project_record(&self, scrutinee: CExpr<'a>, alt: &'a Alternative<'a>) -> Option<CExpr<'a>> {
+        let fields = match alt.pattern {
+            Pattern::Record(ref fields) => fields,
+            _ => return None,
+        };
+        let (id, exprs) = match *self.known_record(scrutinee)? {
+            Expr::Data(ref id, exprs, _) => (id, exprs),
+            _ => return None,
+        };
+        let mut bindings = Vec::new();
+        for &(ref field, ref binding) in fields {
+            let (field_type, field_expr) = id
+                .typ
+                .row_iter()
+                .zip(exprs)
+                .find(|&(record_field, _)| record_field.name.name_eq(&field.name))?;
+            if !is_atom(field_expr) {
+                return None;
+            }
+            let name = TypedIdent {
+                name: binding.as_ref().unwrap_or(&field.name).clone(),
+                typ: field_type.typ.clone(),
+            };
+            bindings.push((name, field_expr));
+        }
+
+        match (&bindings[..], alt.expr) {
+            (&[(ref name, field_expr)], &Expr::Ident(ref id, _)) if name.name == id.name => {
+                return Some(field_expr)
+            }
+            _ => (),
+        }
+        Some(
+            bindings
+                .into_iter()
+                .rev()
+                .fold(alt.expr, |body, (name, field_expr)| {
+                    &*self.allocator.arena.alloc(Expr::Let(
+                        LetBinding {
+                            name: name,
+                            expr: Named::Expr(field_expr),
+                            span_start: field_expr.span().start(),
+                        },
+                        body,
+                    ))
+                }),
+        )
+    }
+
+    fn constant(&self, value: &Value, span: Span<BytePos>) -> Option<CExpr<'a>> {
+        let literal = match value.get_repr() {
+            ValueRepr::Int(i) => Literal::Int(i as i64),
+            ValueRepr::Byte(b) => Literal::Byte(b),
+            _ => return None,
+        };
+        Some(self.allocator.arena.alloc(Expr::Const(literal, span)))
+    }
+
+    fn inline_call(
+        &self,
+        f: CExpr<'a>,
+        args: &'a [Expr<'a>],
+        span: Span<BytePos>,
+    ) -> Option<CExpr<'a>> {
+        let function = self.known_value(f)?;
+
+        // Arguments which are not atoms are bound before the body so they are still evaluated
+        // exactly once
+        let mut bindings = Vec::new();
+        let args = args
+            .iter()
+            .map(|arg| match self.known_value(arg) {
+                Some(value) => Inlined::Value(value),
+                None if is_atom(arg) => Inlined::Expr(arg),
+                None => {
+                    let name = TypedIdent {
+                        name: Symbol::from("inline_arg"),
+                        typ: arg.try_type_of(self.env).unwrap_or_else(|_| Type::hole()),
+                    };
+                    bindings.push(LetBinding {
+                        name: name.clone(),
+                        expr: Named::Expr(arg),
+                        span_start: arg.span().start(),
+                    });
+                    Inlined::Expr(self.allocator.arena.alloc(Expr::Ident(name, arg.span())))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let body = match self.apply(&function, &args, span)? {
+            Inlined::Expr(expr) => expr,
+            Inlined::Value(value) => self.constant(&value, span)?,
+        };
+        debug!("Inlined `{}` as `{}`", f, body);
+        Some(bindings.into_iter().rev().fold(body, |body, bind| {
+            &*self.allocator.arena.alloc(Expr::Let(bind, body))
+        }))
+    }
+
+    fn apply(
+        &self,
+        function: &Value,
+        args: &[Inlined<'a>],
+        span: Span<BytePos>,
+    ) -> Option<Inlined<'a>> {
+        let closure = match function.get_repr() {
+            ValueRepr::Closure(closure) => closure,
+            _ => return None,
+        };
+        let arity = closure.function.args as usize;
+        if arity == 0
+            || arity > args.len()
+            || closure.function.instructions.len() > INLINE_SIZE_LIMIT
+        {
+            return None;
+        }
+        let result = self.eval(closure, args[..arity].to_vec(), span)?;
+        if arity == args.len() {
+            Some(result)
+        } else {
+            match result {
+                Inlined::Value(function) => self.apply(&function, &args[arity..], span),
+                Inlined::Expr(_) => None,
+            }
+        }
+    }
+
+    /// Evaluates the instructions of `closure` with `stack` holding its arguments, returning
+    /// `None` if any of them can't be translated into core
+    fn eval(
+        &self,
+        closure: GcPtr<ClosureData>,
+        mut stack: Vec<Inlined<'a>>,
+        span: Span<BytePos>,
+    ) -> Option<Inlined<'a>> {
+        let function = &closure.function;
+        let mut pc = 0;
+        while let Some(&instruction) = function.instructions.get(pc) {
+            pc += 1;
+            match instruction {
+                Push(i) => {
+                    let value = stack.get(i as usize)?.clone();
+                    if !value.is_atom() {
+                        return None;
+                    }
+                    stack.push(value);
+                }
+                PushInt(i) => {
+                    let expr = self
+                        .allocator
+                        .arena
+                        .alloc(Expr::Const(Literal::Int(i as i64), span));
+                    stack.push(Inlined::Expr(expr));
+                }
+                PushByte(b) => {
+                    let expr = self
+                        .allocator
+                        .arena
+                        .alloc(Expr::Const(Literal::Byte(b), span));
+                    stack.push(Inlined::Expr(expr));
+                }
+                PushUpVar(i) => stack.push(Inlined::Value(closure.upvars.get(i as usize)?.clone())),
+                GetOffset(i) => {
+                    let field = match stack.pop()? {
+                        Inlined::Value(value) => match value.get_repr() {
+                            ValueRepr::Data(data) => data.fields.get(i as usize)?.clone(),
+                            _ => return None,
+                        },
+                        Inlined::Expr(_) => return None,
+                    };
+                    stack.push(Inlined::Value(field));
+                }
+                GetField(i) => {
+                    let name = function.strings.get(i as usize)?;
+                    let field = match stack.pop()? {
+                        Inlined::Value(value) => field_value(&value, name)?,
+                        Inlined::Expr(_) => return None,
+                    };
+                    stack.push(Inlined::Value(field));
+                }
+                Split(_) => match stack.pop()? {
+                    Inlined::Value(value) => match value.get_repr() {
+                        ValueRepr::Data(data) => {
+                            stack.extend(data.fields.iter().cloned().map(Inlined::Value))
+                        }
+                        _ => return None,
+                    },
+                    Inlined::Expr(_) => return None,
+                },
+                Pop(n) => {
+                    for _ in 0..n {
+                        if !stack.pop()?.is_atom() {
+                            return None;
+                        }
+                    }
+                }
+                Slide(n) => {
+                    let top = stack.pop()?;
+                    for _ in 0..n {
+                        if !stack.pop()?.is_atom() {
+                            return None;
+                        }
+                    }
+                    stack.push(top);
+                }
+                // Only jumping forward is allowed so the evaluation always terminates
+                Jump(target) if target as usize >= pc => pc = target as usize,
+                _ => {
+                    let (op, operand_type, return_type) = self.primitive(instruction)?;
+                    let rhs = self.to_expr(stack.pop()?, span)?;
+                    let lhs = self.to_expr(stack.pop()?, span)?;
+                    let op = TypedIdent {
+                        name: Symbol::from(op),
+                        typ: Type::function(vec![operand_type.clone(), operand_type], return_type),
+                    };
+                    let op = self.allocator.arena.alloc(Expr::Ident(op, span));
+                    let args = self
+                        .allocator
+                        .arena
+                        .alloc_extend(vec![lhs.clone(), rhs.clone()]);
+                    stack.push(Inlined::Expr(
+                        self.allocator.arena.alloc(Expr::Call(op, args)),
+                    ));
+                }
+            }
+        }
+        stack.pop()
+    }
+
+    fn to_expr(&self, value: Inlined<'a>, span: Span<BytePos>) -> Option<CExpr<'a>> {
+        match value {
+            Inlined::Value(value) => self.constant(&value, span),
+            Inlined::Expr(expr) => Some(expr),
+        }
+    }
+
+    /// Returns the name of the primitive operator `instruction` executes along with the types of
+    /// its operands and result
+    fn primitive(&self, instruction: Instruction) -> Option<(&'static str, ArcType, ArcType)> {
+        let bool_type = || self.env.get_bool().clone();
+        Some(match instruction {
+            AddInt => ("#Int+", Type::int(), Type::int()),
+            SubtractInt => ("#Int-", Type::int(), Type::int()),
+            MultiplyInt => ("#Int*", Type::int(), Type::int()),
+            DivideInt => ("#Int/", Type::int(), Type::int()),
+            IntLT => ("#Int<", Type::int(), bool_type()),
+            IntEQ => ("#Int==", Type::int(), bool_type()),
+
+            AddByte => ("#Byte+", Type::byte(), Type::byte()),
+            SubtractByte => ("#Byte-", Type::byte(), Type::byte()),
+            MultiplyByte => ("#Byte*", Type::byte(), Type::byte()),
+            DivideByte => ("#Byte/", Type::byte(), Type::byte()),
+            ByteLT => ("#Byte<", Type::byte(), bool_type()),
+            ByteEQ => ("#Byte==", Type::byte(), bool_type()),
+
+            AddFloat => ("#Float+", Type::float(), Type::float()),
+            SubtractFloat => ("#Float-", Type::float(), Type::float()),
+            MultiplyFloat => ("#Float*", Type::float(), Type::float()),
+            DivideFloat => ("#Float/", Type::float(), Type::float()),
+            FloatLT => ("#Float<", Type::float(), bool_type()),
+            FloatEQ => ("#Float==", Type::float(), bool_type()),
+            _ => return None,
+        })
+    }
+}
+
+/// Returns the field `name` of `record` if `record` is a record
+fn field_value<N>(record: &Value, name: &N) -> Option<Value>
+where
+    N: ?Sized + AsRef<str>,
+{
+    match record.get_repr() {
+        ValueRepr::Data(data) if data.is_record() => data
+            .field_names()
+            .iter()
+            .position(|field| **field == *name.as_ref())
+            .and_then(|i| data.fields.get(i).cloned()),
+        _ => None,
+    }
 }
 
 pub fn walk_expr_alloc<'a, 'b, V>(visitor: &mut V, expr: CExpr<'b>) -> Option<CExpr<'a>>
@@ -322,15 +780,67 @@ where
 mod tests {
     use super::*;
 
-    use base::symbol::Symbols;
+    use base::metadata::Metadata;
+    use base::source::Source;
+    use base::symbol::{SymbolModule, Symbols};
 
+    use compiler::Compiler;
     use core;
     use core::grammar::ExprParser;
+    use core::tests::PatternEq;
+    use thread::{RootedThread, Thread, ThreadInternal};
+
+    /// Compiles `expr_str` and stores the resulting value as the global `name`
+    fn load_global(vm: &Thread, symbols: &mut Symbols, name: &str, expr_str: &str) {
+        let allocator = core::Allocator::new();
+        let expr = ExprParser::new()
+            .parse(symbols, &allocator, expr_str)
+            .unwrap();
+        let module = {
+            let env = vm.get_env();
+            let source: ::codespan::FileMap = Source::new(expr_str);
+            let mut compiler = Compiler::new(
+                &*env,
+                vm.global_env(),
+                SymbolModule::new(name.into(), symbols),
+                &source,
+                name.into(),
+                false,
+            );
+            compiler.compile_expr(&expr).unwrap()
+        };
+        let closure = vm.global_env().new_global_thunk(module).unwrap();
+        let (_, value) = vm.call_thunk(closure).sync_or_error().unwrap();
+        vm.set_global(
+            symbols.symbol(name),
+            Type::hole(),
+            Metadata::default(),
+            value,
+        ).unwrap();
+    }
+
+    fn check_optimization(vm: &Thread, symbols: &mut Symbols, expr_str: &str, expected_str: &str) {
+        let allocator = core::Allocator::new();
+        let expr = allocator.arena.alloc(
+            ExprParser::new()
+                .parse(symbols, &allocator, expr_str)
+                .unwrap(),
+        );
+
+        let env = vm.get_env();
+        let optimized_expr = optimize(&*env, &allocator, expr);
+
+        let expected_expr = ExprParser::new()
+            .parse(symbols, &allocator, expected_str)
+            .unwrap();
+        assert_deq!(PatternEq(optimized_expr), expected_expr);
+    }
 
     #[test]
     fn unnecessary_allocation() {
         let mut symbols = Symbols::new();
         let allocator = core::Allocator::new();
+        let vm = RootedThread::new();
 
         let initial_str = r#"
             match { l, r } with
@@ -343,7 +853,7 @@ mod tests {
                 .unwrap(),
         );
 
-        let optimized_expr = optimize(&allocator, initial_expr);
+        let optimized_expr = optimize(&*vm.get_env(), &allocator, initial_expr);
 
         let expected_str = r#"
             let l = l
@@ -357,4 +867,100 @@ mod tests {
             .unwrap();
         assert_deq!(*optimized_expr, expected_expr);
     }
+
+    #[test]
+    fn inline_global_function() {
+        let _ = ::env_logger::try_init();
+
+        let mut symbols = Symbols::new();
+        let vm = RootedThread::new();
+        load_global(
+            &vm,
+            &mut symbols,
+            "@test",
+            "let add x y = (#Int+) x y in { add }",
+        );
+
+        let expr_str = r#"
+            match @test with
+            | { add } -> add 1 2
+            end
+            "#;
+        let expected_str = r#"
+            match @test with
+            | { add } -> (#Int+) 1 2
+            end
+            "#;
+        check_optimization(&vm, &mut symbols, expr_str, expected_str);
+    }
+
+    #[test]
+    fn inline_function_returned_from_global_function() {
+        let _ = ::env_logger::try_init();
+
+        let mut symbols = Symbols::new();
+        let vm = RootedThread::new();
+        load_global(
+            &vm,
+            &mut symbols,
+            "@test",
+            "let add x y = (#Int+) x y in let const_add z = add in { const_add }",
+        );
+
+        let expr_str = r#"
+            match @test with
+            | { const_add } -> const_add 0 (f 1) 2
+            end
+            "#;
+        let expected_str = r#"
+            match @test with
+            | { const_add } ->
+                let inline_arg = f 1
+                in
+                (#Int+) inline_arg 2
+            end
+            "#;
+        check_optimization(&vm, &mut symbols, expr_str, expected_str);
+    }
+
+    #[test]
+    fn do_not_inline_unknown_function() {
+        let _ = ::env_logger::try_init();
+
+        let mut symbols = Symbols::new();
+        let vm = RootedThread::new();
+
+        let expr_str = r#"
+            match test with
+            | { add } -> add 1 2
+            end
+            "#;
+        check_optimization(&vm, &mut symbols, expr_str, expr_str);
+    }
+
+    #[test]
+    fn inline_record_projection() {
+        let _ = ::env_logger::try_init();
+
+        let mut symbols = Symbols::new();
+        let vm = RootedThread::new();
+
+        let expr_str = r#"
+            let x = f 1
+            in
+            let r = { x }
+            in
+            match r with
+            | { x } -> x
+            end
+            "#;
+        let expected_str = r#"
+            let x = f 1
+            in
+            let r = { x }
+            in
+            x
+            "#;
+        check_optimization(&vm, &mut symbols, expr_str, expected_str);
+    }
 }
@@ -1,12 +1,98 @@
+use log::{self, Level, Record};
+
 use api::generic::A;
-use api::Generic;
-use thread::Thread;
+use api::{Generic, WithVM};
+use stack::State;
+use thread::{Thread, ThreadInternal};
 use {ExternModule, Result};
 
 fn trace(a: Generic<A>) {
     println!("{:?}", a);
 }
 
+/// Returns the stacktrace of the function calling `stacktrace`
+fn stacktrace(WithVM { vm, .. }: WithVM<()>) -> String {
+    let mut stacktrace = vm.context().stack.stacktrace(0);
+    // Remove the frame of this function
+    stacktrace.frames.pop();
+    stacktrace.to_string()
+}
+
+/// Returns the documentation comment of the global at `name` (eg. `std.prelude.empty`) if the
+/// global exists and has a comment
+fn comment(WithVM { vm, value: name }: WithVM<&str>) -> Option<String> {
+    let env = vm.get_env();
+    env.get_metadata(name)
+        .ok()
+        .and_then(|metadata| metadata.comment.as_ref())
+        .map(|comment| comment.content.clone())
+}
+
+/// Forwards `message` to the `log` crate. The name of the module and the line of the gluon function
+/// which called the log function are used as the target and location of the log record.
+fn log_message(vm: &Thread, level: Level, message: &str) {
+    if level > log::max_level() {
+        return;
+    }
+    let caller = {
+        let context = vm.context();
+        // Extern functions, such as the log function itself, do not have any debug information
+        let caller = context
+            .stack
+            .get_frames()
+            .iter()
+            .rev()
+            .filter_map(|frame| match frame.state {
+                State::Closure(ref closure) => Some((closure, frame.instruction_index)),
+                _ => None,
+            })
+            .next();
+        caller.map(|(closure, instruction_index)| {
+            let debug_info = &closure.function.debug_info;
+            // The caller has already moved past the instruction which called the log function
+            let line = debug_info
+                .source_map
+                .location(instruction_index.saturating_sub(1))
+                .map(|(line, _)| line.0 + 1);
+            (debug_info.source_name.clone(), line)
+        })
+    };
+    let (module, line) = match caller {
+        Some((ref module, line)) => (&module[..], line),
+        None => ("gluon", None),
+    };
+    log::logger().log(
+        &Record::builder()
+            .args(format_args!("{}", message))
+            .level(level)
+            .target(module)
+            .module_path(Some(module))
+            .file(Some(module))
+            .line(line)
+            .build(),
+    );
+}
+
+fn log_trace(WithVM { vm, value: message }: WithVM<&str>) {
+    log_message(vm, Level::Trace, message)
+}
+
+fn log_debug(WithVM { vm, value: message }: WithVM<&str>) {
+    log_message(vm, Level::Debug, message)
+}
+
+fn log_info(WithVM { vm, value: message }: WithVM<&str>) {
+    log_message(vm, Level::Info, message)
+}
+
+fn log_warn(WithVM { vm, value: message }: WithVM<&str>) {
+    log_message(vm, Level::Warn, message)
+}
+
+fn log_error(WithVM { vm, value: message }: WithVM<&str>) {
+    log_message(vm, Level::Error, message)
+}
+
 mod std {
     pub use debug;
 }
@@ -17,7 +103,34 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
     ExternModule::new(
         vm,
         record!{
-            trace => primitive!(1 std::debug::trace)
+            trace => primitive!(1 std::debug::trace),
+            stacktrace => primitive!(1 std::debug::stacktrace)
+        },
+    )
+}
+
+pub fn load_log(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record!{
+            trace => named_primitive!(1, "std.log.prim.trace", std::debug::log_trace),
+            debug => named_primitive!(1, "std.log.prim.debug", std::debug::log_debug),
+            info => named_primitive!(1, "std.log.prim.info", std::debug::log_info),
+            warn => named_primitive!(1, "std.log.prim.warn", std::debug::log_warn),
+            error => named_primitive!(1, "std.log.prim.error", std::debug::log_error)
+        },
+    )
+}
+
+pub fn load_doc(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    ExternModule::new(
+        vm,
+        record!{
+            comment => named_primitive!(1, "std.doc.comment", std::debug::comment)
         },
     )
 }
@@ -0,0 +1,132 @@
+//! A debugger with breakpoints and stepping, built on the hooks of a `Thread`
+
+use std::sync::{Arc, Mutex};
+
+use futures::Async;
+
+use base::fnv::{FnvMap, FnvSet};
+use base::pos::Line;
+
+use thread::{DebugInfo, HookFlags, Thread, ThreadInternal};
+use Result;
+
+/// What a thread should do after the debugger has paused it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Run until the next breakpoint
+    Continue,
+    /// Pause at the next line, entering any function that is called
+    Into,
+    /// Pause at the next line of the current function or of a function which it returns to
+    Over,
+    /// Pause once the current function has returned
+    Out,
+}
+
+/// Called each time the debugger pauses. The thread is locked while this function is called so
+/// it must only be inspected through the `DebugInfo`.
+pub type PauseFn = Box<FnMut(&Thread, &DebugInfo) -> Step + Send>;
+
+struct DebuggerState {
+    /// Breakpoints indexed by the name of the module they are in
+    breakpoints: FnvMap<String, FnvSet<Line>>,
+    step: Step,
+    /// The number of frames on the stack when the debugger last paused
+    depth: usize,
+    /// `None` while the function is running
+    on_pause: Option<PauseFn>,
+}
+
+/// A debugger which can be attached to threads. Cloning a `Debugger` returns a handle to the
+/// same debugger so breakpoints can be changed while attached threads are running.
+#[derive(Clone)]
+pub struct Debugger {
+    state: Arc<Mutex<DebuggerState>>,
+}
+
+impl Debugger {
+    pub fn new<F>(on_pause: F) -> Debugger
+    where
+        F: FnMut(&Thread, &DebugInfo) -> Step + Send + 'static,
+    {
+        Debugger {
+            state: Arc::new(Mutex::new(DebuggerState {
+                breakpoints: FnvMap::default(),
+                step: Step::Continue,
+                depth: 0,
+                on_pause: Some(Box::new(on_pause)),
+            })),
+        }
+    }
+
+    /// Installs the debugger as the hook of `thread`, replacing any existing hook
+    pub fn attach(&self, thread: &Thread) {
+        let debugger = self.clone();
+        let mut context = thread.context();
+        context.set_hook(Some(Box::new(move |thread, info| {
+            debugger.on_line(thread, &info)
+        })));
+        context.set_hook_mask(HookFlags::LINE_FLAG);
+    }
+
+    /// Pauses any attached thread which reaches `line` in the module `source_name`
+    pub fn set_breakpoint(&self, source_name: &str, line: Line) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .breakpoints
+            .entry(source_name.to_string())
+            .or_insert_with(FnvSet::default)
+            .insert(line);
+    }
+
+    /// Removes a breakpoint. Returns `false` if the breakpoint did not exist
+    pub fn remove_breakpoint(&self, source_name: &str, line: Line) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state
+            .breakpoints
+            .get_mut(source_name)
+            .map_or(false, |lines| lines.remove(&line))
+    }
+
+    /// Sets how attached threads should continue to run. Use `Step::Into` before running a thread
+    /// to pause at its first line.
+    pub fn set_step(&self, step: Step) {
+        self.state.lock().unwrap().step = step;
+    }
+
+    fn on_line(&self, thread: &Thread, info: &DebugInfo) -> Result<Async<()>> {
+        let depth = info.stack_info_len();
+        let mut on_pause = {
+            let mut state = self.state.lock().unwrap();
+            let at_breakpoint = info.stack_info(0).map_or(false, |frame| {
+                match (state.breakpoints.get(frame.source_name()), frame.line()) {
+                    (Some(lines), Some(line)) => lines.contains(&line),
+                    _ => false,
+                }
+            });
+            let pause = at_breakpoint || match state.step {
+                Step::Continue => false,
+                Step::Into => true,
+                Step::Over => depth <= state.depth,
+                Step::Out => depth < state.depth,
+            };
+            if !pause {
+                return Ok(Async::Ready(()));
+            }
+            // Release the lock while the pause function runs so that it can use the debugger
+            match state.on_pause.take() {
+                Some(on_pause) => on_pause,
+                // Another thread is already paused
+                None => return Ok(Async::Ready(())),
+            }
+        };
+
+        let step = on_pause(thread, info);
+
+        let mut state = self.state.lock().unwrap();
+        state.on_pause = Some(on_pause);
+        state.step = step;
+        state.depth = depth;
+        Ok(Async::Ready(()))
+    }
+}
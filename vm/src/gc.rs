@@ -9,11 +9,15 @@ use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::usize;
 
-use base::fnv::FnvMap;
+use base::fnv::{FnvMap, FnvSet};
 use interner::InternedStr;
+use thread::Thread;
 use types::VmIndex;
+use value::{Finalizer, Userdata};
 use {Error, Result};
 
 #[inline]
@@ -129,7 +133,35 @@ impl Generation {
     }
 }
 
+/// The default number of values which are swept per allocation after an automatic collection
+pub const DEFAULT_SWEEP_STEP: usize = 256;
+
+/// The default number of values which are traversed per allocation while an automatic
+/// collection is marking
+pub const DEFAULT_MARK_STEP: usize = 256;
+
+/// A collection forced by the memory limit which frees less than `1 / LIMIT_COLLECT_BACKOFF` of
+/// the limit makes the garbage collector skip the collections triggered by `collect_limit` until
+/// as many bytes have been allocated. Allocations which do not fit below the memory limit always
+/// collect before failing.
+const LIMIT_COLLECT_BACKOFF: usize = 16;
+
 /// A mark and sweep garbage collector.
+///
+/// Collections which are triggered by allocations are incremental. The values reachable from the
+/// roots are marked a few at a time (see `set_mark_step`) on the allocations following the start
+/// of the collection. Once every value has been traversed, the roots and all mutable values
+/// (userdata and threads) which were marked are traversed again to find any value which was
+/// stored in them in the meantime, after which the unreachable values are freed a few at a time
+/// (see `set_sweep_step`). Values allocated while marking are only kept if they are found in
+/// that last traversal.
+///
+/// The only other values which change after being allocated are closures, whose upvariables are
+/// set after the closure is allocated, which must be passed to `write_barrier` after they are
+/// changed.
+///
+/// Threads which have spawned other threads always mark in one go since the values of the child
+/// threads, which run in parallel, are marked by the same collection.
 #[derive(Debug)]
 #[cfg_attr(feature = "serde_derive", derive(DeserializeState, SerializeState))]
 #[cfg_attr(feature = "serde_derive", serde(deserialize_state = "::serialization::DeSeed"))]
@@ -138,12 +170,32 @@ pub struct Gc {
     /// Linked list of all objects allocted by this garbage collector.
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     values: Option<AllocPtr>,
+    /// Values which were traversed by the last collection but which have yet to be swept. Any
+    /// unmarked value in this list is unreachable.
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    unswept: Option<AllocPtr>,
     /// How many bytes which is currently allocated
     allocated_memory: usize,
+    /// How many bytes have been allocated in total, including values which have been collected
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    total_allocated: usize,
     /// How many bytes this garbage collector can allocate before a collection is run
     collect_limit: usize,
     /// The maximum number of bytes this garbage collector may contain
     memory_limit: usize,
+    /// How many more bytes must be allocated before `collect_limit` may trigger a collection
+    /// again
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    limit_collect_backoff: usize,
+    /// How many values to sweep on each allocation after a collection. `None` sweeps all values
+    /// directly after marking.
+    sweep_step: Option<usize>,
+    /// How many values to traverse on each allocation while marking. `None` marks all values as
+    /// soon as a collection starts.
+    mark_step: Option<usize>,
+    /// The state of an incremental collection which is marking values
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    marking: Option<MarkState>,
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     type_infos: FnvMap<TypeId, Box<TypeInfo>>,
     #[cfg_attr(feature = "serde_derive", serde(skip))]
@@ -168,6 +220,118 @@ pub struct Gc {
     /// only refer to each other through some reference or channel allocated in generation 0 (and
     /// if they do interact with eachother this means the values are cloned into generation 0).
     generation: Generation,
+    /// Slots of all the `WeakPtr`s which refer to values allocated by this garbage collector
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    weak_slots: WeakSlots,
+    /// Finalizers of collected userdata which have yet to be run
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    finalizers: Finalizers,
+    /// How many values are currently allocated
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    object_count: usize,
+    /// How many collections this garbage collector has done
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    collections: usize,
+    /// How long the last collection paused the thread
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    last_pause: Duration,
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    collect_callback: CollectCallback,
+    /// Set while a `HeapSnapshot` is taken to record the references between values instead of
+    /// marking them
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    recorder: Option<Box<HeapRecorder>>,
+}
+
+/// Statistics about the values allocated by a garbage collector
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeapStats {
+    /// How many bytes which are currently allocated
+    pub allocated_memory: usize,
+    /// How many bytes have been allocated in total, including values which have been collected
+    pub total_allocated: usize,
+    /// How many values which are currently allocated. Unreachable values are included until
+    /// they have been swept.
+    pub live_objects: usize,
+    /// How many collections have been run
+    pub collections: usize,
+    /// How long the last collection paused the thread. Always zero on targets without a clock.
+    pub last_pause: Duration,
+}
+
+/// A snapshot of all values allocated by a garbage collector and the references between them
+#[derive(Clone, Debug, Default)]
+pub struct HeapSnapshot {
+    pub objects: Vec<HeapObject>,
+    /// Indexes into `objects` of the values which are directly referenced by the roots
+    pub roots: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HeapObject {
+    /// The kind of value this object is, such as `"Closure"` or `"Userdata"`
+    pub type_name: &'static str,
+    /// The field names of the object if it is a record
+    pub fields: Vec<String>,
+    /// The number of bytes this object uses, including its header
+    pub size: usize,
+    /// Indexes into `HeapSnapshot::objects` of the values this object references. Unreachable
+    /// objects do not record any references.
+    pub references: Vec<usize>,
+}
+
+#[derive(Debug, Default)]
+struct HeapRecorder {
+    ids: FnvMap<*const GcHeader, usize>,
+    visited: FnvSet<*const GcHeader>,
+    /// The object which is currently traversed, `None` while traversing the roots
+    current: Option<usize>,
+    edges: Vec<(Option<usize>, usize)>,
+}
+
+// The pointers are only used as keys and are never dereferenced
+unsafe impl Send for HeapRecorder {}
+
+impl HeapRecorder {
+    /// Records a reference to `header`. Returns true if `header` was already visited
+    fn visit(&mut self, header: *const GcHeader) -> bool {
+        if let Some(&id) = self.ids.get(&header) {
+            self.edges.push((self.current, id));
+        }
+        !self.visited.insert(header)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MarkState {
+    /// Values which have been marked but which have yet to be traversed
+    gray: Vec<*const GcHeader>,
+    /// Mutable values which have been traversed. They are traversed again when marking finishes
+    /// as new values may have been stored in them.
+    traversed_mutable: Vec<*const GcHeader>,
+}
+
+// The pointers only refer to values owned by the `Gc` which owns the `MarkState`
+unsafe impl Send for MarkState {}
+
+#[derive(Default)]
+struct CollectCallback(Option<Box<FnMut(&HeapStats) + Send>>);
+
+impl fmt::Debug for CollectCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CollectCallback({})", self.0.is_some())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+// `Instant` is not supported on wasm
+#[cfg(target_arch = "wasm32")]
+fn now() -> Option<Instant> {
+    None
 }
 
 /// Trait which creates a typed pointer from a *mut () pointer.
@@ -220,7 +384,13 @@ unsafe impl<T> DataDef for Move<T> {
 
 #[derive(Debug)]
 struct TypeInfo {
+    type_id: TypeId,
     drop: unsafe fn(*mut ()),
+    finalize: Option<unsafe fn(*mut ()) -> Option<Finalizer>>,
+    traverse: unsafe fn(*mut (), &mut Gc),
+    /// If values of this type can be changed after they have been allocated, in which case they
+    /// must be traversed again at the end of an incremental collection
+    mutable: bool,
     generation: Generation,
     fields: FnvMap<InternedStr, VmIndex>,
     fields_key: Arc<Vec<InternedStr>>,
@@ -408,6 +578,11 @@ impl<T: ?Sized> GcPtr<T> {
         self.header().generation()
     }
 
+    /// Returns true if both pointers point to the same allocation
+    pub fn ptr_eq(&self, other: &GcPtr<T>) -> bool {
+        self.ptr as *const u8 == other.ptr as *const u8
+    }
+
     pub fn field_map(&self) -> &FnvMap<InternedStr, VmIndex> {
         unsafe { &(*self.header().type_info).fields }
     }
@@ -449,10 +624,113 @@ impl GcPtr<str> {
     }
 }
 
+/// Trait for values which may point to memory allocated by the garbage collector and which can
+/// be referred to by a `WeakPtr`
+pub trait WeakTarget: Clone + Send + Sync + 'static {
+    /// Returns the generation of the garbage collector which allocated the value
+    fn generation(&self) -> Generation;
+    /// Returns `true` if the value has been marked during the current collection. Values which
+    /// are not allocated by the garbage collector are always considered marked.
+    fn is_marked(&self) -> bool;
+}
+
+impl<T: ?Sized + Send + Sync + 'static> WeakTarget for GcPtr<T> {
+    fn generation(&self) -> Generation {
+        GcPtr::generation(self)
+    }
+
+    fn is_marked(&self) -> bool {
+        self.header().marked.get()
+    }
+}
+
+/// A weak reference to a value allocated by the garbage collector. A `WeakPtr` does not keep the
+/// value alive, once the value has been collected `upgrade` returns `None`.
+///
+/// Values which were allocated in a parent generation of the garbage collector the `WeakPtr` was
+/// created in can't be tracked so they are kept alive as if the `WeakPtr` was a normal pointer.
+pub struct WeakPtr<T> {
+    slot: Arc<Mutex<Option<T>>>,
+    strong: bool,
+}
+
+impl<T> Clone for WeakPtr<T> {
+    fn clone(&self) -> WeakPtr<T> {
+        WeakPtr {
+            slot: self.slot.clone(),
+            strong: self.strong,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for WeakPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WeakPtr({:?})", *self.slot.lock().unwrap())
+    }
+}
+
+impl<T: WeakTarget> WeakPtr<T> {
+    /// Returns the value if it has not been collected yet
+    pub fn upgrade(&self) -> Option<T> {
+        self.slot.lock().unwrap().clone()
+    }
+}
+
+impl<T> Traverseable for WeakPtr<T>
+where
+    T: Traverseable,
+{
+    fn traverse(&self, gc: &mut Gc) {
+        if self.strong {
+            self.slot.lock().unwrap().traverse(gc);
+        }
+    }
+}
+
+trait WeakSlot: Send + Sync {
+    /// Clears the slot if its value were not marked. Returns `false` if the slot is empty.
+    fn sweep(&self) -> bool;
+}
+
+impl<T: WeakTarget> WeakSlot for Mutex<Option<T>> {
+    fn sweep(&self) -> bool {
+        let mut value = self.lock().unwrap();
+        if value.as_ref().map_or(false, |value| !value.is_marked()) {
+            *value = None;
+        }
+        value.is_some()
+    }
+}
+
+#[derive(Default)]
+struct WeakSlots(Vec<Arc<WeakSlot>>);
+
+impl fmt::Debug for WeakSlots {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WeakSlots({})", self.0.len())
+    }
+}
+
+#[derive(Default)]
+struct Finalizers(Vec<Finalizer>);
+
+impl fmt::Debug for Finalizers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Finalizers({})", self.0.len())
+    }
+}
+
 pub trait CollectScope {
     fn scope<F>(&self, gc: &mut Gc, f: F)
     where
         F: FnOnce(&mut Gc);
+
+    /// Returns true if the values reachable from the roots may be marked over several
+    /// allocations. Roots which make the collection mark values that can be changed by anything
+    /// but the thread doing the allocations must return false.
+    fn allows_incremental_marking(&self) -> bool {
+        false
+    }
 }
 
 /// Trait which must be implemented on all root types which contain `GcPtr`
@@ -559,6 +837,17 @@ where
     }
 }
 
+impl<T> Traverseable for Option<T>
+where
+    T: Traverseable,
+{
+    fn traverse(&self, gc: &mut Gc) {
+        if let Some(ref x) = *self {
+            x.traverse(gc);
+        }
+    }
+}
+
 impl<T> Traverseable for Vec<T>
 where
     T: Traverseable,
@@ -585,7 +874,14 @@ where
     fn traverse(&self, gc: &mut Gc) {
         if !gc.mark(*self) {
             // Continue traversing if this ptr was not already marked
-            (**self).traverse(gc);
+            if gc.recorder.is_some() {
+                gc.record_references(self.header(), |gc| (**self).traverse(gc));
+            } else if let Some(ref mut marking) = gc.marking {
+                // Incremental collections traverse the value in a later step
+                marking.gray.push(self.header());
+            } else {
+                (**self).traverse(gc);
+            }
         }
     }
 }
@@ -595,12 +891,25 @@ impl Gc {
     pub fn new(generation: Generation, memory_limit: usize) -> Gc {
         Gc {
             values: None,
+            unswept: None,
             allocated_memory: 0,
+            total_allocated: 0,
             collect_limit: 100,
             memory_limit: memory_limit,
+            limit_collect_backoff: 0,
+            sweep_step: Some(DEFAULT_SWEEP_STEP),
+            mark_step: Some(DEFAULT_MARK_STEP),
+            marking: None,
             type_infos: FnvMap::default(),
             record_infos: FnvMap::default(),
             generation: generation,
+            weak_slots: WeakSlots::default(),
+            finalizers: Finalizers::default(),
+            object_count: 0,
+            collections: 0,
+            last_pause: Duration::default(),
+            collect_callback: CollectCallback::default(),
+            recorder: None,
         }
     }
 
@@ -608,16 +917,54 @@ impl Gc {
         self.allocated_memory
     }
 
+    pub fn heap_stats(&self) -> HeapStats {
+        HeapStats {
+            allocated_memory: self.allocated_memory,
+            total_allocated: self.total_allocated,
+            live_objects: self.object_count,
+            collections: self.collections,
+            last_pause: self.last_pause,
+        }
+    }
+
+    /// Sets a function which is called with the current `HeapStats` after every collection
+    pub fn set_collect_callback(&mut self, callback: Option<Box<FnMut(&HeapStats) + Send>>) {
+        self.collect_callback = CollectCallback(callback);
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
     pub fn set_memory_limit(&mut self, memory_limit: usize) {
         self.memory_limit = memory_limit;
     }
 
+    /// Sets how many values are swept per allocation after an automatic collection. Smaller
+    /// steps spread the cost of freeing garbage over more allocations at the cost of keeping it
+    /// around for longer. `None` makes every collection sweep all values at once.
+    pub fn set_sweep_step(&mut self, sweep_step: Option<usize>) {
+        assert!(sweep_step != Some(0), "The sweep step must be greater than 0");
+        self.sweep_step = sweep_step;
+    }
+
+    /// Sets how many values are traversed per allocation while an automatic collection is
+    /// marking. Smaller steps give shorter pauses but let more garbage be allocated before the
+    /// collection finishes. `None` makes every collection mark all values at once.
+    pub fn set_mark_step(&mut self, mark_step: Option<usize>) {
+        assert!(mark_step != Some(0), "The mark step must be greater than 0");
+        self.mark_step = mark_step;
+    }
+
     pub fn generation(&self) -> Generation {
         self.generation
     }
 
     pub fn new_child_gc(&self) -> Gc {
-        Gc::new(self.generation.next(), self.memory_limit)
+        let mut gc = Gc::new(self.generation.next(), self.memory_limit);
+        gc.sweep_step = self.sweep_step;
+        gc.mark_step = self.mark_step;
+        gc
     }
 
     /// Allocates a new object. If the garbage collector has hit the collection limit a collection
@@ -628,7 +975,7 @@ impl Gc {
     where
         R: Traverseable + CollectScope,
         D: DataDef + Traverseable,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         struct Scope1<A, B>(A, B);
 
@@ -652,9 +999,30 @@ impl Gc {
             {
                 self.0.scope(gc, f)
             }
+
+            fn allows_incremental_marking(&self) -> bool {
+                self.0.allows_incremental_marking()
+            }
         }
 
-        self.check_collect(Scope1(roots, &def));
+        let size = def.size();
+        let needed = self.allocated_memory.saturating_add(size);
+        if needed >= self.memory_limit {
+            // Make sure that the limit is only hit if the memory is actually in use
+            let before = self.allocated_memory;
+            self.collect(Scope1(roots, &def));
+            // Freeing little memory means that the heap is mostly live. Collecting before it is
+            // necessary would most likely not free much either.
+            let min_freed = self.memory_limit / LIMIT_COLLECT_BACKOFF;
+            if before.saturating_sub(self.allocated_memory) < min_freed {
+                self.limit_collect_backoff = min_freed;
+            }
+        } else if self.limit_collect_backoff > 0 && self.unswept.is_none() {
+            // The allocation fits without collecting
+            self.limit_collect_backoff = self.limit_collect_backoff.saturating_sub(size);
+        } else {
+            self.check_collect(Scope1(roots, &def));
+        }
         self.alloc(def)
     }
 
@@ -662,10 +1030,15 @@ impl Gc {
     pub fn alloc<D>(&mut self, def: D) -> Result<GcPtr<D::Value>>
     where
         D: DataDef,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         let size = def.size();
-        let needed = self.allocated_memory.saturating_add(size);
+        let mut needed = self.allocated_memory.saturating_add(size);
+        if needed >= self.memory_limit && self.unswept.is_some() {
+            // Some of the allocated memory may already be known to be garbage
+            self.finish_sweep();
+            needed = self.allocated_memory.saturating_add(size);
+        }
         if needed >= self.memory_limit {
             return Err(Error::OutOfMemory {
                 limit: self.memory_limit,
@@ -678,16 +1051,56 @@ impl Gc {
     pub fn alloc_ignore_limit<D>(&mut self, def: D) -> GcPtr<D::Value>
     where
         D: DataDef,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         self.alloc_ignore_limit_(def.size(), def)
     }
 
+    /// Creates a weak reference to `value`
+    pub fn new_weak<T: WeakTarget>(&mut self, value: T) -> WeakPtr<T> {
+        let strong = value.generation().is_parent_of(self.generation);
+        let slot = Arc::new(Mutex::new(Some(value)));
+        if !strong {
+            self.weak_slots.0.push(slot.clone());
+        }
+        WeakPtr { slot, strong }
+    }
+
+    /// Takes the finalizers of all userdata which were freed since the last call
+    pub fn take_finalizers(&mut self) -> Vec<Finalizer> {
+        mem::replace(&mut self.finalizers.0, Vec::new())
+    }
+
+    pub fn has_finalizers(&self) -> bool {
+        !self.finalizers.0.is_empty()
+    }
+
+    /// Takes the finalizers of all userdata which were freed since the last call along with the
+    /// finalizers of every userdata which is still allocated. Must only be called just before the
+    /// garbage collector is dropped, which frees the remaining values without finalizing them.
+    pub fn take_all_finalizers(&mut self) -> Vec<Finalizer> {
+        self.finish_sweep();
+        let mut finalizers = self.take_finalizers();
+        let mut current = self.values.as_mut();
+        while let Some(ptr) = current {
+            unsafe {
+                if let Some(finalize) = (*ptr.type_info).finalize {
+                    finalizers.extend(finalize(ptr.value()));
+                }
+            }
+            current = ptr.next.as_mut();
+        }
+        finalizers
+    }
+
     fn get_type_info(
         &mut self,
         fields: Option<&[InternedStr]>,
         type_id: TypeId,
         drop: unsafe fn(*mut ()),
+        finalize: Option<unsafe fn(*mut ()) -> Option<Finalizer>>,
+        traverse: unsafe fn(*mut (), &mut Gc),
+        mutable: bool,
     ) -> *const TypeInfo {
         match fields {
             Some(fields) => match self
@@ -700,7 +1113,11 @@ impl Gc {
                     .record_infos
                     .entry(fields.to_owned())
                     .or_insert(Box::new(TypeInfo {
+                        type_id,
                         drop,
+                        finalize,
+                        traverse,
+                        mutable,
                         generation: self.generation,
                         fields: fields
                             .iter()
@@ -713,7 +1130,11 @@ impl Gc {
             None => match self.type_infos.entry(type_id) {
                 Entry::Occupied(entry) => &**entry.get(),
                 Entry::Vacant(entry) => &**entry.insert(Box::new(TypeInfo {
+                    type_id,
                     drop,
+                    finalize,
+                    traverse,
+                    mutable,
                     generation: self.generation,
                     fields: FnvMap::default(),
                     fields_key: Arc::new(Vec::new()),
@@ -725,18 +1146,44 @@ impl Gc {
     fn alloc_ignore_limit_<D>(&mut self, size: usize, def: D) -> GcPtr<D::Value>
     where
         D: DataDef,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         unsafe fn drop<T>(t: *mut ()) {
             ptr::drop_in_place(t as *mut T);
         }
 
-        let type_info =
-            self.get_type_info(def.fields(), TypeId::of::<D::Value>(), drop::<D::Value>);
+        unsafe fn traverse<T: Traverseable>(t: *mut (), gc: &mut Gc) {
+            (*(t as *const T)).traverse(gc)
+        }
+
+        unsafe fn finalize_userdata(t: *mut ()) -> Option<Finalizer> {
+            (*(t as *mut Box<Userdata>)).finalize()
+        }
+
+        let finalize = if TypeId::of::<D::Value>() == TypeId::of::<Box<Userdata>>() {
+            Some(finalize_userdata as unsafe fn(*mut ()) -> Option<Finalizer>)
+        } else {
+            None
+        };
+
+        // Userdata and threads may store new values in themselves at any time
+        let mutable = TypeId::of::<D::Value>() == TypeId::of::<Box<Userdata>>()
+            || TypeId::of::<D::Value>() == TypeId::of::<Thread>();
+
+        let type_info = self.get_type_info(
+            def.fields(),
+            TypeId::of::<D::Value>(),
+            drop::<D::Value>,
+            finalize,
+            traverse::<D::Value>,
+            mutable,
+        );
 
         let mut ptr = AllocPtr::new::<D::Value>(type_info, size);
         ptr.next = self.values.take();
         self.allocated_memory += ptr.size();
+        self.total_allocated += ptr.size();
+        self.object_count += 1;
         unsafe {
             let p: *mut D::Value = D::Value::make_ptr(&def, ptr.value());
             let ret: *const D::Value = &*def.initialize(WriteOnly::new(p));
@@ -748,18 +1195,70 @@ impl Gc {
         }
     }
 
+    /// Continues the current collection or starts a new one if enough memory has been allocated
+    /// since the last one. Returns true if the marking of a collection was finished.
+    ///
+    /// Unsafe since `roots` must be able to traverse all accesible `GcPtr` values.
     pub unsafe fn check_collect<R>(&mut self, roots: R) -> bool
     where
         R: Traverseable + CollectScope,
     {
-        if self.allocated_memory >= self.collect_limit {
-            self.collect(roots);
-            true
+        if self.unswept.is_some() {
+            let step = self.sweep_step.unwrap_or(usize::MAX);
+            self.sweep_values(step);
+            false
+        } else if self.marking.is_some() {
+            let step = self.mark_step.unwrap_or(usize::MAX);
+            if !roots.allows_incremental_marking() || self.mark_values(step) {
+                self.finish_collect(roots);
+                true
+            } else {
+                false
+            }
+        } else if self.allocated_memory >= self.collect_limit {
+            // The heap is no longer close to the limit
+            self.limit_collect_backoff = 0;
+            match self.mark_step {
+                Some(step) if roots.allows_incremental_marking() => {
+                    info!("Start incremental collect {:?}", self.generation);
+                    self.marking = Some(MarkState::default());
+                    roots.traverse(self);
+                    if self.mark_values(step) {
+                        self.finish_collect(roots);
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ => {
+                    info!("Start collect {:?}", self.generation);
+                    self.finish_collect(roots);
+                    true
+                }
+            }
         } else {
             false
         }
     }
 
+    /// Marks all values which have yet to be marked and starts to sweep
+    unsafe fn finish_collect<R>(&mut self, roots: R)
+    where
+        R: Traverseable + CollectScope,
+    {
+        let start = now();
+        roots.scope(self, |self_| {
+            roots.traverse(self_);
+            self_.finish_marking();
+            self_.begin_sweep();
+        });
+        match self.sweep_step {
+            Some(step) => self.sweep_values(step),
+            None => self.finish_sweep(),
+        }
+        self.collected(start);
+    }
+
     /// Does a mark and sweep collection by walking from `roots`. This function is unsafe since
     /// roots need to cover all reachable object.
     pub unsafe fn collect<R>(&mut self, roots: R)
@@ -767,11 +1266,100 @@ impl Gc {
         R: Traverseable + CollectScope,
     {
         info!("Start collect {:?}", self.generation);
+        let start = now();
+        // Values left over from the last collection must be swept before marking again
+        self.finish_sweep();
         roots.scope(self, |self_| {
             roots.traverse(self_);
+            self_.finish_marking();
             self_.sweep();
-            self_.collect_limit = 2 * self_.allocated_memory;
-        })
+        });
+        self.collected(start);
+    }
+
+    /// Traverses at most `count` of the values which have been marked by an incremental
+    /// collection. Returns true if every marked value has been traversed.
+    fn mark_values(&mut self, count: usize) -> bool {
+        for _ in 0..count {
+            let header = match self.marking.as_mut().and_then(|marking| marking.gray.pop()) {
+                Some(header) => header,
+                None => return true,
+            };
+            unsafe {
+                self.traverse_header(header);
+            }
+        }
+        self.marking
+            .as_ref()
+            .map_or(true, |marking| marking.gray.is_empty())
+    }
+
+    unsafe fn traverse_header(&mut self, header: *const GcHeader) {
+        let type_info = &*(*header).type_info;
+        if type_info.mutable {
+            if let Some(ref mut marking) = self.marking {
+                marking.traversed_mutable.push(header);
+            }
+        }
+        let value = (header as *mut u8).offset(GcHeader::value_offset() as isize) as *mut ();
+        (type_info.traverse)(value, self);
+    }
+
+    /// Finishes the marking of an incremental collection by traversing the mutable values which
+    /// were traversed before the roots were traversed for the last time, along with every value
+    /// which has been marked since.
+    fn finish_marking(&mut self) {
+        let traversed_mutable = match self.marking {
+            Some(ref mut marking) => mem::replace(&mut marking.traversed_mutable, Vec::new()),
+            None => return,
+        };
+        for header in traversed_mutable {
+            unsafe {
+                self.traverse_header(header);
+            }
+        }
+        self.mark_values(usize::MAX);
+        self.marking = None;
+    }
+
+    /// Stops an incremental collection which is marking, clearing the marks it has set. Must be
+    /// called before the values of this garbage collector are marked by another collection.
+    pub fn cancel_marking(&mut self) {
+        if self.marking.take().is_some() {
+            let mut current = self.values.as_ref();
+            while let Some(ptr) = current {
+                ptr.marked.set(false);
+                current = ptr.next.as_ref();
+            }
+        }
+    }
+
+    /// Must be called after a pointer is stored in `value` if `value` is of a type which is
+    /// otherwise never changed after it has been allocated, such as a closure whose upvariables
+    /// are set after it is allocated. Lets an incremental collection traverse `value` again if it
+    /// has already been traversed.
+    pub fn write_barrier<T: ?Sized>(&mut self, value: GcPtr<T>) {
+        let generation = self.generation;
+        if let Some(ref mut marking) = self.marking {
+            let header = value.header();
+            if header.marked.get() && !header.generation().is_parent_of(generation) {
+                marking.gray.push(header);
+            }
+        }
+    }
+
+    fn collected(&mut self, start: Option<Instant>) {
+        self.collections += 1;
+        self.last_pause = start.map_or(Duration::default(), |start| start.elapsed());
+        let stats = self.heap_stats();
+        if let Some(ref mut callback) = self.collect_callback.0 {
+            callback(&stats);
+        }
+    }
+
+    /// Sweeps all values which are left over from an incremental collection
+    pub fn finish_sweep(&mut self) {
+        self.sweep_values(usize::MAX);
     }
 
     /// Marks the GcPtr
@@ -779,7 +1367,13 @@ impl Gc {
     pub fn mark<T: ?Sized>(&mut self, value: GcPtr<T>) -> bool {
         let header = value.header();
         // We only need to mark and traverse values from this garbage collectors generation
-        if header.generation().is_parent_of(self.generation()) || header.marked.get() {
+        if header.generation().is_parent_of(self.generation()) {
+            return true;
+        }
+        if let Some(ref mut recorder) = self.recorder {
+            return recorder.visit(header);
+        }
+        if header.marked.get() {
             true
         } else {
             header.marked.set(true);
@@ -787,57 +1381,139 @@ impl Gc {
         }
     }
 
+    /// Takes a snapshot of every value allocated by this garbage collector along with the
+    /// references between the values which are reachable from `roots`. `type_name` names the
+    /// type of each value given the `TypeId` it was allocated with.
+    ///
+    /// Unsafe since `roots` must be able to traverse all accesible `GcPtr` values.
+    pub unsafe fn heap_snapshot<R>(
+        &mut self,
+        roots: R,
+        type_name: fn(TypeId) -> &'static str,
+    ) -> HeapSnapshot
+    where
+        R: Traverseable,
+    {
+        self.finish_sweep();
+
+        let mut recorder = HeapRecorder::default();
+        let mut objects = Vec::new();
+        {
+            let mut current = self.values.as_ref();
+            while let Some(ptr) = current {
+                let header: &GcHeader = ptr;
+                let type_info = &*header.type_info;
+                recorder.ids.insert(header, objects.len());
+                objects.push(HeapObject {
+                    type_name: type_name(type_info.type_id),
+                    fields: type_info.fields_key.iter().map(|s| s.to_string()).collect(),
+                    size: GcHeader::value_offset() + header.value_size,
+                    references: Vec::new(),
+                });
+                current = header.next.as_ref();
+            }
+        }
+
+        self.recorder = Some(Box::new(recorder));
+        roots.traverse(self);
+        let recorder = self.recorder.take().expect("Recorder");
+
+        let mut root_ids = Vec::new();
+        for (from, to) in recorder.edges {
+            match from {
+                Some(from) => objects[from].references.push(to),
+                None => root_ids.push(to),
+            }
+        }
+        HeapSnapshot {
+            objects,
+            roots: root_ids,
+        }
+    }
+
+    /// Traverses the value behind `header` with `f`, attributing any references found to it
+    fn record_references<F>(&mut self, header: &GcHeader, f: F)
+    where
+        F: FnOnce(&mut Gc),
+    {
+        let id = self
+            .recorder
+            .as_ref()
+            .and_then(|recorder| recorder.ids.get(&(header as *const _)).cloned());
+        let parent = self
+            .recorder
+            .as_mut()
+            .map(|recorder| mem::replace(&mut recorder.current, id));
+        f(self);
+        if let (Some(recorder), Some(parent)) = (self.recorder.as_mut(), parent) {
+            recorder.current = parent;
+        }
+    }
+
     /// Clears out any unmarked pointers and resets marked pointers.
     ///
     /// Unsafe as it is up to the caller to make sure that all reachable pointers have been marked
     pub unsafe fn sweep(&mut self) {
-        fn moving<T>(t: T) -> T {
-            t
+        self.begin_sweep();
+        self.finish_sweep();
+    }
+
+    /// Moves all values to the unswept list so that they can be swept by `sweep_values`.
+    ///
+    /// Unsafe as it is up to the caller to make sure that all reachable pointers have been marked
+    unsafe fn begin_sweep(&mut self) {
+        debug_assert!(self.unswept.is_none());
+        // Clear the weak references to values which are about to be freed while the marks are
+        // still set. Slots without any `WeakPtr` left are dropped as well.
+        self.weak_slots
+            .0
+            .retain(|slot| Arc::strong_count(slot) > 1 && slot.sweep());
+
+        self.unswept = self.values.take();
+    }
+
+    /// Sweeps at most `count` values of the unswept list. Values which are still reachable are
+    /// unmarked and moved back to the list of allocated values.
+    fn sweep_values(&mut self, count: usize) {
+        if self.unswept.is_none() {
+            return;
         }
 
-        let mut count = 0;
+        let mut traversed = 0;
         let mut free_count = 0;
-
-        let mut first = self.values.take();
-        {
-            // Pointer to the current pointer (if it exists)
-            let mut maybe_header = &mut first;
-            loop {
-                let mut free = false;
-                let mut replaced_next = None;
-                match *maybe_header {
-                    Some(ref mut header) => {
-                        // If the current pointer is not marked we take the rest of the list and
-                        // move it to `replaced_next`
-                        if !header.marked.get() {
-                            replaced_next = header.next.take();
-                            free = true;
-                        } else {
-                            header.marked.set(false);
-                        }
-                    }
-                    // Reached the end of the list
-                    None => break,
-                }
-                count += 1;
-                if free {
-                    free_count += 1;
-                    // Free the current pointer
-                    self.free(maybe_header.take());
-                    *maybe_header = replaced_next;
-                } else {
-                    // Just move to the next pointer
-                    maybe_header = &mut moving(maybe_header).as_mut().unwrap().next;
-                }
+        while traversed < count {
+            let mut header = match self.unswept.take() {
+                Some(header) => header,
+                None => break,
+            };
+            self.unswept = header.next.take();
+            traversed += 1;
+            if header.marked.get() {
+                header.marked.set(false);
+                header.next = self.values.take();
+                self.values = Some(header);
+            } else {
+                free_count += 1;
+                self.free(Some(header));
             }
         }
-        info!("GC: Freed {} / Traversed {}", free_count, count);
-        self.values = first;
+        info!("GC: Freed {} / Traversed {}", free_count, traversed);
+
+        if self.unswept.is_none() {
+            self.collect_limit = 2 * self.allocated_memory;
+        }
     }
 
-    fn free(&mut self, header: Option<AllocPtr>) {
-        if let Some(ref ptr) = header {
+    fn free(&mut self, mut header: Option<AllocPtr>) {
+        if let Some(ref mut ptr) = header {
             self.allocated_memory -= ptr.size();
+            self.object_count -= 1;
+            let finalize = unsafe { (*ptr.type_info).finalize };
+            if let Some(finalize) = finalize {
+                if let Some(finalizer) = unsafe { finalize(ptr.value()) } {
+                    self.finalizers.0.push(finalizer);
+                }
+            }
         }
         debug!("FREE: {:?}", header);
         drop(header);
@@ -871,10 +1547,18 @@ mod tests {
         {
             f(gc)
         }
+
+        fn allows_incremental_marking(&self) -> bool {
+            true
+        }
     }
 
     fn object_count(gc: &Gc) -> usize {
-        let mut header: &GcHeader = match gc.values {
+        list_count(&gc.values) + list_count(&gc.unswept)
+    }
+
+    fn list_count(list: &Option<AllocPtr>) -> usize {
+        let mut header: &GcHeader = match *list {
             Some(ref x) => &**x,
             None => return 0,
         };
@@ -919,6 +1603,11 @@ mod tests {
             result.write(self.elems.to_owned())
         }
     }
+    impl<'a> Traverseable for Def<'a> {
+        fn traverse(&self, gc: &mut Gc) {
+            self.elems.traverse(gc)
+        }
+    }
 
     #[derive(Copy, Clone, PartialEq, Debug)]
     enum Value {
@@ -987,6 +1676,8 @@ mod tests {
         }
     }
 
+    impl Traverseable for Dropable {}
+
     #[test]
     fn drop() {
         let dropped = Rc::new(Cell::new(false));
@@ -1004,4 +1695,207 @@ mod tests {
         }
         assert_eq!(true, dropped.get());
     }
+
+    #[test]
+    fn weak_ptr() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        let mut stack: Vec<Value> = Vec::new();
+        let kept = gc.alloc(Def { elems: &[Int(1)] }).unwrap();
+        let collected = gc.alloc(Def { elems: &[Int(2)] }).unwrap();
+        stack.push(new_data(kept));
+
+        let weak_kept = gc.new_weak(kept);
+        let weak_collected = gc.new_weak(collected);
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        assert_eq!(object_count(&gc), 1);
+        assert!(weak_kept.upgrade().map_or(false, |ptr| ptr.ptr_eq(&kept)));
+        assert!(weak_collected.upgrade().is_none());
+        assert_eq!(gc.weak_slots.0.len(), 1);
+
+        mem::drop(weak_kept);
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        assert_eq!(gc.weak_slots.0.len(), 0);
+    }
+
+    #[test]
+    fn incremental_sweep() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        gc.set_sweep_step(Some(2));
+        gc.collect_limit = 0;
+        let mut stack: Vec<Value> = Vec::new();
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(1)] }).unwrap()));
+        for i in 0..4 {
+            gc.alloc(Def { elems: &[Int(i)] }).unwrap();
+        }
+        assert_eq!(object_count(&gc), 5);
+
+        let ptr = unsafe { gc.alloc_and_collect(&mut *stack, Def { elems: &[Int(5)] }) }.unwrap();
+        stack.push(new_data(ptr));
+        // Only the first two values were swept by the collection
+        assert_eq!(list_count(&gc.unswept), 3);
+        assert_eq!(object_count(&gc), 4);
+
+        unsafe {
+            gc.check_collect(&mut *stack);
+        }
+        assert_eq!(list_count(&gc.unswept), 1);
+
+        gc.finish_sweep();
+        assert_eq!(list_count(&gc.unswept), 0);
+        assert_eq!(object_count(&gc), 2);
+    }
+
+    #[test]
+    fn incremental_mark() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        gc.set_mark_step(Some(1));
+        gc.set_sweep_step(None);
+        gc.collect_limit = 0;
+        let mut stack: Vec<Value> = Vec::new();
+        let leaf = gc.alloc(Def { elems: &[Int(1)] }).unwrap();
+        let middle = gc.alloc(Def { elems: &[new_data(leaf)] }).unwrap();
+        stack.push(new_data(gc.alloc(Def { elems: &[new_data(middle)] }).unwrap()));
+        for i in 0..2 {
+            gc.alloc(Def { elems: &[Int(i)] }).unwrap();
+        }
+        assert_eq!(object_count(&gc), 5);
+
+        // Each step only traverses one value
+        assert!(!unsafe { gc.check_collect(&mut *stack) });
+        assert!(!unsafe { gc.check_collect(&mut *stack) });
+        assert!(gc.marking.is_some());
+
+        // Values allocated while marking are kept if they are reachable when marking finishes
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(2)] }).unwrap()));
+        gc.alloc(Def { elems: &[Int(3)] }).unwrap();
+
+        while !unsafe { gc.check_collect(&mut *stack) } {}
+        assert!(gc.marking.is_none());
+        assert_eq!(object_count(&gc), 4);
+        match stack[1] {
+            Data(ref data) => assert_eq!(data.fields[0], Int(2)),
+            _ => ice!(),
+        }
+    }
+
+    #[test]
+    fn write_barrier_traverses_changed_values_again() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        gc.set_mark_step(Some(1));
+        gc.set_sweep_step(None);
+        gc.collect_limit = 0;
+        let mut stack: Vec<Value> = Vec::new();
+        let mut changed = gc.alloc(Def { elems: &[Int(0)] }).unwrap();
+        stack.push(new_data(changed));
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(1)] }).unwrap()));
+
+        // `changed` is traversed by the first step
+        assert!(!unsafe { gc.check_collect(&mut *stack) });
+        assert!(changed.header().marked.get());
+
+        let stored = gc.alloc(Def { elems: &[Int(2)] }).unwrap();
+        unsafe {
+            changed.as_mut()[0] = new_data(stored);
+        }
+        gc.write_barrier(changed);
+
+        while !unsafe { gc.check_collect(&mut *stack) } {}
+        assert_eq!(object_count(&gc), 3);
+        assert_eq!(stored[0], Int(2));
+    }
+
+    #[test]
+    fn collections_near_the_memory_limit_back_off() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        gc.set_sweep_step(None);
+        gc.collect_limit = usize::MAX;
+        gc.alloc(Def { elems: &[Int(0)] }).unwrap();
+        let value_size = gc.allocated_memory();
+        gc.set_memory_limit(100 * value_size);
+
+        let mut stack: Vec<Value> = Vec::new();
+        while gc.allocated_memory() + 3 * value_size < 100 * value_size {
+            stack.push(new_data(gc.alloc(Def { elems: &[Int(0)] }).unwrap()));
+        }
+
+        // Only a few garbage values fit next to the live values but as they can be reclaimed
+        // every allocation must succeed
+        let collections = gc.collections;
+        for _ in 0..200 {
+            unsafe { gc.alloc_and_collect(&mut *stack, Def { elems: &[Int(0)] }) }.unwrap();
+        }
+        assert!(gc.collections > collections);
+
+        let collections = gc.collections;
+        while gc.collections == collections {
+            unsafe { gc.alloc_and_collect(&mut *stack, Def { elems: &[Int(0)] }) }.unwrap();
+        }
+
+        // The last collection freed little so an allocation which fits does not collect even
+        // though `collect_limit` is reached
+        gc.collect_limit = 0;
+        let collections = gc.collections;
+        unsafe { gc.alloc_and_collect(&mut *stack, Def { elems: &[Int(0)] }) }.unwrap();
+        assert_eq!(gc.collections, collections);
+    }
+
+    #[test]
+    fn heap_stats() {
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        let collected = Arc::new(Mutex::new(Vec::new()));
+        {
+            let collected = collected.clone();
+            gc.set_collect_callback(Some(Box::new(move |stats: &HeapStats| {
+                collected.lock().unwrap().push(*stats);
+            })));
+        }
+
+        let mut stack: Vec<Value> = Vec::new();
+        stack.push(new_data(gc.alloc(Def { elems: &[Int(1)] }).unwrap()));
+        gc.alloc(Def { elems: &[Int(2)] }).unwrap();
+        let stats = gc.heap_stats();
+        assert_eq!(stats.live_objects, 2);
+        assert_eq!(stats.collections, 0);
+        assert_eq!(stats.total_allocated, stats.allocated_memory);
+
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        let stats = gc.heap_stats();
+        assert_eq!(stats.live_objects, 1);
+        assert_eq!(stats.collections, 1);
+        assert!(stats.total_allocated > stats.allocated_memory);
+        assert_eq!(*collected.lock().unwrap(), [stats]);
+    }
+
+    #[test]
+    fn heap_snapshot() {
+        fn type_name(_: TypeId) -> &'static str {
+            "Test"
+        }
+
+        let mut gc: Gc = Gc::new(Generation::default(), usize::MAX);
+        let mut stack: Vec<Value> = Vec::new();
+        let child = gc.alloc(Def { elems: &[Int(1)] }).unwrap();
+        stack.push(new_data(gc.alloc(Def { elems: &[new_data(child)] }).unwrap()));
+        gc.alloc(Def { elems: &[Int(2)] }).unwrap();
+
+        let snapshot = unsafe { gc.heap_snapshot(&mut *stack, type_name) };
+        assert_eq!(snapshot.objects.len(), 3);
+        assert_eq!(snapshot.roots, [1]);
+        assert_eq!(snapshot.objects[1].references, [2]);
+        assert!(snapshot.objects[0].references.is_empty());
+        assert!(snapshot.objects[2].references.is_empty());
+        assert_eq!(snapshot.objects[2].type_name, "Test");
+
+        // Taking a snapshot must not leave any values marked
+        unsafe {
+            gc.collect(&mut *stack);
+        }
+        assert_eq!(object_count(&gc), 2);
+    }
 }
@@ -24,6 +24,9 @@ extern crate pretty;
 extern crate quick_error;
 #[cfg(not(target_arch = "wasm32"))]
 extern crate tokio_core;
+extern crate caseless;
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
 
 #[cfg(feature = "serde_derive")]
 #[macro_use]
@@ -34,6 +37,8 @@ extern crate serde_derive_state;
 #[cfg(feature = "serde_state")]
 #[macro_use]
 extern crate serde_state as serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
 
 #[macro_use]
 extern crate gluon_base as base;
@@ -49,18 +54,24 @@ pub mod channel;
 pub mod compiler;
 pub mod core;
 pub mod debug;
+pub mod debugger;
 pub mod dynamic;
 #[macro_use]
 pub mod future;
 pub mod gc;
 pub mod lazy;
 pub mod macros;
+pub mod peephole;
 pub mod primitives;
+pub mod profiler;
 pub mod reference;
 pub mod stack;
+pub mod sync;
 pub mod thread;
 pub mod types;
+pub mod verifier;
 pub mod vm;
+pub mod weak;
 
 mod array;
 mod interner;
@@ -147,9 +158,25 @@ quick_error! {
             display("{}", err)
             from()
         }
+        Marshal(err: api::MarshalError) {
+            display("{}", err)
+            from()
+        }
         Interrupted {
             display("Thread was interrupted")
         }
+        OutOfFuel {
+            display("Thread ran out of fuel")
+        }
+        TimedOut {
+            display("Thread exceeded its deadline")
+        }
+        IntegerOverflow {
+            display("Integer arithmetic overflowed")
+        }
+        InvalidBytecode(function: String, instruction: usize, message: String) {
+            display("Invalid bytecode in `{}` at instruction {}: {}", function, instruction, message)
+        }
         Panic(err: String, stacktrace: Option<Stacktrace>) {
             display("{}", Panic { err, stacktrace })
         }
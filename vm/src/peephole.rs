@@ -0,0 +1,364 @@
+//! Peephole optimizations of the instructions emitted by the compiler
+
+use compiler::CompiledFunction;
+use types::Instruction::{self, *};
+use types::VmIndex;
+
+/// Optimizes the instructions of `function` and all of its inner functions.
+///
+/// Each pass looks at short sequences of instructions which no jump enters in the middle of and
+/// replaces them with cheaper sequences which have the same effect:
+///
+/// * Arithmetic and comparisons on constants are computed at compile time
+/// * Values which are popped directly after being pushed are never pushed
+/// * Values which are slid off the stack before anything reads the stack are never pushed
+/// * Consecutive `Pop` or `Slide` instructions are merged into one
+/// * Jumps to another jump go directly to the final target and jumps to the next instruction are
+///   removed
+///
+/// Jump targets and debug information are updated to the new instruction indexes.
+pub fn optimize(function: &mut CompiledFunction) {
+    for inner in &mut function.inner_functions {
+        optimize(inner);
+    }
+    while optimize_pass(function) {}
+}
+
+/// Runs one pass over `function`, returning `true` if anything was changed
+fn optimize_pass(function: &mut CompiledFunction) -> bool {
+    let mut changed = thread_jumps(&mut function.instructions);
+
+    let len = function.instructions.len();
+    let mut is_target = vec![false; len + 1];
+    for instruction in &function.instructions {
+        match *instruction {
+            Jump(target) | CJump(target) => is_target[target as usize] = true,
+            _ => (),
+        }
+    }
+
+    let mut removed = vec![false; len];
+    {
+        let instructions = &mut function.instructions;
+        let mut i = 0;
+        while i < len {
+            // Instructions after `i` may only be part of the sequence if no jump enters them
+            let available = 1 + (i + 1..len).take_while(|&j| !is_target[j]).count();
+            let consumed = rewrite(&mut instructions[i..i + available], &mut removed[i..], i);
+            if consumed != 0 {
+                changed = true;
+                i += consumed;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    if removed.iter().any(|&removed| removed) {
+        remove_instructions(function, &removed);
+    }
+    changed
+}
+
+/// Tries to rewrite the sequence starting at `instructions[0]`, which is at `index` in the
+/// function. Returns the number of instructions which were rewritten or removed.
+fn rewrite(instructions: &mut [Instruction], removed: &mut [bool], index: usize) -> usize {
+    let (first, second, third) = {
+        let at = |i: usize| instructions.get(i).cloned();
+        (at(0), at(1), at(2))
+    };
+
+    if let (Some(l), Some(r), Some(op)) = (first, second, third) {
+        if let Some(constant) = fold(l, r, op) {
+            instructions[0] = constant;
+            removed[1] = true;
+            removed[2] = true;
+            return 3;
+        }
+    }
+    if let Some(push) = first {
+        if is_pure_push(push) {
+            // `push` is removed by the `Slide` if no more values than it removes are pushed
+            // after `push`
+            let pushes = instructions[1..]
+                .iter()
+                .take_while(|&&instruction| is_stack_independent_push(instruction))
+                .count();
+            match instructions.get(1 + pushes).cloned() {
+                Some(Slide(n)) if pushes != 0 && pushes as VmIndex <= n => {
+                    removed[0] = true;
+                    instructions[1 + pushes] = Slide(n - 1);
+                    return 2 + pushes;
+                }
+                _ => (),
+            }
+        }
+    }
+    match (first, second) {
+        (Some(Construct { tag, args: 0 }), Some(CJump(target))) => {
+            if tag == 0 {
+                removed[0] = true;
+            } else {
+                instructions[0] = Jump(target);
+            }
+            removed[1] = true;
+            2
+        }
+        (Some(push), Some(Pop(n))) if is_pure_push(push) => {
+            removed[0] = true;
+            if n == 1 {
+                removed[1] = true;
+            } else {
+                instructions[1] = Pop(n - 1);
+            }
+            2
+        }
+        (Some(Pop(l)), Some(Pop(r))) => {
+            instructions[0] = Pop(l + r);
+            removed[1] = true;
+            2
+        }
+        (Some(Slide(l)), Some(Slide(r))) => {
+            instructions[0] = Slide(l + r);
+            removed[1] = true;
+            2
+        }
+        (Some(Pop(0)), _) | (Some(Slide(0)), _) => {
+            removed[0] = true;
+            1
+        }
+        (Some(Jump(target)), _) if target as usize == index + 1 => {
+            removed[0] = true;
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Instructions which only push a value without any other effect
+fn is_pure_push(instruction: Instruction) -> bool {
+    match instruction {
+        PushInt(_) | PushByte(_) | PushFloat(_) | PushString(_) | PushUpVar(_) | Push(_) => true,
+        Construct { args: 0, .. } => true,
+        _ => false,
+    }
+}
+
+/// Instructions which push a value without any other effect or reading the stack
+fn is_stack_independent_push(instruction: Instruction) -> bool {
+    match instruction {
+        Push(_) => false,
+        instruction => is_pure_push(instruction),
+    }
+}
+
+fn bool_tag(b: bool) -> Instruction {
+    Construct {
+        tag: b as VmIndex,
+        args: 0,
+    }
+}
+
+/// Computes `l op r` if both operands are constants. Operations which would fail at runtime,
+/// such as overflow or division by zero, are left to fail at runtime.
+fn fold(l: Instruction, r: Instruction, op: Instruction) -> Option<Instruction> {
+    Some(match (l, r) {
+        (PushInt(l), PushInt(r)) => match op {
+            AddInt => PushInt(l.checked_add(r)?),
+            SubtractInt => PushInt(l.checked_sub(r)?),
+            MultiplyInt => PushInt(l.checked_mul(r)?),
+            DivideInt => PushInt(l.checked_div(r)?),
+            IntLT => bool_tag(l < r),
+            IntEQ => bool_tag(l == r),
+            _ => return None,
+        },
+        (PushByte(l), PushByte(r)) => match op {
+            AddByte => PushByte(l.checked_add(r)?),
+            SubtractByte => PushByte(l.checked_sub(r)?),
+            MultiplyByte => PushByte(l.checked_mul(r)?),
+            DivideByte => PushByte(l.checked_div(r)?),
+            ByteLT => bool_tag(l < r),
+            ByteEQ => bool_tag(l == r),
+            _ => return None,
+        },
+        (PushFloat(l), PushFloat(r)) => match op {
+            AddFloat => PushFloat(l + r),
+            SubtractFloat => PushFloat(l - r),
+            MultiplyFloat => PushFloat(l * r),
+            DivideFloat => PushFloat(l / r),
+            FloatLT => bool_tag(l < r),
+            FloatEQ => bool_tag(l == r),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// Makes jumps to a `Jump` instruction jump directly to its target
+fn thread_jumps(instructions: &mut [Instruction]) -> bool {
+    let mut changed = false;
+    for i in 0..instructions.len() {
+        let target = match instructions[i] {
+            Jump(target) | CJump(target) => target,
+            _ => continue,
+        };
+        let mut new_target = target;
+        // Bound the number of steps as a jump may be part of an infinite loop
+        for _ in 0..instructions.len() {
+            match instructions.get(new_target as usize) {
+                Some(&Jump(next)) if next != new_target => new_target = next,
+                _ => break,
+            }
+        }
+        if new_target != target {
+            changed = true;
+            instructions[i] = match instructions[i] {
+                Jump(_) => Jump(new_target),
+                _ => CJump(new_target),
+            };
+        }
+    }
+    changed
+}
+
+fn remove_instructions(function: &mut CompiledFunction, removed: &[bool]) {
+    // Maps each old index to the index of the first instruction which is kept at or after it
+    let mut new_indexes = Vec::with_capacity(removed.len() + 1);
+    let mut new_index = 0;
+    for &removed in removed {
+        new_indexes.push(new_index);
+        if !removed {
+            new_index += 1;
+        }
+    }
+    new_indexes.push(new_index);
+
+    let instructions = function
+        .instructions
+        .iter()
+        .zip(removed)
+        .filter(|&(_, &removed)| !removed)
+        .map(|(&instruction, _)| match instruction {
+            Jump(target) => Jump(new_indexes[target as usize] as VmIndex),
+            CJump(target) => CJump(new_indexes[target as usize] as VmIndex),
+            instruction => instruction,
+        })
+        .collect();
+    function.instructions = instructions;
+
+    let debug_info = &mut function.debug_info;
+    debug_info
+        .source_map
+        .map_instruction_indexes(|index| new_indexes[index]);
+    debug_info
+        .local_map
+        .map_instruction_indexes(|index| new_indexes[index]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base::pos::{Column, Line};
+    use base::symbol::Symbol;
+    use base::types::Type;
+
+    fn optimized(instructions: Vec<Instruction>) -> CompiledFunction {
+        let mut function =
+            CompiledFunction::new(0, Symbol::from("test"), Type::hole(), "test".into());
+        function.instructions = instructions;
+        optimize(&mut function);
+        function
+    }
+
+    #[test]
+    fn fold_constants() {
+        let function = optimized(vec![PushInt(1), PushInt(2), AddInt, PushInt(3), MultiplyInt]);
+        assert_eq!(function.instructions, vec![PushInt(9)]);
+    }
+
+    #[test]
+    fn do_not_fold_division_by_zero() {
+        let instructions = vec![PushInt(1), PushInt(0), DivideInt];
+        assert_eq!(optimized(instructions.clone()).instructions, instructions);
+    }
+
+    #[test]
+    fn remove_dead_push() {
+        let function = optimized(vec![PushInt(1), PushInt(2), Pop(1), Slide(0)]);
+        assert_eq!(function.instructions, vec![PushInt(1)]);
+    }
+
+    #[test]
+    fn remove_dead_store() {
+        // let x = y in 1
+        let function = optimized(vec![Push(0), PushInt(1), Slide(1)]);
+        assert_eq!(function.instructions, vec![PushInt(1)]);
+
+        let function = optimized(vec![PushInt(1), PushInt(2), PushString(0), Slide(2)]);
+        assert_eq!(function.instructions, vec![PushString(0)]);
+
+        let function = optimized(vec![Push(0), PushInt(2), PushInt(3), Slide(1)]);
+        assert_eq!(function.instructions, vec![Push(0), PushInt(3)]);
+    }
+
+    #[test]
+    fn keep_stores_which_are_read() {
+        let instructions = vec![PushInt(1), Push(0), Slide(1)];
+        assert_eq!(optimized(instructions.clone()).instructions, instructions);
+    }
+
+    #[test]
+    fn merge_slides() {
+        let function = optimized(vec![Push(0), Push(1), Push(2), Slide(1), Slide(1)]);
+        assert_eq!(
+            function.instructions,
+            vec![Push(0), Push(1), Push(2), Slide(2)]
+        );
+    }
+
+    #[test]
+    fn thread_jumps_and_update_targets() {
+        let function = optimized(vec![
+            PushInt(1),
+            CJump(4),
+            PushInt(2),
+            Jump(6),
+            Jump(5),
+            PushInt(3),
+            Slide(0),
+        ]);
+        assert_eq!(
+            function.instructions,
+            vec![PushInt(1), CJump(4), PushInt(2), Jump(5), PushInt(3)]
+        );
+    }
+
+    #[test]
+    fn do_not_rewrite_across_jump_targets() {
+        let instructions = vec![Push(0), CJump(4), PushInt(1), Jump(5), PushInt(2), AddInt];
+        assert_eq!(optimized(instructions.clone()).instructions, instructions);
+    }
+
+    #[test]
+    fn update_source_map() {
+        let mut function =
+            CompiledFunction::new(0, Symbol::from("test"), Type::hole(), "test".into());
+        function.instructions = vec![PushInt(1), PushInt(2), AddInt, PushInt(4)];
+        for (i, line) in [0, 0, 1, 2].iter().enumerate() {
+            function.debug_info.source_map.emit(
+                i,
+                Line::from(*line as u32),
+                Column::from(0),
+            );
+        }
+        function.debug_info.source_map.close(4, None);
+        optimize(&mut function);
+
+        assert_eq!(function.instructions, vec![PushInt(3), PushInt(4)]);
+        let source_map = &function.debug_info.source_map;
+        assert_eq!(source_map.line(0), Some(Line::from(0)));
+        assert_eq!(source_map.line(1), Some(Line::from(2)));
+        assert_eq!(source_map.line(2), None);
+    }
+}
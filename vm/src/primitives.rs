@@ -1,15 +1,23 @@
 //! Module containing functions for interacting with gluon's primitive types.
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
 use std::result::Result as StdResult;
 use std::str::FromStr;
 use std::string::String as StdString;
+use std::sync::Mutex;
 
 use api::generic::A;
-use api::{generic, primitive, Array, Generic, Getable, Pushable, RuntimeResult, ValueRef, WithVM};
-use gc::{DataDef, Gc, Traverseable, WriteOnly};
-use stack::StackFrame;
+use api::{
+    generic, primitive, Array, Generic, Getable, Pushable, RuntimeResult, Userdata, ValueRef,
+    VmType, WithVM,
+};
+use gc::{DataDef, Gc, GcPtr, Move, Traverseable, WriteOnly};
+use stack::{StackFrame, State};
 use thread::ThreadInternal;
 use types::VmInt;
-use value::{Def, GcStr, Repr, ValueArray, ValueRepr};
+use base::types::{ArcType, Type};
+use value::{Cloner, Def, GcStr, Repr, Value, ValueArray, ValuePrinter, ValueRepr};
 use vm::{Status, Thread};
 use Result;
 use {Error, ExternModule, Variants};
@@ -93,6 +101,98 @@ pub mod array {
             ))
         }
     }
+
+    /// A growable array which lets gluon collect elements in linear time instead of copying the
+    /// array on every `append`
+    pub struct ArrayBuilder<T> {
+        values: Mutex<Vec<Value>>,
+        // The thread which owns the values stored in the builder
+        thread: GcPtr<Thread>,
+        _marker: PhantomData<T>,
+    }
+
+    impl<T> Userdata for ArrayBuilder<T> where T: Any + Send + Sync {}
+
+    impl<T> fmt::Debug for ArrayBuilder<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "ArrayBuilder({:?})", *self.values.lock().unwrap())
+        }
+    }
+
+    impl<T> Traverseable for ArrayBuilder<T> {
+        fn traverse(&self, gc: &mut Gc) {
+            self.values.lock().unwrap().traverse(gc)
+        }
+    }
+
+    impl<T> VmType for ArrayBuilder<T>
+    where
+        T: VmType,
+        T::Type: Sized,
+    {
+        type Type = ArrayBuilder<T::Type>;
+
+        fn make_type(vm: &Thread) -> ArcType {
+            let env = vm.global_env().get_env();
+            let symbol = env.find_type_info("ArrayBuilder").unwrap().name.clone();
+            Type::app(Type::ident(symbol), collect![T::make_type(vm)])
+        }
+    }
+
+    pub fn builder_new(WithVM { vm, .. }: WithVM<()>) -> ArrayBuilder<A> {
+        ArrayBuilder {
+            values: Mutex::new(Vec::new()),
+            thread: unsafe { GcPtr::from_raw(vm) },
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn builder_push(builder: &ArrayBuilder<A>, value: Generic<A>) -> RuntimeResult<(), String> {
+        let value = unsafe {
+            builder
+                .thread
+                .deep_clone_value(&builder.thread, value.get_value())
+        };
+        match value {
+            Ok(value) => {
+                builder.values.lock().unwrap().push(value);
+                RuntimeResult::Return(())
+            }
+            Err(err) => RuntimeResult::Panic(err.to_string()),
+        }
+    }
+
+    pub fn builder_len(builder: &ArrayBuilder<A>) -> VmInt {
+        builder.values.lock().unwrap().len() as VmInt
+    }
+
+    pub fn builder_build(builder: &ArrayBuilder<A>) -> Vec<Generic<A>> {
+        builder
+            .values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|value| Generic::from(value.clone()))
+            .collect()
+    }
+}
+
+#[doc(hidden)]
+pub mod bytes {
+    use super::*;
+
+    pub fn slice(bytes: &[u8], start: usize, end: usize) -> RuntimeResult<&[u8], String> {
+        if start <= end && end <= bytes.len() {
+            RuntimeResult::Return(&bytes[start..end])
+        } else {
+            RuntimeResult::Panic(format!(
+                "Range {}..{} is out of bounds for bytes of length {}",
+                start,
+                end,
+                bytes.len()
+            ))
+        }
+    }
 }
 
 mod string {
@@ -213,6 +313,112 @@ mod string {
             &s[..(s.len() - iter.as_str().len())]
         ))
     }
+
+    pub fn try_slice(s: &str, start: usize, end: usize) -> Option<&str> {
+        if start <= end && s.is_char_boundary(start) && s.is_char_boundary(end) {
+            Some(&s[start..end])
+        } else {
+            None
+        }
+    }
+
+    /// Splits `s` into its extended grapheme clusters, the units a reader perceives as characters
+    pub fn graphemes(s: &str) -> Vec<StdString> {
+        use unicode_segmentation::UnicodeSegmentation;
+        s.graphemes(true).map(StdString::from).collect()
+    }
+
+    pub fn char_count(s: &str) -> VmInt {
+        s.chars().count() as VmInt
+    }
+
+    pub fn to_lowercase(s: &str) -> StdString {
+        s.to_lowercase()
+    }
+
+    pub fn to_uppercase(s: &str) -> StdString {
+        s.to_uppercase()
+    }
+
+    /// Folds the case of `s` so that strings which only differ in case compare equal, unlike
+    /// `to_lowercase` this also maps characters such as `ß` to `ss`
+    pub fn case_fold(s: &str) -> StdString {
+        ::caseless::default_case_fold_str(s)
+    }
+
+    pub fn nfc(s: &str) -> StdString {
+        use unicode_normalization::UnicodeNormalization;
+        s.nfc().collect()
+    }
+
+    pub fn nfd(s: &str) -> StdString {
+        use unicode_normalization::UnicodeNormalization;
+        s.nfd().collect()
+    }
+
+    pub fn nfkc(s: &str) -> StdString {
+        use unicode_normalization::UnicodeNormalization;
+        s.nfkc().collect()
+    }
+
+    pub fn nfkd(s: &str) -> StdString {
+        use unicode_normalization::UnicodeNormalization;
+        s.nfkd().collect()
+    }
+
+    /// Concatenates all strings in `strings`, allocating the result once
+    pub fn concat(strings: Array<StdString>) -> StdString {
+        let array = strings.get_value_array();
+        let mut len = 0;
+        for value in array.iter() {
+            if let ValueRef::String(s) = value.as_ref() {
+                len += s.len();
+            }
+        }
+        let mut result = StdString::with_capacity(len);
+        for value in array.iter() {
+            if let ValueRef::String(s) = value.as_ref() {
+                result.push_str(s);
+            }
+        }
+        result
+    }
+
+    /// A growable string buffer which lets gluon build a string in linear time
+    #[derive(Debug)]
+    pub struct StringBuilder(Mutex<StdString>);
+
+    impl Userdata for StringBuilder {
+        fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<GcPtr<Box<Userdata>>> {
+            let contents = self.0.lock().unwrap().clone();
+            let data: Box<Userdata> = Box::new(StringBuilder(Mutex::new(contents)));
+            deep_cloner.gc().alloc(Move(data))
+        }
+    }
+
+    impl VmType for StringBuilder {
+        type Type = StringBuilder;
+    }
+
+    impl Traverseable for StringBuilder {
+        fn traverse(&self, _: &mut Gc) {}
+    }
+
+    pub fn builder_new(_: ()) -> StringBuilder {
+        StringBuilder(Mutex::new(StdString::new()))
+    }
+
+    pub fn builder_append(builder: &StringBuilder, s: &str) {
+        builder.0.lock().unwrap().push_str(s);
+    }
+
+    pub fn builder_len(builder: &StringBuilder) -> VmInt {
+        builder.0.lock().unwrap().len() as VmInt
+    }
+
+    pub fn builder_build(builder: &StringBuilder) -> StdString {
+        builder.0.lock().unwrap().clone()
+    }
 }
 
 fn parse<T>(s: &str) -> StdResult<T, ()>
@@ -244,6 +450,41 @@ extern "C" fn error(_: &Thread) -> Status {
     Status::Error
 }
 
+/// Fails with `message` followed by `value`, printed using the type at `type_index` in the debug
+/// info of the calling function (see `Compiler::compile_unmatched_pattern`)
+fn unmatched_pattern(
+    WithVM { vm, value: message }: WithVM<&str>,
+    type_index: VmInt,
+    value: Generic<A>,
+) -> RuntimeResult<Generic<generic::B>, StdString> {
+    let typ = {
+        let context = vm.context();
+        let caller = context
+            .stack
+            .get_frames()
+            .iter()
+            .rev()
+            .filter_map(|frame| match frame.state {
+                State::Closure(ref closure) => Some(closure.function),
+                _ => None,
+            })
+            .next();
+        caller.and_then(|function| function.debug_info.types.get(type_index as usize).cloned())
+    };
+    let rendered = match typ {
+        Some(typ) => {
+            let env = vm.global_env().get_env();
+            let value = unsafe { value.get_value() };
+            ValuePrinter::new(&*env, &typ, unsafe { Variants::new(&value) })
+                .width(80)
+                .max_level(5)
+                .to_string()
+        }
+        None => format!("{:?}", value),
+    };
+    RuntimeResult::Panic(format!("{}: {}", message, rendered))
+}
+
 extern "C" fn discriminant_value(thread: &Thread) -> Status {
     let mut context = thread.context();
     let tag = {
@@ -271,6 +512,9 @@ mod std {
     pub mod array {
         pub use primitives::array as prim;
     }
+    pub mod bytes {
+        pub use primitives::bytes as prim;
+    }
     pub mod byte {
         pub type prim = u8;
     }
@@ -406,6 +650,16 @@ pub fn load_int(vm: &Thread) -> Result<ExternModule> {
             to_be => primitive!(1 std::int::prim::to_be),
             to_le => primitive!(1 std::int::prim::to_le),
             pow => primitive!(2 std::int::prim::pow),
+            checked_add => primitive!(2 std::int::prim::checked_add),
+            checked_sub => primitive!(2 std::int::prim::checked_sub),
+            checked_mul => primitive!(2 std::int::prim::checked_mul),
+            checked_div => primitive!(2 std::int::prim::checked_div),
+            wrapping_add => primitive!(2 std::int::prim::wrapping_add),
+            wrapping_sub => primitive!(2 std::int::prim::wrapping_sub),
+            wrapping_mul => primitive!(2 std::int::prim::wrapping_mul),
+            saturating_add => primitive!(2 std::int::prim::saturating_add),
+            saturating_sub => primitive!(2 std::int::prim::saturating_sub),
+            saturating_mul => primitive!(2 std::int::prim::saturating_mul),
             abs => primitive!(1 std::int::prim::abs),
             signum => primitive!(1 std::int::prim::signum),
             is_positive => primitive!(1 std::int::prim::is_positive),
@@ -420,18 +674,37 @@ pub fn load_int(vm: &Thread) -> Result<ExternModule> {
 #[allow(non_camel_case_types)]
 pub fn load_array(vm: &Thread) -> Result<ExternModule> {
     use self::std;
+
+    let _ = vm.register_type::<array::ArrayBuilder<A>>("ArrayBuilder", &["a"]);
+
     ExternModule::new(
         vm,
         record! {
+            type ArrayBuilder a => array::ArrayBuilder<A>,
             len => primitive!(1 std::array::prim::len),
             index => primitive!(2 std::array::prim::index),
-            append => primitive!(2 std::array::prim::append)
+            append => primitive!(2 std::array::prim::append),
+            builder_new => primitive!(1 std::array::prim::builder_new),
+            builder_push => primitive!(2 std::array::prim::builder_push),
+            builder_len => primitive!(1 std::array::prim::builder_len),
+            builder_build => primitive!(1 std::array::prim::builder_build)
+        },
+    )
+}
+
+pub fn load_bytes(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+    ExternModule::new(
+        vm,
+        record! {
+            slice => primitive!(3 std::bytes::prim::slice)
         },
     )
 }
 
 pub fn load_string(vm: &Thread) -> Result<ExternModule> {
     use self::string;
+    vm.register_type::<string::StringBuilder>("StringBuilder", &[])?;
     ExternModule::new(
         vm,
         record! {
@@ -466,7 +739,38 @@ pub fn load_string(vm: &Thread) -> Result<ExternModule> {
                 "std.string.prim.from_utf8",
                 string::from_utf8
             ),
-            char_at => named_primitive!(2, "std.string.prim.char_at", string::char_at)
+            char_at => named_primitive!(2, "std.string.prim.char_at", string::char_at),
+            try_slice => named_primitive!(3, "std.string.prim.try_slice", string::try_slice),
+            graphemes => named_primitive!(1, "std.string.prim.graphemes", string::graphemes),
+            char_count => named_primitive!(1, "std.string.prim.char_count", string::char_count),
+            to_lowercase => named_primitive!(
+                1,
+                "std.string.prim.to_lowercase",
+                string::to_lowercase
+            ),
+            to_uppercase => named_primitive!(
+                1,
+                "std.string.prim.to_uppercase",
+                string::to_uppercase
+            ),
+            case_fold => named_primitive!(1, "std.string.prim.case_fold", string::case_fold),
+            nfc => named_primitive!(1, "std.string.prim.nfc", string::nfc),
+            nfd => named_primitive!(1, "std.string.prim.nfd", string::nfd),
+            nfkc => named_primitive!(1, "std.string.prim.nfkc", string::nfkc),
+            nfkd => named_primitive!(1, "std.string.prim.nfkd", string::nfkd),
+            concat => named_primitive!(1, "std.string.prim.concat", string::concat),
+            builder_new => named_primitive!(1, "std.string.prim.builder_new", string::builder_new),
+            builder_append => named_primitive!(
+                2,
+                "std.string.prim.builder_append",
+                string::builder_append
+            ),
+            builder_len => named_primitive!(1, "std.string.prim.builder_len", string::builder_len),
+            builder_build => named_primitive!(
+                1,
+                "std.string.prim.builder_build",
+                string::builder_build
+            )
         },
     )
 }
@@ -502,6 +806,11 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
         primitive::<fn(StdString) -> Generic<A>>("@error", std::prim::error),
     )?;
 
+    vm.define_global(
+        "@unmatched_pattern",
+        named_primitive!(3, "@unmatched_pattern", std::prim::unmatched_pattern),
+    )?;
+
     vm.define_global(
         "@string_eq",
         named_primitive!(2, "@string_eq", <str as PartialEq>::eq),
@@ -0,0 +1,166 @@
+//! A sampling profiler, built on the hooks of a `Thread`
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::Async;
+
+use base::fnv::FnvMap;
+use base::symbol::Symbol;
+
+use thread::{DebugInfo, HookFlags, Thread, ThreadInternal};
+use Result;
+
+/// The number of instructions executed between each check of whether a new sample should be
+/// taken. Reading the clock on every instruction would slow down the profiled thread too much.
+pub const INSTRUCTIONS_PER_CHECK: usize = 1000;
+
+/// The result of profiling one or more threads
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// The number of times each call stack was sampled. Stacks start with the outermost function.
+    stacks: FnvMap<Vec<Symbol>, u64>,
+}
+
+impl Profile {
+    /// Returns the total number of samples taken
+    pub fn samples(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    /// Returns each sampled call stack, starting with the outermost function, together with the
+    /// number of times it was sampled
+    pub fn stacks(&self) -> impl Iterator<Item = (&[Symbol], u64)> {
+        self.stacks
+            .iter()
+            .map(|(stack, &count)| (&stack[..], count))
+    }
+
+    /// Returns the number of samples for each function. The first count is the number of samples
+    /// where the function was executing itself and the second is the number of samples where the
+    /// function was anywhere on the stack. Functions are sorted by their own samples, highest
+    /// first.
+    pub fn functions(&self) -> Vec<(Symbol, u64, u64)> {
+        let mut functions = FnvMap::<Symbol, (u64, u64)>::default();
+        for (stack, &count) in &self.stacks {
+            for (i, function) in stack.iter().enumerate() {
+                // Recursive functions are only counted once per sample
+                if stack[..i].contains(function) {
+                    continue;
+                }
+                functions.entry(function.clone()).or_insert((0, 0)).1 += count;
+            }
+            if let Some(function) = stack.last() {
+                functions.get_mut(function).unwrap().0 += count;
+            }
+        }
+        let mut functions: Vec<_> = functions
+            .into_iter()
+            .map(|(function, (own, total))| (function, own, total))
+            .collect();
+        functions.sort_by(|l, r| r.1.cmp(&l.1).then(r.2.cmp(&l.2)));
+        functions
+    }
+
+    /// Writes the profile in the "folded stacks" format where each line contains a stack, with
+    /// functions separated by `;`, followed by the number of samples. This is the format
+    /// consumed by `flamegraph.pl` and `inferno-flamegraph`.
+    pub fn write_folded<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut stacks: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, &count)| {
+                let names: Vec<_> = stack
+                    .iter()
+                    .map(|function| function.definition_name())
+                    .collect();
+                (names.join(";"), count)
+            })
+            .collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+struct ProfilerState {
+    interval: Duration,
+    last_sample: Option<Instant>,
+    profile: Profile,
+}
+
+/// A profiler which samples the call stack of attached threads every `interval`. Cloning a
+/// `Profiler` returns a handle to the same profiler so the profile can be read while attached
+/// threads are running.
+#[derive(Clone)]
+pub struct Profiler {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+impl Profiler {
+    pub fn new(interval: Duration) -> Profiler {
+        Profiler {
+            state: Arc::new(Mutex::new(ProfilerState {
+                interval,
+                last_sample: None,
+                profile: Profile::default(),
+            })),
+        }
+    }
+
+    /// Installs the profiler as the hook of `thread`, replacing any existing hook
+    pub fn attach(&self, thread: &Thread) {
+        let profiler = self.clone();
+        let mut context = thread.context();
+        context.set_hook(Some(Box::new(move |_, info| profiler.on_count(&info))));
+        context.set_hook_mask(HookFlags::COUNT_FLAG);
+        context.set_hook_count(INSTRUCTIONS_PER_CHECK);
+    }
+
+    /// Returns the samples collected so far
+    pub fn profile(&self) -> Profile {
+        self.state.lock().unwrap().profile.clone()
+    }
+
+    /// Discards all samples collected so far
+    pub fn reset(&self) {
+        self.state.lock().unwrap().profile = Profile::default();
+    }
+
+    // `Instant::now` is not supported on wasm
+    #[cfg(target_arch = "wasm32")]
+    fn on_count(&self, _info: &DebugInfo) -> Result<Async<()>> {
+        Err(::Error::Message("profiling is not supported on wasm".to_string()))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn on_count(&self, info: &DebugInfo) -> Result<Async<()>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let sample = state
+            .last_sample
+            .map_or(true, |last_sample| now.duration_since(last_sample) >= state.interval);
+        if !sample {
+            return Ok(Async::Ready(()));
+        }
+        state.last_sample = Some(now);
+
+        let stack: Vec<_> = (0..info.stack_info_len())
+            .rev()
+            .filter_map(|level| {
+                info.stack_info(level)
+                    .and_then(|frame| frame.function_symbol().cloned())
+            })
+            .collect();
+        if !stack.is_empty() {
+            *state.profile.stacks.entry(stack).or_insert(0) += 1;
+        }
+        Ok(Async::Ready(()))
+    }
+}
@@ -15,7 +15,7 @@ use base::symbol::{Symbol, Symbols};
 use base::types::ArcType;
 
 use array::Array;
-use gc::{DataDef, GcPtr, WriteOnly};
+use gc::{DataDef, GcPtr, Traverseable, WriteOnly};
 use thread::{RootedThread, Thread, ThreadInternal};
 use types::VmIndex;
 use value::{
@@ -635,7 +635,7 @@ struct DataDefSeed<T>(PhantomData<T>);
 impl<'de, T> DeserializeSeed<'de> for ::serialization::Seed<DataDefSeed<T>>
 where
     T: DataDef + 'static,
-    <T as DataDef>::Value: Sized,
+    <T as DataDef>::Value: Sized + Traverseable,
     T: DeserializeState<'de, DeSeed>,
 {
     type Value = GcPtr<<T as DataDef>::Value>;
@@ -659,7 +659,7 @@ where
         impl<'de, T> DeserializeState<'de, GcSeed<T>> for GcPtr<T::Value>
         where
             T: DataDef + 'static,
-            <T as DataDef>::Value: Sized,
+            <T as DataDef>::Value: Sized + Traverseable,
             T: DeserializeState<'de, DeSeed>,
         {
             fn deserialize_state<D>(seed: &mut GcSeed<T>, deserializer: D) -> Result<Self, D::Error>
@@ -1,6 +1,6 @@
 use std::slice::Iter;
 
-use base::pos::Line;
+use base::pos::{Column, Line};
 use base::symbol::Symbol;
 use base::types::ArcType;
 
@@ -9,8 +9,8 @@ use types::VmIndex;
 #[derive(Debug, Default, PartialEq)]
 #[cfg_attr(feature = "serde_derive", derive(Deserialize, Serialize))]
 pub struct SourceMap {
-    /// The index of the first instruction for each line
-    map: Vec<(usize, Line)>,
+    /// The index of the first instruction for each position
+    map: Vec<(usize, Line, Column)>,
 }
 
 impl SourceMap {
@@ -18,30 +18,49 @@ impl SourceMap {
         SourceMap { map: Vec::new() }
     }
 
-    /// Defines the instruction at `instruction_index` to be at `current_line`.
-    /// This function must be called with indexes in increasing order
-    pub fn emit(&mut self, instruction_index: usize, current_line: Line) {
-        let last_emitted_line = self.map.last().map(|&(_, x)| x);
-        if last_emitted_line != Some(current_line) {
-            self.map.push((instruction_index, current_line));
+    /// Defines the instruction at `instruction_index` to be at `current_line` and
+    /// `current_column`. This function must be called with indexes in increasing order
+    pub fn emit(&mut self, instruction_index: usize, current_line: Line, current_column: Column) {
+        let last_emitted = self.map.last().map(|&(_, line, column)| (line, column));
+        if last_emitted != Some((current_line, current_column)) {
+            self.map.push((instruction_index, current_line, current_column));
         }
     }
 
     pub fn close(&mut self, instruction_index: usize, current_line: Option<Line>) {
         // Push one final item to indicate the end of the function
-        if let Some(current_line) = current_line.or_else(|| self.map.last().map(|t| t.1)) {
-            self.map.push((instruction_index, current_line));
+        let end = current_line
+            .map(|line| (line, Column::from(0)))
+            .or_else(|| self.map.last().map(|&(_, line, column)| (line, column)));
+        if let Some((line, column)) = end {
+            self.map.push((instruction_index, line, column));
+        }
+    }
+
+    /// Moves each instruction index to `f(index)`. Used when instructions are removed from the
+    /// function after it has been compiled.
+    pub fn map_instruction_indexes<F>(&mut self, f: F)
+    where
+        F: Fn(usize) -> usize,
+    {
+        for entry in &mut self.map {
+            entry.0 = f(entry.0);
         }
     }
 
     /// Returns the line where the instruction at `instruction_index` were defined
     pub fn line(&self, instruction_index: usize) -> Option<Line> {
+        self.location(instruction_index).map(|(line, _)| line)
+    }
+
+    /// Returns the line and column where the instruction at `instruction_index` were defined
+    pub fn location(&self, instruction_index: usize) -> Option<(Line, Column)> {
         // The line for `instruction_index` is at the last index still larger than
         // the index in `map`
         let p = self
             .map
             .iter()
-            .position(|&(index, _)| index > instruction_index)
+            .position(|&(index, _, _)| index > instruction_index)
             .unwrap_or(self.map.len());
         if p == 0
             || (p == self.map.len()
@@ -50,7 +69,8 @@ impl SourceMap {
             // instruction_index is not valid in the function
             None
         } else {
-            Some(self.map[p - 1].1)
+            let (_, line, column) = self.map[p - 1];
+            Some((line, column))
         }
     }
 }
@@ -104,6 +124,18 @@ impl LocalMap {
         }
     }
 
+    /// Moves the start and end of each local to `f(index)`. Used when instructions are removed
+    /// from the function after it has been compiled.
+    pub fn map_instruction_indexes<F>(&mut self, f: F)
+    where
+        F: Fn(usize) -> usize,
+    {
+        for local in &mut self.map {
+            local.start = f(local.start);
+            local.end = f(local.end);
+        }
+    }
+
     /// Returns an iterator over the variables in scope at `instruction_index`
     pub fn locals(&self, instruction_index: usize) -> LocalIter {
         LocalIter {
@@ -1,7 +1,7 @@
 use std::fmt;
 use std::ops::{Deref, DerefMut, Index, IndexMut, Range, RangeFrom, RangeFull, RangeTo};
 
-use base::pos::Line;
+use base::pos::{Column, Line};
 use base::symbol::Symbol;
 
 use gc::{Gc, GcPtr, Traverseable};
@@ -184,23 +184,33 @@ impl Stack {
 
     /// Creates a stackrace starting from `frame_level`
     pub fn stacktrace(&self, frame_level: usize) -> Stacktrace {
-        let frames = self.get_frames()[frame_level..]
+        let all_frames = self.get_frames();
+        let top = all_frames.len() - 1;
+        let frames = all_frames[frame_level..]
             .iter()
-            .filter_map(|frame| match frame.state {
+            .enumerate()
+            .filter_map(|(i, frame)| match frame.state {
                 State::Closure(ref closure) => {
-                    let line = closure
-                        .function
-                        .debug_info
-                        .source_map
-                        .line(frame.instruction_index);
-                    Some(line.map(|line| StacktraceFrame {
+                    let debug_info = &closure.function.debug_info;
+                    // Frames below the top have already stepped past the `Call` instruction
+                    let instruction_index = if frame_level + i == top {
+                        frame.instruction_index
+                    } else {
+                        frame.instruction_index.saturating_sub(1)
+                    };
+                    let location = debug_info.source_map.location(instruction_index);
+                    Some(location.map(|(line, column)| StacktraceFrame {
                         name: closure.function.name.clone(),
+                        source_name: debug_info.source_name.clone(),
                         line: line,
+                        column: column,
                     }))
                 }
                 State::Extern(ref ext) => Some(Some(StacktraceFrame {
                     name: ext.id.clone(),
+                    source_name: String::new(),
                     line: Line::from(0),
+                    column: Column::from(0),
                 })),
                 State::Unknown => Some(None),
                 State::Lock | State::Excess => None,
@@ -485,7 +495,10 @@ impl<'b> IndexMut<RangeFrom<VmIndex>> for StackFrame<'b> {
 #[derive(Debug, PartialEq)]
 pub struct StacktraceFrame {
     pub name: Symbol,
+    /// The name of the module the function were defined in. Empty for extern functions
+    pub source_name: String,
     pub line: Line,
+    pub column: Column,
 }
 
 #[derive(Debug, PartialEq)]
@@ -498,12 +511,17 @@ impl fmt::Display for Stacktrace {
         writeln!(f, "Stacktrace:\n")?;
         for (i, frame) in self.frames.iter().enumerate() {
             match *frame {
+                Some(ref frame) if frame.source_name.is_empty() => {
+                    writeln!(f, "{}: {}", i, frame.name.declared_name())
+                }
                 Some(ref frame) => writeln!(
                     f,
-                    "{}: {}:Line {}",
+                    "{}: {} at {}:{}:{}",
                     i,
                     frame.name.declared_name(),
-                    frame.line.number()
+                    frame.source_name,
+                    frame.line.number(),
+                    frame.column.number()
                 ),
                 None => writeln!(f, "{}: <unknown>", i),
             }?
@@ -0,0 +1,362 @@
+//! Synchronization primitives for values shared between threads running in parallel.
+//!
+//! Unlike `std.reference`, which offers no coordination between threads, the locks in this module
+//! block the calling OS thread until the value is available. A lock is only held while the
+//! function passed to `modify` runs, so that function must not wait on the same lock.
+
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex as StdMutex};
+
+use api::generic::A;
+use api::{Generic, OwnedFunction, PrimitiveFuture, RuntimeResult, Userdata, VmType, WithVM};
+use base::types::{ArcType, Type};
+use future::FutureValue;
+use gc::{CollectScope, Gc, Generation, Traverseable};
+use thread::ThreadInternal;
+use types::VmInt;
+use value::Value;
+use vm::Thread;
+use {Error, ExternModule, Result};
+
+struct State {
+    value: Value,
+    /// The heap which `value` lives in. Nothing outside of this state points into it, so it can
+    /// be collected with `value` as its only root.
+    heap: Gc,
+    held: bool,
+}
+
+/// A value which can be acquired by one thread at a time. Both `Mutex` and `RwLock` are built on
+/// top of this, the difference being that a `RwLock` can also be read without acquiring it.
+///
+/// The value is stored in a heap owned by the lock, and each thread which acquires or reads it
+/// gets a clone of its own so that threads never share mutable values.
+struct Guarded {
+    state: StdMutex<State>,
+    released: Condvar,
+}
+
+impl fmt::Debug for Guarded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        write!(f, "{:?}", state.value)?;
+        if state.held {
+            write!(f, " (held)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The root of the heap of a `Guarded` value
+struct Root<'a>(&'a Value);
+
+impl<'a> Traverseable for Root<'a> {
+    fn traverse(&self, gc: &mut Gc) {
+        self.0.traverse(gc)
+    }
+}
+
+impl<'a> CollectScope for Root<'a> {
+    fn scope<F>(&self, gc: &mut Gc, f: F)
+    where
+        F: FnOnce(&mut Gc),
+    {
+        f(gc)
+    }
+}
+
+impl State {
+    /// Deep clones `value` into the heap of the lock, replacing the previous value
+    fn store(&mut self, vm: &Thread, value: &Value) -> Result<()> {
+        self.value = ::value::Cloner::new(vm, &mut self.heap).deep_clone(value)?;
+        // Values replaced by earlier stores are only reachable from this heap so they are freed
+        // here instead of accumulating until the lock is dropped
+        unsafe {
+            self.heap.check_collect(Root(&self.value));
+        }
+        Ok(())
+    }
+}
+
+/// Deep clones `value` into the heap of `vm`
+fn load_value(vm: &Thread, value: &Value) -> Result<Value> {
+    let mut context = vm.context();
+    let mut cloner = ::value::Cloner::new(vm, &mut context.gc);
+    cloner.force_full_clone();
+    cloner.deep_clone(value)
+}
+
+/// Keeps a `Guarded` value held until it is dropped, which releases the lock even if the
+/// computation holding it fails.
+struct Held(Arc<Guarded>);
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.held = false;
+        self.0.released.notify_all();
+    }
+}
+
+impl Held {
+    /// Stores `value` and lets the next waiting thread acquire it. If the value can't be stored
+    /// the previous value is kept.
+    fn release(self, vm: &Thread, value: Value) -> Result<()> {
+        // `self` is dropped, releasing the value, after `state` is unlocked
+        let mut state = self.0.state.lock().unwrap();
+        state.store(vm, &value)
+    }
+}
+
+impl Guarded {
+    fn new(vm: &Thread, value: Value) -> Result<Guarded> {
+        // The heap shares the root generation with the global heap so the garbage collectors of
+        // threads never mark its values (and values which can't outlive a thread are rejected)
+        let mut heap = Gc::new(Generation::default(), usize::MAX);
+        heap.set_sweep_step(None);
+        let value = ::value::Cloner::new(vm, &mut heap).deep_clone(&value)?;
+        Ok(Guarded {
+            state: StdMutex::new(State {
+                value,
+                heap,
+                held: false,
+            }),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Waits until no other thread holds the value and then marks it as held by the caller
+    fn acquire(guarded: &Arc<Guarded>, vm: &Thread) -> Result<(Held, Value)> {
+        let mut state = guarded.state.lock().unwrap();
+        while state.held {
+            state = guarded.released.wait(state).unwrap();
+        }
+        let value = load_value(vm, &state.value)?;
+        state.held = true;
+        Ok((Held(guarded.clone()), value))
+    }
+
+    fn try_acquire(guarded: &Arc<Guarded>, vm: &Thread) -> Result<Option<(Held, Value)>> {
+        let mut state = guarded.state.lock().unwrap();
+        if state.held {
+            Ok(None)
+        } else {
+            let value = load_value(vm, &state.value)?;
+            state.held = true;
+            Ok(Some((Held(guarded.clone()), value)))
+        }
+    }
+
+    /// Waits until no other thread holds the value and returns it without acquiring it
+    fn read(&self, vm: &Thread) -> Result<Value> {
+        let mut state = self.state.lock().unwrap();
+        while state.held {
+            state = self.released.wait(state).unwrap();
+        }
+        load_value(vm, &state.value)
+    }
+}
+
+/// Applies `f` to the value held by `held` and stores the result. The value is released whether
+/// or not `f` succeeds.
+fn modify_held(
+    vm: &Thread,
+    held: Held,
+    value: Value,
+    mut f: OwnedFunction<fn(Generic<A>) -> Generic<A>>,
+) -> PrimitiveFuture<()> {
+    let vm = vm.root_thread();
+    f.call_fast_async(Generic::from(value))
+        .and_then(move |value| FutureValue::sync(held.release(&vm, unsafe { value.get_value() })))
+        .boxed()
+}
+
+pub struct Mutex<T>(Arc<Guarded>, PhantomData<T>);
+
+impl<T> Userdata for Mutex<T> where T: Any + Send + Sync {}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mutex({:?})", self.0)
+    }
+}
+
+// The value lives in a heap of its own which the garbage collector of the thread never marks
+impl<T> Traverseable for Mutex<T> {}
+
+impl<T> VmType for Mutex<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = Mutex<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("Mutex").unwrap().name.clone();
+        Type::app(Type::ident(symbol), collect![T::make_type(vm)])
+    }
+}
+
+pub struct RwLock<T>(Arc<Guarded>, PhantomData<T>);
+
+impl<T> Userdata for RwLock<T> where T: Any + Send + Sync {}
+
+impl<T> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RwLock({:?})", self.0)
+    }
+}
+
+// The value lives in a heap of its own which the garbage collector of the thread never marks
+impl<T> Traverseable for RwLock<T> {}
+
+impl<T> VmType for RwLock<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = RwLock<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("RwLock").unwrap().name.clone();
+        Type::app(Type::ident(symbol), collect![T::make_type(vm)])
+    }
+}
+
+/// An integer which can be updated atomically from multiple threads
+#[derive(Debug)]
+pub struct AtomicInt(AtomicIsize);
+
+impl Userdata for AtomicInt {}
+
+impl VmType for AtomicInt {
+    type Type = AtomicInt;
+}
+
+impl Traverseable for AtomicInt {
+    fn traverse(&self, _: &mut Gc) {}
+}
+
+fn mutex(a: WithVM<Generic<A>>) -> RuntimeResult<Mutex<A>, Error> {
+    Guarded::new(a.vm, unsafe { a.value.get_value() })
+        .map(|guarded| Mutex(Arc::new(guarded), PhantomData))
+        .into()
+}
+
+fn mutex_read(WithVM { vm, value: mutex }: WithVM<&Mutex<A>>) -> RuntimeResult<Generic<A>, Error> {
+    mutex.0.read(vm).map(Generic::from).into()
+}
+
+fn mutex_modify(
+    WithVM { vm, value: mutex }: WithVM<&Mutex<A>>,
+    f: OwnedFunction<fn(Generic<A>) -> Generic<A>>,
+) -> PrimitiveFuture<()> {
+    match Guarded::acquire(&mutex.0, vm) {
+        Ok((held, value)) => modify_held(vm, held, value, f),
+        Err(err) => FutureValue::Value(Err(err)),
+    }
+}
+
+/// Like `mutex_modify` but returns `false` instead of waiting if the mutex is held
+fn mutex_try_modify(
+    WithVM { vm, value: mutex }: WithVM<&Mutex<A>>,
+    f: OwnedFunction<fn(Generic<A>) -> Generic<A>>,
+) -> PrimitiveFuture<bool> {
+    match Guarded::try_acquire(&mutex.0, vm) {
+        Ok(Some((held, value))) => modify_held(vm, held, value, f).map(|()| true).boxed(),
+        Ok(None) => FutureValue::Value(Ok(false)),
+        Err(err) => FutureValue::Value(Err(err)),
+    }
+}
+
+fn rw_lock(a: WithVM<Generic<A>>) -> RuntimeResult<RwLock<A>, Error> {
+    Guarded::new(a.vm, unsafe { a.value.get_value() })
+        .map(|guarded| RwLock(Arc::new(guarded), PhantomData))
+        .into()
+}
+
+fn rw_lock_read(
+    WithVM { vm, value: lock }: WithVM<&RwLock<A>>,
+) -> RuntimeResult<Generic<A>, Error> {
+    lock.0.read(vm).map(Generic::from).into()
+}
+
+fn rw_lock_modify(
+    WithVM { vm, value: lock }: WithVM<&RwLock<A>>,
+    f: OwnedFunction<fn(Generic<A>) -> Generic<A>>,
+) -> PrimitiveFuture<()> {
+    match Guarded::acquire(&lock.0, vm) {
+        Ok((held, value)) => modify_held(vm, held, value, f),
+        Err(err) => FutureValue::Value(Err(err)),
+    }
+}
+
+fn atomic_int(value: VmInt) -> AtomicInt {
+    AtomicInt(AtomicIsize::new(value))
+}
+
+fn atomic_load(atomic: &AtomicInt) -> VmInt {
+    atomic.0.load(Ordering::SeqCst)
+}
+
+fn atomic_store(atomic: &AtomicInt, value: VmInt) {
+    atomic.0.store(value, Ordering::SeqCst)
+}
+
+fn atomic_swap(atomic: &AtomicInt, value: VmInt) -> VmInt {
+    atomic.0.swap(value, Ordering::SeqCst)
+}
+
+/// Adds `value` and returns the previous value
+fn atomic_fetch_add(atomic: &AtomicInt, value: VmInt) -> VmInt {
+    atomic.0.fetch_add(value, Ordering::SeqCst)
+}
+
+/// Stores `new` if the current value is `current`, returning the value before the operation
+fn atomic_compare_and_swap(atomic: &AtomicInt, current: VmInt, new: VmInt) -> VmInt {
+    atomic
+        .0
+        .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+        .unwrap_or_else(|previous| previous)
+}
+
+mod std {
+    pub mod sync {
+        pub use sync as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    vm.register_type::<Mutex<A>>("Mutex", &["a"])?;
+    vm.register_type::<RwLock<A>>("RwLock", &["a"])?;
+    vm.register_type::<AtomicInt>("AtomicInt", &[])?;
+
+    ExternModule::new(
+        vm,
+        record!{
+            type Mutex a => Mutex<A>,
+            type RwLock a => RwLock<A>,
+            type AtomicInt => AtomicInt,
+            mutex => primitive!(1 std::sync::prim::mutex),
+            mutex_read => primitive!(1 std::sync::prim::mutex_read),
+            mutex_modify => primitive!(2 std::sync::prim::mutex_modify),
+            mutex_try_modify => primitive!(2 std::sync::prim::mutex_try_modify),
+            rw_lock => primitive!(1 std::sync::prim::rw_lock),
+            rw_lock_read => primitive!(1 std::sync::prim::rw_lock_read),
+            rw_lock_modify => primitive!(2 std::sync::prim::rw_lock_modify),
+            atomic_int => primitive!(1 std::sync::prim::atomic_int),
+            atomic_load => primitive!(1 std::sync::prim::atomic_load),
+            atomic_store => primitive!(2 std::sync::prim::atomic_store),
+            atomic_swap => primitive!(2 std::sync::prim::atomic_swap),
+            atomic_fetch_add => primitive!(2 std::sync::prim::atomic_fetch_add),
+            atomic_compare_and_swap => primitive!(3 std::sync::prim::atomic_compare_and_swap)
+        },
+    )
+}
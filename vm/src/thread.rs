@@ -10,9 +10,10 @@ use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
 use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::usize;
+use std::time::Instant;
 
 use future::FutureValue;
-use futures::{Async, Future, Poll};
+use futures::{task, Async, Future, Poll};
 
 use base::metadata::Metadata;
 use base::pos::Line;
@@ -21,9 +22,9 @@ use base::types::{self, Alias, ArcType};
 
 use api::{Getable, Pushable, ValueRef, VmType};
 use compiler::UpvarInfo;
-use gc::{DataDef, Gc, GcPtr, Generation, Move};
+use gc::{DataDef, Gc, GcPtr, Generation, HeapSnapshot, HeapStats, Move};
 use macros::MacroEnv;
-use source_map::LocalIter;
+use source_map::{Local, LocalIter};
 use stack::{Frame, Lock, Stack, StackFrame, State};
 use types::*;
 use value::{
@@ -62,18 +63,30 @@ where
     // Returns `T` so that it can be reused by the caller
     fn poll(&mut self) -> Poll<(T, Value), Error> {
         let value = {
-            let mut context = try_ready!(
-                self.thread
-                    .as_ref()
-                    .expect("cannot poll Execute future after it has succeded")
-                    .resume()
-            );
+            let thread = self
+                .thread
+                .as_ref()
+                .expect("cannot poll Execute future after it has succeded");
+            let mut context = match thread.resume()? {
+                Async::Ready(context) => context,
+                Async::NotReady => {
+                    // Nothing else will wake a thread which yielded at a safepoint so notify the
+                    // task directly to be polled again
+                    if mem::replace(&mut thread.context().yielded, false) {
+                        task::current().notify();
+                    }
+                    return Ok(Async::NotReady);
+                }
+            };
             context.stack.pop()
         };
         Ok(Async::Ready((self.thread.take().unwrap(), value)))
     }
 }
 
+/// The default maximum number of values on the stack of a thread
+pub const DEFAULT_MAX_STACK_SIZE: VmIndex = 1 << 20;
+
 /// Enum signaling a successful or unsuccess ful call to an extern function.
 /// If an error occured the error message is expected to be on the top of the stack.
 #[derive(Eq, PartialEq)]
@@ -84,8 +97,9 @@ pub enum Status {
     Error,
 }
 
-/// A rooted value
-#[derive(Clone)]
+/// A rooted value. The value is kept alive by the thread it was rooted in for as long as the
+/// `RootedValue` (or any clone of it) exists. A `RootedValue<RootedThread>` does not borrow the
+/// thread so it can be stored and used independently of the thread that produced it.
 pub struct RootedValue<T>
 where
     T: Deref<Target = Thread>,
@@ -114,13 +128,32 @@ where
     }
 }
 
+impl<T> Clone for RootedValue<T>
+where
+    T: Deref<Target = Thread> + Clone,
+{
+    fn clone(&self) -> Self {
+        self.vm.rooted_values.write().unwrap().push(self.value.clone());
+        RootedValue {
+            vm: self.vm.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
 impl<T> Drop for RootedValue<T>
 where
     T: Deref<Target = Thread>,
 {
     fn drop(&mut self) {
-        // TODO not safe if the root changes order of being dropped with another root
-        self.vm.rooted_values.write().unwrap().pop();
+        let mut rooted_values = self.vm.rooted_values.write().unwrap();
+        // Roots are usually dropped in the reverse order that they were created in so search
+        // from the end
+        let index = rooted_values
+            .iter()
+            .rposition(|value| value.obj_eq(&self.value))
+            .unwrap_or_else(|| ice!("Rooted value was not found among the roots"));
+        rooted_values.remove(index);
     }
 }
 
@@ -177,6 +210,12 @@ impl<'vm> RootedValue<&'vm Thread> {
     pub fn vm_(&self) -> &'vm Thread {
         self.vm
     }
+
+    /// Converts the value into one which roots the thread as well, letting it outlive the borrow
+    /// of the thread
+    pub fn into_owned(self) -> RootedValue<RootedThread> {
+        self.vm.root_value(self.value.clone())
+    }
 }
 
 /// A rooted userdata value
@@ -244,6 +283,11 @@ impl<'b> ::gc::CollectScope for Roots<'b> {
             }
         }
     }
+
+    fn allows_incremental_marking(&self) -> bool {
+        // Child threads may run in parallel while their values are marked
+        self.vm.child_threads.read().unwrap().is_empty()
+    }
 }
 
 impl<'b> Roots<'b> {
@@ -276,7 +320,10 @@ impl<'b> Roots<'b> {
                 Vec::push(&mut stack, *child);
             }
 
-            let context = thread.context.lock().unwrap();
+            let mut context = thread.context.lock().unwrap();
+            // The marks from the child's own last collection must be cleared before marking
+            context.gc.cancel_marking();
+            context.gc.finish_sweep();
 
             // Since we locked the context we need to scan the thread using `Roots` rather than
             // letting it be scanned normally
@@ -315,6 +362,11 @@ pub struct Thread {
     /// garbage collected values
     #[cfg_attr(feature = "serde_derive", serde(state))]
     child_threads: RwLock<Vec<GcPtr<Thread>>>,
+    /// Threads spawned by descendants of this thread which have been sent to this thread. They
+    /// live in the heap of the thread which spawned them so they are kept rooted for as long as
+    /// this thread is alive
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    shared_threads: RwLock<Vec<RootedThread>>,
     #[cfg_attr(feature = "serde_derive", serde(state))]
     context: Mutex<Context>,
     #[cfg_attr(feature = "serde_derive", serde(skip))]
@@ -340,6 +392,19 @@ impl Traverseable for Thread {
     }
 }
 
+impl Drop for Thread {
+    fn drop(&mut self) {
+        // The values of the thread are freed along with it so any finalizers must run now
+        let finalizers = match self.context.get_mut() {
+            Ok(context) => context.gc.take_all_finalizers(),
+            Err(_) => return,
+        };
+        for mut finalizer in finalizers {
+            finalizer(&*self);
+        }
+    }
+}
+
 impl PartialEq for Thread {
     fn eq(&self, other: &Thread) -> bool {
         self as *const _ == other as *const _
@@ -433,6 +498,7 @@ impl RootedThread {
             roots: RwLock::new(Vec::new()),
             rooted_values: RwLock::new(Vec::new()),
             child_threads: RwLock::new(Vec::new()),
+            shared_threads: RwLock::new(Vec::new()),
             interrupt: AtomicBool::new(false),
         };
         let mut gc = Gc::new(Generation::default(), usize::MAX);
@@ -469,13 +535,21 @@ impl Thread {
     /// Spawns a new gluon thread with its own stack and heap but while still sharing the same
     /// global environment
     pub fn new_thread(&self) -> Result<RootedThread> {
+        let context = {
+            let parent_context = self.current_context();
+            let mut context = Context::new(parent_context.gc.new_child_gc());
+            context.max_stack_size = parent_context.max_stack_size;
+            context.int_overflow = parent_context.int_overflow;
+            context
+        };
         let vm = Thread {
             global_state: self.global_state.clone(),
             parent: Some(self.root_thread()),
-            context: Mutex::new(Context::new(self.current_context().gc.new_child_gc())),
+            context: Mutex::new(context),
             roots: RwLock::new(Vec::new()),
             rooted_values: RwLock::new(Vec::new()),
             child_threads: RwLock::new(Vec::new()),
+            shared_threads: RwLock::new(Vec::new()),
             interrupt: AtomicBool::new(false),
         };
         // Enter the top level scope
@@ -498,6 +572,31 @@ impl Thread {
         }
     }
 
+    /// Returns `true` if a thread other than `self` and the threads that `self` was spawned from
+    /// is alive. If there is no such thread then no other code can run while `self` is blocked.
+    pub(crate) fn has_other_threads(&self) -> bool {
+        let mut ancestors = Vec::new();
+        let mut thread = Some(self);
+        while let Some(current) = thread {
+            Vec::push(&mut ancestors, current as *const Thread);
+            thread = current.parent.as_ref().map(|parent| &**parent);
+        }
+
+        let mut stack = self
+            .global_state
+            .generation_0_threads
+            .read()
+            .unwrap()
+            .clone();
+        while let Some(thread) = stack.pop() {
+            if !ancestors.contains(&(&*thread as *const Thread)) {
+                return true;
+            }
+            stack.extend(thread.child_threads.read().unwrap().iter().cloned());
+        }
+        false
+    }
+
     /// Creates a new global value at `name`.
     /// Fails if a global called `name` already exists.
     ///
@@ -644,12 +743,18 @@ impl Thread {
         self.global_env().get_event_loop()
     }
 
-    /// Runs a garbage collection.
+    /// Runs a garbage collection, followed by the finalizers of any userdata which was freed.
     pub fn collect(&self) {
-        let mut context = self.current_context();
-        self.with_roots(&mut context, |gc, roots| unsafe {
-            gc.collect(roots);
-        })
+        let finalizers = {
+            let mut context = self.current_context();
+            self.with_roots(&mut context, |gc, roots| unsafe {
+                gc.collect(roots);
+            });
+            context.gc.take_finalizers()
+        };
+        for mut finalizer in finalizers {
+            finalizer(self);
+        }
     }
 
     /// Pushes a value to the top of the stack
@@ -666,10 +771,50 @@ impl Thread {
         self.current_context().stack.pop();
     }
 
+    /// Sets the maximum number of bytes this thread may allocate. Allocations which would exceed
+    /// the limit return `Error::OutOfMemory` instead. Threads spawned from this thread inherit
+    /// the limit but count their allocations separately.
     pub fn set_memory_limit(&self, memory_limit: usize) {
         self.current_context().gc.set_memory_limit(memory_limit)
     }
 
+    /// Returns statistics about the values allocated by this thread
+    pub fn heap_stats(&self) -> HeapStats {
+        self.current_context().gc.heap_stats()
+    }
+
+    /// Takes a snapshot of all values allocated by this thread along with the references between
+    /// them, which can be used to find out what is keeping a value alive.
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let mut context = self.current_context();
+        self.with_roots(&mut context, |gc, roots| unsafe {
+            gc.heap_snapshot(roots, ::value::type_name)
+        })
+    }
+
+    /// Sets a function which is called after every garbage collection of this thread's heap.
+    /// The function is called while the thread is locked and must not use the thread.
+    pub fn set_collect_callback<F>(&self, callback: F)
+    where
+        F: FnMut(&HeapStats) + Send + 'static,
+    {
+        self.current_context()
+            .gc
+            .set_collect_callback(Some(Box::new(callback)))
+    }
+
+    /// Sets how many values are swept on each allocation after an automatic collection (see
+    /// `Gc::set_sweep_step`). Threads spawned from this thread inherit the setting.
+    pub fn set_sweep_step(&self, sweep_step: Option<usize>) {
+        self.current_context().gc.set_sweep_step(sweep_step)
+    }
+
+    /// Sets how many values are marked on each allocation while an automatic collection is in
+    /// progress (see `Gc::set_mark_step`). Threads spawned from this thread inherit the setting.
+    pub fn set_mark_step(&self, mark_step: Option<usize>) {
+        self.current_context().gc.set_mark_step(mark_step)
+    }
+
     pub fn interrupt(&self) {
         self.interrupt.store(true, atomic::Ordering::Relaxed)
     }
@@ -689,6 +834,30 @@ impl Thread {
         self.child_threads.read().unwrap().traverse(gc);
     }
 
+    /// Returns true if `other` was spawned by `self` or by one of the threads spawned by `self`
+    pub(crate) fn is_ancestor_of(&self, other: &Thread) -> bool {
+        let mut current = other.parent.as_ref();
+        while let Some(thread) = current {
+            if &**thread as *const Thread == self as *const Thread {
+                return true;
+            }
+            current = thread.parent.as_ref();
+        }
+        false
+    }
+
+    /// Keeps `thread` alive for as long as `self`, letting values in the heap of `self` refer to
+    /// it
+    pub(crate) fn share_thread(&self, thread: GcPtr<Thread>) {
+        let mut shared_threads = self.shared_threads.write().unwrap();
+        let is_shared = shared_threads
+            .iter()
+            .any(|shared| &**shared as *const Thread == &*thread as *const Thread);
+        if !is_shared {
+            Vec::push(&mut shared_threads, thread.root_thread());
+        }
+    }
+
     fn parent_threads(&self) -> RwLockWriteGuard<Vec<GcPtr<Thread>>> {
         match self.parent {
             Some(ref parent) => parent.child_threads.write().unwrap(),
@@ -1000,8 +1169,9 @@ impl<'a> StackInfo<'a> {
         &self.info.stack.get_frames()[self.index]
     }
 
+    /// Returns the index of the instruction currently executing in this frame
     // For frames except the top we subtract one to account for the `Call` instruction adding one
-    fn instruction_index(&self) -> usize {
+    pub fn instruction_index(&self) -> usize {
         if self.info.stack.get_frames().len() - 1 == self.index {
             self.frame().instruction_index
         } else {
@@ -1030,6 +1200,15 @@ impl<'a> StackInfo<'a> {
         }
     }
 
+    /// Returns the symbol of the function executing at this frame
+    pub fn function_symbol(&self) -> Option<&Symbol> {
+        match self.frame().state {
+            State::Unknown | State::Lock | State::Excess => None,
+            State::Closure(ref closure) => Some(&closure.function.name),
+            State::Extern(ref function) => Some(&function.id),
+        }
+    }
+
     /// Returns the name of the function executing at this frame
     pub fn function_name(&self) -> Option<&str> {
         match self.frame().state {
@@ -1052,6 +1231,13 @@ impl<'a> StackInfo<'a> {
         }
     }
 
+    /// Returns the value of `local`, which must be one of the locals returned from `locals`
+    pub fn local_value(&self, local: &Local) -> Variants<'a> {
+        let stack: &'a Stack = self.info.stack;
+        let offset = self.frame().offset;
+        unsafe { Variants::new(&stack[offset + local.index]) }
+    }
+
     /// Returns a slice with information about the values bound to this closure
     pub fn upvars(&self) -> &[UpvarInfo] {
         match self.frame().state {
@@ -1059,6 +1245,17 @@ impl<'a> StackInfo<'a> {
             _ => ice!("Attempted to access upvar in non closure function"),
         }
     }
+
+    /// Returns the value of the upvar at `index`, in the same order as `upvars`
+    pub fn upvar_value(&self, index: usize) -> Option<Variants> {
+        match self.frame().state {
+            State::Closure(ref closure) => closure
+                .upvars
+                .get(index)
+                .map(|value| unsafe { Variants::new(value) }),
+            _ => None,
+        }
+    }
 }
 
 bitflags! {
@@ -1068,6 +1265,28 @@ bitflags! {
         const LINE_FLAG = 0b01;
         /// Call the hook when a function is called
         const CALL_FLAG = 0b10;
+        /// Call the hook when a gluon function is about to return
+        const RETURN_FLAG = 0b100;
+        /// Call the hook after every `count` instructions (see `Context::set_hook_count`)
+        const COUNT_FLAG = 0b1000;
+    }
+}
+
+/// How the integer arithmetic instructions behave when the result does not fit in an `Int`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IntOverflow {
+    /// Wrap around at the boundary of `Int` (the default)
+    Wrap,
+    /// Clamp the result to the smallest or largest `Int`
+    Saturate,
+    /// Stop execution with `Error::IntegerOverflow`, which can be caught like any other runtime
+    /// error (for instance with `std.io.catch`)
+    Error,
+}
+
+impl Default for IntOverflow {
+    fn default() -> IntOverflow {
+        IntOverflow::Wrap
     }
 }
 
@@ -1077,6 +1296,10 @@ struct Hook {
     flags: HookFlags,
     // The index of the last executed instruction
     previous_instruction_index: usize,
+    /// How many instructions to execute between each call of a `COUNT_FLAG` hook
+    count: usize,
+    /// Instructions executed since the hook was last called for `COUNT_FLAG`
+    instructions_since_count: usize,
 }
 
 #[cfg_attr(feature = "serde_derive", derive(DeserializeState, SerializeState))]
@@ -1091,6 +1314,25 @@ pub struct Context {
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     hook: Hook,
     max_stack_size: VmIndex,
+    /// What happens when integer arithmetic overflows
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    int_overflow: IntOverflow,
+
+    /// The number of function calls the thread may make before it yields to the executor
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    yield_interval: Option<usize>,
+    /// Function calls made since the thread last yielded
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    calls_since_yield: usize,
+    /// Set when the thread yields at a safepoint, `Execute` notifies its task when it sees this
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    yielded: bool,
+    /// The number of instructions the thread may execute before it errors with `OutOfFuel`
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    fuel: Option<usize>,
+    /// The thread errors with `TimedOut` if it makes a function call after this point in time
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    deadline: Option<Instant>,
 
     /// Stack of polling functions used for extern functions returning futures
     #[cfg_attr(feature = "serde_derive", serde(skip))]
@@ -1109,8 +1351,16 @@ impl Context {
                 function: None,
                 flags: HookFlags::empty(),
                 previous_instruction_index: usize::max_value(),
+                count: 1,
+                instructions_since_count: 0,
             },
-            max_stack_size: VmIndex::max_value(),
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            int_overflow: IntOverflow::default(),
+            yield_interval: None,
+            calls_since_yield: 0,
+            yielded: false,
+            fuel: None,
+            deadline: None,
             poll_fns: Vec::new(),
         }
     }
@@ -1126,10 +1376,33 @@ impl Context {
             .map(Value::from)
     }
 
+    /// Allocates a record with the fields `field_names`, `fields` must contain one value for each
+    /// field name, in the same order.
+    pub fn new_record(
+        &mut self,
+        thread: &Thread,
+        fields: &[Value],
+        field_names: &[&str],
+    ) -> Result<Value> {
+        debug_assert!(fields.len() == field_names.len());
+        let field_names = field_names
+            .iter()
+            .map(|name| thread.global_env().intern(name))
+            .collect::<Result<Vec<_>>>()?;
+        self.alloc_with(
+            thread,
+            RecordDef {
+                elems: fields,
+                fields: &field_names,
+            },
+        ).map(ValueRepr::Data)
+            .map(Value::from)
+    }
+
     pub fn alloc_with<D>(&mut self, thread: &Thread, data: D) -> Result<GcPtr<D::Value>>
     where
         D: DataDef + Traverseable,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         alloc(&mut self.gc, thread, &self.stack, data)
     }
@@ -1137,7 +1410,7 @@ impl Context {
     pub fn alloc_ignore_limit<D>(&mut self, data: D) -> GcPtr<D::Value>
     where
         D: DataDef + Traverseable,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         self.gc.alloc_ignore_limit(data)
     }
@@ -1150,10 +1423,92 @@ impl Context {
         self.hook.flags = flags;
     }
 
+    /// Sets how many instructions are executed between each call of the hook when
+    /// `HookFlags::COUNT_FLAG` is set (default: 1)
+    pub fn set_hook_count(&mut self, count: usize) {
+        assert!(count != 0, "The hook count must be greater than 0");
+        self.hook.count = count;
+        self.hook.instructions_since_count = 0;
+    }
+
+    /// Returns how many instructions have been executed since the `HookFlags::COUNT_FLAG` hook was
+    /// last called or the count was last set with `set_hook_count`
+    pub fn instructions_since_count(&self) -> usize {
+        self.hook.instructions_since_count
+    }
+
+    /// Sets the maximum number of values the stack may contain. Calling a function which could
+    /// exceed the limit errors with `Error::StackOverflow` instead. Threads spawned from this
+    /// thread inherit the limit.
     pub fn set_max_stack_size(&mut self, limit: VmIndex) {
         self.max_stack_size = limit;
     }
 
+    /// Sets how `Int` addition, subtraction, multiplication and division behave when the result
+    /// overflows. Threads spawned from this thread inherit the setting.
+    ///
+    /// Division by zero always stops execution with an error.
+    pub fn set_int_overflow(&mut self, int_overflow: IntOverflow) {
+        self.int_overflow = int_overflow;
+    }
+
+    pub fn int_overflow(&self) -> IntOverflow {
+        self.int_overflow
+    }
+
+    /// Makes the thread yield after every `interval` function calls, letting other tasks run on
+    /// the same executor when the thread is driven as a future (see `Compiler::run_expr_async`).
+    /// Passing `None` (the default) runs the thread until it completes or waits on a future.
+    ///
+    /// Since yielding returns `Async::NotReady` a thread with an interval set must not be run
+    /// with functions that expect a synchronous result, such as `FutureValue::sync_or_error`.
+    pub fn set_yield_interval(&mut self, interval: Option<usize>) {
+        assert!(interval != Some(0), "The yield interval must be greater than 0");
+        self.yield_interval = interval;
+        self.calls_since_yield = 0;
+    }
+
+    /// Limits the thread to executing `fuel` more instructions, after which execution stops with
+    /// `Error::OutOfFuel`. The stopped thread can be continued with `ThreadInternal::resume` once
+    /// more fuel has been added. Passing `None` (the default) lets the thread run without limit.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// Returns how much fuel the thread has left
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// Makes the thread stop with `Error::TimedOut` at the first function call or backward jump
+    /// after `deadline` has passed. Passing `None` (the default) removes the deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Called before each function call. Returns `Async::NotReady` if the thread has used up its
+    /// yield interval and should let other tasks run before continuing.
+    fn safepoint(&mut self) -> Async<()> {
+        match self.yield_interval {
+            Some(interval) if self.calls_since_yield >= interval => {
+                self.calls_since_yield = 0;
+                // The task can't be notified here as the thread may be resumed outside of one
+                // (`ThreadInternal::resume`, coroutines), `Execute::poll` notifies it instead
+                self.yielded = true;
+                Async::NotReady
+            }
+            Some(_) => {
+                self.calls_since_yield += 1;
+                Async::Ready(())
+            }
+            None => Async::Ready(()),
+        }
+    }
+
     /// "Returns a future", letting the virtual machine know that `future` must be resolved to
     /// produce the actual value.
     ///
@@ -1192,7 +1547,7 @@ impl<'b> OwnedContext<'b> {
     pub fn alloc<D>(&mut self, data: D) -> Result<GcPtr<D::Value>>
     where
         D: DataDef + Traverseable,
-        D::Value: Sized + Any,
+        D::Value: Sized + Any + Traverseable,
     {
         let Context {
             ref mut gc,
@@ -1213,7 +1568,7 @@ impl<'b> OwnedContext<'b> {
 pub fn alloc<D>(gc: &mut Gc, thread: &Thread, stack: &Stack, def: D) -> Result<GcPtr<D::Value>>
 where
     D: DataDef + Traverseable,
-    D::Value: Sized + Any,
+    D::Value: Sized + Any + Traverseable,
 {
     let roots = Roots {
         vm: unsafe {
@@ -1267,6 +1622,32 @@ impl<'b> OwnedContext<'b> {
             let state = context.borrow_mut().stack.frame.state;
 
             let instruction_index = context.borrow_mut().stack.frame.instruction_index;
+            if instruction_index == 0 {
+                match state {
+                    State::Extern(_) | State::Closure(_) => {
+                        if let Some(deadline) = context.deadline {
+                            if Instant::now() >= deadline {
+                                return Err(Error::TimedOut);
+                            }
+                        }
+                        if let Async::NotReady = context.safepoint() {
+                            return Ok(Async::NotReady);
+                        }
+                        if context.gc.has_finalizers() {
+                            // Finalizers may need to use the thread so the context must be
+                            // unlocked while they run
+                            let thread = context.thread;
+                            let finalizers = context.gc.take_finalizers();
+                            drop(context);
+                            for mut finalizer in finalizers {
+                                finalizer(thread);
+                            }
+                            context = thread.current_context();
+                        }
+                    }
+                    _ => (),
+                }
+            }
             if instruction_index == 0 && context.hook.flags.contains(HookFlags::CALL_FLAG) {
                 match state {
                     State::Extern(_) | State::Closure(_) => {
@@ -1487,6 +1868,9 @@ impl<'b> OwnedContext<'b> {
             gc: &mut context.gc,
             stack: StackFrame::current(&mut context.stack),
             hook: &mut context.hook,
+            fuel: &mut context.fuel,
+            deadline: context.deadline,
+            int_overflow: context.int_overflow,
         }
     }
 }
@@ -1496,6 +1880,9 @@ struct ExecuteContext<'b> {
     stack: StackFrame<'b>,
     gc: &'b mut Gc,
     hook: &'b mut Hook,
+    fuel: &'b mut Option<usize>,
+    deadline: Option<Instant>,
+    int_overflow: IntOverflow,
 }
 
 impl<'b> ExecuteContext<'b> {
@@ -1504,6 +1891,19 @@ impl<'b> ExecuteContext<'b> {
         self.hook.previous_instruction_index = usize::max_value();
     }
 
+    /// Loops do not necessarily make any function calls so backward jumps check the deadline
+    /// as well
+    fn check_deadline(&self, index: usize, target: usize) -> Result<()> {
+        if target <= index {
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    return Err(Error::TimedOut);
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn exit_scope(&mut self) -> StdResult<(), ()> {
         match self.stack.exit_scope() {
             Ok(_) => {
@@ -1634,6 +2034,32 @@ impl<'b> ExecuteContext<'b> {
         while let Some(&instr) = instructions.get(index) {
             debug_instruction(&self.stack, index, instr);
 
+            if self.hook.flags.contains(HookFlags::COUNT_FLAG) {
+                self.hook.instructions_since_count += 1;
+                if self.hook.instructions_since_count >= self.hook.count {
+                    self.hook.instructions_since_count = 0;
+                    if let Some(ref mut hook) = self.hook.function {
+                        self.stack.frame.instruction_index = index;
+                        self.stack.store_frame();
+                        let info = DebugInfo {
+                            stack: &self.stack.stack,
+                            state: HookFlags::COUNT_FLAG,
+                        };
+                        try_ready!(hook(self.thread, info))
+                    }
+                }
+            }
+
+            if let Some(ref mut fuel) = *self.fuel {
+                if *fuel == 0 {
+                    // Store the frame so the thread can be resumed at this instruction
+                    self.stack.frame.instruction_index = index;
+                    self.stack.store_frame();
+                    return Err(Error::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+
             if self.hook.flags.contains(HookFlags::LINE_FLAG) {
                 if let Some(ref mut hook) = self.hook.function {
                     let current_line = function.debug_info.source_map.line(index);
@@ -1814,13 +2240,21 @@ impl<'b> ExecuteContext<'b> {
                     self.stack
                         .push(ValueRepr::Tag(if data_tag == tag { 1 } else { 0 }));
                 }
-                Split => {
+                Split(n) => {
                     match self.stack.pop().get_repr() {
-                        Data(data) => for field in &data.fields {
-                            self.stack.push(field);
-                        },
+                        Data(ref data) if data.fields.len() == n as usize => {
+                            for field in &data.fields {
+                                self.stack.push(field);
+                            }
+                        }
                         // Zero argument variant
-                        ValueRepr::Tag(_) => (),
+                        ValueRepr::Tag(_) if n == 0 => (),
+                        Data(_) | ValueRepr::Tag(_) => {
+                            return Err(Error::Message(format!(
+                                "Op Split expected a value with {} fields",
+                                n
+                            )))
+                        }
                         _ => {
                             return Err(Error::Message(
                                 "Op Split called on non data type".to_string(),
@@ -1829,12 +2263,14 @@ impl<'b> ExecuteContext<'b> {
                     }
                 }
                 Jump(i) => {
+                    self.check_deadline(index, i as usize)?;
                     index = i as usize;
                     continue;
                 }
                 CJump(i) => match self.stack.pop().get_repr() {
                     ValueRepr::Tag(0) => (),
                     _ => {
+                        self.check_deadline(index, i as usize)?;
                         index = i as usize;
                         continue;
                     }
@@ -1897,6 +2333,7 @@ impl<'b> ExecuteContext<'b> {
                                     *var = self.stack.pop();
                                 }
                             }
+                            self.gc.write_barrier(closure);
                             self.stack.pop(); //Remove the closure
                         }
                         x => ice!("Expected closure, got {:?}", x),
@@ -1906,10 +2343,20 @@ impl<'b> ExecuteContext<'b> {
                     let v = self.stack.get_upvar(i).clone();
                     self.stack.push(v);
                 }
-                AddInt => binop_int(self.thread, &mut self.stack, VmInt::add),
-                SubtractInt => binop_int(self.thread, &mut self.stack, VmInt::sub),
-                MultiplyInt => binop_int(self.thread, &mut self.stack, VmInt::mul),
-                DivideInt => binop_int(self.thread, &mut self.stack, VmInt::div),
+                AddInt | SubtractInt | MultiplyInt | DivideInt => {
+                    let (l, r) = {
+                        let r = self.stack.get_variant(self.stack.len() - 1).unwrap();
+                        let l = self.stack.get_variant(self.stack.len() - 2).unwrap();
+                        (
+                            VmInt::from_value(self.thread, l),
+                            VmInt::from_value(self.thread, r),
+                        )
+                    };
+                    let result = int_arithmetic(self.int_overflow, instr, l, r)?;
+                    self.stack.pop();
+                    self.stack.pop();
+                    self.stack.push(Int(result));
+                }
                 IntLT => binop_bool(self.thread, &mut self.stack, |l: VmInt, r| l < r),
                 IntEQ => binop_bool(self.thread, &mut self.stack, |l: VmInt, r| l == r),
 
@@ -1929,6 +2376,20 @@ impl<'b> ExecuteContext<'b> {
             }
             index += 1;
         }
+
+        if self.hook.flags.contains(HookFlags::RETURN_FLAG) {
+            if let Some(ref mut hook) = self.hook.function {
+                // If the hook does not return `Ready` it is called again once the thread resumes
+                self.stack.frame.instruction_index = index;
+                self.stack.store_frame();
+                let info = DebugInfo {
+                    stack: &self.stack.stack,
+                    state: HookFlags::RETURN_FLAG,
+                };
+                try_ready!(hook(self.thread, info))
+            }
+        }
+
         let result = self.stack.top().clone();
         debug!("Return {:?}", result);
         let len = self.stack.len();
@@ -1973,13 +2434,42 @@ impl<'b> ExecuteContext<'b> {
     }
 }
 
-#[inline]
-fn binop_int<'b, F, T>(vm: &'b Thread, stack: &mut StackFrame<'b>, f: F)
-where
-    F: FnOnce(T, T) -> VmInt,
-    T: Getable<'b> + fmt::Debug,
-{
-    binop(vm, stack, |l, r| ValueRepr::Int(f(l, r)))
+/// Computes `l instruction r` for one of the `Int` arithmetic instructions
+fn int_arithmetic(
+    overflow: IntOverflow,
+    instruction: Instruction,
+    l: VmInt,
+    r: VmInt,
+) -> Result<VmInt> {
+    let checked = match instruction {
+        AddInt => l.checked_add(r),
+        SubtractInt => l.checked_sub(r),
+        MultiplyInt => l.checked_mul(r),
+        DivideInt if r == 0 => {
+            return Err(Error::Message("Attempted to divide by zero".to_string()))
+        }
+        DivideInt => l.checked_div(r),
+        _ => ice!("Expected an arithmetic instruction, got {:?}", instruction),
+    };
+    match checked {
+        Some(result) => Ok(result),
+        None => match overflow {
+            IntOverflow::Wrap => Ok(match instruction {
+                AddInt => l.wrapping_add(r),
+                SubtractInt => l.wrapping_sub(r),
+                MultiplyInt => l.wrapping_mul(r),
+                _ => l.wrapping_div(r),
+            }),
+            IntOverflow::Saturate => Ok(match instruction {
+                AddInt => l.saturating_add(r),
+                SubtractInt => l.saturating_sub(r),
+                MultiplyInt => l.saturating_mul(r),
+                // `Int.min_value / -1` is the only division which overflows
+                _ => VmInt::max_value(),
+            }),
+            IntOverflow::Error => Err(Error::IntegerOverflow),
+        },
+    }
 }
 
 #[inline]
@@ -2057,4 +2547,25 @@ mod tests {
         fn send<T: Send>(_: T) {}
         send(RootedThread::new());
     }
+
+    #[test]
+    fn rooted_values_dropped_out_of_order() {
+        let thread = RootedThread::new();
+        let (first, second) = {
+            let mut context = thread.context();
+            let first = context.new_data(&thread, 0, &[Value::int(1)]).unwrap();
+            let second = context.new_data(&thread, 0, &[Value::int(1)]).unwrap();
+            (first, second)
+        };
+
+        let first: RootedValue<RootedThread> = thread.root_value(first);
+        let second = thread.root_value::<&Thread>(second).into_owned();
+        let first_clone = first.clone();
+        drop(first);
+
+        let rooted_values = thread.rooted_values.read().unwrap();
+        assert_eq!(rooted_values.len(), 2);
+        assert!(rooted_values.iter().any(|value| value.obj_eq(&first_clone)));
+        assert!(rooted_values.iter().any(|value| value.obj_eq(&second)));
+    }
 }
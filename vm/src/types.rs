@@ -1,7 +1,7 @@
 use base::fnv::FnvMap;
 use base::kind::{ArcKind, Kind, KindEnv};
 use base::symbol::{Symbol, SymbolRef};
-use base::types::{Alias, ArcType, Type, TypeEnv};
+use base::types::{self, Alias, ArcType, Field, Type, TypeEnv};
 
 pub use self::Instruction::*;
 
@@ -58,8 +58,9 @@ pub enum Instruction {
     /// and using that to retrieve lookup the field. The result of the
     /// field access replaces the object on the stack.
     GetField(VmIndex),
-    /// Splits a object, pushing all contained values to the stack.
-    Split,
+    /// Splits a object, pushing all contained values to the stack. The object must contain
+    /// exactly `n` values.
+    Split(VmIndex),
     /// Tests if the value at the top of the stack is tagged with `tag`. Pushes `True` if the tag
     /// matches, otherwise `False`
     TestTag(VmTag),
@@ -129,7 +130,7 @@ impl Instruction {
             GetField(_) | GetOffset(_) => 0,
             // The number of added stack slots are handled separately as the type is needed to
             // calculate the number of slots needed
-            Split => -1,
+            Split(_) => -1,
             TestTag(_) => 1,
             Jump(_) => 0,
             CJump(_) => -1,
@@ -196,3 +197,51 @@ impl TypeInfos {
         self.id_to_type.extend(id_to_type);
     }
 }
+
+/// Returns `true` if the variant `row` is open (`| A Int | ..r`)
+fn is_open_variant(row: &ArcType) -> bool {
+    let mut iter = row.row_iter();
+    for _ in iter.by_ref() {}
+    **iter.current_type() != Type::EmptyRow
+}
+
+fn constructor_name_tag(field: &Field<Symbol, ArcType>) -> VmTag {
+    types::open_variant_tag(field.name.declared_name())
+}
+
+/// Returns the first constructor of the variant `row` which matches `predicate` along with the
+/// tag of the constructor.
+///
+/// The constructors of closed variants are tagged by their position in the variant. An open
+/// variant can be extended with more constructors which moves its own constructors to other
+/// positions so the constructors of open variants are instead tagged by a hash of their name.
+pub fn find_variant_constructor<F>(
+    row: &ArcType,
+    mut predicate: F,
+) -> Option<(VmTag, &Field<Symbol, ArcType>)>
+where
+    F: FnMut(&Field<Symbol, ArcType>) -> bool,
+{
+    let open = is_open_variant(row);
+    row.row_iter()
+        .enumerate()
+        .find(|&(_, field)| predicate(field))
+        .map(|(index, field)| {
+            let tag = if open {
+                constructor_name_tag(field)
+            } else {
+                index as VmTag
+            };
+            (tag, field)
+        })
+}
+
+/// Returns the constructor of the variant `row` which is tagged by `tag`
+pub fn variant_constructor(row: &ArcType, tag: VmTag) -> Option<&Field<Symbol, ArcType>> {
+    if is_open_variant(row) {
+        row.row_iter()
+            .find(|field| constructor_name_tag(field) == tag)
+    } else {
+        row.row_iter().nth(tag as usize)
+    }
+}
@@ -1,3 +1,4 @@
+use std::any::TypeId;
 use std::collections::hash_map::Entry;
 use std::fmt;
 use std::mem::size_of;
@@ -15,19 +16,31 @@ use types::*;
 
 use array::Array;
 use compiler::DebugInfo;
-use gc::{DataDef, Gc, GcPtr, Generation, Move, Traverseable, WriteOnly};
+use gc::{DataDef, Gc, GcPtr, Generation, Move, Traverseable, WeakTarget, WriteOnly};
 use interner::InternedStr;
 use thread::{Status, Thread};
 use {Error, Result, Variants};
 
 use self::ValueRepr::{Closure, Float, Function, Int, PartialApplication, String};
 
+/// A function which is run on the thread which owned a collected userdata value.
+pub type Finalizer = Box<FnMut(&Thread) + Send>;
+
 mopafy!(Userdata);
 pub trait Userdata: ::mopa::Any + Traverseable + fmt::Debug + Send + Sync {
     fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<GcPtr<Box<Userdata>>> {
         let _ = deep_cloner;
         Err(Error::Message("Userdata cannot be cloned".into()))
     }
+
+    /// Called by the garbage collector just before the value is freed. The returned finalizer
+    /// is run at the next function call of the thread which owned the value (or at the end of
+    /// `Thread::collect`), at which point it is safe to interact with the thread again. Values
+    /// which are still alive when their thread is dropped are finalized before the thread's
+    /// heap is freed.
+    fn finalize(&mut self) -> Option<Finalizer> {
+        None
+    }
 }
 
 impl PartialEq for Userdata {
@@ -393,6 +406,26 @@ impl Value {
         self.0
     }
 
+    /// Returns true if `self` and `other` are the same value. Unlike `==` values which are
+    /// allocated by the garbage collector are only equal if they point to the same allocation.
+    pub fn obj_eq(&self, other: &Value) -> bool {
+        match (self.get_repr(), other.get_repr()) {
+            (ValueRepr::Byte(l), ValueRepr::Byte(r)) => l == r,
+            (Int(l), Int(r)) => l == r,
+            (Float(l), Float(r)) => l.to_bits() == r.to_bits(),
+            (ValueRepr::Tag(l), ValueRepr::Tag(r)) => l == r,
+            (String(l), String(r)) => l.into_inner().ptr_eq(&r.into_inner()),
+            (ValueRepr::Data(l), ValueRepr::Data(r)) => l.ptr_eq(&r),
+            (Function(l), Function(r)) => l.ptr_eq(&r),
+            (Closure(l), Closure(r)) => l.ptr_eq(&r),
+            (ValueRepr::Array(l), ValueRepr::Array(r)) => l.ptr_eq(&r),
+            (PartialApplication(l), PartialApplication(r)) => l.ptr_eq(&r),
+            (ValueRepr::Userdata(l), ValueRepr::Userdata(r)) => l.ptr_eq(&r),
+            (ValueRepr::Thread(l), ValueRepr::Thread(r)) => l.ptr_eq(&r),
+            _ => false,
+        }
+    }
+
     pub fn generation(&self) -> Generation {
         match self.get_repr() {
             String(p) => p.generation(),
@@ -408,6 +441,26 @@ impl Value {
     }
 }
 
+impl WeakTarget for Value {
+    fn generation(&self) -> Generation {
+        Value::generation(self)
+    }
+
+    fn is_marked(&self) -> bool {
+        match self.get_repr() {
+            String(p) => p.into_inner().is_marked(),
+            ValueRepr::Data(p) => p.is_marked(),
+            Function(p) => p.is_marked(),
+            Closure(p) => p.is_marked(),
+            ValueRepr::Array(p) => p.is_marked(),
+            PartialApplication(p) => p.is_marked(),
+            ValueRepr::Userdata(p) => p.is_marked(),
+            ValueRepr::Thread(p) => p.is_marked(),
+            ValueRepr::Tag(_) | ValueRepr::Byte(_) | Int(_) | Float(_) => true,
+        }
+    }
+}
+
 #[derive(PartialEq, Copy, Clone, PartialOrd)]
 enum Prec {
     Top,
@@ -596,10 +649,8 @@ impl<'a, 't> InternalPrinter<'a, 't> {
                         ]
             }
             Type::Variant(ref row) => {
-                let type_field = row
-                    .row_iter()
-                    .nth(tag as usize)
-                    .expect("Variant tag is out of bounds");
+                let type_field =
+                    variant_constructor(row, tag).expect("Variant tag is out of bounds");
                 let mut empty = true;
                 let doc = chain![arena;
                             type_field.name.declared_name().to_string(),
@@ -1148,6 +1199,29 @@ unsafe impl<'a> DataDef for &'a ValueArray {
     }
 }
 
+/// Names the kind of a value allocated by the virtual machine for use in `HeapSnapshot`s
+pub(crate) fn type_name(type_id: TypeId) -> &'static str {
+    if type_id == TypeId::of::<DataStruct>() {
+        "Data"
+    } else if type_id == TypeId::of::<ValueArray>() {
+        "Array"
+    } else if type_id == TypeId::of::<ClosureData>() {
+        "Closure"
+    } else if type_id == TypeId::of::<PartialApplicationData>() {
+        "PartialApplication"
+    } else if type_id == TypeId::of::<BytecodeFunction>() {
+        "BytecodeFunction"
+    } else if type_id == TypeId::of::<ExternFunction>() {
+        "ExternFunction"
+    } else if type_id == TypeId::of::<Box<Userdata>>() {
+        "Userdata"
+    } else if type_id == TypeId::of::<Thread>() {
+        "Thread"
+    } else {
+        "Unknown"
+    }
+}
+
 pub(crate) struct ArrayDef<'b>(pub &'b [Value]);
 impl<'b> Traverseable for ArrayDef<'b> {
     fn traverse(&self, gc: &mut Gc) {
@@ -1240,11 +1314,33 @@ impl<'t> Cloner<'t> {
             Int(i) => Ok(Int(i)),
             Float(f) => Ok(Float(f)),
             ValueRepr::Userdata(userdata) => userdata.deep_clone(self).map(ValueRepr::Userdata),
-            ValueRepr::Thread(_) => Err(Error::Message("Threads cannot be deep cloned yet".into())),
+            ValueRepr::Thread(thread) => self.deep_clone_thread(thread).map(ValueRepr::Thread),
         };
         result.map(Value::from)
     }
 
+    /// Threads can't be copied so the receiver gets a reference to the same thread instead.
+    fn deep_clone_thread(&mut self, thread: GcPtr<Thread>) -> Result<GcPtr<Thread>> {
+        // The global heap outlives every thread so it can't keep a thread alive
+        if self.receiver_generation.is_root() {
+            return Err(Error::Message(
+                "Threads spawned by other threads can't be stored in global values".into(),
+            ));
+        }
+        // The receiving garbage collector must not mark values in a heap it doesn't sweep, which
+        // would be the case unless it belongs to an ancestor of the thread
+        if !self.thread.is_ancestor_of(&thread) {
+            return Err(Error::Message(
+                "Threads can only be sent to threads which spawned them (directly or indirectly)"
+                    .into(),
+            ));
+        }
+        // The thread is still owned by the heap which allocated it, which does not see the new
+        // reference. The receiving thread keeps it rooted until the receiver is dropped.
+        self.thread.share_thread(thread);
+        Ok(thread)
+    }
+
     fn deep_clone_ptr<T, A, R>(
         &mut self,
         value: GcPtr<T>,
@@ -1342,7 +1438,7 @@ impl<'t> Cloner<'t> {
                             deep_clone_elems(new_array, |e| self.deep_clone_userdata(*e))
                         }
                         Repr::Thread => {
-                            return Err(Error::Message("Threads cannot be deep cloned yet".into()))
+                            deep_clone_elems(new_array, |e| self.deep_clone_thread(*e))
                         }
                     }?;
                 }
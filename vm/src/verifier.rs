@@ -0,0 +1,396 @@
+//! Verification of compiled bytecode before it is loaded into a vm
+
+use base::fnv::FnvMap;
+
+use compiler::{CompiledFunction, CompiledModule};
+use types::Instruction::*;
+use types::VmIndex;
+use {Error, Result};
+
+/// Checks that executing `module` can not access anything outside of the stack, upvariables,
+/// strings, records or inner functions of the executing function.
+///
+/// The depth of the stack is tracked through every instruction so that no instruction uses more
+/// values than exist on the stack and so that every instruction is reached with the same depth
+/// from all of its predecessors. `Split` states how many values it pushes and the vm checks that
+/// the split value contains exactly that many, so the depth is known at every instruction.
+pub fn verify(module: &CompiledModule) -> Result<()> {
+    verify_function(
+        &module.function,
+        Some(module.module_globals.len() as VmIndex),
+    )
+}
+
+/// `upvars` is the number of upvariables closures of `function` are created with or `None` if no
+/// closure is created from it
+fn verify_function(function: &CompiledFunction, upvars: Option<VmIndex>) -> Result<()> {
+    let mut verifier = Verifier {
+        function,
+        upvars,
+        depths: vec![None; function.instructions.len() + 1],
+        queue: Vec::new(),
+        inner_upvars: FnvMap::default(),
+    };
+    verifier.verify()?;
+
+    let inner_upvars = verifier.inner_upvars;
+    for (i, inner) in function.inner_functions.iter().enumerate() {
+        verify_function(inner, inner_upvars.get(&i).cloned())?;
+    }
+    Ok(())
+}
+
+struct Verifier<'a> {
+    function: &'a CompiledFunction,
+    upvars: Option<VmIndex>,
+    /// The depth each instruction is reached with, `None` for instructions which have not been
+    /// reached yet. The extra entry at the end is for returning from the function.
+    depths: Vec<Option<VmIndex>>,
+    /// Instructions which need to be (re)checked
+    queue: Vec<usize>,
+    /// The number of upvariables closures of each inner function are created with
+    inner_upvars: FnvMap<usize, VmIndex>,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&mut self) -> Result<()> {
+        let args = self.function.args;
+        self.reach(0, 0, args)?;
+        while let Some(index) = self.queue.pop() {
+            let depth = self.depths[index].expect("Queued instruction is reached");
+            self.step(index, depth)?;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, index: usize, depth: VmIndex) -> Result<()> {
+        let function = self.function;
+        let instruction = match function.instructions.get(index) {
+            Some(&instruction) => instruction,
+            None => {
+                // Returns the value at the top of the stack
+                self.pop(index, depth, 1)?;
+                return Ok(());
+            }
+        };
+        let next = index + 1;
+        match instruction {
+            PushInt(_) | PushByte(_) | PushFloat(_) => {
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            PushString(string) => {
+                self.check_index(index, "string", string, function.strings.len())?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            PushUpVar(upvar) => {
+                if let Some(upvars) = self.upvars {
+                    self.check_index(index, "upvariable", upvar, upvars as usize)?;
+                }
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            Push(value) => {
+                self.check_index(index, "stack value", value, depth as usize)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            Call(args) => {
+                let depth = self.pop(index, depth, self.add(index, args, 1)?)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            TailCall(args) => {
+                // The function returns through the called function
+                self.pop(index, depth, self.add(index, args, 1)?)?;
+                Ok(())
+            }
+            Construct { args, .. } | ConstructArray(args) => {
+                let depth = self.pop(index, depth, args)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            ConstructRecord { record, args } => {
+                self.check_index(index, "record", record, function.records.len())?;
+                let fields = function.records[record as usize].len();
+                if fields != args as usize {
+                    return Err(self.error(
+                        index,
+                        format!(
+                            "record is constructed from {} values but has {} fields",
+                            args, fields
+                        ),
+                    ));
+                }
+                let depth = self.pop(index, depth, args)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            GetOffset(_) => {
+                let depth = self.pop(index, depth, 1)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            GetField(string) => {
+                self.check_index(index, "string", string, function.strings.len())?;
+                let depth = self.pop(index, depth, 1)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            Split(fields) => {
+                let depth = self.pop(index, depth, 1)?;
+                self.reach(index, next, self.add(index, depth, fields)?)
+            }
+            TestTag(_) => {
+                self.pop(index, depth, 1)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            Jump(target) => self.reach(index, target as usize, depth),
+            CJump(target) => {
+                let depth = self.pop(index, depth, 1)?;
+                self.reach(index, target as usize, depth)?;
+                self.reach(index, next, depth)
+            }
+            Pop(n) => {
+                let depth = self.pop(index, depth, n)?;
+                self.reach(index, next, depth)
+            }
+            Slide(n) => {
+                let depth = self.pop(index, depth, self.add(index, n, 1)?)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            MakeClosure {
+                function_index,
+                upvars,
+            } => {
+                self.closure(index, function_index, upvars)?;
+                let depth = self.pop(index, depth, upvars)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            NewClosure {
+                function_index,
+                upvars,
+            } => {
+                self.closure(index, function_index, upvars)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+            CloseClosure(upvars) => {
+                let depth = self.pop(index, depth, self.add(index, upvars, 1)?)?;
+                self.reach(index, next, depth)
+            }
+            AddInt | SubtractInt | MultiplyInt | DivideInt | IntLT | IntEQ | AddByte
+            | SubtractByte | MultiplyByte | DivideByte | ByteLT | ByteEQ | AddFloat
+            | SubtractFloat | MultiplyFloat | DivideFloat | FloatLT | FloatEQ => {
+                let depth = self.pop(index, depth, 2)?;
+                self.reach(index, next, self.add(index, depth, 1)?)
+            }
+        }
+    }
+
+    /// Records that `target` is reached from `index` with `depth` values on the stack
+    fn reach(&mut self, index: usize, target: usize, depth: VmIndex) -> Result<()> {
+        if target >= self.depths.len() {
+            return Err(self.error(
+                index,
+                format!(
+                    "jump to instruction {} but the function only has {} instructions",
+                    target,
+                    self.function.instructions.len()
+                ),
+            ));
+        }
+        match self.depths[target] {
+            Some(previous) if previous != depth => Err(self.error(
+                index,
+                format!(
+                    "instruction {} is reached with both {} and {} values on the stack",
+                    target, previous, depth
+                ),
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.depths[target] = Some(depth);
+                self.queue.push(target);
+                Ok(())
+            }
+        }
+    }
+
+    fn pop(&self, index: usize, depth: VmIndex, count: VmIndex) -> Result<VmIndex> {
+        if depth < count {
+            Err(self.error(
+                index,
+                format!(
+                    "instruction uses {} values but the stack only contains {}",
+                    count, depth
+                ),
+            ))
+        } else {
+            Ok(depth - count)
+        }
+    }
+
+    /// Adds two operands of the bytecode without letting a malicious instruction overflow them
+    fn add(&self, index: usize, lhs: VmIndex, rhs: VmIndex) -> Result<VmIndex> {
+        lhs.checked_add(rhs).ok_or_else(|| {
+            self.error(
+                index,
+                format!("instruction operand {} + {} overflows", lhs, rhs),
+            )
+        })
+    }
+
+    fn closure(&mut self, index: usize, function_index: VmIndex, upvars: VmIndex) -> Result<()> {
+        let inner_functions = self.function.inner_functions.len();
+        self.check_index(index, "inner function", function_index, inner_functions)?;
+        let previous = *self
+            .inner_upvars
+            .entry(function_index as usize)
+            .or_insert(upvars);
+        if previous != upvars {
+            return Err(self.error(
+                index,
+                format!(
+                    "closures of inner function {} are created with both {} and {} upvariables",
+                    function_index, previous, upvars
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_index(&self, index: usize, what: &str, value: VmIndex, len: usize) -> Result<()> {
+        if (value as usize) < len {
+            Ok(())
+        } else {
+            Err(self.error(
+                index,
+                format!("{} {} is out of bounds (there are {})", what, value, len),
+            ))
+        }
+    }
+
+    fn error(&self, index: usize, message: String) -> Error {
+        Error::InvalidBytecode(self.function.id.to_string(), index, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base::symbol::Symbol;
+    use base::types::Type;
+    use types::Instruction;
+
+    fn function(args: VmIndex, instructions: Vec<Instruction>) -> CompiledModule {
+        let mut function =
+            CompiledFunction::new(args, Symbol::from("test"), Type::hole(), "test".into());
+        function.instructions = instructions;
+        function.into()
+    }
+
+    #[test]
+    fn valid_function() {
+        let module = function(
+            1,
+            vec![
+                Push(0),
+                PushInt(0),
+                IntEQ,
+                CJump(6),
+                PushInt(1),
+                Jump(7),
+                PushInt(2),
+                Slide(1),
+            ],
+        );
+        assert_eq!(verify(&module), Ok(()));
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let module = function(0, vec![PushInt(1), AddInt]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 1, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+        let module = function(0, vec![PushInt(1), Jump(10)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 1, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn inconsistent_depth_at_join() {
+        let module = function(
+            1,
+            vec![Push(0), CJump(4), PushInt(1), PushInt(2), PushInt(3)],
+        );
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, _, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn string_out_of_bounds() {
+        let module = function(0, vec![PushString(0)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 0, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn upvar_out_of_bounds() {
+        let module = function(0, vec![PushUpVar(0)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 0, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn split_pushes_its_fields() {
+        let module = function(
+            1,
+            vec![
+                Push(0),
+                TestTag(0),
+                CJump(5),
+                PushInt(0),
+                Jump(8),
+                Push(0),
+                Split(2),
+                Slide(1),
+                Slide(1),
+            ],
+        );
+        assert_eq!(verify(&module), Ok(()));
+    }
+
+    #[test]
+    fn stack_underflow_after_split() {
+        let module = function(1, vec![Push(0), Split(1), Slide(2)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 2, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn call_with_too_many_arguments() {
+        let module = function(0, vec![Call(VmIndex::MAX)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 0, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn split_with_too_many_fields() {
+        let module = function(1, vec![Push(0), Split(VmIndex::MAX)]);
+        match verify(&module) {
+            Err(Error::InvalidBytecode(_, 1, _)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+}
@@ -16,6 +16,7 @@ use base::types::{
 
 use api::{ValueRef, IO};
 use compiler::{CompiledFunction, CompiledModule, CompilerEnv, Variable};
+use core::optimize::OptimizeEnv;
 use gc::{Gc, GcPtr, Generation, Move, Traverseable};
 use interner::{InternedStr, Interner};
 use lazy::Lazy;
@@ -225,6 +226,12 @@ impl PrimitiveEnv for VmEnv {
     }
 }
 
+impl OptimizeEnv for VmEnv {
+    fn find_global_value(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).map(|global| global.value.clone())
+    }
+}
+
 impl MetadataEnv for VmEnv {
     fn get_metadata(&self, id: &SymbolRef) -> Option<&Metadata> {
         self.get_metadata(id.definition_name()).ok()
@@ -0,0 +1,79 @@
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+
+use api::generic::A;
+use api::{Generic, Userdata, VmType, WithVM};
+use base::types::{ArcType, Type};
+use gc::{Gc, Traverseable, WeakPtr};
+use thread::ThreadInternal;
+use value::Value;
+use vm::Thread;
+use {ExternModule, Result};
+
+/// A weak reference to a value. The value may be collected while `Weak` references to it exist.
+pub struct Weak<T> {
+    ptr: WeakPtr<Value>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Userdata for Weak<T> where T: Any + Send + Sync {}
+
+impl<T> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Weak({:?})", self.ptr)
+    }
+}
+
+impl<T> Traverseable for Weak<T> {
+    fn traverse(&self, gc: &mut Gc) {
+        self.ptr.traverse(gc)
+    }
+}
+
+impl<T> VmType for Weak<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = Weak<T::Type>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let env = vm.global_env().get_env();
+        let symbol = env.find_type_info("Weak").unwrap().name.clone();
+        let ctor = Type::ident(symbol);
+        Type::app(ctor, collect![T::make_type(vm)])
+    }
+}
+
+fn new(a: WithVM<Generic<A>>) -> Weak<A> {
+    let mut context = a.vm.context();
+    Weak {
+        ptr: context.gc.new_weak(unsafe { a.value.get_value() }),
+        _marker: PhantomData,
+    }
+}
+
+fn upgrade(weak: &Weak<A>) -> Option<Generic<A>> {
+    weak.ptr.upgrade().map(Generic::from)
+}
+
+mod std {
+    pub mod weak {
+        pub use weak as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    use self::std;
+
+    let _ = vm.register_type::<Weak<A>>("Weak", &["a"]);
+    ExternModule::new(
+        vm,
+        record!{
+            type Weak a => Weak<A>,
+            new => named_primitive!(1, "std.weak.prim.new", std::weak::prim::new),
+            upgrade => named_primitive!(1, "std.weak.prim.upgrade", std::weak::prim::upgrade),
+        },
+    )
+}